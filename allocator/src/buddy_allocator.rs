@@ -100,6 +100,15 @@ impl BuddyAllocator {
 
         // 尝试合并内存块
         self.merge(level, ptr);
+
+        // 更新统计信息
+        self.user -= layout.size();
+        self.allocated -= size;
+    }
+
+    /// 统计信息: (管理的内存总量, 用户请求的内存总量, 实际分配的内存总量)
+    pub fn stats(&self) -> (usize, usize, usize) {
+        (self.total, self.user, self.allocated)
     }
 
 
@@ -23,6 +23,10 @@ impl SafeBuddyHeap {
     pub unsafe fn add_segment(&self, start: usize, end: usize) {
         self.allocator.lock().add_segment(start, end);
     }
+    /// (total, user, allocated) in bytes, see `BuddyAllocator::stats`
+    pub fn stats(&self) -> (usize, usize, usize) {
+        self.allocator.lock().stats()
+    }
 }
 
 ///  Implementation of `GlobalAlloc`,
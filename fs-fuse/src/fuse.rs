@@ -0,0 +1,818 @@
+// fs-fuse/src/fuse.rs
+// FUSE 协议层: 把内核发来的 FUSE 请求翻译为 easy-fs 的操作
+// 不依赖 /dev/fuse: `FuseServer::handle` 输入一个完整的请求、返回完整的回复，可以在进程内测试
+// 数据结构与 include/uapi/linux/fuse.h 一致
+
+use std::mem::size_of;
+use std::sync::Arc;
+use easy_fs::block_cache::get_block_cache;
+use easy_fs::efs::EasyFileSystem;
+use easy_fs::super_block::SuperBlock;
+use easy_fs::{Inode, Permissions, RenameError, RenameMode, Timestamp, XattrError, XattrMode, NAME_LENGTH_LIMIT, SYMLINK_LENGTH_LIMIT};
+use libc::{EEXIST, EFBIG, EINVAL, EIO, EISDIR, ENAMETOOLONG, ENODATA, ENOENT, ENOSPC, ENOSYS, ENOTDIR, ENOTEMPTY, ERANGE, ESTALE};
+use libc::{RENAME_EXCHANGE, RENAME_NOREPLACE, XATTR_CREATE, XATTR_REPLACE};
+
+// ----- protocol -----
+
+pub const FUSE_KERNEL_VERSION: u32 = 7;
+pub const FUSE_KERNEL_MINOR_VERSION: u32 = 31;
+pub const FUSE_ROOT_ID: u64 = 1;
+/// 一次 WRITE 请求最多携带的数据
+pub const MAX_WRITE: u32 = 128 * 1024;
+/// 读取请求的缓冲区大小: 最大的 WRITE 请求加上请求头
+pub const BUFFER_SIZE: usize = MAX_WRITE as usize + 4096;
+
+const FUSE_BIG_WRITES: u32 = 1 << 5;
+const FATTR_MODE: u32 = 1 << 0;
+const FATTR_UID: u32 = 1 << 1;
+const FATTR_GID: u32 = 1 << 2;
+const FATTR_SIZE: u32 = 1 << 3;
+const FATTR_ATIME: u32 = 1 << 4;
+const FATTR_MTIME: u32 = 1 << 5;
+const FATTR_ATIME_NOW: u32 = 1 << 7;
+const FATTR_MTIME_NOW: u32 = 1 << 8;
+const FUSE_COMPAT_22_INIT_OUT_SIZE: usize = 24;
+const DT_DIR: u32 = 4;
+const DT_REG: u32 = 8;
+const DT_LNK: u32 = 10;
+
+pub mod opcode {
+    pub const LOOKUP: u32 = 1;
+    pub const FORGET: u32 = 2;
+    pub const GETATTR: u32 = 3;
+    pub const SETATTR: u32 = 4;
+    pub const READLINK: u32 = 5;
+    pub const SYMLINK: u32 = 6;
+    pub const MKDIR: u32 = 9;
+    pub const UNLINK: u32 = 10;
+    pub const RMDIR: u32 = 11;
+    pub const RENAME: u32 = 12;
+    pub const OPEN: u32 = 14;
+    pub const READ: u32 = 15;
+    pub const WRITE: u32 = 16;
+    pub const STATFS: u32 = 17;
+    pub const RELEASE: u32 = 18;
+    pub const FSYNC: u32 = 20;
+    pub const SETXATTR: u32 = 21;
+    pub const GETXATTR: u32 = 22;
+    pub const LISTXATTR: u32 = 23;
+    pub const REMOVEXATTR: u32 = 24;
+    pub const FLUSH: u32 = 25;
+    pub const INIT: u32 = 26;
+    pub const OPENDIR: u32 = 27;
+    pub const READDIR: u32 = 28;
+    pub const RELEASEDIR: u32 = 29;
+    pub const FSYNCDIR: u32 = 30;
+    pub const CREATE: u32 = 35;
+    pub const INTERRUPT: u32 = 36;
+    pub const DESTROY: u32 = 38;
+    pub const BATCH_FORGET: u32 = 42;
+    pub const RENAME2: u32 = 45;
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct InHeader {
+    pub len: u32,
+    pub opcode: u32,
+    pub unique: u64,
+    pub nodeid: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub pid: u32,
+    pub total_extlen: u16,
+    pub padding: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct OutHeader {
+    pub len: u32,
+    pub error: i32,
+    pub unique: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct InitIn {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct InitOut {
+    pub major: u32,
+    pub minor: u32,
+    pub max_readahead: u32,
+    pub flags: u32,
+    pub max_background: u16,
+    pub congestion_threshold: u16,
+    pub max_write: u32,
+    pub time_gran: u32,
+    pub max_pages: u16,
+    pub map_alignment: u16,
+    pub flags2: u32,
+    pub unused: [u32; 7],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Attr {
+    pub ino: u64,
+    pub size: u64,
+    pub blocks: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u32,
+    pub blksize: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EntryOut {
+    pub nodeid: u64,
+    pub generation: u64,
+    pub entry_valid: u64,
+    pub attr_valid: u64,
+    pub entry_valid_nsec: u32,
+    pub attr_valid_nsec: u32,
+    pub attr: Attr,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct AttrOut {
+    pub attr_valid: u64,
+    pub attr_valid_nsec: u32,
+    pub dummy: u32,
+    pub attr: Attr,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RenameIn {
+    pub newdir: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Rename2In {
+    pub newdir: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SetattrIn {
+    pub valid: u32,
+    pub padding: u32,
+    pub fh: u64,
+    pub size: u64,
+    pub lock_owner: u64,
+    pub atime: u64,
+    pub mtime: u64,
+    pub ctime: u64,
+    pub atimensec: u32,
+    pub mtimensec: u32,
+    pub ctimensec: u32,
+    pub mode: u32,
+    pub unused4: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub unused5: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct OpenOut {
+    pub fh: u64,
+    pub open_flags: u32,
+    pub padding: u32,
+}
+
+/// READ 与 READDIR 共用
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ReadIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub read_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+/// FSYNC 与 FSYNCDIR 共用
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FsyncIn {
+    pub fh: u64,
+    pub fsync_flags: u32,
+    pub padding: u32,
+}
+
+/// FsyncIn::fsync_flags: 只同步数据 (fdatasync)
+pub const FUSE_FSYNC_FDATASYNC: u32 = 1;
+
+/// 后面紧跟 size 字节的数据
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct WriteIn {
+    pub fh: u64,
+    pub offset: u64,
+    pub size: u32,
+    pub write_flags: u32,
+    pub lock_owner: u64,
+    pub flags: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct WriteOut {
+    pub size: u32,
+    pub padding: u32,
+}
+
+/// 后面紧跟以 '\0' 结尾的文件名
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CreateIn {
+    pub flags: u32,
+    pub mode: u32,
+    pub umask: u32,
+    pub open_flags: u32,
+}
+
+/// 后面紧跟以 '\0' 结尾的目录名
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MkdirIn {
+    pub mode: u32,
+    pub umask: u32,
+}
+
+/// 后面紧跟 namelen 字节的名字，整体按 8 字节对齐
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Dirent {
+    pub ino: u64,
+    pub off: u64,
+    pub namelen: u32,
+    pub type_: u32,
+}
+
+/// 后面紧跟以 '\0' 结尾的属性名与 size 字节的值
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SetxattrIn {
+    pub size: u32,
+    pub flags: u32,
+}
+
+/// GETXATTR (后面紧跟以 '\0' 结尾的属性名) 与 LISTXATTR 共用
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct GetxattrIn {
+    pub size: u32,
+    pub padding: u32,
+}
+
+/// GETXATTR/LISTXATTR 的 size 为 0 时，回复所需的缓冲区大小
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct GetxattrOut {
+    pub size: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StatfsOut {
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub bsize: u32,
+    pub namelen: u32,
+    pub frsize: u32,
+    pub padding: u32,
+    pub spare: [u32; 6],
+}
+
+/// 协议结构体的字节表示
+pub fn as_bytes<T: Copy>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// 从字节序列开头解析协议结构体，长度不足时返回 None
+pub fn from_bytes<T: Copy>(bytes: &[u8]) -> Option<T> {
+    if bytes.len() < size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// 请求体中以 '\0' 结尾的名字
+fn name_arg(bytes: &[u8]) -> Result<&str, i32> {
+    let end = bytes.iter().position(|b| *b == 0).ok_or(EINVAL)?;
+    std::str::from_utf8(&bytes[..end]).map_err(|_| EINVAL)
+}
+
+/// 属于发出请求的用户、权限位为 mode 的新 inode
+fn owned_by(header: &InHeader, mode: u32) -> Permissions {
+    Permissions { mode: (mode & 0o7777) as u16, uid: header.uid, gid: header.gid }
+}
+
+fn xattr_errno(err: XattrError) -> i32 {
+    match err {
+        XattrError::NotFound => ENODATA,
+        XattrError::Exists => EEXIST,
+        XattrError::Invalid => ERANGE,
+        XattrError::NoSpace => ENOSPC,
+    }
+}
+
+/// size 为 0 时只回复数据的长度，缓冲区放不下时返回 ERANGE
+fn xattr_reply(size: u32, data: Vec<u8>) -> Reply {
+    match size {
+        0 => Ok(as_bytes(&GetxattrOut { size: data.len() as u32, padding: 0 }).to_vec()),
+        size if (size as usize) < data.len() => Err(ERANGE),
+        _ => Ok(data),
+    }
+}
+
+// ----- FuseServer -----
+
+type Reply = Result<Vec<u8>, i32>;
+
+/// 处理一个 easy-fs 镜像上的 FUSE 请求
+/// FUSE 的 nodeid 为 easy-fs 的 inode ID 加一 (根目录的 nodeid 为 FUSE_ROOT_ID)
+/// 文件的权限位与属主原样取自镜像，新建的文件属于发出请求的用户
+pub struct FuseServer {
+    efs: Arc<spin::Mutex<EasyFileSystem>>,
+    initialized: bool,
+    destroyed: bool,
+}
+
+impl FuseServer {
+    pub fn new(efs: Arc<spin::Mutex<EasyFileSystem>>) -> Self {
+        Self { efs, initialized: false, destroyed: false }
+    }
+
+    /// 是否已收到 DESTROY (文件系统被卸载)
+    pub fn is_destroyed(&self) -> bool {
+        self.destroyed
+    }
+
+    /// 处理一个请求，返回回复；FORGET 等请求不需要回复，返回 None
+    pub fn handle(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let header: InHeader = from_bytes(request)?;
+        let end = (header.len as usize).clamp(size_of::<InHeader>(), request.len());
+        let body = &request[size_of::<InHeader>()..end];
+        let reply = match header.opcode {
+            opcode::FORGET | opcode::BATCH_FORGET | opcode::INTERRUPT => return None,
+            opcode::INIT => self.init(body),
+            _ if !self.initialized => Err(EIO),
+            opcode::DESTROY => {
+                self.destroyed = true;
+                Ok(Vec::new())
+            }
+            opcode::LOOKUP => self.lookup(header.nodeid, body),
+            opcode::GETATTR => self.inode(header.nodeid).map(|inode| self.attr_out(header.nodeid, &inode)),
+            opcode::SETATTR => self.setattr(header.nodeid, body),
+            opcode::READLINK => self.readlink(header.nodeid),
+            opcode::SYMLINK => self.symlink(&header, body),
+            opcode::OPEN | opcode::OPENDIR => self.open(header.nodeid, header.opcode == opcode::OPENDIR),
+            opcode::READ => self.read(header.nodeid, body),
+            opcode::WRITE => self.write(header.nodeid, body),
+            opcode::CREATE => self.create(&header, body),
+            opcode::MKDIR => self.mkdir(&header, body),
+            opcode::UNLINK => self.unlink(header.nodeid, body, false),
+            opcode::RMDIR => self.unlink(header.nodeid, body, true),
+            opcode::RENAME => self.rename(header.nodeid, body, false),
+            opcode::RENAME2 => self.rename(header.nodeid, body, true),
+            opcode::READDIR => self.readdir(header.nodeid, body),
+            opcode::SETXATTR => self.setxattr(header.nodeid, body),
+            opcode::GETXATTR => self.getxattr(header.nodeid, body),
+            opcode::LISTXATTR => self.listxattr(header.nodeid, body),
+            opcode::REMOVEXATTR => self.removexattr(header.nodeid, body),
+            opcode::STATFS => Ok(self.statfs()),
+            // easy-fs 的每个操作在返回前都已提交到磁盘
+            opcode::FSYNC | opcode::FSYNCDIR => self.fsync(header.nodeid, body),
+            opcode::RELEASE | opcode::RELEASEDIR | opcode::FLUSH => Ok(Vec::new()),
+            _ => Err(ENOSYS),
+        };
+        // 请求读到了校验和不符的块: 报告损坏的块并返回 EIO (修改可能已部分完成)
+        let checksum_errors = self.efs.lock().take_checksum_errors();
+        for block in checksum_errors.iter() {
+            eprintln!("easy-fs: block {}: checksum mismatch", block);
+        }
+        let reply = if checksum_errors.is_empty() { reply } else { Err(EIO) };
+        let (error, data) = match reply {
+            Ok(data) => (0, data),
+            Err(errno) => (-errno, Vec::new()),
+        };
+        let out = OutHeader {
+            len: (size_of::<OutHeader>() + data.len()) as u32,
+            error,
+            unique: header.unique,
+        };
+        let mut reply = as_bytes(&out).to_vec();
+        reply.extend_from_slice(&data);
+        Some(reply)
+    }
+
+    // ----- helpers -----
+
+    fn inode(&self, nodeid: u64) -> Result<Arc<Inode>, i32> {
+        let inode_id = nodeid.checked_sub(FUSE_ROOT_ID).ok_or(EINVAL)?;
+        let inode_id = u32::try_from(inode_id).map_err(|_| EINVAL)?;
+        // 内核持有的 nodeid 对应的文件已被删除
+        EasyFileSystem::get_inode(&self.efs, inode_id).ok_or(ESTALE)
+    }
+
+    fn dir(&self, nodeid: u64) -> Result<Arc<Inode>, i32> {
+        let inode = self.inode(nodeid)?;
+        if !inode.is_dir() {
+            return Err(ENOTDIR);
+        }
+        Ok(inode)
+    }
+
+    fn attr(&self, nodeid: u64, inode: &Inode) -> Attr {
+        let size = inode.size() as u64;
+        let perm = inode.permissions();
+        let times = inode.times();
+        let (type_, nlink) = if inode.is_dir() {
+            (libc::S_IFDIR, 2)
+        } else if inode.is_symlink() {
+            (libc::S_IFLNK, 1)
+        } else {
+            (libc::S_IFREG, 1)
+        };
+        Attr {
+            ino: nodeid,
+            size,
+            blocks: size.div_ceil(512),
+            atime: times.atime.sec,
+            mtime: times.mtime.sec,
+            ctime: times.ctime.sec,
+            atimensec: times.atime.nsec,
+            mtimensec: times.mtime.nsec,
+            ctimensec: times.ctime.nsec,
+            mode: type_ | perm.mode as u32,
+            nlink,
+            uid: perm.uid,
+            gid: perm.gid,
+            blksize: 512,
+            ..Attr::default()
+        }
+    }
+
+    fn attr_out(&self, nodeid: u64, inode: &Inode) -> Vec<u8> {
+        let out = AttrOut { attr_valid: 1, attr: self.attr(nodeid, inode), ..AttrOut::default() };
+        as_bytes(&out).to_vec()
+    }
+
+    fn entry_out(&self, inode: &Inode) -> EntryOut {
+        let nodeid = inode.inode_id() as u64 + FUSE_ROOT_ID;
+        EntryOut {
+            nodeid,
+            entry_valid: 1,
+            attr_valid: 1,
+            attr: self.attr(nodeid, inode),
+            ..EntryOut::default()
+        }
+    }
+
+    // ----- operations -----
+
+    fn init(&mut self, body: &[u8]) -> Reply {
+        let init: InitIn = from_bytes(body).ok_or(EINVAL)?;
+        if init.major < FUSE_KERNEL_VERSION {
+            return Err(EIO);
+        }
+        self.initialized = true;
+        let out = InitOut {
+            major: FUSE_KERNEL_VERSION,
+            minor: FUSE_KERNEL_MINOR_VERSION,
+            max_readahead: init.max_readahead,
+            flags: init.flags & FUSE_BIG_WRITES,
+            max_background: 16,
+            congestion_threshold: 12,
+            max_write: MAX_WRITE,
+            time_gran: 1,
+            ..InitOut::default()
+        };
+        let mut reply = as_bytes(&out).to_vec();
+        if init.major == FUSE_KERNEL_VERSION && init.minor < 23 {
+            reply.truncate(FUSE_COMPAT_22_INIT_OUT_SIZE);
+        }
+        Ok(reply)
+    }
+
+    fn lookup(&self, parent: u64, body: &[u8]) -> Reply {
+        let name = name_arg(body)?;
+        if name.len() > NAME_LENGTH_LIMIT as usize {
+            return Err(ENAMETOOLONG);
+        }
+        let inode = self.dir(parent)?.find_inode(name).ok_or(ENOENT)?;
+        Ok(as_bytes(&self.entry_out(&inode)).to_vec())
+    }
+
+    fn setattr(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let setattr: SetattrIn = from_bytes(body).ok_or(EINVAL)?;
+        let inode = self.inode(nodeid)?;
+        if setattr.valid & (FATTR_MODE | FATTR_UID | FATTR_GID) != 0 {
+            let mut perm = inode.permissions();
+            if setattr.valid & FATTR_MODE != 0 {
+                perm.mode = (setattr.mode & 0o7777) as u16;
+            }
+            if setattr.valid & FATTR_UID != 0 {
+                perm.uid = setattr.uid;
+            }
+            if setattr.valid & FATTR_GID != 0 {
+                perm.gid = setattr.gid;
+            }
+            inode.set_permissions(perm);
+        }
+        if setattr.valid & FATTR_SIZE != 0 {
+            if inode.is_dir() {
+                return Err(EISDIR);
+            }
+            if inode.is_symlink() {
+                return Err(EINVAL);
+            }
+            if setattr.size > inode.max_size() as u64 {
+                return Err(EFBIG);
+            }
+            if !inode.truncate(setattr.size as usize) {
+                return Err(ENOSPC);
+            }
+        }
+        // utimensat: *_NOW 表示设置为当前时间
+        let now = self.efs.lock().now();
+        let time = |set: u32, set_now: u32, sec: u64, nsec: u32| match setattr.valid {
+            valid if valid & set_now != 0 => now,
+            valid if valid & set != 0 => Some(Timestamp { sec, nsec }),
+            _ => None,
+        };
+        let atime = time(FATTR_ATIME, FATTR_ATIME_NOW, setattr.atime, setattr.atimensec);
+        let mtime = time(FATTR_MTIME, FATTR_MTIME_NOW, setattr.mtime, setattr.mtimensec);
+        if atime.is_some() || mtime.is_some() {
+            inode.set_times(atime, mtime);
+        }
+        Ok(self.attr_out(nodeid, &inode))
+    }
+
+    fn open(&self, nodeid: u64, dir: bool) -> Reply {
+        let inode = self.inode(nodeid)?;
+        match (dir, inode.is_dir()) {
+            (true, false) => Err(ENOTDIR),
+            (false, true) => Err(EISDIR),
+            _ => Ok(as_bytes(&OpenOut::default()).to_vec()),
+        }
+    }
+
+    fn read(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let read: ReadIn = from_bytes(body).ok_or(EINVAL)?;
+        let inode = self.inode(nodeid)?;
+        if inode.is_dir() {
+            return Err(EISDIR);
+        }
+        let mut data = vec![0u8; read.size as usize];
+        let len = inode.read_at(read.offset as usize, &mut data);
+        data.truncate(len);
+        Ok(data)
+    }
+
+    /// 提交失败 (事务超出日志容量，什么都没有写入磁盘) 时返回 EIO
+    fn fsync(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let fsync: FsyncIn = from_bytes(body).ok_or(EINVAL)?;
+        let inode = self.inode(nodeid)?;
+        let synced = if fsync.fsync_flags & FUSE_FSYNC_FDATASYNC != 0 { inode.sync_data() } else { inode.sync_all() };
+        if synced { Ok(Vec::new()) } else { Err(EIO) }
+    }
+
+    fn write(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let write: WriteIn = from_bytes(body).ok_or(EINVAL)?;
+        let data = body.get(size_of::<WriteIn>()..size_of::<WriteIn>() + write.size as usize).ok_or(EINVAL)?;
+        let inode = self.inode(nodeid)?;
+        if inode.is_dir() {
+            return Err(EISDIR);
+        }
+        if write.offset + data.len() as u64 > inode.max_size() as u64 {
+            return Err(EFBIG);
+        }
+        let written = inode.write_at(write.offset as usize, data);
+        if written == 0 && !data.is_empty() {
+            return Err(ENOSPC);
+        }
+        Ok(as_bytes(&WriteOut { size: written as u32, padding: 0 }).to_vec())
+    }
+
+    fn readlink(&self, nodeid: u64) -> Reply {
+        let inode = self.inode(nodeid)?;
+        if !inode.is_symlink() {
+            return Err(EINVAL);
+        }
+        let mut target = vec![0u8; inode.size()];
+        let len = inode.read_at(0, &mut target);
+        target.truncate(len);
+        Ok(target)
+    }
+
+    fn setxattr(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let setxattr: SetxattrIn = from_bytes(body).ok_or(EINVAL)?;
+        let name = name_arg(&body[size_of::<SetxattrIn>()..])?;
+        let value_start = size_of::<SetxattrIn>() + name.len() + 1;
+        let value = body.get(value_start..value_start + setxattr.size as usize).ok_or(EINVAL)?;
+        let mode = match setxattr.flags as i32 {
+            0 => XattrMode::Set,
+            XATTR_CREATE => XattrMode::Create,
+            XATTR_REPLACE => XattrMode::Replace,
+            _ => return Err(EINVAL),
+        };
+        self.inode(nodeid)?.set_xattr(name, value, mode).map_err(xattr_errno)?;
+        Ok(Vec::new())
+    }
+
+    fn getxattr(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let getxattr: GetxattrIn = from_bytes(body).ok_or(EINVAL)?;
+        let name = name_arg(&body[size_of::<GetxattrIn>()..])?;
+        let value = self.inode(nodeid)?.get_xattr(name).ok_or(ENODATA)?;
+        xattr_reply(getxattr.size, value)
+    }
+
+    /// 回复所有属性名，每个以 '\0' 结尾
+    fn listxattr(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let listxattr: GetxattrIn = from_bytes(body).ok_or(EINVAL)?;
+        let names = self.inode(nodeid)?.list_xattr();
+        xattr_reply(listxattr.size, names.iter().flat_map(|name| name.bytes().chain([0])).collect())
+    }
+
+    fn removexattr(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let name = name_arg(body)?;
+        self.inode(nodeid)?.remove_xattr(name).map_err(xattr_errno)?;
+        Ok(Vec::new())
+    }
+
+    /// 在 parent 中用 create 新建文件、目录或符号链接
+    fn new_inode(&self, parent: u64, name: &str, create: impl FnOnce(&Inode) -> Option<Arc<Inode>>)
+        -> Result<EntryOut, i32> {
+        if name.len() > NAME_LENGTH_LIMIT as usize {
+            return Err(ENAMETOOLONG);
+        }
+        let parent = self.dir(parent)?;
+        if parent.find_inode(name).is_some() {
+            return Err(EEXIST);
+        }
+        Ok(self.entry_out(&*create(&parent).ok_or(ENOSPC)?))
+    }
+
+    fn create(&self, header: &InHeader, body: &[u8]) -> Reply {
+        let create: CreateIn = from_bytes(body).ok_or(EINVAL)?;
+        let name = name_arg(body.get(size_of::<CreateIn>()..).ok_or(EINVAL)?)?;
+        let perm = owned_by(header, create.mode & !create.umask);
+        let entry = self.new_inode(header.nodeid, name, |parent| parent.create_with(name, perm))?;
+        let mut reply = as_bytes(&entry).to_vec();
+        reply.extend_from_slice(as_bytes(&OpenOut::default()));
+        Ok(reply)
+    }
+
+    fn mkdir(&self, header: &InHeader, body: &[u8]) -> Reply {
+        let mkdir: MkdirIn = from_bytes(body).ok_or(EINVAL)?;
+        let name = name_arg(body.get(size_of::<MkdirIn>()..).ok_or(EINVAL)?)?;
+        let perm = owned_by(header, mkdir.mode & !mkdir.umask);
+        Ok(as_bytes(&self.new_inode(header.nodeid, name, |parent| parent.create_dir_with(name, perm))?).to_vec())
+    }
+
+    fn symlink(&self, header: &InHeader, body: &[u8]) -> Reply {
+        // 请求体为 "name\0target\0"
+        let name = name_arg(body)?;
+        let target = name_arg(&body[name.len() + 1..])?;
+        if target.is_empty() {
+            return Err(ENOENT);
+        }
+        if target.len() > SYMLINK_LENGTH_LIMIT as usize {
+            return Err(ENAMETOOLONG);
+        }
+        let perm = owned_by(header, 0o777);
+        let entry = self.new_inode(header.nodeid, name, |parent| parent.create_symlink_with(name, target, perm))?;
+        Ok(as_bytes(&entry).to_vec())
+    }
+
+    fn unlink(&self, parent: u64, body: &[u8], rmdir: bool) -> Reply {
+        let name = name_arg(body)?;
+        let parent = self.dir(parent)?;
+        let inode = parent.find_inode(name).ok_or(ENOENT)?;
+        match (rmdir, inode.is_dir()) {
+            (true, false) => return Err(ENOTDIR),
+            (false, true) => return Err(EISDIR),
+            (true, true) if inode.size() > 0 => return Err(ENOTEMPTY),
+            _ => {}
+        }
+        if !parent.unlink(name) {
+            return Err(ENOENT);
+        }
+        Ok(Vec::new())
+    }
+
+    /// 请求体为 RenameIn (RENAME2 为 Rename2In)、旧名字、新名字
+    fn rename(&self, parent: u64, body: &[u8], rename2: bool) -> Reply {
+        let (newdir, flags, names) = if rename2 {
+            let rename: Rename2In = from_bytes(body).ok_or(EINVAL)?;
+            (rename.newdir, rename.flags, &body[size_of::<Rename2In>()..])
+        } else {
+            let rename: RenameIn = from_bytes(body).ok_or(EINVAL)?;
+            (rename.newdir, 0, &body[size_of::<RenameIn>()..])
+        };
+        let old_name = name_arg(names)?;
+        let new_name = name_arg(&names[old_name.len() + 1..])?;
+        if new_name.len() > NAME_LENGTH_LIMIT as usize {
+            return Err(ENAMETOOLONG);
+        }
+        let mode = match flags {
+            0 => RenameMode::Replace,
+            RENAME_NOREPLACE => RenameMode::NoReplace,
+            RENAME_EXCHANGE => RenameMode::Exchange,
+            _ => return Err(EINVAL),
+        };
+        let (old_dir, new_dir) = (self.dir(parent)?, self.dir(newdir)?);
+        Inode::rename(&old_dir, old_name, &new_dir, new_name, mode).map_err(|err| match err {
+            RenameError::NotFound => ENOENT,
+            RenameError::Exists => EEXIST,
+            RenameError::IsDir => EISDIR,
+            RenameError::NotDir => ENOTDIR,
+            RenameError::NotEmpty => ENOTEMPTY,
+            RenameError::Invalid => EINVAL,
+            RenameError::NoSpace => ENOSPC,
+        })?;
+        Ok(Vec::new())
+    }
+
+    /// 目录项的偏移量是它的序号 ("." 为 0，".." 为 1)
+    fn readdir(&self, nodeid: u64, body: &[u8]) -> Reply {
+        let read: ReadIn = from_bytes(body).ok_or(EINVAL)?;
+        let dir = self.dir(nodeid)?;
+        // easy-fs 不记录父目录，".." 的 ino 用目录自身代替，内核不会使用它
+        let mut entries = vec![(String::from("."), nodeid, DT_DIR), (String::from(".."), nodeid, DT_DIR)];
+        for name in dir.ls() {
+            let inode = dir.find_inode(&name).ok_or(EIO)?;
+            let type_ = if inode.is_dir() {
+                DT_DIR
+            } else if inode.is_symlink() {
+                DT_LNK
+            } else {
+                DT_REG
+            };
+            entries.push((name, inode.inode_id() as u64 + FUSE_ROOT_ID, type_));
+        }
+        let mut reply = Vec::new();
+        for (index, (name, ino, type_)) in entries.iter().enumerate().skip(read.offset as usize) {
+            let dirent = Dirent { ino: *ino, off: index as u64 + 1, namelen: name.len() as u32, type_: *type_ };
+            let entry_len = (size_of::<Dirent>() + name.len()).next_multiple_of(8);
+            if reply.len() + entry_len > read.size as usize {
+                break;
+            }
+            reply.extend_from_slice(as_bytes(&dirent));
+            reply.extend_from_slice(name.as_bytes());
+            reply.resize(reply.len().next_multiple_of(8), 0);
+        }
+        Ok(reply)
+    }
+
+    fn statfs(&self) -> Vec<u8> {
+        let fs = self.efs.lock();
+        let super_block = get_block_cache(0, Arc::clone(&fs.block_device)).lock()
+            .read(0, |super_block: &SuperBlock| *super_block);
+        let out = StatfsOut {
+            blocks: super_block.data_area_blocks as u64,
+            bfree: fs.free_data_blocks() as u64,
+            bavail: fs.free_data_blocks() as u64,
+            files: fs.inode_bitmap.maximum() as u64,
+            ffree: fs.free_inodes() as u64,
+            bsize: 512,
+            namelen: NAME_LENGTH_LIMIT,
+            frsize: 512,
+            ..StatfsOut::default()
+        };
+        as_bytes(&out).to_vec()
+    }
+}
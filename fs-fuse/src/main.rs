@@ -1,16 +1,36 @@
 // fs-fuse/src/main.rs
 // this file is mainly from rCore-ch6
-// use `cargo run -- -s ../user/target/riscv64gc-unknown-none-elf/release/ -t ./target/` to create image
+// easy-fs 镜像工具，例如:
+//   cargo run -- pack -s ../user/target/riscv64gc-unknown-none-elf/release/ -t ./target/ [-d ./testdata/]
+//   cargo run -- ls ./target/fs.img /
+//   cargo run -- get ./target/fs.img /output.txt ./output.txt
+//   cargo run -- mv ./target/fs.img /bin/app.new /bin/app
+//   cargo run -- ln ./target/fs.img user_shell /bin/sh
+//   cargo run -- chmod ./target/fs.img 4755 /bin/su
+//   cargo run -- mount ./target/fs.img /mnt/easy-fs
 
-use std::fs::{read_dir, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+mod fuse;
+mod mount;
+mod pack;
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::sync::Arc;
-use clap::{App, Arg};
-use easy_fs::BlockDevice;
+use std::time::{SystemTime, UNIX_EPOCH};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use easy_fs::{BlockDevice, ChecksumMode, Inode, Permissions, RenameError, RenameMode, Timestamp, SYMLINK_LENGTH_LIMIT};
+use easy_fs::block_cache::get_block_cache;
 use easy_fs::efs::EasyFileSystem;
+use easy_fs::fsck::fsck;
+use easy_fs::super_block::SuperBlock;
+use pack::{pack, parse_size, PackOptions};
 
 const BLOCK_SZ: usize = 512;
+/// 路径解析中最多跟随的符号链接数
+const MAX_SYMLINKS: usize = 40;
 
 struct BlockFile(Mutex<File>);
 
@@ -28,86 +48,564 @@ impl BlockDevice for BlockFile {
             .expect("Error when seeking!");
         assert_eq!(file.write(buf).unwrap(), BLOCK_SZ, "Not a complete block!");
     }
-}
 
-fn main() {
-    easy_fs_pack().expect("Error when packing easy-fs!");
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        let mut file = self.0.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SZ) as u64))
+            .expect("Error when seeking!");
+        file.read_exact(buf).expect("Not complete blocks!");
+    }
 }
 
-fn easy_fs_pack() -> std::io::Result<()> {
-    let matches = App::new("EasyFileSystem packer")
-        .arg(
-            Arg::with_name("source")
-                .short("s")
-                .long("source")
-                .takes_value(true)
-                .help("Executable source dir(with backslash)"),
+fn main() {
+    let image_arg = || Arg::with_name("image").required(true).help("Path of the easy-fs image");
+    let path_arg = || Arg::with_name("path").required(true).help("Absolute path inside the image");
+    let matches = App::new("EasyFileSystem tool")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("pack")
+                .about("Create a new image from executables and data files")
+                .arg(
+                    Arg::with_name("source")
+                        .short("s")
+                        .long("source")
+                        .takes_value(true)
+                        .help("Executable source dir: files without '.' are copied to /"),
+                )
+                .arg(
+                    Arg::with_name("target")
+                        .short("t")
+                        .long("target")
+                        .takes_value(true)
+                        .help("Target dir, the image is written to <target>/fs.img"),
+                )
+                .arg(Arg::with_name("output").short("o").long("output").takes_value(true).help("Image path"))
+                .arg(
+                    Arg::with_name("data")
+                        .short("d")
+                        .long("data")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Host dir copied recursively into /"),
+                )
+                .arg(
+                    Arg::with_name("manifest")
+                        .short("m")
+                        .long("manifest")
+                        .takes_value(true)
+                        .help("File with `<host path> <image path>` lines"),
+                )
+                .arg(Arg::with_name("size").long("size").default_value("16M").help("Image size, e.g. 16M"))
+                .arg(Arg::with_name("inodes").long("inodes").default_value("4096").help("Number of inodes"))
+                .arg(Arg::with_name("block-size").long("block-size").default_value("512").help("Block size in bytes, only 512 is supported"))
+                .arg(
+                    Arg::with_name("journal-blocks")
+                        .long("journal-blocks")
+                        .default_value("64")
+                        .help("Journal size in blocks, 0 disables the journal"),
+                )
+                .arg(
+                    Arg::with_name("checksums")
+                        .long("checksums")
+                        .possible_values(&["none", "metadata", "all"])
+                        .default_value("metadata")
+                        .help("Blocks protected by CRC32C checksums"),
+                )
+                .arg(
+                    Arg::with_name("block-map")
+                        .long("block-map")
+                        .possible_values(&["extents", "indirect"])
+                        .default_value("extents")
+                        .help("How files map their data blocks: extent trees or direct and indirect pointers"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ls")
+                .about("List a directory")
+                .arg(image_arg())
+                .arg(Arg::with_name("path").default_value("/").help("Directory inside the image")),
+        )
+        .subcommand(SubCommand::with_name("cat").about("Print a file to stdout").arg(image_arg()).arg(path_arg()))
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Copy a file out of the image")
+                .arg(image_arg())
+                .arg(path_arg())
+                .arg(Arg::with_name("host").required(true).help("Destination on the host")),
+        )
+        .subcommand(
+            SubCommand::with_name("put")
+                .about("Copy a host file into the image, overwriting an existing file")
+                .arg(image_arg())
+                .arg(Arg::with_name("host").required(true).help("Source on the host"))
+                .arg(path_arg()),
+        )
+        .subcommand(SubCommand::with_name("rm").about("Remove a file or an empty directory").arg(image_arg()).arg(path_arg()))
+        .subcommand(
+            SubCommand::with_name("mv")
+                .about("Rename or move a file or directory, atomically replacing the destination")
+                .arg(image_arg())
+                .arg(Arg::with_name("path").required(true).help("Source inside the image"))
+                .arg(Arg::with_name("dest").required(true).help("Destination inside the image"))
+                .arg(Arg::with_name("no-clobber").short("n").long("no-clobber").help("Fail if the destination exists"))
+                .arg(
+                    Arg::with_name("exchange")
+                        .short("x")
+                        .long("exchange")
+                        .conflicts_with("no-clobber")
+                        .help("Atomically swap the source and the destination"),
+                ),
         )
-        .arg(
-            Arg::with_name("target")
-                .short("t")
-                .long("target")
-                .takes_value(true)
-                .help("Executable target dir(with backslash)"),
+        .subcommand(
+            SubCommand::with_name("ln")
+                .about("Create a symbolic link")
+                .arg(image_arg())
+                .arg(Arg::with_name("target").required(true).help("Link target, stored as given"))
+                .arg(path_arg()),
+        )
+        .subcommand(SubCommand::with_name("mkdir").about("Create a directory").arg(image_arg()).arg(path_arg()))
+        .subcommand(
+            SubCommand::with_name("chmod")
+                .about("Change the permission bits of a file")
+                .arg(image_arg())
+                .arg(Arg::with_name("mode").required(true).help("Octal mode, e.g. 4755"))
+                .arg(path_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("chown")
+                .about("Change the owner and group of a file")
+                .arg(image_arg())
+                .arg(Arg::with_name("owner").required(true).help("<uid>[:<gid>]"))
+                .arg(path_arg()),
+        )
+        .subcommand(SubCommand::with_name("info").about("Print superblock and free space").arg(image_arg()))
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("Check the consistency of an easy-fs image")
+                .arg(image_arg())
+                .arg(Arg::with_name("repair").short("r").long("repair").help("Repair the problems found")),
+        )
+        .subcommand(
+            SubCommand::with_name("mount")
+                .about("Serve an image through FUSE until it is unmounted")
+                .arg(image_arg())
+                .arg(Arg::with_name("mountpoint").required(true).help("Directory to mount the image on")),
         )
         .get_matches();
-    let src_path = matches.value_of("source").unwrap();
-    let target_path = matches.value_of("target").unwrap();
-    println!("src_path = {}\ntarget_path = {}", src_path, target_path);
-    let block_file = Arc::new(BlockFile(Mutex::new({
-        let f = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(format!("{}{}", target_path, "fs.img"))?;
-        f.set_len(16 * 2048 * 512).unwrap();
-        f
-    })));
-    // 16MiB, at most 4095 files
-    let efs = EasyFileSystem::create(block_file, 16 * 2048, 1);
-    let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
-    let apps: Vec<_> = read_dir(src_path)
-        .unwrap()
-        .into_iter()
-        .filter_map(|dir_entry| {
-            let dir_entry = dir_entry.unwrap();
-            // 排除目录
-            if let Ok(file_type) = dir_entry.file_type() {
-                if file_type.is_file() {
-                    let name = dir_entry.file_name().into_string().unwrap();
-                    // 不包含点号
-                    if !name.contains('.') {
-                        return Some(name);
-                    }
-                }
+
+    let result = match matches.subcommand() {
+        ("pack", Some(matches)) => easy_fs_pack(matches),
+        ("ls", Some(matches)) => easy_fs_ls(matches),
+        ("cat", Some(matches)) => easy_fs_cat(matches),
+        ("get", Some(matches)) => easy_fs_get(matches),
+        ("put", Some(matches)) => easy_fs_put(matches),
+        ("rm", Some(matches)) => easy_fs_rm(matches),
+        ("mv", Some(matches)) => easy_fs_mv(matches),
+        ("ln", Some(matches)) => easy_fs_ln(matches),
+        ("mkdir", Some(matches)) => easy_fs_mkdir(matches),
+        ("chmod", Some(matches)) => easy_fs_chmod(matches),
+        ("chown", Some(matches)) => easy_fs_chown(matches),
+        ("info", Some(matches)) => easy_fs_info(matches),
+        ("fsck", Some(matches)) => easy_fs_fsck(matches),
+        ("mount", Some(matches)) => easy_fs_mount(matches),
+        _ => unreachable!(),
+    };
+    if let Err(e) = result {
+        eprintln!("fs-fuse: {}", e);
+        std::process::exit(1);
+    }
+}
+
+// ----- image helpers -----
+
+/// 主机的墙上时间，作为 easy-fs 的时钟
+fn host_clock() -> Timestamp {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Timestamp { sec: now.as_secs(), nsec: now.subsec_nanos() }
+}
+
+/// 以 UTC 显示时间，如 "2025-03-01 08:30"
+fn time_string(time: Timestamp) -> String {
+    let days = (time.sec / 86400) as i64;
+    let minutes = time.sec % 86400 / 60;
+    // 由 1970-01-01 起的天数计算公历日期 (以 3 月 1 日为一年的开始，使闰日位于年末)
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
+
+fn open_image(path: &str) -> std::io::Result<Arc<spin::Mutex<EasyFileSystem>>> {
+    let image = OpenOptions::new().read(true).write(true).open(path)?;
+    let block_file = Arc::new(BlockFile(Mutex::new(image)));
+    let efs = EasyFileSystem::try_open(block_file)
+        .ok_or_else(|| Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: not an easy-fs image or bad superblock checksum", path),
+        ))?;
+    efs.lock().set_clock(host_clock);
+    Ok(efs)
+}
+
+/// 按绝对路径在镜像中查找 inode，跟随路径中的符号链接
+fn lookup(efs: &Arc<spin::Mutex<EasyFileSystem>>, path: &str) -> std::io::Result<Arc<Inode>> {
+    let not_found = |message: &str| Error::new(ErrorKind::NotFound, format!("{}: {}", path, message));
+    // 从根目录到当前位置经过的 inode，".." 回到上一个
+    let mut inodes = vec![EasyFileSystem::root_inode(efs)];
+    let mut names: VecDeque<String> = path.split('/').map(String::from).collect();
+    let mut links = 0;
+    while let Some(name) = names.pop_front() {
+        let inode = inodes.last().unwrap().clone();
+        if name.is_empty() {
+            continue;
+        }
+        if !inode.is_dir() {
+            return Err(not_found("not a directory"));
+        }
+        if name == "." {
+            continue;
+        }
+        if name == ".." {
+            if inodes.len() > 1 {
+                inodes.pop();
             }
-            None
-        })
-        .collect();
-    for app in apps {
-        // load app data from host file system
-        let file_path = format!("{}{}", src_path, app);
-        let mut host_file = match File::open(&file_path) {
-            Ok(file) => file,
-            Err(e) => {
-                eprintln!("Error! Fail to open {}: {}", file_path, e);
-                return Err(e);
+            continue;
+        }
+        let child = inode.find_inode(&name).ok_or_else(|| not_found("no such file or directory"))?;
+        if !child.is_symlink() {
+            inodes.push(child);
+            continue;
+        }
+        // 用链接目标替换这一级，相对目标从链接所在目录开始解析
+        links += 1;
+        if links > MAX_SYMLINKS {
+            return Err(Error::other(format!("{}: too many levels of symbolic links", path)));
+        }
+        let target = String::from_utf8_lossy(&read_all(&child)).into_owned();
+        if target.starts_with('/') {
+            inodes.truncate(1);
+        }
+        for name in target.split('/').rev() {
+            names.push_front(String::from(name));
+        }
+    }
+    Ok(inodes.pop().unwrap())
+}
+
+/// 查找路径的父目录，返回 (父目录, 最后一级的名字)
+fn lookup_parent<'a>(efs: &Arc<spin::Mutex<EasyFileSystem>>, path: &'a str) -> std::io::Result<(Arc<Inode>, &'a str)> {
+    let path = path.trim_end_matches('/');
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{}: invalid path", path)));
+    }
+    let parent = lookup(efs, parent)?;
+    if !parent.is_dir() {
+        return Err(Error::new(ErrorKind::NotFound, format!("{}: not a directory", path)));
+    }
+    Ok((parent, name))
+}
+
+/// ls -l 风格的类型与权限位，如 "-rwsr-xr-x"
+fn mode_string(inode: &Inode) -> String {
+    let mode = inode.permissions().mode;
+    let kind = if inode.is_dir() {
+        'd'
+    } else if inode.is_symlink() {
+        'l'
+    } else {
+        '-'
+    };
+    let mut result = String::from(kind);
+    // (权限位的位置, setuid/setgid/sticky 位, 该位置位时显示的字符)
+    for (shift, special, mark) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = mode >> shift;
+        result.push(if bits & 4 != 0 { 'r' } else { '-' });
+        result.push(if bits & 2 != 0 { 'w' } else { '-' });
+        result.push(match (mode & special != 0, bits & 1 != 0) {
+            (true, true) => mark,
+            (true, false) => mark.to_ascii_uppercase(),
+            (false, true) => 'x',
+            (false, false) => '-',
+        });
+    }
+    result
+}
+
+fn read_all(inode: &Inode) -> Vec<u8> {
+    let mut data = vec![0u8; inode.size()];
+    let len = inode.read_at(0, &mut data);
+    data.truncate(len);
+    data
+}
+
+// ----- subcommands -----
+
+fn easy_fs_pack(matches: &ArgMatches) -> std::io::Result<()> {
+    let output = match (matches.value_of("output"), matches.value_of("target")) {
+        (Some(output), _) => PathBuf::from(output),
+        (None, Some(target)) => Path::new(target).join("fs.img"),
+        (None, None) => return Err(Error::new(ErrorKind::InvalidInput, "either --target or --output is required")),
+    };
+    let block_size = matches.value_of("block-size").unwrap();
+    if block_size.parse::<u32>() != Ok(BLOCK_SZ as u32) {
+        // 块大小是 easy-fs 磁盘格式 (DiskInode、位图) 与内核块设备驱动共同的常量
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("unsupported block size {}: easy-fs only supports {}-byte blocks", block_size, BLOCK_SZ),
+        ));
+    }
+    let parse_u32 = |name: &str| -> std::io::Result<u32> {
+        let value = matches.value_of(name).unwrap();
+        value.parse().map_err(|_| Error::new(ErrorKind::InvalidInput, format!("invalid --{}: {}", name, value)))
+    };
+    let options = PackOptions {
+        output,
+        size: parse_size(matches.value_of("size").unwrap())?,
+        inodes: parse_u32("inodes")?,
+        journal_blocks: parse_u32("journal-blocks")?,
+        checksums: match matches.value_of("checksums").unwrap() {
+            "none" => ChecksumMode::None,
+            "all" => ChecksumMode::All,
+            _ => ChecksumMode::Metadata,
+        },
+        extents: matches.value_of("block-map").unwrap() == "extents",
+        apps: matches.value_of("source").map(PathBuf::from),
+        data: matches.values_of("data").map_or(Vec::new(), |data| data.map(PathBuf::from).collect()),
+        manifest: matches.value_of("manifest").map(PathBuf::from),
+    };
+    let efs = pack(&options)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    for name in root_inode.ls() {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+fn easy_fs_ls(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let dir = lookup(&efs, matches.value_of("path").unwrap())?;
+    let print = |inode: &Inode, name: &str| {
+        let perm = inode.permissions();
+        let mtime = time_string(inode.times().mtime);
+        let mut line = format!(
+            "{} {:>5} {:>5} {:>10} {} {}", mode_string(inode), perm.uid, perm.gid, inode.size(), mtime, name
+        );
+        if inode.is_symlink() {
+            line += &format!(" -> {}", String::from_utf8_lossy(&read_all(inode)));
+        }
+        println!("{}", line);
+    };
+    if !dir.is_dir() {
+        print(&dir, matches.value_of("path").unwrap());
+        return Ok(());
+    }
+    for name in dir.ls() {
+        print(&dir.find_inode(&name).unwrap(), &name);
+    }
+    Ok(())
+}
+
+fn easy_fs_cat(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let inode = lookup(&efs, matches.value_of("path").unwrap())?;
+    if inode.is_dir() {
+        return Err(Error::new(ErrorKind::InvalidInput, "is a directory"));
+    }
+    std::io::stdout().write_all(&read_all(&inode))
+}
+
+fn easy_fs_get(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let inode = lookup(&efs, matches.value_of("path").unwrap())?;
+    if inode.is_dir() {
+        return Err(Error::new(ErrorKind::InvalidInput, "is a directory"));
+    }
+    File::create(matches.value_of("host").unwrap())?.write_all(&read_all(&inode))
+}
+
+fn easy_fs_put(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let mut data = Vec::new();
+    File::open(matches.value_of("host").unwrap())?.read_to_end(&mut data)?;
+    let (parent, name) = lookup_parent(&efs, matches.value_of("path").unwrap())?;
+    let inode = match parent.find_inode(name) {
+        Some(inode) if inode.is_dir() => {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("{}: is a directory", name)));
+        }
+        // 符号链接被替换为普通文件
+        Some(inode) if inode.is_symlink() => {
+            if !parent.unlink(name) {
+                return Err(Error::other(format!("{}: cannot remove symbolic link", name)));
             }
+            parent.create(name)
+                .ok_or_else(|| Error::other(format!("{}: cannot create file", name)))?
+        }
+        Some(inode) => {
+            inode.clear();
+            inode
+        }
+        None => parent.create(name)
+            .ok_or_else(|| Error::other(format!("{}: cannot create file", name)))?,
+    };
+    if inode.write_at(0, &data) < data.len() {
+        return Err(Error::other("no space left on image"));
+    }
+    Ok(())
+}
+
+fn easy_fs_rm(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let path = matches.value_of("path").unwrap();
+    let (parent, name) = lookup_parent(&efs, path)?;
+    let inode = parent.find_inode(name)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{}: no such file or directory", path)))?;
+    if inode.is_dir() && inode.size() > 0 {
+        return Err(Error::other(format!("{}: directory not empty", path)));
+    }
+    if !parent.unlink(name) {
+        return Err(Error::other(format!("{}: cannot remove", path)));
+    }
+    Ok(())
+}
+
+fn easy_fs_mv(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let (path, dest) = (matches.value_of("path").unwrap(), matches.value_of("dest").unwrap());
+    let (old_dir, old_name) = lookup_parent(&efs, path)?;
+    let (new_dir, new_name) = lookup_parent(&efs, dest)?;
+    let mode = if matches.is_present("exchange") {
+        RenameMode::Exchange
+    } else if matches.is_present("no-clobber") {
+        RenameMode::NoReplace
+    } else {
+        RenameMode::Replace
+    };
+    Inode::rename(&old_dir, old_name, &new_dir, new_name, mode).map_err(|err| {
+        let (kind, message) = match err {
+            RenameError::NotFound => (ErrorKind::NotFound, "no such file or directory"),
+            RenameError::Exists => (ErrorKind::AlreadyExists, "destination exists"),
+            RenameError::IsDir => (ErrorKind::Other, "cannot overwrite a directory with a file"),
+            RenameError::NotDir => (ErrorKind::Other, "cannot overwrite a file with a directory"),
+            RenameError::NotEmpty => (ErrorKind::Other, "destination directory not empty"),
+            RenameError::Invalid => (ErrorKind::InvalidInput, "invalid destination"),
+            RenameError::NoSpace => (ErrorKind::Other, "no space left on image"),
         };
-        let mut all_data: Vec<u8> = Vec::new();
-        host_file.read_to_end(&mut all_data).unwrap();
-        // create a file in easy-fs
-        let inode = root_inode.create(app.as_str()).unwrap();
-        // write data to easy-fs
-        inode.write_at(0, all_data.as_slice());
+        Error::new(kind, format!("{} -> {}: {}", path, dest, message))
+    })
+}
+
+fn easy_fs_ln(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let (target, path) = (matches.value_of("target").unwrap(), matches.value_of("path").unwrap());
+    if target.is_empty() || target.len() > SYMLINK_LENGTH_LIMIT as usize {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("link target must be 1 to {} bytes", SYMLINK_LENGTH_LIMIT),
+        ));
+    }
+    let (parent, name) = lookup_parent(&efs, path)?;
+    if parent.find_inode(name).is_some() {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("{}: file exists", path)));
+    }
+    parent.create_symlink(name, target)
+        .map(|_| ())
+        .ok_or_else(|| Error::other(format!("{}: cannot create symbolic link", path)))
+}
+
+fn easy_fs_mkdir(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let path = matches.value_of("path").unwrap();
+    let (parent, name) = lookup_parent(&efs, path)?;
+    if parent.find_inode(name).is_some() {
+        return Err(Error::new(ErrorKind::AlreadyExists, format!("{}: file exists", path)));
+    }
+    parent.create_dir(name)
+        .map(|_| ())
+        .ok_or_else(|| Error::other(format!("{}: cannot create directory", path)))
+}
+
+fn easy_fs_chmod(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let mode = matches.value_of("mode").unwrap();
+    let mode = u16::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("invalid mode: {}", mode)))?;
+    let inode = lookup(&efs, matches.value_of("path").unwrap())?;
+    inode.set_permissions(Permissions { mode, ..inode.permissions() });
+    Ok(())
+}
+
+fn easy_fs_chown(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let owner = matches.value_of("owner").unwrap();
+    let invalid = || Error::new(ErrorKind::InvalidInput, format!("invalid owner: {}", owner));
+    let (uid, gid) = match owner.split_once(':') {
+        Some((uid, gid)) => (uid.parse().map_err(|_| invalid())?, Some(gid.parse().map_err(|_| invalid())?)),
+        None => (owner.parse().map_err(|_| invalid())?, None),
+    };
+    let inode = lookup(&efs, matches.value_of("path").unwrap())?;
+    let perm = inode.permissions();
+    inode.set_permissions(Permissions { uid, gid: gid.unwrap_or(perm.gid), ..perm });
+    Ok(())
+}
+
+fn easy_fs_info(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let fs = efs.lock();
+    let super_block = get_block_cache(0, Arc::clone(&fs.block_device)).lock()
+        .read(0, |super_block: &SuperBlock| *super_block);
+    println!("total blocks:        {}", super_block.total_blocks);
+    println!("inode bitmap blocks: {}", super_block.inode_bitmap_blocks);
+    println!("inode area blocks:   {}", super_block.inode_area_blocks);
+    println!("data bitmap blocks:  {}", super_block.data_bitmap_blocks);
+    println!("data area blocks:    {}", super_block.data_area_blocks);
+    println!("journal blocks:      {}", super_block.journal_blocks);
+    println!("checksum blocks:     {} ({:?})", super_block.checksum_blocks, super_block.checksum_mode());
+    println!("block map:           {}", if fs.uses_extents() { "extents" } else { "indirect" });
+    println!("free inodes:         {} / {}", fs.free_inodes(), fs.inode_bitmap.maximum());
+    println!(
+        "free data blocks:    {} / {} ({} KiB free)",
+        fs.free_data_blocks(),
+        super_block.data_area_blocks,
+        fs.free_data_blocks() * BLOCK_SZ / 1024,
+    );
+    Ok(())
+}
+
+fn easy_fs_fsck(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    let report = fsck(&efs, matches.is_present("repair"));
+    for problem in report.problems.iter() {
+        println!("{}", problem);
     }
-    // list apps
-    for app in root_inode.ls() {
-        println!("{}", app);
+    println!(
+        "{} files, {} directories, {} blocks in use, {} problems{}",
+        report.files,
+        report.directories,
+        report.data_blocks,
+        report.problems.len(),
+        if report.repaired { " (repaired)" } else { "" },
+    );
+    if !report.is_clean() && !report.repaired {
+        std::process::exit(1);
     }
     Ok(())
 }
 
+fn easy_fs_mount(matches: &ArgMatches) -> std::io::Result<()> {
+    let efs = open_image(matches.value_of("image").unwrap())?;
+    mount::mount(efs, Path::new(matches.value_of("mountpoint").unwrap()))
+}
+
 #[test]
 fn efs_test() -> std::io::Result<()> {
     let block_file = Arc::new(BlockFile(Mutex::new({
@@ -119,7 +617,7 @@ fn efs_test() -> std::io::Result<()> {
         f.set_len(8192 * 512).unwrap();
         f
     })));
-    EasyFileSystem::create(block_file.clone(), 4096, 1);
+    EasyFileSystem::create(block_file.clone(), 8192, 1);
     let efs = EasyFileSystem::open(block_file.clone());
     let root_inode = EasyFileSystem::root_inode(&efs);
     root_inode.create("filea");
@@ -170,3 +668,1449 @@ fn efs_test() -> std::io::Result<()> {
 
     Ok(())
 }
+
+/// 写入次数用完后丢弃所有写入的块设备，模拟写到一半时断电
+#[cfg(test)]
+struct CrashBlockFile {
+    inner: BlockFile,
+    writes_left: Mutex<usize>,
+}
+
+#[cfg(test)]
+impl BlockDevice for CrashBlockFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.inner.read_block(block_id, buf);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut writes_left = self.writes_left.lock().unwrap();
+        if *writes_left > 0 {
+            *writes_left -= 1;
+            self.inner.write_block(block_id, buf);
+        }
+    }
+}
+
+#[test]
+fn efs_journal_test() -> std::io::Result<()> {
+    use easy_fs::config::INODE_PER_BLOCK;
+    let open_image = || -> std::io::Result<File> {
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open("target/fs-journal.img")?;
+        f.set_len(4096 * 512).unwrap();
+        Ok(f)
+    };
+    let content = |i: usize| -> Vec<u8> {
+        (0..(i * 37 % 80 + 1) * BLOCK_SZ).map(|j| (i + j) as u8).collect()
+    };
+    // 在不同的写入次数处"断电"，重新打开后每个事务要么完整生效，要么完全没有生效
+    for budget in (1..400).step_by(13) {
+        let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(open_image()?)));
+        EasyFileSystem::create(block_file, 4096, 1);
+
+        let crash_file: Arc<dyn BlockDevice> = Arc::new(CrashBlockFile {
+            inner: BlockFile(Mutex::new(open_image()?)),
+            writes_left: Mutex::new(budget),
+        });
+        let efs = EasyFileSystem::open(crash_file);
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        for i in 0..8 {
+            let inode = root_inode.create(&format!("file{}", i)).unwrap();
+            inode.write_at(0, &content(i));
+        }
+
+        let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(open_image()?)));
+        let efs = EasyFileSystem::open(block_file);
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        let names = root_inode.ls();
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(*name, format!("file{}", i));
+            // 大文件的写入分为多个事务，崩溃后文件内容是完整内容的一个前缀
+            let inode = root_inode.find_inode(name).unwrap();
+            let expected = content(i);
+            let mut buffer = vec![0u8; expected.len()];
+            let len = inode.read_at(0, &mut buffer);
+            assert_eq!(len, inode.size());
+            assert_eq!(buffer[..len], expected[..len]);
+        }
+        // 恢复后的文件系统可以继续使用，新分配的块不会覆盖已有文件
+        let inode = root_inode.create("after-crash").unwrap();
+        inode.write_at(0, &[0xff; 4 * BLOCK_SZ]);
+        for (i, name) in names.iter().enumerate() {
+            let inode = root_inode.find_inode(name).unwrap();
+            let mut buffer = vec![0u8; inode.size()];
+            inode.read_at(0, &mut buffer);
+            assert_eq!(buffer[..], content(i)[..buffer.len()]);
+        }
+    }
+
+    // 日志只有一个块时格式化与之后的操作都放不进日志，直接写回原位，镜像仍然可用
+    let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(open_image()?)));
+    let efs = EasyFileSystem::create_with_journal(block_file, 4096, 1, 2);
+    EasyFileSystem::root_inode(&efs).create("file").unwrap().write_at(0, &content(3));
+    let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(open_image()?)));
+    let efs = EasyFileSystem::open(block_file);
+    assert!(fsck(&efs, false).is_clean());
+    assert_eq!(read_all(&EasyFileSystem::root_inode(&efs).find_inode("file").unwrap()), content(3));
+
+    // 超出日志容量的事务无法提交时，下一个操作把它直接写回，而不是继续累积
+    let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(open_image()?)));
+    let efs = EasyFileSystem::create(Arc::clone(&block_file), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("file").unwrap();
+    efs.lock().set_delayed_commit(true);
+    assert_eq!(file.write_at(0, b"pending"), 7);
+    let transaction_blocks = efs.lock().transaction_blocks();
+    let inode_blocks: Vec<u32> = (0..transaction_blocks as u32 * 2)
+        .map(|i| efs.lock().get_disk_inode_pos(i * INODE_PER_BLOCK).0)
+        .collect();
+    for block_id in inode_blocks {
+        get_block_cache(block_id as usize, Arc::clone(&block_file)).lock().modify(0, |_: &mut [u8; BLOCK_SZ]| {});
+    }
+    assert!(!efs.lock().commit());
+    assert_eq!(file.write_at(7, b" data"), 5);
+    assert!(efs.lock().dirty_blocks() <= transaction_blocks);
+    assert!(file.sync_all());
+    let reopened = EasyFileSystem::open(Arc::new(BlockFile(Mutex::new(open_image()?))));
+    assert_eq!(read_all(&EasyFileSystem::root_inode(&reopened).find_inode("file").unwrap()), b"pending data");
+    assert!(fsck(&reopened, false).is_clean());
+    Ok(())
+}
+
+#[test]
+fn efs_fsck_test() -> std::io::Result<()> {
+    use easy_fs::fsck::{FsckReport, Problem};
+    let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open("target/fs-fsck.img")?;
+        f.set_len(4096 * 512).unwrap();
+        f
+    })));
+    let efs = EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let dir = root_inode.create_dir("dir").unwrap();
+    let filea = dir.create("filea").unwrap();
+    filea.write_at(0, &[1; 200 * BLOCK_SZ]);
+    let fileb = root_inode.create("fileb").unwrap();
+    fileb.write_at(0, &[2; 3 * BLOCK_SZ]);
+    root_inode.create("filec").unwrap();
+    let report = fsck(&efs, false);
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!((report.files, report.directories), (3, 2));
+
+    let read_block = |block_id: usize| {
+        let mut buf = [0u8; BLOCK_SZ];
+        block_file.read_block(block_id, &mut buf);
+        buf
+    };
+    let write_u32 = |block_id: usize, offset: usize, value: u32| {
+        let mut buf = read_block(block_id);
+        buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        block_file.write_block(block_id, &buf);
+    };
+    // 直接修改磁盘 (绕过块缓存)，之后重新打开镜像
+    // 布局: 0 SuperBlock, 1 inode 位图, 2..2050 inode 区 (每块 2 个 inode，每个 256 字节)
+    // inode 3 (fileb): direct[0] 指向 filea (inode 2) 的第一个数据块
+    let filea_block0 = u32::from_le_bytes(read_block(3)[4..8].try_into().unwrap());
+    write_u32(3, 256 + 4, filea_block0);
+    // 根目录的第 3 个目录项 ("filec"，位于索引记录、"dir" 与 "fileb" 的 8 + 12 + 16 字节之后) 名字中间插入 '/'
+    let root_block0 = u32::from_le_bytes(read_block(2)[4..8].try_into().unwrap()) as usize;
+    let mut dirents = read_block(root_block0);
+    assert_eq!(&dirents[36 + 8..36 + 13], b"filec");
+    dirents[36 + 8 + 1] = b'/';
+    block_file.write_block(root_block0, &dirents);
+    // inode 位图中多标记一个 inode
+    write_u32(1, 0, 0b111111);
+
+    let reopen = || {
+        let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(
+            OpenOptions::new().read(true).write(true).open("target/fs-fsck.img").unwrap(),
+        )));
+        EasyFileSystem::open(block_file)
+    };
+    let report: FsckReport = fsck(&reopen(), false);
+    assert!(report.problems.contains(&Problem::InvalidName { dir: 0, offset: 36 }));
+    assert!(report.problems.contains(&Problem::InodeLeaked { inode: 4 }));
+    assert!(report.problems.contains(&Problem::InodeLeaked { inode: 5 }));
+    // 按广度优先顺序 fileb 先于 filea 被检查，filea 的第一个块被报告为与 fileb 重复引用
+    assert!(report.problems.contains(&Problem::DoubleAllocated { inode: 2, index: 0, block: filea_block0, owner: 3 }));
+    assert!(!report.repaired);
+
+    let efs = reopen();
+    let report = fsck(&efs, true);
+    assert!(report.repaired);
+    let report = fsck(&reopen(), false);
+    assert!(report.is_clean(), "{:?}", report.problems);
+    // filec 的目录项被删除，filea 在重复引用的块处被截断
+    let root_inode = EasyFileSystem::root_inode(&reopen());
+    assert_eq!(root_inode.ls(), vec!["dir", "fileb"]);
+    let filea = root_inode.find_inode("dir").unwrap().find_inode("filea").unwrap();
+    assert_eq!(filea.size(), 0);
+    Ok(())
+}
+
+#[test]
+fn efs_unlink_test() -> std::io::Result<()> {
+    let block_file = Arc::new(BlockFile(Mutex::new({
+        let f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open("target/fs-unlink.img")?;
+        f.set_len(4096 * 512).unwrap();
+        f
+    })));
+    let efs = EasyFileSystem::create(block_file, 4096, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let free_blocks = efs.lock().free_data_blocks();
+    let free_inodes = efs.lock().free_inodes();
+    // 目录项跨越多个块，删除时用最后一个目录项填补空位并缩小目录
+    for i in 0..40 {
+        let inode = root_inode.create(&format!("file{}", i)).unwrap();
+        inode.write_at(0, &vec![i as u8; i * 97 * BLOCK_SZ / 100]);
+    }
+    let dir = root_inode.create_dir("dir").unwrap();
+    dir.create("inner").unwrap();
+    assert!(!root_inode.unlink("dir"));
+    assert!(dir.unlink("inner"));
+    assert!(root_inode.unlink("dir"));
+    assert!(!root_inode.unlink("dir"));
+    for i in (0..40).step_by(3) {
+        assert!(root_inode.unlink(&format!("file{}", i)));
+    }
+    assert!(fsck(&efs, false).is_clean());
+    for i in 0..40 {
+        let inode = root_inode.find_inode(&format!("file{}", i));
+        assert_eq!(inode.is_some(), i % 3 != 0);
+        if let Some(inode) = inode {
+            assert_eq!(read_all(&inode), vec![i as u8; i * 97 * BLOCK_SZ / 100]);
+        }
+    }
+    for name in root_inode.ls() {
+        assert!(root_inode.unlink(&name));
+    }
+    assert_eq!(root_inode.size(), 0);
+    // 被删除的目录仍被打开，最后一个引用被丢弃时才释放
+    drop(dir);
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks);
+    assert_eq!(efs.lock().free_inodes(), free_inodes);
+    Ok(())
+}
+
+#[test]
+fn efs_pack_test() -> std::io::Result<()> {
+    use std::fs::{create_dir_all, read, write};
+    let src = Path::new("target/pack-test");
+    create_dir_all(src.join("apps"))?;
+    create_dir_all(src.join("data/sub/deeper"))?;
+    write(src.join("apps/hello"), b"\x7fELF hello")?;
+    write(src.join("apps/hello.d"), b"dependency info")?;
+    write(src.join("data/input.txt"), b"1 2 3\n")?;
+    write(src.join("data/sub/a.dat"), vec![7u8; 3000])?;
+    write(src.join("data/sub/deeper/b"), b"b")?;
+    write(src.join("manifest"), "# extra files\ndata/input.txt /etc/conf/input.txt\ndata/sub /copy\n")?;
+
+    let options = |output: &str| PackOptions {
+        output: PathBuf::from(output),
+        size: parse_size("2M").unwrap(),
+        inodes: 100,
+        apps: Some(src.join("apps")),
+        data: vec![src.join("data")],
+        manifest: Some(src.join("manifest")),
+        ..PackOptions::default()
+    };
+    let efs = pack(&options("target/pack-test/fs1.img"))?;
+    assert!(fsck(&efs, false).is_clean());
+    let cat = |path: &str| read_all(&lookup(&efs, path).unwrap());
+    assert_eq!(EasyFileSystem::root_inode(&efs).ls(), vec!["hello", "input.txt", "sub", "etc", "copy"]);
+    assert_eq!(cat("/input.txt"), b"1 2 3\n");
+    assert_eq!(cat("/sub/a.dat"), vec![7u8; 3000]);
+    assert_eq!(cat("/sub/deeper/b"), b"b");
+    assert_eq!(cat("/etc/conf/input.txt"), b"1 2 3\n");
+    assert_eq!(cat("/copy/deeper/b"), b"b");
+
+    // 相同的输入得到逐字节相同的镜像
+    pack(&options("target/pack-test/fs2.img"))?;
+    assert!(read("target/pack-test/fs1.img")? == read("target/pack-test/fs2.img")?);
+
+    assert!(parse_size("16MiB").unwrap() == 16 << 20 && parse_size("4k").unwrap() == 4096);
+    assert!(parse_size("12Q").is_err());
+    assert!(pack(&PackOptions { size: 64 * 1024, ..options("target/pack-test/small.img") }).is_err());
+    Ok(())
+}
+
+#[test]
+fn efs_fuse_test() -> std::io::Result<()> {
+    use std::mem::size_of;
+    use fuse::*;
+    let efs = pack(&PackOptions {
+        output: PathBuf::from("target/fuse-test.img"),
+        size: parse_size("2M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    let mut server = FuseServer::new(Arc::clone(&efs));
+    let mut unique = 0;
+    // 发送一个请求，返回 (error, 回复数据)
+    type Call<'a> = dyn FnMut(u32, u64, &[&[u8]]) -> Option<(i32, Vec<u8>)> + 'a;
+    let mut call = |opcode: u32, nodeid: u64, args: &[&[u8]]| -> Option<(i32, Vec<u8>)> {
+        unique += 1;
+        let body = args.concat();
+        let header = InHeader {
+            len: (size_of::<InHeader>() + body.len()) as u32,
+            opcode,
+            unique,
+            nodeid,
+            uid: 1000,
+            gid: 100,
+            ..InHeader::default()
+        };
+        let reply = server.handle(&[as_bytes(&header), &body].concat())?;
+        let out: OutHeader = from_bytes(&reply).unwrap();
+        assert_eq!((out.len as usize, out.unique), (reply.len(), unique));
+        Some((out.error, reply[size_of::<OutHeader>()..].to_vec()))
+    };
+
+    // 初始化之前的请求被拒绝
+    assert_eq!(call(opcode::GETATTR, FUSE_ROOT_ID, &[&[0; 16]]).unwrap().0, -libc::EIO);
+    let init = InitIn { major: 7, minor: 38, max_readahead: 65536, flags: 1 << 5 };
+    let (error, data) = call(opcode::INIT, 0, &[as_bytes(&init)]).unwrap();
+    let init_out: InitOut = from_bytes(&data).unwrap();
+    assert_eq!((error, data.len(), init_out.major, init_out.max_write), (0, 64, 7, MAX_WRITE));
+
+    let (error, data) = call(opcode::GETATTR, FUSE_ROOT_ID, &[&[0; 16]]).unwrap();
+    let attr: AttrOut = from_bytes(&data).unwrap();
+    assert_eq!((error, attr.attr.mode, attr.attr.uid), (0, libc::S_IFDIR | 0o755, 0));
+
+    // create + write + read，新文件属于发出请求的用户
+    let create = CreateIn { mode: 0o666, umask: 0o022, ..CreateIn::default() };
+    let (error, data) = call(opcode::CREATE, FUSE_ROOT_ID, &[as_bytes(&create), b"hello.txt\0"]).unwrap();
+    assert_eq!((error, data.len()), (0, size_of::<EntryOut>() + size_of::<OpenOut>()));
+    let entry = from_bytes::<EntryOut>(&data).unwrap();
+    assert_eq!((entry.attr.mode, entry.attr.uid, entry.attr.gid), (libc::S_IFREG | 0o644, 1000, 100));
+    let file = entry.nodeid;
+    let content: Vec<u8> = (0..3000u32).map(|i| i as u8).collect();
+    let write = WriteIn { offset: 100, size: content.len() as u32, ..WriteIn::default() };
+    let (error, data) = call(opcode::WRITE, file, &[as_bytes(&write), &content]).unwrap();
+    assert_eq!((error, from_bytes::<WriteOut>(&data).unwrap().size), (0, 3000));
+    let read = ReadIn { offset: 100, size: 4096, ..ReadIn::default() };
+    assert_eq!(call(opcode::READ, file, &[as_bytes(&read)]).unwrap(), (0, content.clone()));
+    assert_eq!(read_all(&*lookup(&efs, "/hello.txt")?)[100..], content[..]);
+
+    // lookup + setattr(size)
+    let (error, data) = call(opcode::LOOKUP, FUSE_ROOT_ID, &[b"hello.txt\0"]).unwrap();
+    let entry: EntryOut = from_bytes(&data).unwrap();
+    assert_eq!((error, entry.nodeid, entry.attr.size), (0, file, 3100));
+    assert_eq!(call(opcode::LOOKUP, FUSE_ROOT_ID, &[b"missing\0"]).unwrap().0, -libc::ENOENT);
+    let setattr = SetattrIn { valid: 1 << 3, size: 10, ..SetattrIn::default() };
+    let (error, data) = call(opcode::SETATTR, file, &[as_bytes(&setattr)]).unwrap();
+    assert_eq!((error, from_bytes::<AttrOut>(&data).unwrap().attr.size), (0, 10));
+    // chmod + chown
+    let setattr = SetattrIn { valid: 0b111, mode: 0o4750, uid: 0, gid: 0, ..SetattrIn::default() };
+    let (error, data) = call(opcode::SETATTR, file, &[as_bytes(&setattr)]).unwrap();
+    let attr = from_bytes::<AttrOut>(&data).unwrap().attr;
+    assert_eq!((error, attr.mode, attr.uid, attr.gid), (0, libc::S_IFREG | 0o4750, 0, 0));
+    // utimensat
+    let setattr = SetattrIn { valid: (1 << 4) | (1 << 5), atime: 1, mtime: 2, mtimensec: 3, ..SetattrIn::default() };
+    let (error, data) = call(opcode::SETATTR, file, &[as_bytes(&setattr)]).unwrap();
+    let attr = from_bytes::<AttrOut>(&data).unwrap().attr;
+    assert_eq!((error, attr.atime, attr.mtime, attr.mtimensec), (0, 1, 2, 3));
+
+    // mkdir + readdir
+    let (error, data) = call(opcode::MKDIR, FUSE_ROOT_ID, &[as_bytes(&MkdirIn::default()), b"dir\0"]).unwrap();
+    let dir = from_bytes::<EntryOut>(&data).unwrap().nodeid;
+    assert_eq!(error, 0);
+    assert_eq!(call(opcode::CREATE, dir, &[as_bytes(&CreateIn::default()), b"inner\0"]).unwrap().0, 0);
+    assert_eq!(call(opcode::MKDIR, FUSE_ROOT_ID, &[as_bytes(&MkdirIn::default()), b"dir\0"]).unwrap().0, -libc::EEXIST);
+    let readdir = |call: &mut Call, offset: u64, size: u32| {
+        let read = ReadIn { offset, size, ..ReadIn::default() };
+        let (error, data) = call(opcode::READDIR, FUSE_ROOT_ID, &[as_bytes(&read)]).unwrap();
+        assert_eq!(error, 0);
+        let mut names = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let dirent: Dirent = from_bytes(&data[pos..]).unwrap();
+            let name = &data[pos + size_of::<Dirent>()..pos + size_of::<Dirent>() + dirent.namelen as usize];
+            names.push((String::from_utf8(name.to_vec()).unwrap(), dirent.off));
+            pos += (size_of::<Dirent>() + dirent.namelen as usize).next_multiple_of(8);
+        }
+        names
+    };
+    let names = readdir(&mut call, 0, 4096);
+    let names: Vec<&str> = names.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec![".", "..", "hello.txt", "dir"]);
+    // 缓冲区只放得下一项时，按偏移量继续读取
+    assert_eq!(readdir(&mut call, 2, 40), vec![(String::from("hello.txt"), 3)]);
+    assert_eq!(readdir(&mut call, 3, 40), vec![(String::from("dir"), 4)]);
+    assert!(readdir(&mut call, 4, 4096).is_empty());
+
+    // rename + rename2
+    let rename = RenameIn { newdir: dir };
+    assert_eq!(call(opcode::RENAME, FUSE_ROOT_ID, &[as_bytes(&rename), b"hello.txt\0moved\0"]).unwrap().0, 0);
+    assert_eq!(call(opcode::LOOKUP, dir, &[b"moved\0"]).unwrap().0, 0);
+    let rename2 = Rename2In { newdir: FUSE_ROOT_ID, flags: libc::RENAME_NOREPLACE, ..Rename2In::default() };
+    assert_eq!(call(opcode::RENAME2, dir, &[as_bytes(&rename2), b"moved\0dir\0"]).unwrap().0, -libc::EEXIST);
+    assert_eq!(call(opcode::RENAME2, dir, &[as_bytes(&rename2), b"moved\0hello.txt\0"]).unwrap().0, 0);
+    assert_eq!(call(opcode::RENAME, FUSE_ROOT_ID, &[as_bytes(&rename), b"dir\0sub\0"]).unwrap().0, -libc::EINVAL);
+
+    // symlink + readlink
+    let (error, data) = call(opcode::SYMLINK, FUSE_ROOT_ID, &[b"link\0dir/inner\0"]).unwrap();
+    let entry: EntryOut = from_bytes(&data).unwrap();
+    assert_eq!((error, entry.attr.mode, entry.attr.size), (0, libc::S_IFLNK | 0o777, 9));
+    assert_eq!(call(opcode::READLINK, entry.nodeid, &[]).unwrap(), (0, b"dir/inner".to_vec()));
+    assert_eq!(call(opcode::READLINK, dir, &[]).unwrap().0, -libc::EINVAL);
+    assert_eq!(call(opcode::SYMLINK, FUSE_ROOT_ID, &[b"link\0x\0"]).unwrap().0, -libc::EEXIST);
+    assert_eq!(call(opcode::SYMLINK, FUSE_ROOT_ID, &[b"empty\0\0"]).unwrap().0, -libc::ENOENT);
+    assert_eq!(call(opcode::UNLINK, FUSE_ROOT_ID, &[b"link\0"]).unwrap().0, 0);
+
+    // unlink + rmdir
+    assert_eq!(call(opcode::UNLINK, FUSE_ROOT_ID, &[b"dir\0"]).unwrap().0, -libc::EISDIR);
+    assert_eq!(call(opcode::RMDIR, FUSE_ROOT_ID, &[b"dir\0"]).unwrap().0, -libc::ENOTEMPTY);
+    assert_eq!(call(opcode::UNLINK, dir, &[b"inner\0"]).unwrap().0, 0);
+    assert_eq!(call(opcode::RMDIR, FUSE_ROOT_ID, &[b"dir\0"]).unwrap().0, 0);
+    assert_eq!(call(opcode::RMDIR, FUSE_ROOT_ID, &[b"hello.txt\0"]).unwrap().0, -libc::ENOTDIR);
+    assert_eq!(call(opcode::UNLINK, FUSE_ROOT_ID, &[b"hello.txt\0"]).unwrap().0, 0);
+    assert!(EasyFileSystem::root_inode(&efs).ls().is_empty());
+
+    // fsync: 事务超出日志容量、提交失败时返回 EIO，下一个操作把它写回后恢复
+    let (error, data) = call(opcode::CREATE, FUSE_ROOT_ID, &[as_bytes(&create), b"sync.txt\0"]).unwrap();
+    assert_eq!(error, 0);
+    let sync_file = from_bytes::<EntryOut>(&data).unwrap().nodeid;
+    let fdatasync = FsyncIn { fsync_flags: FUSE_FSYNC_FDATASYNC, ..FsyncIn::default() };
+    assert_eq!(call(opcode::FSYNC, sync_file, &[as_bytes(&FsyncIn::default())]).unwrap().0, 0);
+    efs.lock().set_delayed_commit(true);
+    let write = WriteIn { size: 5, ..WriteIn::default() };
+    assert_eq!(call(opcode::WRITE, sync_file, &[as_bytes(&write), b"hello"]).unwrap().0, 0);
+    let block_device = Arc::clone(&efs.lock().block_device);
+    let total_blocks = parse_size("2M").unwrap() as usize / BLOCK_SZ;
+    for block_id in total_blocks - efs.lock().transaction_blocks() * 2..total_blocks {
+        get_block_cache(block_id, Arc::clone(&block_device)).lock().modify(0, |_: &mut [u8; BLOCK_SZ]| {});
+    }
+    assert_eq!(call(opcode::FSYNC, sync_file, &[as_bytes(&fdatasync)]).unwrap().0, -libc::EIO);
+    assert_eq!(call(opcode::FSYNC, sync_file, &[as_bytes(&FsyncIn::default())]).unwrap().0, -libc::EIO);
+    assert_eq!(call(opcode::WRITE, sync_file, &[as_bytes(&write), b"world"]).unwrap().0, 0);
+    assert_eq!(call(opcode::FSYNC, sync_file, &[as_bytes(&FsyncIn::default())]).unwrap().0, 0);
+    assert_eq!(call(opcode::UNLINK, FUSE_ROOT_ID, &[b"sync.txt\0"]).unwrap().0, 0);
+    efs.lock().set_delayed_commit(false);
+
+    // 不需要回复的请求与未实现的请求
+    assert!(call(opcode::FORGET, file, &[&[0; 8]]).is_none());
+    assert_eq!(call(4242, FUSE_ROOT_ID, &[]).unwrap().0, -libc::ENOSYS);
+    assert_eq!(call(opcode::DESTROY, 0, &[]).unwrap().0, 0);
+    assert!(server.is_destroyed());
+    assert!(fsck(&efs, false).is_clean());
+    Ok(())
+}
+
+#[test]
+fn efs_long_name_test() -> std::io::Result<()> {
+    use easy_fs::NAME_LENGTH_LIMIT;
+    let efs = pack(&PackOptions {
+        output: PathBuf::from("target/long-name-test.img"),
+        size: parse_size("2M").unwrap(),
+        inodes: 200,
+        ..PackOptions::default()
+    })?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let long_name = "n".repeat(NAME_LENGTH_LIMIT as usize);
+    let file = root_inode.create(&long_name).unwrap();
+    file.write_at(0, b"long");
+    assert_eq!(read_all(&root_inode.find_inode(&long_name).unwrap()), b"long");
+    // 超长或空的名字返回错误而不是 panic
+    let too_long = "n".repeat(NAME_LENGTH_LIMIT as usize + 1);
+    assert!(root_inode.create(&too_long).is_none());
+    assert!(root_inode.find_inode(&too_long).is_none());
+    assert!(root_inode.create("").is_none());
+    assert!(root_inode.create(&long_name).is_none());
+
+    // 长短不一的名字跨越多个目录块
+    let name = |i: usize| format!("{}-{}", "x".repeat(i * 7 % 200), i);
+    let renamed = |i: usize| name(i).replace('-', "+");
+    for i in 0..100 {
+        root_inode.create(&name(i)).unwrap();
+    }
+    let size = root_inode.size();
+    assert!(size > 4 * BLOCK_SZ && size.is_multiple_of(BLOCK_SZ));
+    assert!(fsck(&efs, false).is_clean());
+    // 删除后空出的空间被同样长度的名字复用，目录不再增长
+    for i in (0..100).step_by(2) {
+        assert!(root_inode.unlink(&name(i)));
+    }
+    for i in (0..100).step_by(2) {
+        root_inode.create(&renamed(i)).unwrap();
+    }
+    assert_eq!(root_inode.size(), size);
+    assert!(fsck(&efs, false).is_clean());
+    let mut names = root_inode.ls();
+    let mut expected: Vec<String> = (0..100).map(|i| if i % 2 == 0 { renamed(i) } else { name(i) }).collect();
+    expected.push(long_name);
+    names.sort();
+    expected.sort();
+    assert_eq!(names, expected);
+    // 删除全部目录项后目录大小回到 0
+    for name in root_inode.ls() {
+        assert!(root_inode.unlink(&name));
+    }
+    assert_eq!(root_inode.size(), 0);
+    Ok(())
+}
+
+#[test]
+fn efs_rename_test() -> std::io::Result<()> {
+    let efs = pack(&PackOptions {
+        output: PathBuf::from("target/rename-test.img"),
+        size: parse_size("2M").unwrap(),
+        inodes: 200,
+        ..PackOptions::default()
+    })?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let free_blocks = efs.lock().free_data_blocks();
+    let free_inodes = efs.lock().free_inodes();
+    let put = |dir: &Inode, name: &str, data: &[u8]| {
+        dir.create(name).unwrap().write_at(0, data);
+    };
+    let rename = |old_dir: &Inode, old_name: &str, new_dir: &Inode, new_name: &str, mode: RenameMode| {
+        Inode::rename(old_dir, old_name, new_dir, new_name, mode)
+    };
+    put(&root_inode, "app", b"v1");
+    let bin = root_inode.create_dir("bin").unwrap();
+    let sub = bin.create_dir("sub").unwrap();
+
+    // 改名与跨目录移动
+    rename(&root_inode, "app", &root_inode, "app.old", RenameMode::Replace).unwrap();
+    assert!(root_inode.find_inode("app").is_none());
+    rename(&root_inode, "app.old", &bin, "app", RenameMode::Replace).unwrap();
+    assert_eq!(read_all(&*lookup(&efs, "/bin/app")?), b"v1");
+    assert_eq!(rename(&root_inode, "missing", &bin, "x", RenameMode::Replace), Err(RenameError::NotFound));
+    assert_eq!(rename(&bin, "app", &bin, "app", RenameMode::NoReplace), Ok(()));
+
+    // 原子地替换已存在的文件，被替换的文件被释放
+    put(&bin, "app.new", b"version 2");
+    assert_eq!(rename(&bin, "app.new", &bin, "app", RenameMode::NoReplace), Err(RenameError::Exists));
+    rename(&bin, "app.new", &bin, "app", RenameMode::Replace).unwrap();
+    assert_eq!(read_all(&*lookup(&efs, "/bin/app")?), b"version 2");
+    assert!(bin.find_inode("app.new").is_none());
+    assert!(fsck(&efs, false).is_clean());
+
+    // 交换
+    put(&sub, "other", b"other");
+    rename(&bin, "app", &sub, "other", RenameMode::Exchange).unwrap();
+    assert_eq!(read_all(&*lookup(&efs, "/bin/app")?), b"other");
+    assert_eq!(read_all(&*lookup(&efs, "/bin/sub/other")?), b"version 2");
+    assert_eq!(rename(&bin, "app", &sub, "missing", RenameMode::Exchange), Err(RenameError::NotFound));
+
+    // 目录: 类型检查、非空目录与循环
+    let empty = root_inode.create_dir("empty").unwrap();
+    assert_eq!(rename(&bin, "app", &root_inode, "empty", RenameMode::Replace), Err(RenameError::IsDir));
+    assert_eq!(rename(&root_inode, "empty", &bin, "app", RenameMode::Replace), Err(RenameError::NotDir));
+    assert_eq!(rename(&root_inode, "empty", &root_inode, "bin", RenameMode::Replace), Err(RenameError::NotEmpty));
+    assert_eq!(rename(&root_inode, "bin", &sub, "bin", RenameMode::Replace), Err(RenameError::Invalid));
+    assert_eq!(rename(&root_inode, "bin", &bin, "bin", RenameMode::Replace), Err(RenameError::Invalid));
+    assert_eq!(rename(&bin, "sub", &empty, "sub", RenameMode::Exchange), Err(RenameError::NotFound));
+    assert_eq!(rename(&sub, "other", &root_inode, "bin", RenameMode::Exchange), Err(RenameError::Invalid));
+    rename(&bin, "sub", &empty, "sub", RenameMode::Replace).unwrap();
+    assert_eq!(read_all(&*lookup(&efs, "/empty/sub/other")?), b"version 2");
+    rename(&root_inode, "empty", &bin, "moved", RenameMode::Replace).unwrap();
+    assert_eq!(read_all(&*lookup(&efs, "/bin/moved/sub/other")?), b"version 2");
+    assert!(fsck(&efs, false).is_clean());
+
+    // 在有索引的大目录中改名
+    let name = |i: usize| format!("{}-{}", "x".repeat(i * 7 % 200), i);
+    for i in 0..100 {
+        put(&root_inode, &name(i), &i.to_le_bytes());
+    }
+    for i in 0..100 {
+        let new_name = format!("renamed-{}", i);
+        rename(&root_inode, &name(i), &root_inode, &new_name, RenameMode::Replace).unwrap();
+    }
+    for i in 0..100 {
+        assert!(root_inode.find_inode(&name(i)).is_none());
+        assert_eq!(read_all(&*lookup(&efs, &format!("/renamed-{}", i))?), i.to_le_bytes());
+    }
+    assert!(fsck(&efs, false).is_clean());
+
+    // 删除全部文件后空间全部归还
+    for path in ["/bin/moved/sub/other", "/bin/moved/sub", "/bin/moved", "/bin/app"] {
+        let (parent, name) = lookup_parent(&efs, path)?;
+        assert!(parent.unlink(name));
+    }
+    for name in root_inode.ls() {
+        assert!(root_inode.unlink(&name));
+    }
+    drop((bin, sub, empty));
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks);
+    assert_eq!(efs.lock().free_inodes(), free_inodes);
+    Ok(())
+}
+
+#[test]
+fn efs_symlink_test() -> std::io::Result<()> {
+    use std::fs::{create_dir_all, remove_file, write};
+    use std::os::unix::fs::symlink;
+    let efs = pack(&PackOptions {
+        output: PathBuf::from("target/symlink-test.img"),
+        size: parse_size("2M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let free_blocks = efs.lock().free_data_blocks();
+    let free_inodes = efs.lock().free_inodes();
+    let bin = root_inode.create_dir("bin").unwrap();
+    bin.create("user_shell").unwrap().write_at(0, b"shell");
+
+    // 短目标保存在 inode 内，不占数据块
+    let used_blocks = free_blocks - efs.lock().free_data_blocks();
+    let sh = bin.create_symlink("sh", "user_shell").unwrap();
+    assert!(sh.is_symlink() && !sh.is_dir());
+    assert_eq!(read_all(&sh), b"user_shell");
+    assert_eq!(free_blocks - efs.lock().free_data_blocks(), used_blocks);
+    assert_eq!(sh.write_at(0, b"x"), 0);
+    assert!(!sh.truncate(0));
+    assert_eq!(read_all(&*lookup(&efs, "/bin/sh")?), b"shell");
+    root_inode.create_symlink("usr", "/bin").unwrap();
+    root_inode.create_symlink("up", "bin/../usr/./sh").unwrap();
+    assert_eq!(read_all(&*lookup(&efs, "/usr/sh")?), b"shell");
+    assert_eq!(read_all(&*lookup(&efs, "/up")?), b"shell");
+    assert!(bin.create_symlink("sh", "other").is_none());
+    assert!(bin.create_symlink("empty", "").is_none());
+
+    // 长目标保存在数据块中
+    let long_target = format!("/{}/../bin/sh", "x".repeat(3000));
+    let long = root_inode.create_symlink("long", &long_target).unwrap();
+    assert_eq!(read_all(&long), long_target.as_bytes());
+    assert!(free_blocks - efs.lock().free_data_blocks() > used_blocks + 1);
+    assert!(lookup(&efs, "/long").is_err());
+    assert!(root_inode.create_symlink("too-long", &"x".repeat(SYMLINK_LENGTH_LIMIT as usize + 1)).is_none());
+
+    // 循环
+    root_inode.create_symlink("loop", "loop").unwrap();
+    assert!(lookup(&efs, "/loop").is_err());
+    assert!(fsck(&efs, false).is_clean());
+
+    // 删除后空间全部归还
+    for name in ["long", "loop", "usr", "up"] {
+        assert!(root_inode.unlink(name));
+    }
+    for name in bin.ls() {
+        assert!(bin.unlink(&name));
+    }
+    assert!(root_inode.unlink("bin"));
+    drop((bin, sh, long));
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks);
+    assert_eq!(efs.lock().free_inodes(), free_inodes);
+
+    // pack 保留主机上的符号链接，清单可以创建符号链接
+    let src = Path::new("target/symlink-test");
+    create_dir_all(src.join("data/bin"))?;
+    write(src.join("data/bin/user_shell"), b"shell")?;
+    let _ = remove_file(src.join("data/bin/sh"));
+    symlink("user_shell", src.join("data/bin/sh"))?;
+    write(src.join("manifest"), "/etc/init -> /bin/sh\n")?;
+    let efs = pack(&PackOptions {
+        output: src.join("fs.img"),
+        size: parse_size("2M").unwrap(),
+        inodes: 100,
+        data: vec![src.join("data")],
+        manifest: Some(src.join("manifest")),
+        ..PackOptions::default()
+    })?;
+    let (bin, _) = lookup_parent(&efs, "/bin/sh")?;
+    assert!(bin.find_inode("sh").unwrap().is_symlink());
+    assert_eq!(read_all(&*lookup(&efs, "/etc/init")?), b"shell");
+    assert!(fsck(&efs, false).is_clean());
+    Ok(())
+}
+
+#[test]
+fn efs_times_test() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use easy_fs::Times;
+    // 只给这个测试的文件系统设置可控的时钟
+    static NOW: AtomicU64 = AtomicU64::new(1000);
+    fn clock() -> Timestamp {
+        Timestamp { sec: NOW.load(Ordering::Relaxed), nsec: 7 }
+    }
+    let at = |sec: u64| Timestamp { sec, nsec: 7 };
+    let set_now = |sec: u64| NOW.store(sec, Ordering::Relaxed);
+    let efs = pack(&PackOptions {
+        output: PathBuf::from("target/times-test.img"),
+        size: parse_size("2M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    efs.lock().set_clock(clock);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // 新建: 三个时间都是当前时间，父目录的 mtime/ctime 随之更新
+    let file = root_inode.create("a").unwrap();
+    assert_eq!(file.times(), Times { atime: at(1000), mtime: at(1000), ctime: at(1000) });
+    assert_eq!((root_inode.times().mtime, root_inode.times().ctime), (at(1000), at(1000)));
+    // 写入更新 mtime/ctime
+    set_now(2000);
+    file.write_at(0, b"hello");
+    assert_eq!(file.times(), Times { atime: at(1000), mtime: at(2000), ctime: at(2000) });
+    // relatime: atime 不晚于 mtime 时读取更新 atime，之后一天内的读取不再更新
+    set_now(3000);
+    assert_eq!(read_all(&file), b"hello");
+    assert_eq!(file.times().atime, at(3000));
+    set_now(4000);
+    read_all(&file);
+    assert_eq!(file.times().atime, at(3000));
+    set_now(3000 + 24 * 60 * 60);
+    read_all(&file);
+    assert_eq!(file.times().atime, at(3000 + 24 * 60 * 60));
+    // chmod 只改变 ctime
+    set_now(200000);
+    file.set_permissions(Permissions { mode: 0o600, ..file.permissions() });
+    assert_eq!((file.times().mtime, file.times().ctime), (at(2000), at(200000)));
+    // 显式设置 atime/mtime，ctime 为当前时间
+    set_now(300000);
+    file.set_times(Some(Timestamp { sec: 1, nsec: 2 }), None);
+    assert_eq!(file.times(), Times { atime: Timestamp { sec: 1, nsec: 2 }, mtime: at(2000), ctime: at(300000) });
+    // truncate 与 unlink
+    set_now(400000);
+    assert!(file.truncate(0));
+    assert_eq!(file.times().mtime, at(400000));
+    set_now(500000);
+    assert!(root_inode.unlink("a"));
+    assert_eq!(root_inode.times().mtime, at(500000));
+    drop(file);
+    // 时间戳持久化在磁盘上
+    let efs = EasyFileSystem::open(Arc::new(BlockFile(Mutex::new(
+        OpenOptions::new().read(true).write(true).open("target/times-test.img")?,
+    ))));
+    assert_eq!(EasyFileSystem::root_inode(&efs).times().mtime, at(500000));
+    assert!(fsck(&efs, false).is_clean());
+    Ok(())
+}
+
+#[test]
+fn efs_xattr_test() -> std::io::Result<()> {
+    use std::mem::size_of;
+    use easy_fs::{XattrError, XattrMode, XATTR_SIZE_MAX};
+    use fuse::*;
+    let efs = pack(&PackOptions {
+        output: PathBuf::from("target/xattr-test.img"),
+        size: parse_size("2M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let file = root_inode.create("artifact").unwrap();
+    let free_blocks = efs.lock().free_data_blocks();
+    assert!(file.list_xattr().is_empty() && file.get_xattr("user.test").is_none());
+
+    // 第一个属性分配属性块，之后的属性放在同一个块中
+    file.set_xattr("user.test", b"42", XattrMode::Set).unwrap();
+    file.set_xattr("user.sum", b"deadbeef", XattrMode::Create).unwrap();
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks - 1);
+    assert_eq!(file.list_xattr(), vec!["user.test", "user.sum"]);
+    assert_eq!(file.get_xattr("user.sum").unwrap(), b"deadbeef");
+    assert_eq!(file.set_xattr("user.sum", b"x", XattrMode::Create), Err(XattrError::Exists));
+    assert_eq!(file.set_xattr("user.none", b"x", XattrMode::Replace), Err(XattrError::NotFound));
+    file.set_xattr("user.test", b"", XattrMode::Replace).unwrap();
+    assert_eq!(file.get_xattr("user.test").unwrap(), b"");
+    assert_eq!(file.set_xattr("", b"x", XattrMode::Set), Err(XattrError::Invalid));
+    let big = vec![7u8; XATTR_SIZE_MAX];
+    assert_eq!(file.set_xattr("user.big", &big, XattrMode::Set), Err(XattrError::NoSpace));
+    assert_eq!(file.list_xattr().len(), 2);
+    assert!(fsck(&efs, false).is_clean());
+
+    // 删除最后一个属性时释放属性块
+    assert_eq!(file.remove_xattr("user.none"), Err(XattrError::NotFound));
+    file.remove_xattr("user.test").unwrap();
+    file.remove_xattr("user.sum").unwrap();
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks);
+    // 一个属性可以占满整个块；删除文件时一并释放属性块
+    file.set_xattr("u", &big[..XATTR_SIZE_MAX - 1], XattrMode::Set).unwrap();
+    assert!(fsck(&efs, false).is_clean());
+    assert!(root_inode.unlink("artifact"));
+    drop(file);
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks + 1); // 根目录的目录块也被释放
+
+    // FUSE
+    let mut server = FuseServer::new(Arc::clone(&efs));
+    let mut call = |opcode: u32, nodeid: u64, args: &[&[u8]]| -> (i32, Vec<u8>) {
+        let body = args.concat();
+        let header = InHeader {
+            len: (size_of::<InHeader>() + body.len()) as u32,
+            opcode,
+            unique: 1,
+            nodeid,
+            ..InHeader::default()
+        };
+        let reply = server.handle(&[as_bytes(&header), &body].concat()).unwrap();
+        let out: OutHeader = from_bytes(&reply).unwrap();
+        (out.error, reply[size_of::<OutHeader>()..].to_vec())
+    };
+    let init = InitIn { major: 7, minor: 38, ..InitIn::default() };
+    assert_eq!(call(opcode::INIT, 0, &[as_bytes(&init)]).0, 0);
+    let setxattr = SetxattrIn { size: 2, flags: libc::XATTR_CREATE as u32 };
+    assert_eq!(call(opcode::SETXATTR, FUSE_ROOT_ID, &[as_bytes(&setxattr), b"user.id\0", b"17"]).0, 0);
+    assert_eq!(call(opcode::SETXATTR, FUSE_ROOT_ID, &[as_bytes(&setxattr), b"user.id\0", b"18"]).0, -libc::EEXIST);
+    let (error, data) = call(opcode::GETXATTR, FUSE_ROOT_ID, &[as_bytes(&GetxattrIn::default()), b"user.id\0"]);
+    assert_eq!((error, from_bytes::<GetxattrOut>(&data).unwrap().size), (0, 2));
+    let getxattr = GetxattrIn { size: 1, padding: 0 };
+    assert_eq!(call(opcode::GETXATTR, FUSE_ROOT_ID, &[as_bytes(&getxattr), b"user.id\0"]).0, -libc::ERANGE);
+    let getxattr = GetxattrIn { size: 64, padding: 0 };
+    assert_eq!(call(opcode::GETXATTR, FUSE_ROOT_ID, &[as_bytes(&getxattr), b"user.id\0"]), (0, b"17".to_vec()));
+    assert_eq!(call(opcode::GETXATTR, FUSE_ROOT_ID, &[as_bytes(&getxattr), b"user.x\0"]).0, -libc::ENODATA);
+    assert_eq!(call(opcode::LISTXATTR, FUSE_ROOT_ID, &[as_bytes(&getxattr)]), (0, b"user.id\0".to_vec()));
+    assert_eq!(call(opcode::REMOVEXATTR, FUSE_ROOT_ID, &[b"user.id\0"]).0, 0);
+    assert_eq!(call(opcode::REMOVEXATTR, FUSE_ROOT_ID, &[b"user.id\0"]).0, -libc::ENODATA);
+
+    assert!(fsck(&efs, false).is_clean());
+    Ok(())
+}
+
+#[test]
+fn efs_checksum_test() -> std::io::Result<()> {
+    use std::mem::size_of;
+    use easy_fs::fsck::Problem;
+    use fuse::*;
+    const IMAGE: &str = "target/checksum-test.img";
+    let pack_image = |checksums: ChecksumMode| pack(&PackOptions {
+        output: PathBuf::from(IMAGE),
+        size: parse_size("4M").unwrap(),
+        inodes: 100,
+        checksums,
+        extents: false, // 下面直接读取 direct 与 indirect1
+        ..PackOptions::default()
+    });
+    let reopen = || open_image(IMAGE).unwrap();
+    // 直接修改磁盘 (绕过块缓存)，每次重新打开得到新的块设备，相当于重新挂载
+    let raw = || BlockFile(Mutex::new(OpenOptions::new().read(true).write(true).open(IMAGE).unwrap()));
+    let read_u32 = |block_id: u32, offset: usize| {
+        let mut buf = [0u8; BLOCK_SZ];
+        raw().read_block(block_id as usize, &mut buf);
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+    };
+    let flip_bit = |block_id: u32, offset: usize| {
+        let (device, mut buf) = (raw(), [0u8; BLOCK_SZ]);
+        device.read_block(block_id as usize, &mut buf);
+        buf[offset] ^= 1;
+        device.write_block(block_id as usize, &buf);
+    };
+    // 40 个数据块 (用到 indirect1)，第 i 块的内容全为 i + 1
+    let content: Vec<u8> = (0..40).flat_map(|i| [i as u8 + 1; BLOCK_SZ]).collect();
+    let write_file = |efs: &Arc<spin::Mutex<EasyFileSystem>>| {
+        let file = EasyFileSystem::root_inode(efs).create("data").unwrap();
+        assert_eq!(file.write_at(0, &content), content.len());
+        let inode_id = file.inode_id();
+        let (block_id, offset) = efs.lock().get_disk_inode_pos(inode_id);
+        // (direct[4], indirect1)
+        (read_u32(block_id, offset + 4 + 4 * 4), read_u32(block_id, offset + 4 + 28 * 4))
+    };
+    let read_file = |efs: &Arc<spin::Mutex<EasyFileSystem>>| {
+        let file = EasyFileSystem::root_inode(efs).find_inode("data").unwrap();
+        let mut buf = vec![0u8; content.len()];
+        file.read_at(0, &mut buf);
+        buf
+    };
+
+    // 数据块也有校验和: 损坏的块在加载时被发现
+    let efs = pack_image(ChecksumMode::All)?;
+    let (data_block, _) = write_file(&efs);
+    assert!(fsck(&efs, false).is_clean());
+    drop(efs);
+    flip_bit(data_block, 100);
+    let efs = reopen();
+    assert_ne!(read_file(&efs), content);
+    assert_eq!(efs.lock().take_checksum_errors(), vec![data_block]);
+    assert!(efs.lock().take_checksum_errors().is_empty());
+    // 损坏的块留在缓存中，之后每次访问都会再次报告
+    read_file(&efs);
+    assert_eq!(efs.lock().take_checksum_errors(), vec![data_block]);
+
+    // FUSE 请求读到损坏的块时返回 EIO
+    let mut server = FuseServer::new(reopen());
+    let mut call = |opcode: u32, nodeid: u64, body: &[u8]| -> i32 {
+        let header = InHeader {
+            len: (size_of::<InHeader>() + body.len()) as u32,
+            opcode,
+            unique: 1,
+            nodeid,
+            ..InHeader::default()
+        };
+        let reply = server.handle(&[as_bytes(&header), body].concat()).unwrap();
+        from_bytes::<OutHeader>(&reply).unwrap().error
+    };
+    let init = InitIn { major: 7, minor: 38, ..InitIn::default() };
+    assert_eq!(call(opcode::INIT, 0, as_bytes(&init)), 0);
+    let read = ReadIn { offset: 0, size: 4 * BLOCK_SZ as u32, ..ReadIn::default() };
+    assert_eq!(call(opcode::READ, FUSE_ROOT_ID + 1, as_bytes(&read)), 0);
+    let read = ReadIn { offset: 4 * BLOCK_SZ as u64, size: BLOCK_SZ as u32, ..ReadIn::default() };
+    assert_eq!(call(opcode::READ, FUSE_ROOT_ID + 1, as_bytes(&read)), -libc::EIO);
+
+    // fsck 报告损坏的块，修复时接受它的当前内容
+    let report = fsck(&reopen(), false);
+    assert_eq!(report.problems, vec![Problem::BadChecksum { block: data_block }]);
+    assert!(fsck(&reopen(), true).repaired);
+    assert!(fsck(&reopen(), false).is_clean());
+
+    // 损坏的校验表块: 它记录的校验和不可信
+    let super_block = get_block_cache(0, Arc::clone(&reopen().lock().block_device)).lock()
+        .read(0, |super_block: &SuperBlock| *super_block);
+    let table_block = super_block.journal_start_block() - super_block.checksum_blocks;
+    flip_bit(table_block, 0);
+    let report = fsck(&reopen(), false);
+    assert_eq!(report.problems, vec![Problem::BadChecksumTable { block: table_block }]);
+    assert!(fsck(&reopen(), true).repaired);
+    assert!(fsck(&reopen(), false).is_clean());
+
+    // SuperBlock 的校验和不符时拒绝打开
+    flip_bit(0, 20); // data_area_blocks
+    assert!(open_image(IMAGE).is_err());
+    flip_bit(0, 20);
+    assert_eq!(read_file(&reopen()).len(), content.len());
+
+    // 默认只有元数据有校验和: 索引块损坏会被发现，数据块不会
+    let efs = pack_image(ChecksumMode::Metadata)?;
+    let (data_block, indirect1) = write_file(&efs);
+    drop(efs);
+    flip_bit(data_block, 100);
+    flip_bit(indirect1, 0);
+    let efs = reopen();
+    read_file(&efs);
+    assert_eq!(efs.lock().take_checksum_errors(), vec![indirect1]);
+    let report = fsck(&reopen(), false);
+    assert!(report.problems.contains(&Problem::BadChecksum { block: indirect1 }));
+    assert!(!report.problems.contains(&Problem::BadChecksum { block: data_block }));
+    Ok(())
+}
+
+#[test]
+fn efs_extent_test() -> std::io::Result<()> {
+    use easy_fs::fsck::Problem;
+    use easy_fs::MAX_FILE_SIZE;
+    const IMAGE: &str = "target/extent-test.img";
+    let efs = pack(&PackOptions {
+        output: PathBuf::from(IMAGE),
+        size: parse_size("24M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    assert!(efs.lock().uses_extents());
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // 超过直接与间接索引上限 (约 8 MiB) 的文件
+    let big = root_inode.create("big").unwrap();
+    assert!(big.max_size() > MAX_FILE_SIZE);
+    let content: Vec<u8> = (0..10 * 1024 * 1024).map(|i| (i / BLOCK_SZ % 251) as u8).collect();
+    assert_eq!(big.write_at(0, &content), content.len());
+    assert_eq!(read_all(&big), content);
+
+    // 把空闲空间切成单个的块 (直接标记位图中一半的空闲位)，
+    // 之后两个文件交替追加，每个块都是单独的 extent，树的深度达到 2
+    let blockers: Vec<usize> = {
+        let fs = efs.lock();
+        (1..fs.data_blocks()).step_by(2).filter(|pos| !fs.data_bitmap.is_set(&fs.block_device, *pos)).collect()
+    };
+    let set_blockers = |value: bool| {
+        let mut fs = efs.lock();
+        blockers.iter().for_each(|pos| fs.data_bitmap.set(&fs.block_device, *pos, value));
+        fs.recount_free();
+        fs.commit();
+    };
+    set_blockers(true);
+    let (filea, fileb) = (root_inode.create("a").unwrap(), root_inode.create("b").unwrap());
+    for i in 0..400 {
+        filea.write_at(i * BLOCK_SZ, &[(i % 200) as u8 + 1; BLOCK_SZ]);
+        fileb.write_at(i * BLOCK_SZ, &[(i % 200) as u8 + 2; BLOCK_SZ]);
+    }
+    set_blockers(false);
+    let expected = |base: u8, blocks: usize| -> Vec<u8> {
+        (0..blocks).flat_map(|i| [(i % 200) as u8 + base; BLOCK_SZ]).collect()
+    };
+    assert_eq!(read_all(&filea), expected(1, 400));
+    let report = fsck(&efs, false);
+    assert!(report.is_clean(), "{:?}", report.problems);
+
+    // 截断释放 extent 与不再需要的节点块
+    assert!(fileb.truncate(50 * BLOCK_SZ));
+    assert_eq!(read_all(&fileb), expected(2, 50));
+    assert!(fileb.truncate(0));
+    assert_eq!(fileb.write_at(0, b"again"), 5);
+    assert!(fsck(&efs, false).is_clean());
+    assert!(big.truncate(3 * BLOCK_SZ + 10));
+    assert_eq!(read_all(&big), content[..3 * BLOCK_SZ + 10]);
+    assert!(fsck(&efs, false).is_clean());
+
+    // 损坏 a 的第一个子节点: fsck 报告它 (以及它下面不可达的数据块)，并把文件截断到仍然可读的前缀
+    let inode_id = filea.inode_id();
+    let (block_id, offset) = efs.lock().get_disk_inode_pos(inode_id);
+    drop((root_inode, big, filea, fileb));
+    drop(efs);
+    let device = BlockFile(Mutex::new(OpenOptions::new().read(true).write(true).open(IMAGE)?));
+    let mut buf = [0u8; BLOCK_SZ];
+    device.read_block(block_id as usize, &mut buf);
+    // 根节点从 direct 开始: 8 字节的头部之后是 (logical, start, len)
+    let root = offset + 4;
+    assert!(u16::from_le_bytes(buf[root + 4..root + 6].try_into().unwrap()) > 0);
+    let child = u32::from_le_bytes(buf[root + 12..root + 16].try_into().unwrap());
+    device.read_block(child as usize, &mut buf);
+    buf[0] ^= 1;
+    device.write_block(child as usize, &buf);
+    drop(device);
+
+    let report = fsck(&open_image(IMAGE)?, false);
+    assert_eq!(report.problems[..2], [
+        Problem::BadChecksum { block: child },
+        Problem::BadExtentNode { inode: inode_id, block: child },
+    ]);
+    assert!(report.problems[2..].iter().all(|problem| matches!(problem, Problem::BlockLeaked { .. })));
+    assert!(fsck(&open_image(IMAGE)?, true).repaired);
+    let efs = open_image(IMAGE)?;
+    let report = fsck(&efs, false);
+    assert!(report.is_clean(), "{:?}", report.problems);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.find_inode("a").unwrap().size(), 0);
+    assert_eq!(read_all(&root_inode.find_inode("b").unwrap()), b"again");
+    assert_eq!(read_all(&root_inode.find_inode("big").unwrap()), content[..3 * BLOCK_SZ + 10]);
+    Ok(())
+}
+
+#[test]
+fn efs_alloc_test() -> std::io::Result<()> {
+    use easy_fs::fsck::Problem;
+    use easy_fs::config::PREALLOC_BLOCKS;
+    const IMAGE: &str = "target/alloc-test.img";
+    let pack_image = |checksums: ChecksumMode| pack(&PackOptions {
+        output: PathBuf::from(IMAGE),
+        size: parse_size("8M").unwrap(),
+        inodes: 100,
+        checksums,
+        ..PackOptions::default()
+    });
+    let reopen = || open_image(IMAGE).unwrap();
+    let raw = || BlockFile(Mutex::new(OpenOptions::new().read(true).write(true).open(IMAGE).unwrap()));
+    // SuperBlock 中的 (features, free_inodes, free_blocks)
+    let super_block = || get_block_cache(0, Arc::clone(&reopen().lock().block_device)).lock()
+        .read(0, |super_block: &SuperBlock| (super_block.features, super_block.free_inodes, super_block.free_blocks));
+    let modify_super_block = |f: &dyn Fn(&mut [u8])| {
+        let (device, mut buf) = (raw(), [0u8; BLOCK_SZ]);
+        device.read_block(0, &mut buf);
+        f(&mut buf);
+        device.write_block(0, &buf);
+    };
+
+    // 两个文件交替追加: 各自的块在预留窗口中保持连续，extent 数远少于块数
+    let efs = pack_image(ChecksumMode::Metadata)?;
+    let free_inodes = efs.lock().free_inodes();
+    let free_blocks = efs.lock().free_data_blocks();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let (filea, fileb) = (root_inode.create("a").unwrap(), root_inode.create("b").unwrap());
+    for i in 0..300 {
+        filea.write_at(i * BLOCK_SZ, &[1; BLOCK_SZ]);
+        fileb.write_at(i * BLOCK_SZ, &[2; BLOCK_SZ]);
+    }
+    for file in [&filea, &fileb] {
+        let inode_id = file.inode_id();
+        let (block_id, offset) = efs.lock().get_disk_inode_pos(inode_id);
+        // extent 树的根节点从 direct 开始，头部为 (magic, entries, depth, reserved)
+        let (extents, depth) = get_block_cache(block_id as usize, Arc::clone(&efs.lock().block_device)).lock()
+            .read(offset + 4, |header: &[u16; 4]| (header[1], header[2]));
+        assert_eq!(depth, 0);
+        // 第一个块与新文件紧挨着分配，之后每个窗口一个 extent
+        assert!(extents as u32 <= 1 + 300_u32.div_ceil(PREALLOC_BLOCKS), "{} extents", extents);
+    }
+    assert_eq!(efs.lock().free_inodes(), free_inodes - 2);
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks - 600 - 1); // 根目录的第一个目录块
+    assert!(fsck(&efs, false).is_clean());
+
+    // 空闲计数随位图一起提交，重新打开后不变
+    assert!(fileb.truncate(0));
+    drop((root_inode, filea, fileb));
+    drop(efs);
+    assert_eq!(reopen().lock().free_data_blocks(), free_blocks - 300 - 1);
+    assert_eq!(super_block().2 as usize, free_blocks - 300 - 1);
+
+    // 没有空闲计数的镜像 (不使用校验和，直接清除标志位): 打开时由位图计算，下一次提交时写入
+    pack_image(ChecksumMode::None)?;
+    let free_counts = super_block();
+    assert_ne!(free_counts.0 & 8, 0);
+    modify_super_block(&|buf| {
+        buf[28] &= !8;
+        buf[40..48].fill(0);
+    });
+    assert_eq!(super_block().0 & 8, 0);
+    let efs = reopen();
+    assert_eq!(efs.lock().free_inodes() as u32, free_counts.1);
+    assert_eq!(efs.lock().free_data_blocks() as u32, free_counts.2);
+    assert!(fsck(&efs, false).is_clean());
+    EasyFileSystem::root_inode(&efs).create("c").unwrap();
+    drop(efs);
+    assert_eq!(super_block(), (free_counts.0, free_counts.1 - 1, free_counts.2 - 1));
+
+    // 错误的空闲计数由 fsck 发现并修复
+    modify_super_block(&|buf| buf[44..48].copy_from_slice(&7u32.to_le_bytes()));
+    let report = fsck(&reopen(), false);
+    assert_eq!(report.problems, vec![Problem::BadFreeBlockCount { recorded: 7, actual: free_counts.2 - 1 }]);
+    assert!(fsck(&reopen(), true).repaired);
+    assert!(fsck(&reopen(), false).is_clean());
+    assert_eq!(reopen().lock().free_data_blocks() as u32, free_counts.2 - 1);
+    Ok(())
+}
+
+#[test]
+fn efs_concurrency_test() -> std::io::Result<()> {
+    use std::sync::Barrier;
+    use std::thread;
+    use easy_fs::XattrMode;
+    const IMAGE: &str = "target/concurrency-test.img";
+    const BLOCKS: usize = 16;
+    pack(&PackOptions {
+        output: PathBuf::from(IMAGE),
+        size: parse_size("8M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    let efs = open_image(IMAGE)?;
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let content: Vec<u8> = (0..BLOCKS * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    let data = root_inode.create("data").unwrap();
+    assert_eq!(data.write_at(0, &content), content.len());
+    root_inode.create_dir("tmp").unwrap();
+
+    // 同一个 inode 只有一个 Inode，最后一个引用被丢弃后从表中移除
+    assert!(Arc::ptr_eq(&data, &root_inode.find_inode("data").unwrap()));
+    assert!(Arc::ptr_eq(&root_inode, &EasyFileSystem::root_inode(&efs)));
+    let data_id = data.inode_id();
+    drop(data);
+    let data = root_inode.find_inode("data").unwrap();
+    assert_eq!(data.inode_id(), data_id);
+    assert!(Arc::ptr_eq(&data, &EasyFileSystem::get_inode(&efs, data_id).unwrap()));
+    // 已删除与超出 inode 区的编号没有对应的 inode
+    let gone_id = root_inode.create("gone").unwrap().inode_id();
+    assert!(root_inode.unlink("gone"));
+    assert!(EasyFileSystem::get_inode(&efs, gone_id).is_none());
+    assert!(EasyFileSystem::get_inode(&efs, u32::MAX).is_none());
+
+    // 读取 (不需要更新 atime 时) 不获取 fs 锁: 另一个线程持有 fs 锁时仍能读完 (否则 join 不会返回)
+    let mut buf = vec![0u8; BLOCK_SZ];
+    data.read_at(0, &mut buf); // relatime: 第一次读取更新 atime
+    let fs_guard = efs.lock();
+    let reader = Arc::clone(&data);
+    let reader = thread::spawn(move || {
+        let mut buf = vec![0u8; BLOCKS * BLOCK_SZ];
+        reader.read_at(0, &mut buf)
+    });
+    assert_eq!(reader.join().unwrap(), BLOCKS * BLOCK_SZ);
+    drop(fs_guard);
+
+    // 被删除时仍被打开的文件在最后一个引用被丢弃前保留自己的内容，编号不会被新文件重用
+    let free_inodes = efs.lock().free_inodes();
+    let free_blocks = efs.lock().free_data_blocks();
+    let opened = root_inode.create("opened").unwrap();
+    assert_eq!(opened.write_at(0, &[1u8; BLOCK_SZ * 2]), BLOCK_SZ * 2);
+    assert!(root_inode.unlink("opened"));
+    assert!(root_inode.find_inode("opened").is_none());
+    assert!(EasyFileSystem::get_inode(&efs, opened.inode_id()).is_none());
+    let created = root_inode.create("created").unwrap();
+    assert_ne!(created.inode_id(), opened.inode_id());
+    assert_eq!(created.write_at(0, &[2u8; BLOCK_SZ]), BLOCK_SZ);
+    assert_eq!(opened.write_at(BLOCK_SZ * 2, &[3u8; 10]), 10);
+    assert_eq!(read_all(&opened), [vec![1u8; BLOCK_SZ * 2], vec![3u8; 10]].concat());
+    assert_eq!(read_all(&created), vec![2u8; BLOCK_SZ]);
+    assert!(fsck(&efs, false).is_clean());
+    assert!(root_inode.unlink("created"));
+    drop(created);
+    drop(opened);
+    assert_eq!(efs.lock().free_inodes(), free_inodes);
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks);
+    // 替换仍被打开的文件同样保留它的内容
+    let replaced = root_inode.create("replaced").unwrap();
+    assert_eq!(replaced.write_at(0, b"old"), 3);
+    root_inode.create("source").unwrap().write_at(0, b"new");
+    assert_eq!(Inode::rename(&root_inode, "source", &root_inode, "replaced", RenameMode::Replace), Ok(()));
+    assert_eq!(read_all(&replaced), b"old");
+    assert_eq!(read_all(&root_inode.find_inode("replaced").unwrap()), b"new");
+    drop(replaced);
+    assert!(root_inode.unlink("replaced"));
+    assert_eq!(efs.lock().free_inodes(), free_inodes);
+    assert_eq!(efs.lock().free_data_blocks(), free_blocks);
+
+    // 修改不同文件的写者并行 (只在分配与提交时获取 fs 锁)，同时开始以增加交错
+    let writers = 4;
+    let barrier = Arc::new(Barrier::new(writers));
+    let handles: Vec<_> = (0..writers).map(|i| {
+        let (root_inode, barrier) = (Arc::clone(&root_inode), Arc::clone(&barrier));
+        thread::spawn(move || {
+            let dir = root_inode.create_dir(&format!("dir{}", i)).unwrap();
+            barrier.wait();
+            for round in 0..9 {
+                let file = dir.create(&format!("f{}", round)).unwrap();
+                assert_eq!(file.write_at(0, &[i as u8; BLOCK_SZ * 5]), BLOCK_SZ * 5);
+                assert!(file.truncate(BLOCK_SZ * 3 + round));
+                assert_eq!(file.set_xattr("user.round", &[round as u8], XattrMode::Set), Ok(()));
+                if round % 3 == 2 {
+                    assert!(dir.unlink(&format!("f{}", round - 1)));
+                }
+            }
+            dir
+        })
+    }).collect();
+    for (i, handle) in handles.into_iter().enumerate() {
+        let dir = handle.join().unwrap();
+        for round in (0..9).filter(|round| round % 3 != 1) {
+            let file = dir.find_inode(&format!("f{}", round)).unwrap();
+            assert_eq!(read_all(&file), vec![i as u8; BLOCK_SZ * 3 + round]);
+            assert_eq!(file.get_xattr("user.round"), Some(vec![round as u8]));
+        }
+    }
+    assert!(fsck(&efs, false).is_clean());
+
+    // 多个读者与修改其他文件的写者并行，读者总是读到完整的内容
+    let readers: Vec<_> = (0..3).map(|i| {
+        let (root_inode, content) = (Arc::clone(&root_inode), content.clone());
+        thread::spawn(move || {
+            for round in 0..5 {
+                let data = root_inode.find_inode("data").unwrap();
+                let offset = (i * 7 + round) % BLOCKS * BLOCK_SZ;
+                let mut buf = vec![0u8; content.len() - offset];
+                assert_eq!(data.read_at(offset, &mut buf), buf.len());
+                assert!(buf == content[offset..]);
+                assert_eq!(data.size(), content.len());
+            }
+        })
+    }).collect();
+    let writer = {
+        let tmp = root_inode.find_inode("tmp").unwrap();
+        thread::spawn(move || {
+            for i in 0..20 {
+                let name = format!("file{}", i % 10);
+                match tmp.find_inode(&name) {
+                    Some(file) => {
+                        assert_eq!(file.write_at(file.size(), &[i as u8; 100]), 100);
+                    }
+                    None => {
+                        tmp.create(&name).unwrap().write_at(0, &[i as u8; BLOCK_SZ * 3]);
+                    }
+                }
+                if i % 7 == 6 {
+                    tmp.unlink(&format!("file{}", i % 5));
+                }
+            }
+        })
+    };
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    writer.join().unwrap();
+    drop((root_inode, data));
+    assert!(fsck(&efs, false).is_clean());
+    Ok(())
+}
+
+#[test]
+fn efs_sync_test() -> std::io::Result<()> {
+    const IMAGE: &str = "target/sync-test.img";
+    pack(&PackOptions {
+        output: PathBuf::from(IMAGE),
+        size: parse_size("4M").unwrap(),
+        inodes: 200,
+        ..PackOptions::default()
+    })?;
+    let efs = open_image(IMAGE)?;
+    efs.lock().set_delayed_commit(true);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    // 从镜像文件重新打开 (另一个块设备，不共享块缓存)，看到的是已经写入磁盘的内容
+    let on_disk = |path: &str| -> std::io::Result<Option<Vec<u8>>> {
+        Ok(lookup(&open_image(IMAGE)?, path).ok().map(|inode| read_all(&inode)))
+    };
+
+    // 延迟提交: 修改留在块缓存中，fdatasync 之后才写入磁盘
+    let file = root_inode.create("a").unwrap();
+    assert_eq!(file.write_at(0, b"hello"), 5);
+    let other = root_inode.create("b").unwrap();
+    assert_eq!(on_disk("/a")?, None);
+    assert!(efs.lock().dirty_blocks() > 0);
+    file.sync_data();
+    assert_eq!(efs.lock().dirty_blocks(), 0);
+    assert_eq!(on_disk("/a")?.as_deref(), Some(&b"hello"[..]));
+    assert_eq!(on_disk("/b")?.as_deref(), Some(&b""[..]));
+
+    // 只修改属性时 fdatasync 不提交，fsync 提交；fsync 没有修改的 inode 也不提交
+    file.set_times(Some(Timestamp { sec: 1, nsec: 0 }), None);
+    file.sync_data();
+    other.sync_all();
+    assert!(efs.lock().dirty_blocks() > 0);
+    file.sync_all();
+    assert_eq!(efs.lock().dirty_blocks(), 0);
+    assert_eq!(lookup(&open_image(IMAGE)?, "/a")?.times().atime, Timestamp { sec: 1, nsec: 0 });
+
+    // 事务满时自动提交，之前的操作不会丢失
+    let transaction_blocks = efs.lock().transaction_blocks();
+    for i in 0..100 {
+        root_inode.create(&format!("f{}", i)).unwrap().write_at(0, &[i as u8; BLOCK_SZ * 2]);
+        assert!(efs.lock().dirty_blocks() <= transaction_blocks);
+    }
+    assert_eq!(on_disk("/f0")?.as_deref(), Some(&[0u8; BLOCK_SZ * 2][..]));
+    assert_eq!(on_disk("/f99")?, None);
+    root_inode.sync_fs();
+    assert_eq!(on_disk("/f99")?.as_deref(), Some(&[99u8; BLOCK_SZ * 2][..]));
+    drop((root_inode, file, other));
+    assert!(fsck(&open_image(IMAGE)?, false).is_clean());
+    Ok(())
+}
+
+#[test]
+fn efs_writeback_test() -> std::io::Result<()> {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    const IMAGE: &str = "target/writeback-test.img";
+    static NOW: AtomicU64 = AtomicU64::new(100);
+    fn clock() -> Timestamp {
+        Timestamp { sec: NOW.load(Ordering::Relaxed), nsec: 0 }
+    }
+    let at = |sec: u64| Timestamp { sec, nsec: 0 };
+    pack(&PackOptions {
+        output: PathBuf::from(IMAGE),
+        size: parse_size("4M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    let efs = open_image(IMAGE)?;
+    efs.lock().set_clock(clock);
+    efs.lock().set_delayed_commit(true);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    let on_disk = |path: &str| -> std::io::Result<bool> { Ok(lookup(&open_image(IMAGE)?, path).is_ok()) };
+
+    // 事务的年龄从第一个有修改的操作算起，过期之前 writeback 不提交
+    assert_eq!(efs.lock().dirty_since(), None);
+    root_inode.create("a").unwrap();
+    NOW.store(110, Ordering::Relaxed);
+    root_inode.create("b").unwrap();
+    assert_eq!(efs.lock().dirty_since(), Some(at(100)));
+    assert!(!efs.lock().writeback(at(99)));
+    assert!(!on_disk("/a")?);
+    assert!(root_inode.writeback(at(105)));
+    assert_eq!(efs.lock().dirty_since(), None);
+    assert!(on_disk("/a")? && on_disk("/b")?);
+    // 没有修改时什么都不做
+    assert!(!root_inode.writeback(at(200)));
+
+    // 脏块达到高水位时写者同步提交
+    root_inode.set_dirty_limit(8);
+    let file = root_inode.create("c").unwrap();
+    for i in 0..20 {
+        assert_eq!(file.write_at(i * BLOCK_SZ, &[i as u8; BLOCK_SZ]), BLOCK_SZ);
+        assert!(efs.lock().dirty_blocks() < 8);
+    }
+    assert!(on_disk("/c")?);
+    root_inode.sync_fs();
+    assert_eq!(lookup(&open_image(IMAGE)?, "/c")?.size(), 20 * BLOCK_SZ);
+    drop((root_inode, file));
+    assert!(fsck(&open_image(IMAGE)?, false).is_clean());
+    Ok(())
+}
+
+/// 记录读请求次数的块设备，read_blocks 一次读取多块也只算一次
+#[cfg(test)]
+struct CountingBlockFile {
+    inner: BlockFile,
+    reads: Mutex<usize>,
+}
+
+#[cfg(test)]
+impl BlockDevice for CountingBlockFile {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        *self.reads.lock().unwrap() += 1;
+        self.inner.read_block(block_id, buf);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.inner.write_block(block_id, buf);
+    }
+
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        *self.reads.lock().unwrap() += 1;
+        self.inner.read_blocks(block_id, buf);
+    }
+}
+
+#[test]
+fn efs_invalidate_test() -> std::io::Result<()> {
+    use easy_fs::block_cache::invalidate_block_cache;
+    const IMAGE: &str = "target/invalidate-test.img";
+    pack(&PackOptions {
+        output: PathBuf::from(IMAGE),
+        size: parse_size("2M").unwrap(),
+        inodes: 100,
+        ..PackOptions::default()
+    })?;
+    let device: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(OpenOptions::new().read(true).write(true).open(IMAGE)?)));
+    // 绕过缓存直接写入块设备 (内核中的 /dev/vda)，失效后读到新内容
+    let block_id = 1000;
+    let held = get_block_cache(block_id, Arc::clone(&device));
+    assert_eq!(held.lock().data()[0], 0);
+    device.write_block(block_id, &[1u8; BLOCK_SZ]);
+    assert_eq!(get_block_cache(block_id, Arc::clone(&device)).lock().data()[0], 0);
+    // 仍被引用的缓存就地重新读取
+    invalidate_block_cache(block_id, &device);
+    assert_eq!(held.lock().data()[0], 1);
+    drop(held);
+    // 只被管理器引用的缓存移出队列，下次访问时重新加载
+    device.write_block(block_id, &[2u8; BLOCK_SZ]);
+    invalidate_block_cache(block_id, &device);
+    assert_eq!(get_block_cache(block_id, Arc::clone(&device)).lock().data()[0], 2);
+    Ok(())
+}
+
+#[test]
+fn efs_readahead_test() -> std::io::Result<()> {
+    use easy_fs::ReadAhead;
+    const IMAGE: &str = "target/readahead-test.img";
+    const BLOCKS: usize = 64;
+    let content: Vec<u8> = (0..BLOCKS * BLOCK_SZ).map(|i| (i % 251) as u8).collect();
+    for extents in [true, false] {
+        let efs = pack(&PackOptions {
+            output: PathBuf::from(IMAGE),
+            size: parse_size("2M").unwrap(),
+            inodes: 100,
+            extents,
+            ..PackOptions::default()
+        })?;
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        assert_eq!(root_inode.create("data").unwrap().write_at(0, &content), content.len());
+        drop(root_inode);
+        // 每次从镜像重新打开 (新的块设备，缓存中没有它的块)，按 offsets 逐块读取，返回读请求数
+        let read_file = |offsets: &mut dyn Iterator<Item = usize>, mut ra: Option<&mut ReadAhead>| -> std::io::Result<usize> {
+            let device = Arc::new(CountingBlockFile {
+                inner: BlockFile(Mutex::new(OpenOptions::new().read(true).write(true).open(IMAGE)?)),
+                reads: Mutex::new(0),
+            });
+            let efs = EasyFileSystem::open(device.clone());
+            let file = EasyFileSystem::root_inode(&efs).find_inode("data").unwrap();
+            let before = *device.reads.lock().unwrap();
+            let mut buf = [0u8; BLOCK_SZ];
+            for offset in offsets {
+                let len = match ra.as_deref_mut() {
+                    Some(ra) => file.read_at_ahead(offset, &mut buf, ra),
+                    None => file.read_at(offset, &mut buf),
+                };
+                assert!(buf[..len] == content[offset..offset + len]);
+            }
+            Ok(*device.reads.lock().unwrap() - before)
+        };
+        // 不预读时每个数据块一次请求；顺序读取时数据块大约每 MAX_PREFETCH 块一次，另外还有被挤出缓存的元数据块
+        let sequential = || (0..BLOCKS).map(|i| i * BLOCK_SZ);
+        let without = read_file(&mut sequential(), None)?;
+        assert!(without >= BLOCKS);
+        let reads = read_file(&mut sequential(), Some(&mut ReadAhead::new()))?;
+        assert!(reads * 3 <= without, "{} reads with read-ahead, {} without (extents: {})", reads, without, extents);
+        // 随机 (倒序) 读取不预读
+        let reads = read_file(&mut sequential().rev(), Some(&mut ReadAhead::new()))?;
+        assert!(reads >= BLOCKS, "{} reads for backward reads (extents: {})", reads, extents);
+    }
+    Ok(())
+}
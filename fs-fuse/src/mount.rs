@@ -0,0 +1,107 @@
+// fs-fuse/src/mount.rs
+// 把 FuseServer 接到 /dev/fuse 上: 挂载后在前台循环处理请求，直到文件系统被卸载 (fusermount3 -u)
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+use easy_fs::efs::EasyFileSystem;
+use crate::fuse::{FuseServer, BUFFER_SIZE};
+
+pub fn mount(efs: Arc<spin::Mutex<EasyFileSystem>>, mountpoint: &Path) -> std::io::Result<()> {
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    // root 可以直接调用 mount(2)，普通用户需要借助 setuid 的 fusermount3
+    let fd = if uid == 0 { mount_as_root(mountpoint, uid, gid)? } else { fusermount(mountpoint)? };
+    let mut device = File::from(fd);
+    let mut server = FuseServer::new(efs);
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    while !server.is_destroyed() {
+        let len = match device.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.raw_os_error() == Some(libc::ENODEV) => break, // 已卸载
+            Err(e) if e.kind() == ErrorKind::Interrupted || e.raw_os_error() == Some(libc::ENOENT) => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(reply) = server.handle(&buffer[..len]) {
+            match device.write(&reply) {
+                Err(e) if e.raw_os_error() == Some(libc::ENOENT) => {} // 请求已被中断
+                Err(e) => return Err(e),
+                Ok(_) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn path_cstring(path: &Path) -> std::io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{}: invalid path", path.display())))
+}
+
+fn mount_as_root(mountpoint: &Path, uid: u32, gid: u32) -> std::io::Result<OwnedFd> {
+    let fd = OwnedFd::from(OpenOptions::new().read(true).write(true).open("/dev/fuse")?);
+    let target = path_cstring(mountpoint)?;
+    let options = CString::new(format!("fd={},rootmode=40000,user_id={},group_id={}", fd.as_raw_fd(), uid, gid)).unwrap();
+    let ret = unsafe {
+        libc::mount(
+            c"easy-fs".as_ptr(),
+            target.as_ptr(),
+            c"fuse.easy-fs".as_ptr(),
+            libc::MS_NOSUID | libc::MS_NODEV,
+            options.as_ptr() as *const libc::c_void,
+        )
+    };
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// 由 fusermount3 完成挂载，它通过 _FUSE_COMMFD 指定的 socket 把 /dev/fuse 的描述符传回来
+fn fusermount(mountpoint: &Path) -> std::io::Result<OwnedFd> {
+    let (ours, theirs) = UnixStream::pair()?;
+    // 子进程需要继承 theirs
+    if unsafe { libc::fcntl(theirs.as_raw_fd(), libc::F_SETFD, 0) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    let status = Command::new("fusermount3")
+        .arg("-o")
+        .arg("fsname=easy-fs,subtype=easy-fs")
+        .arg("--")
+        .arg(mountpoint)
+        .env("_FUSE_COMMFD", theirs.as_raw_fd().to_string())
+        .status()?;
+    drop(theirs);
+    if !status.success() {
+        return Err(Error::other(format!("fusermount3 failed: {}", status)));
+    }
+    recv_fd(&ours)
+}
+
+fn recv_fd(socket: &UnixStream) -> std::io::Result<OwnedFd> {
+    let mut byte = 0u8;
+    let mut iov = libc::iovec { iov_base: &mut byte as *mut u8 as *mut libc::c_void, iov_len: 1 };
+    let mut control = [0u64; 8]; // 按 cmsghdr 对齐
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+    if unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null() || (*cmsg).cmsg_level != libc::SOL_SOCKET || (*cmsg).cmsg_type != libc::SCM_RIGHTS {
+            return Err(Error::other("fusermount3 did not pass a /dev/fuse descriptor"));
+        }
+        let fd = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+}
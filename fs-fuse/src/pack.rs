@@ -0,0 +1,279 @@
+// fs-fuse/src/pack.rs
+// 创建 easy-fs 镜像并写入主机上的文件
+// 输出是确定的: 目录项按名字排序写入，相同的输入总是得到逐字节相同的镜像
+
+use std::fs::{read_dir, read_link, File, OpenOptions};
+use std::io::{Error, ErrorKind, Read};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use easy_fs::checksum::ChecksumTable;
+use easy_fs::config::BLOCK_SIZE;
+use easy_fs::efs::EasyFileSystem;
+use easy_fs::{ChecksumMode, FormatOptions, Inode, Permissions, Timestamp, NAME_LENGTH_LIMIT, SYMLINK_LENGTH_LIMIT};
+use crate::BlockFile;
+
+const INODES_PER_BITMAP_BLOCK: u32 = BLOCK_SIZE * 8;
+
+pub struct PackOptions {
+    pub output: PathBuf,
+    pub size: u64,           // 镜像大小 (Bytes)
+    pub inodes: u32,         // inode 数 (含根目录)，向上取整到位图块的整数倍
+    pub journal_blocks: u32, // 0 表示不使用日志
+    pub checksums: ChecksumMode,
+    pub extents: bool,       // 文件与目录使用 extent 树，否则使用直接与间接索引
+    pub apps: Option<PathBuf>, // 可执行文件目录: 只复制不含 '.' 的普通文件 (cargo 输出目录中还有 .d 等文件)
+    pub data: Vec<PathBuf>,  // 递归复制到根目录的目录
+    pub manifest: Option<PathBuf>, // 每行 `<主机路径> <镜像中的绝对路径>` 或 `<镜像中的绝对路径> -> <链接目标>`，'#' 开头为注释
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            output: PathBuf::from("fs.img"),
+            size: 16 * 1024 * 1024,
+            inodes: INODES_PER_BITMAP_BLOCK,
+            journal_blocks: easy_fs::config::JOURNAL_BLOCKS,
+            checksums: ChecksumMode::Metadata,
+            extents: true,
+            apps: None,
+            data: Vec::new(),
+            manifest: None,
+        }
+    }
+}
+
+fn invalid_input(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+/// 解析镜像大小，如 `16M`、`512K`、`8388608`
+pub fn parse_size(size: &str) -> std::io::Result<u64> {
+    let (number, unit) = match size.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((pos, _)) => size.split_at(pos),
+        None => (size, ""),
+    };
+    let shift = match unit.to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return Err(invalid_input(format!("invalid size: {}", size))),
+    };
+    number.parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or_else(|| invalid_input(format!("invalid size: {}", size)))
+}
+
+/// 按选项创建镜像，返回打开的文件系统
+pub fn pack(options: &PackOptions) -> std::io::Result<Arc<spin::Mutex<EasyFileSystem>>> {
+    // 1. 计算并检查镜像布局
+    if !options.size.is_multiple_of(BLOCK_SIZE as u64) {
+        return Err(invalid_input(format!("image size must be a multiple of {} bytes", BLOCK_SIZE)));
+    }
+    let total_blocks = u32::try_from(options.size / BLOCK_SIZE as u64)
+        .map_err(|_| invalid_input(String::from("image is too large")))?;
+    if options.journal_blocks == 1 {
+        return Err(invalid_input(String::from("journal needs at least 2 blocks")));
+    }
+    let inode_bitmap_blocks = options.inodes.max(1).div_ceil(INODES_PER_BITMAP_BLOCK);
+    let inode_area_blocks = inode_bitmap_blocks * INODES_PER_BITMAP_BLOCK * 128 / BLOCK_SIZE;
+    // SuperBlock + inode 位图 + inode 区 + 校验表 + 日志区 + 至少一个数据位图块和数据块
+    if total_blocks <= options.journal_blocks {
+        return Err(invalid_input(format!("image of {} blocks is too small for the journal", total_blocks)));
+    }
+    let checksum_blocks = match options.checksums {
+        ChecksumMode::None => 0,
+        _ => ChecksumTable::blocks_needed(total_blocks - options.journal_blocks),
+    };
+    let metadata_blocks = 1 + inode_bitmap_blocks + inode_area_blocks + checksum_blocks + options.journal_blocks;
+    if total_blocks < metadata_blocks + 2 {
+        return Err(invalid_input(format!(
+            "image of {} blocks is too small: {} blocks are needed for metadata", total_blocks, metadata_blocks
+        )));
+    }
+
+    // 2. 创建镜像
+    let image = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&options.output)?;
+    image.set_len(options.size)?;
+    let block_file = Arc::new(BlockFile(Mutex::new(image)));
+    let efs = EasyFileSystem::create_with_options(
+        block_file,
+        total_blocks,
+        inode_bitmap_blocks,
+        FormatOptions {
+            journal_blocks: options.journal_blocks,
+            checksums: options.checksums,
+            extents: options.extents,
+        },
+    );
+    // 不设置时钟: 目录与 inode 的时间为 0，文件只保留主机上的 mtime，相同的输入得到相同的镜像
+    let root_inode = EasyFileSystem::root_inode(&efs);
+
+    // 3. 写入文件
+    if let Some(apps) = &options.apps {
+        for (name, path) in sorted_entries(apps)? {
+            if path.is_file() && !name.contains('.') {
+                put_file(&root_inode, &name, &path)?;
+            }
+        }
+    }
+    for data in options.data.iter() {
+        copy_dir(&root_inode, data)?;
+    }
+    if let Some(manifest) = &options.manifest {
+        apply_manifest(&efs, manifest)?;
+    }
+    Ok(efs)
+}
+
+/// 目录中的条目，按名字排序
+fn sorted_entries(dir: &Path) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let mut entries = Vec::new();
+    for entry in read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string()
+            .map_err(|name| invalid_input(format!("{:?}: file name is not UTF-8", name)))?;
+        entries.push((name, entry.path()));
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+fn check_name(name: &str) -> std::io::Result<()> {
+    if name.len() > NAME_LENGTH_LIMIT as usize {
+        return Err(invalid_input(format!("{}: name longer than {} bytes", name, NAME_LENGTH_LIMIT)));
+    }
+    Ok(())
+}
+
+/// 在 dir 中创建或覆盖文件 name，内容为主机文件 path
+fn put_file(dir: &Inode, name: &str, path: &Path) -> std::io::Result<()> {
+    check_name(name)?;
+    let mut file = File::open(path)?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    // 保留主机上的权限位 (可执行位等) 与 mtime，属主为 root
+    let metadata = file.metadata()?;
+    let perm = Permissions { mode: (metadata.permissions().mode() & 0o7777) as u16, uid: 0, gid: 0 };
+    let create = || dir.create_with(name, perm).ok_or_else(|| Error::other(format!("{}: out of inodes", name)));
+    let inode = match dir.find_inode(name) {
+        Some(inode) if inode.is_dir() => {
+            return Err(invalid_input(format!("{}: is a directory in the image", name)));
+        }
+        Some(inode) if inode.is_symlink() => {
+            if !dir.unlink(name) {
+                return Err(Error::other(format!("{}: cannot remove symbolic link", name)));
+            }
+            create()?
+        }
+        Some(inode) => {
+            inode.clear();
+            inode.set_permissions(perm);
+            inode
+        }
+        None => create()?,
+    };
+    if inode.write_at(0, &data) < data.len() {
+        return Err(Error::other(format!("{}: image is full", path.display())));
+    }
+    let mtime = Timestamp { sec: metadata.mtime().max(0) as u64, nsec: metadata.mtime_nsec() as u32 };
+    inode.set_times(Some(mtime), Some(mtime));
+    Ok(())
+}
+
+/// 在 dir 中创建指向 target 的符号链接 name，替换已存在的同名文件或符号链接
+fn put_symlink(dir: &Inode, name: &str, target: &str) -> std::io::Result<()> {
+    check_name(name)?;
+    if target.is_empty() || target.len() > SYMLINK_LENGTH_LIMIT as usize {
+        return Err(invalid_input(format!("{}: link target must be 1 to {} bytes", name, SYMLINK_LENGTH_LIMIT)));
+    }
+    match dir.find_inode(name) {
+        Some(inode) if inode.is_dir() => {
+            return Err(invalid_input(format!("{}: is a directory in the image", name)));
+        }
+        Some(_) if !dir.unlink(name) => {
+            return Err(Error::other(format!("{}: cannot remove existing file", name)));
+        }
+        Some(_) => {}
+        None => {}
+    }
+    dir.create_symlink(name, target)
+        .map(|_| ())
+        .ok_or_else(|| Error::other(format!("{}: image is full", name)))
+}
+
+/// 获取 dir 中的子目录 name，不存在时创建
+fn make_dir(dir: &Inode, name: &str) -> std::io::Result<Arc<Inode>> {
+    check_name(name)?;
+    match dir.find_inode(name) {
+        Some(inode) if inode.is_dir() => Ok(inode),
+        Some(_) => Err(invalid_input(format!("{}: is a file in the image", name))),
+        None => dir.create_dir(name).ok_or_else(|| Error::other(format!("{}: out of inodes", name))),
+    }
+}
+
+/// 递归地把主机目录 host 的内容复制到 dir 中，符号链接原样复制 (不跟随)
+fn copy_dir(dir: &Inode, host: &Path) -> std::io::Result<()> {
+    for (name, path) in sorted_entries(host)? {
+        if path.is_symlink() {
+            let target = read_link(&path)?;
+            let target = target.to_str()
+                .ok_or_else(|| invalid_input(format!("{}: link target is not UTF-8", path.display())))?;
+            put_symlink(dir, &name, target)?;
+        } else if path.is_dir() {
+            copy_dir(&*make_dir(dir, &name)?, &path)?;
+        } else if path.is_file() {
+            put_file(dir, &name, &path)?;
+        } else {
+            eprintln!("skipping {}: not a regular file or directory", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn apply_manifest(efs: &Arc<spin::Mutex<EasyFileSystem>>, manifest: &Path) -> std::io::Result<()> {
+    let mut content = String::new();
+    File::open(manifest)?.read_to_string(&mut content)?;
+    // 主机上的相对路径相对于清单文件所在目录
+    let base = manifest.parent().unwrap_or(Path::new("."));
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (host, target, link) = match fields.as_slice() {
+            [target, "->", link] if target.starts_with('/') => (PathBuf::new(), *target, Some(*link)),
+            [host, target] if target.starts_with('/') => (base.join(host), *target, None),
+            _ => {
+                return Err(invalid_input(format!(
+                    "{}:{}: expected `<host path> <absolute image path>` or `<absolute image path> -> <link target>`",
+                    manifest.display(),
+                    line_no + 1
+                )));
+            }
+        };
+        // 逐级创建父目录
+        let mut names: Vec<&str> = target.split('/').filter(|name| !name.is_empty()).collect();
+        let last = names.pop();
+        let mut dir = EasyFileSystem::root_inode(efs);
+        for name in names {
+            dir = make_dir(&dir, name)?;
+        }
+        match (last, link) {
+            (Some(name), Some(link)) => put_symlink(&dir, name, link)?,
+            (Some(name), None) if host.is_dir() => copy_dir(&*make_dir(&dir, name)?, &host)?,
+            (Some(name), None) => put_file(&dir, name, &host)?,
+            (None, None) if host.is_dir() => copy_dir(&dir, &host)?,
+            (None, _) => return Err(invalid_input(format!("{}:{}: cannot replace the root directory", manifest.display(), line_no + 1))),
+        }
+    }
+    Ok(())
+}
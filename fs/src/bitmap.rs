@@ -24,6 +24,12 @@ type BitmapBlock = [u64; 64]; // 将位图区域中的一个磁盘块解释为
 每个 u64(每一位对应一个块的使用状态):
 [bit_0][bit_1][bit_2]...[bit_63]
  */
+/// 将位图中的位置分解为 (块位置, 块内第几个 u64, u64 内第几个位)
+fn decomposition(pos: usize) -> (usize, usize, usize) {
+    let bit_in_block = pos % BLOCK_BITS;
+    (pos / BLOCK_BITS, bit_in_block / 64, bit_in_block % 64)
+}
+
 /// 内存中的位图结构体
 pub struct Bitmap {
     start_block_id: usize,
@@ -39,45 +45,129 @@ impl Bitmap {
         }
     }
     // ----- methods -----
-    /// 从位图中分配一位（一个空闲块）
-    pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>) -> Option<usize> {
-        for index in 0..self.block_num {
-            let block_cache = get_block_cache(
-                index + self.start_block_id,
-                block_device.clone()
-            );
-            // 取出并修改，视作 BitmapBlock
-            let pos = block_cache.lock().modify(
-                0, 
-                |bitmap_block: &mut BitmapBlock| {
-                let mut result = None;
-                // 遍历位图块中的每个 u64
-                for bits64_pos in 0..bitmap_block.len() {
-                    let bits64 = bitmap_block[bits64_pos];
-
-                    // 如果找到一个非满的 u64
-                    if bits64 != u64::MAX {
-                        // 计算第一个为 0 的位的位置
-                        let inner_pos = bits64.trailing_ones() as usize;
-
-                        // 修改位图，将该位置为 1
-                        bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+    /// 从位图中分配一位（一个空闲块），只考虑 limit 之前的位
+    /// 从 goal 开始向后查找 (next-fit)，到 limit 后再从头查找到 goal
+    pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>, goal: usize, limit: usize) -> Option<usize> {
+        let limit = limit.min(self.maximum());
+        let goal = if goal < limit { goal } else { 0 };
+        let pos = self.find_free(block_device, goal, limit)
+            .or_else(|| self.find_free(block_device, 0, goal))?;
+        self.set(block_device, pos, true);
+        Some(pos)
+    }
 
-                        // 计算全局位图中的位置
-                        let pos = index * BLOCK_BITS + bits64_pos * 64 + inner_pos;
-                        result = Some(pos);
+    /// [from, to) 中第一个空闲位，只读取位图
+    fn find_free(&self, block_device: &Arc<dyn BlockDevice>, from: usize, to: usize) -> Option<usize> {
+        let mut pos = from;
+        while pos < to {
+            let (block_pos, _, _) = decomposition(pos);
+            let block_end = to.min((block_pos + 1) * BLOCK_BITS);
+            let found = get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |bitmap_block: &BitmapBlock| {
+                    while pos < block_end {
+                        let (_, bits64_pos, inner_pos) = decomposition(pos);
+                        // 忽略 pos 之前的位，整个 u64 已满时直接跳过
+                        let bits64 = bitmap_block[bits64_pos] | ((1u64 << inner_pos) - 1);
+                        if bits64 != u64::MAX {
+                            let free = pos - inner_pos + bits64.trailing_ones() as usize;
+                            return (free < block_end).then_some(free);
+                        }
+                        pos += 64 - inner_pos;
+                    }
+                    None
+                });
+            if found.is_some() {
+                return found;
+            }
+            pos = block_end;
+        }
+        None
+    }
 
-                        break;
+    /// 从 pos 开始分配至多 len 个连续的空闲位 (不超过 limit)，返回分配的位数，pos 已被占用时为 0
+    /// 用于在文件最后一个块之后继续分配
+    pub fn alloc_at(&self, block_device: &Arc<dyn BlockDevice>, pos: usize, len: usize, limit: usize) -> usize {
+        let end = (pos + len).min(limit.min(self.maximum()));
+        let mut count = 0;
+        while pos + count < end && !self.is_set(block_device, pos + count) {
+            count += 1;
+        }
+        self.set_range(block_device, pos, count);
+        count
+    }
+
+    /// 分配一段连续的空闲位，返回 (起始位置, 位数)，只考虑 limit 之前的位
+    /// 从 goal 开始向后、再从头查找第一段不短于 len 的空闲位 (只分配其中的 len 位)，没有时选择最长的一段
+    pub fn alloc_contiguous(&self, block_device: &Arc<dyn BlockDevice>, goal: usize, len: usize, limit: usize) -> Option<(usize, usize)> {
+        if len == 0 {
+            return None;
+        }
+        let limit = limit.min(self.maximum());
+        let goal = if goal < limit { goal } else { 0 };
+        let mut best: Option<(usize, usize)> = None;
+        if !self.find_run(block_device, goal, limit, len, &mut best) {
+            self.find_run(block_device, 0, goal, len, &mut best);
+        }
+        let (start, len) = best?;
+        self.set_range(block_device, start, len);
+        Some((start, len))
+    }
+
+    /// 在 [from, to) 中查找长度为 len 的空闲位，找到时返回 true；best 记录目前为止最长的一段
+    fn find_run(&self, block_device: &Arc<dyn BlockDevice>, from: usize, to: usize, len: usize, best: &mut Option<(usize, usize)>) -> bool {
+        let (mut run_start, mut run_len) = (0, 0);
+        let mut pos = from;
+        while pos < to {
+            let (block_pos, _, _) = decomposition(pos);
+            let bitmap_block = get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |bitmap_block: &BitmapBlock| *bitmap_block);
+            let block_end = to.min((block_pos + 1) * BLOCK_BITS);
+            while pos < block_end {
+                let (_, bits64_pos, inner_pos) = decomposition(pos);
+                let bits64 = bitmap_block[bits64_pos];
+                if bits64 == u64::MAX {
+                    run_len = 0;
+                    pos += 64 - inner_pos;
+                    continue;
+                }
+                if bits64 & (1u64 << inner_pos) != 0 {
+                    run_len = 0;
+                } else {
+                    if run_len == 0 {
+                        run_start = pos;
+                    }
+                    run_len += 1;
+                    if best.is_none_or(|(_, best_len)| run_len > best_len) {
+                        *best = Some((run_start, run_len));
+                    }
+                    if run_len == len {
+                        return true;
                     }
                 }
-                result
-            });
-            // 如果找到了可用位置，则返回
-            if pos.is_some() {
-                return pos;
+                pos += 1;
             }
         }
-        None
+        false
+    }
+
+    /// 将 [start, start + len) 置位，按位图块分组
+    fn set_range(&self, block_device: &Arc<dyn BlockDevice>, start: usize, len: usize) {
+        let mut pos = start;
+        while pos < start + len {
+            let block_pos = pos / BLOCK_BITS;
+            let end = (start + len).min((block_pos + 1) * BLOCK_BITS);
+            get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .modify(0, |bitmap_block: &mut BitmapBlock| {
+                    for bit in pos..end {
+                        let (_, bits64_pos, inner_pos) = decomposition(bit);
+                        bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                    }
+                });
+            pos = end;
+        }
     }
 
     /// 释放位图中的指定位
@@ -107,7 +197,42 @@ impl Bitmap {
                 bitmap_block[bits64_pos] &= !bit_mask;
             });
     }
-    
+
+    /// 位图中指定位是否已被分配
+    pub fn is_set(&self, block_device: &Arc<dyn BlockDevice>, pos: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(pos);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block[bits64_pos] & (1u64 << inner_pos) != 0
+            })
+    }
+
+    /// 直接设置位图中的指定位，不检查原来的状态 (供 fsck 修复位图使用)
+    pub fn set(&self, block_device: &Arc<dyn BlockDevice>, pos: usize, value: bool) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(pos);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                if value {
+                    bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                } else {
+                    bitmap_block[bits64_pos] &= !(1u64 << inner_pos);
+                }
+            });
+    }
+
+    /// 位图中已分配的位数
+    pub fn count_allocated(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        (0..self.block_num).map(|index| {
+            get_block_cache(index + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |bitmap_block: &BitmapBlock| {
+                    bitmap_block.iter().map(|bits64| bits64.count_ones() as usize).sum::<usize>()
+                })
+        }).sum()
+    }
+
     /// 获取位图表示数据的最大数量
     pub fn maximum(&self) -> usize {
         self.block_num * BLOCK_BITS
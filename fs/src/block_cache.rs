@@ -2,9 +2,12 @@
 
 use alloc::sync::Arc;
 use crate::block_dev::BlockDevice;
+use crate::checksum::{crc32c, table_block_checksum, ChecksumBlock, ChecksumTable};
 use crate::config::BLOCK_SIZE;
 
 const BLOCK_CACHE_SIZE: usize = 16;
+/// 一次预读最多读入的块数，只占缓存的一半，预读的块不会在被用到之前就被后读入的块替换掉
+pub const MAX_PREFETCH: usize = BLOCK_CACHE_SIZE / 2;
 
 // ----- BlockCache -----
 // 位于内存缓存中，因此可使用 usize
@@ -13,20 +16,45 @@ pub struct BlockCache {
     block_id: usize,                    // 块的ID
     block_device: Arc<dyn BlockDevice>, // 块设备的引用
     modified: bool,                     // 是否被修改过
+    metadata: bool,                     // 数据区中的元数据块 (索引块)，提交时计算校验和
+    corrupted: bool,                    // 加载时校验和不符，之后每次访问都会记录 (见 take_checksum_errors)
+}
+
+/// 块缓存加载时的校验方式 (见 checksum.rs)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verify {
+    None,       // 不检查
+    Entry(u32), // 与校验表中记录的值比较，0 表示没有记录
+    TableBlock, // 校验表块，检查最后一项
 }
 
 impl BlockCache {
     // ----- constructor -----
     // load a new BlockCache from disk.
-    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
+    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>, verify: Verify) -> Self {
         let mut cache = [0u8; BLOCK_SIZE as usize];
         block_device.read_block(block_id, &mut cache);
-        Self {
+        Self::with_data(block_id, block_device, cache, verify)
+    }
+    /// 用已经从磁盘读出的内容创建 (预读)
+    pub fn with_data(block_id: usize, block_device: Arc<dyn BlockDevice>, cache: [u8; BLOCK_SIZE as usize], verify: Verify)
+        -> Self {
+        let mut block_cache = Self {
             cache,
             block_id,
             block_device,
             modified: false,
-        }
+            metadata: matches!(verify, Verify::Entry(sum) if sum != 0),
+            corrupted: false,
+        };
+        block_cache.corrupted = match verify {
+            Verify::None | Verify::Entry(0) => false,
+            Verify::Entry(sum) => crc32c(&block_cache.cache) != sum,
+            Verify::TableBlock => block_cache.read(0, |block: &ChecksumBlock| {
+                table_block_checksum(block) != block[block.len() - 1]
+            }),
+        };
+        block_cache
     }
     // ----- methods -----
     /// get the memory address of an offset inside the cached block data
@@ -50,6 +78,49 @@ impl BlockCache {
         unsafe { &mut *(addr as *mut T) }
     }
 
+    pub fn block_id(&self) -> usize {
+        self.block_id
+    }
+    /// 是否有尚未写回块设备的修改
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+    /// 整个块的数据
+    pub fn data(&self) -> &[u8; BLOCK_SIZE as usize] {
+        &self.cache
+    }
+    /// 加载时校验和是否不符；提交时不会为损坏的块更新校验和，直到它被整块覆盖或由 fsck 修复
+    pub fn is_corrupted(&self) -> bool {
+        self.corrupted
+    }
+    pub fn is_metadata(&self) -> bool {
+        self.metadata
+    }
+    /// 标记为元数据块 (索引块)，提交时为它计算校验和
+    pub fn set_metadata(&mut self) {
+        self.metadata = true;
+    }
+    /// 接受当前内容 (fsck 修复时): 不再视为损坏，并作为有校验和的块在提交时重新计算
+    pub fn accept(&mut self) {
+        self.modified = true;
+        self.metadata = true;
+        self.corrupted = false;
+    }
+    /// 整块清零 (新分配的块)，原来的内容与标记都不再有效
+    pub fn clear(&mut self) {
+        self.cache.fill(0);
+        self.modified = true;
+        self.metadata = false;
+        self.corrupted = false;
+    }
+
+    /// 重新从块设备读取 (块被绕过缓存改写后)，未写回的修改被丢弃
+    pub fn reload(&mut self) {
+        self.block_device.read_block(self.block_id, &mut self.cache);
+        self.modified = false;
+        self.corrupted = false;
+    }
+
     /// 将块缓存同步到硬盘
     pub fn sync(&mut self) {
         if self.modified {
@@ -65,13 +136,24 @@ impl BlockCache {
     /// `f` is a closure that takes a reference to `T` and returns `V`.
     /// return the result of the closure
     pub fn read<T, V>(&self, offset: usize, f: impl FnOnce(&T) -> V) -> V {
+        self.check();
         f(self.get_ref(offset))
     }
     /// interface for modifying data
     /// return the result of the closure
     pub fn modify<T, V>(&mut self, offset: usize, f: impl FnOnce(&mut T) -> V) -> V {
+        self.check();
         f(self.get_mut(offset))
     }
+    /// 访问损坏的块时记录下来，由读写它的操作取出并报告 (预读等没有被访问的块不记录)
+    fn check(&self) {
+        if self.corrupted {
+            let mut errors = CHECKSUM_ERRORS.lock();
+            if !errors.iter().any(|(id, device)| *id == self.block_id && Arc::ptr_eq(device, &self.block_device)) {
+                errors.push((self.block_id, Arc::clone(&self.block_device)));
+            }
+        }
+    }
 }
 
 impl Drop for BlockCache {
@@ -82,68 +164,142 @@ impl Drop for BlockCache {
 
 // ----- BlockCacheManager -----
 
+use alloc::vec;
 use alloc::vec::Vec;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
+/// 启用了校验和的块设备
+struct DeviceChecksums {
+    block_device: Arc<dyn BlockDevice>,
+    table: ChecksumTable,
+}
+
+/// 队列中的一项: (块号, 块设备, 缓存)
+type CacheEntry = (usize, Arc<dyn BlockDevice>, Arc<Mutex<BlockCache>>);
+
 pub struct BlockCacheManager {
-    queue: Vec<(usize, Arc<Mutex<BlockCache>>)>,
+    queue: Vec<CacheEntry>,
+    checksums: Vec<DeviceChecksums>,
 }
 
 impl BlockCacheManager {
     // ----- constructor -----
     pub fn new() -> Self {
-        Self { queue: Vec::new() }
+        Self { queue: Vec::new(), checksums: Vec::new() }
     }
     // ----- methods -----
     /// 获取指定块ID的缓存，如果不存在则创建新的缓存
     pub fn get_block_cache(&mut self, block_id: usize, block_device: Arc<dyn BlockDevice>)
         -> Arc<Mutex<BlockCache>> {
-        // 在现有缓存中查找指定块ID
-        let mut existing_cache = None;
-        for pair in &self.queue {
-            if pair.0 == block_id {
-                existing_cache = Some(Arc::clone(&pair.1));
-                break;
+        if let Some(cache) = self.find(block_id, &block_device) {
+            return cache; // 找到缓存块，直接返回
+        }
+        let mut data = [0u8; BLOCK_SIZE as usize];
+        block_device.read_block(block_id, &mut data);
+        self.insert(block_id, block_device, data)
+    }
+
+    /// 预读: 把从 block_id 开始的 count 个块中不在缓存里的块读入缓存，连续的一段用一次 read_blocks 读取
+    pub fn prefetch(&mut self, block_id: usize, count: usize, block_device: &Arc<dyn BlockDevice>) {
+        let end = block_id + count.min(MAX_PREFETCH);
+        let mut start = block_id;
+        while start < end {
+            if self.find(start, block_device).is_some() {
+                start += 1;
+                continue;
             }
+            let mut run_end = start + 1;
+            while run_end < end && self.find(run_end, block_device).is_none() {
+                run_end += 1;
+            }
+            let mut data = vec![0u8; (run_end - start) * BLOCK_SIZE as usize];
+            block_device.read_blocks(start, &mut data);
+            for (i, block) in data.chunks_exact(BLOCK_SIZE as usize).enumerate() {
+                self.insert(start + i, Arc::clone(block_device), block.try_into().unwrap());
+            }
+            start = run_end;
         }
-        if let Some(cache) = existing_cache {
-            return cache; // 找到缓存块，直接返回
+    }
+
+    /// 块设备上的块被绕过缓存直接改写后调用，丢弃缓存中的旧内容
+    /// 只被管理器引用的缓存移出队列，下次访问时重新加载 (并校验)；仍被引用的缓存就地重新读取
+    pub fn invalidate(&mut self, block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+        let Some(idx) = self.queue.iter()
+            .position(|(id, device, _)| *id == block_id && Arc::ptr_eq(device, block_device)) else {
+            return;
+        };
+        if Arc::strong_count(&self.queue[idx].2) == 1 {
+            self.queue.remove(idx);
+        } else {
+            self.queue[idx].2.lock().reload();
         }
+    }
+
+    /// 在现有缓存中查找指定块ID
+    fn find(&self, block_id: usize, block_device: &Arc<dyn BlockDevice>) -> Option<Arc<Mutex<BlockCache>>> {
+        // 多个块设备共享同一个缓存管理器，需同时比较块设备
+        self.queue.iter()
+            .find(|(id, device, _)| *id == block_id && Arc::ptr_eq(device, block_device))
+            .map(|(_, _, cache)| Arc::clone(cache))
+    }
 
+    /// 为刚从磁盘读出的块创建缓存并加入队列
+    fn insert(&mut self, block_id: usize, block_device: Arc<dyn BlockDevice>, data: [u8; BLOCK_SIZE as usize])
+        -> Arc<Mutex<BlockCache>> {
         // 如果缓存已满，进行替换
-        if self.queue.len() == BLOCK_CACHE_SIZE {
-            // 查找只有一个强引用(只被管理器引用)的缓存
-            let mut victim_idx = None;
-            for i in 0..self.queue.len() {
-                if Arc::strong_count(&self.queue[i].1) == 1 {
-                    victim_idx = Some(i);
-                    break;
-                }
-            }
-            if let Some(idx) = victim_idx {
-                self.queue.remove(idx);
-            } else {
-                // 所有缓存都有多个引用，无法释放
-                panic!("Run out of BlockCache!");
+        // 只替换没有被修改过、且只被管理器引用的缓存：被修改过的块属于尚未提交的事务，
+        // 必须等到 commit 时先写入日志再写回原位，不能在替换时提前写回
+        // 没有可替换的缓存时允许暂时超出 BLOCK_CACHE_SIZE，事务提交后再逐步收缩
+        while self.queue.len() >= BLOCK_CACHE_SIZE {
+            let victim_idx = self.queue.iter().position(|(_, _, cache)| {
+                Arc::strong_count(cache) == 1 && cache.try_lock().is_some_and(|cache| !cache.is_modified())
+            });
+            match victim_idx {
+                Some(idx) => { self.queue.remove(idx); }
+                None => break,
             }
         }
 
         // 创建和添加新的缓存
-        let block_cache = Arc::new(Mutex::new(BlockCache::new(
-            block_id,
-            Arc::clone(&block_device),
-        )));
-        self.queue.push((block_id, Arc::clone(&block_cache)));
+        let verify = self.verify_mode(block_id, &block_device);
+        let block_cache = Arc::new(Mutex::new(BlockCache::with_data(block_id, Arc::clone(&block_device), data, verify)));
+        self.queue.push((block_id, block_device, Arc::clone(&block_cache)));
 
         block_cache
     }
+
+    fn device_checksums(&mut self, block_device: &Arc<dyn BlockDevice>) -> Option<&mut DeviceChecksums> {
+        self.checksums.iter_mut().find(|checksums| Arc::ptr_eq(&checksums.block_device, block_device))
+    }
+
+    /// 加载块时的校验方式，需要时从校验表中读出记录的校验和
+    fn verify_mode(&mut self, block_id: usize, block_device: &Arc<dyn BlockDevice>) -> Verify {
+        let Some(table) = self.device_checksums(block_device).map(|checksums| checksums.table) else {
+            return Verify::None;
+        };
+        if table.is_table_block(block_id) {
+            return Verify::TableBlock;
+        }
+        let Some((table_block, index)) = table.locate(block_id) else {
+            return Verify::None;
+        };
+        let table_cache = self.get_block_cache(table_block, Arc::clone(block_device));
+        let table_cache = table_cache.lock();
+        if table_cache.is_corrupted() {
+            return Verify::None; // 校验表块本身损坏，其中的记录不可信
+        }
+        Verify::Entry(table_cache.read(0, |block: &ChecksumBlock| block[index]))
+    }
 }
 
 lazy_static! {
     /// 全局块缓存管理器实例，使用 Mutex 保护
     pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> =
         Mutex::new(BlockCacheManager::new());
+    /// 访问到的校验和不符的块 (块号, 块设备)，尚未取出
+    /// 与管理器分开加锁: 持有块缓存的锁时也要记录，而管理器在持有自身的锁时会去获取块缓存的锁
+    static ref CHECKSUM_ERRORS: Mutex<Vec<(usize, Arc<dyn BlockDevice>)>> = Mutex::new(Vec::new());
 }
 
 /// 获取指定块ID的块缓存
@@ -151,11 +307,76 @@ pub fn get_block_cache(block_id: usize, block_device: Arc<dyn BlockDevice>) -> A
     BLOCK_CACHE_MANAGER.lock().get_block_cache(block_id, block_device)
 }
 
+/// 预读从 block_id 开始的 count 个块 (最多 MAX_PREFETCH 个)，见 `BlockCacheManager::prefetch`
+pub fn prefetch_block_caches(block_id: usize, count: usize, block_device: &Arc<dyn BlockDevice>) {
+    BLOCK_CACHE_MANAGER.lock().prefetch(block_id, count, block_device);
+}
+
+/// 块是否已经在缓存中
+pub fn is_block_cached(block_id: usize, block_device: &Arc<dyn BlockDevice>) -> bool {
+    BLOCK_CACHE_MANAGER.lock().find(block_id, block_device).is_some()
+}
+
+/// 块被绕过缓存直接写入块设备后调用，见 `BlockCacheManager::invalidate`
+/// 调用前应先提交文件系统的修改，该块未写回的修改会被丢弃
+pub fn invalidate_block_cache(block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+    BLOCK_CACHE_MANAGER.lock().invalidate(block_id, block_device);
+}
+
+/// 为块设备启用校验和: 之后从该设备加载的块都会与校验表比较
+pub fn register_checksums(block_device: &Arc<dyn BlockDevice>, table: ChecksumTable) {
+    let mut manager = BLOCK_CACHE_MANAGER.lock();
+    match manager.device_checksums(block_device) {
+        Some(checksums) => checksums.table = table,
+        None => manager.checksums.push(DeviceChecksums {
+            block_device: Arc::clone(block_device),
+            table,
+        }),
+    }
+}
+
+/// 取出块设备上自上次调用以来访问到的校验和不符的块号 (同一个块只出现一次)
+pub fn take_checksum_errors(block_device: &Arc<dyn BlockDevice>) -> Vec<usize> {
+    let mut errors = CHECKSUM_ERRORS.lock();
+    let mut taken = Vec::new();
+    errors.retain(|(block_id, device)| {
+        let matched = Arc::ptr_eq(device, block_device);
+        if matched {
+            taken.push(*block_id);
+        }
+        !matched
+    });
+    taken
+}
+
+/// 某个块设备上的所有块缓存
+/// 先释放管理器的锁再逐个检查: 持有块缓存的锁时可能会调用 get_block_cache，反过来加锁会死锁
+fn device_block_caches(block_device: &Arc<dyn BlockDevice>) -> Vec<Arc<Mutex<BlockCache>>> {
+    BLOCK_CACHE_MANAGER.lock().queue.iter()
+        .filter(|(_, device, _)| Arc::ptr_eq(device, block_device))
+        .map(|(_, _, cache)| Arc::clone(cache))
+        .collect()
+}
+
+/// 某个块设备上所有被修改过、尚未写回的块缓存
+pub fn dirty_block_caches(block_device: &Arc<dyn BlockDevice>) -> Vec<Arc<Mutex<BlockCache>>> {
+    device_block_caches(block_device).into_iter()
+        .filter(|cache| cache.lock().is_modified())
+        .collect()
+}
+
+/// 某个块设备上被修改过、尚未写回的块数
+pub fn dirty_block_count(block_device: &Arc<dyn BlockDevice>) -> usize {
+    device_block_caches(block_device).iter()
+        .filter(|cache| cache.lock().is_modified())
+        .count()
+}
+
 /// 将所有块缓存同步到块设备
 pub fn block_cache_sync_all() {
     // 获取管理器锁
     let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
+    for (_, _, cache) in manager.queue.iter() {
         cache.lock().sync();
     }
 }
\ No newline at end of file
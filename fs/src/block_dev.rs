@@ -1,6 +1,7 @@
 // fs/src/block_dev.rs
 
 use core::any::Any;
+use crate::config::BLOCK_SIZE;
 
 /// Trait for block devices
 pub trait BlockDevice : Send + Sync + Any {
@@ -8,4 +9,11 @@ pub trait BlockDevice : Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     /// write a block from buffer to block
     fn write_block(&self, block_id: usize, buf: &[u8]);
-}
\ No newline at end of file
+    /// 从 block_id 开始连续读取 buf.len() / BLOCK_SIZE 个块 (预读时使用)
+    /// 默认逐块读取，能在一次请求中读取多个块的设备应覆盖这个实现
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        for (i, block) in buf.chunks_mut(BLOCK_SIZE as usize).enumerate() {
+            self.read_block(block_id + i, block);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// fs/src/checksum.rs
+
+use crate::config::BLOCK_SIZE;
+
+// ----- CRC32C -----
+
+/// CRC32C (Castagnoli) 的查找表，多项式 0x82F63B78 (反射形式)
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82F6_3B78 } else { crc >> 1 };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC32C_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+// ----- Checksum Table -----
+/*
+校验表位于日志区之前，为校验表之前的每个块 (0 号块除外) 记录一个 CRC32C:
+[校验表块0][校验表块1]...[校验表块n-1][日志区]
+
+每个校验表块: [entry_0][entry_1]...[entry_126][self]
+entry_i 为 (t * 127 + i) 号块的校验和，0 表示没有记录 (不校验)；self 为前 127 项的校验和
+SuperBlock 在自身的 checksum 字段中保存校验和，不使用校验表
+
+位图与 inode 区总是有校验和；数据区中只有索引块有校验和，开启数据校验时所有数据块都有
+修改过的块在事务提交时 (写入日志之前) 更新校验和，块缓存从磁盘加载时检查
+ */
+
+pub const CHECKSUMS_PER_BLOCK: u32 = BLOCK_SIZE / 4 - 1; // 127
+/// 校验表块，最后一项为前面各项的校验和
+pub type ChecksumBlock = [u32; (BLOCK_SIZE / 4) as usize];
+
+/// 新建文件系统时选择的校验范围
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChecksumMode {
+    None,     // 不使用校验和 (旧镜像)
+    Metadata, // SuperBlock、位图、inode 区与索引块
+    All,      // 此外还有所有数据块
+}
+
+/// 校验表块自身的校验和
+pub fn table_block_checksum(block: &ChecksumBlock) -> u32 {
+    let entries = &block[..CHECKSUMS_PER_BLOCK as usize];
+    crc32c(unsafe { core::slice::from_raw_parts(entries.as_ptr() as *const u8, entries.len() * 4) })
+}
+
+/// 内存中的校验表描述
+#[derive(Clone, Copy, Debug)]
+pub struct ChecksumTable {
+    start_block: u32,     // 校验表的起始块号，也是被校验的块的上界
+    blocks: u32,          // 校验表块数
+    data_area_start: u32, // 数据区的起始块号，之前的块 (位图与 inode 区) 总是有校验和
+    all: bool,            // 数据块是否也有校验和
+}
+
+impl ChecksumTable {
+    // ----- constructor -----
+    pub fn new(start_block: u32, blocks: u32, data_area_start: u32, all: bool) -> Self {
+        Self { start_block, blocks, data_area_start, all }
+    }
+    /// 校验表与它覆盖的块共 total 个时，校验表的块数
+    pub fn blocks_needed(total: u32) -> u32 {
+        // 校验表块数 n 满足 n * 127 >= total - n，即 n * 128 >= total
+        total.div_ceil(CHECKSUMS_PER_BLOCK + 1)
+    }
+    // ----- methods -----
+    pub fn start_block(&self) -> u32 {
+        self.start_block
+    }
+    pub fn blocks(&self) -> u32 {
+        self.blocks
+    }
+    pub fn is_table_block(&self, block_id: usize) -> bool {
+        (self.start_block as usize..(self.start_block + self.blocks) as usize).contains(&block_id)
+    }
+    /// 块的校验和在校验表中的位置 (校验表块号, 块内下标)，没有校验和的位置 (0 号块、校验表与日志区) 返回 None
+    pub fn locate(&self, block_id: usize) -> Option<(usize, usize)> {
+        if block_id == 0 || block_id >= self.start_block as usize {
+            return None;
+        }
+        let per_block = CHECKSUMS_PER_BLOCK as usize;
+        Some((self.start_block as usize + block_id / per_block, block_id % per_block))
+    }
+    /// 块是否总是有校验和 (位图与 inode 区，开启数据校验时还有数据区)
+    /// 其余的块只有被标记为元数据 (索引块) 时才有
+    pub fn always_covers(&self, block_id: usize) -> bool {
+        self.locate(block_id).is_some() && (self.all || block_id < self.data_area_start as usize)
+    }
+}
+
@@ -1,11 +1,14 @@
 // fs/src/config.rs
 
-pub const EFS_MAGIC: u32 = 0x3b800001;
+pub const EFS_MAGIC: u32 = 0x3b800003; // inode 扩展为 256 字节 (权限与属主) 后更新
+pub const JOURNAL_MAGIC: u32 = 0x4a524e4c; // "JRNL"
 pub const CACHE_SIZE: u32 = 512;
 pub const BLOCK_SIZE: u32 = 512;
-pub const INODE_SIZE: u32 = 32 * 4;
+pub const INODE_SIZE: u32 = 64 * 4;
 pub const DNODE_SIZE: u32 = 32 * 16;
 pub const INODE_PER_BLOCK: u32 = BLOCK_SIZE / INODE_SIZE;
+pub const JOURNAL_BLOCKS: u32 = 64; // 日志区块数 (含日志头)
+pub const PREALLOC_BLOCKS: u32 = 64; // 文件无法在原地增长时，在新位置之后为它预留的块数
 
 // inode & disk_inode
 pub(crate) const INODE_DIRECT_COUNT: u32 = 28;
@@ -0,0 +1,178 @@
+// fs/src/dir_index.rs
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::block_cache::get_block_cache;
+use crate::block_dev::BlockDevice;
+
+pub const DIR_INDEX_MAGIC: u32 = 0x58444944; // "DIDX"
+/// 目录达到这么多块时建立索引，更小的目录顺序查找即可
+pub const INDEX_MIN_BLOCKS: u32 = 4;
+/// 根块最多记录的桶数
+pub const MAX_BUCKETS: usize = 125;
+/// 每个桶最多记录的目录项数
+pub const BUCKET_CAPACITY: usize = 63;
+
+// ----- Directory Index -----
+/*
+目录块 0 的第一条记录是索引记录 (见 disk_inode.rs)，其 inode_number 字段保存索引根块的块号，0 表示没有索引
+根块: [magic][buckets][entries][桶 0 的块号]...[桶 buckets-1 的块号]
+桶:   [count][reserved][(hash, location)] * count
+名字的哈希值 hash 决定桶 (hash % buckets)，location 是目录项在目录中的字节偏移
+目录项的位置在插入后不会改变 (删除时并入前一项，前一项不移动)，因此索引只需随 create / unlink 增删一项
+
+索引根块的 magic 写入之前索引不可用，此时按顺序查找；重建分多个事务完成，中途崩溃只会留下不可用的索引
+没有索引记录的目录 (旧镜像) 始终按顺序查找
+ */
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IndexRoot {
+    pub magic: u32,
+    pub buckets: u32,
+    pub entries: u32,
+    pub bucket_blocks: [u32; MAX_BUCKETS],
+}
+
+impl IndexRoot {
+    /// 索引是否可用 (重建完成)
+    pub fn is_valid(&self) -> bool {
+        self.magic == DIR_INDEX_MAGIC && (1..=MAX_BUCKETS as u32).contains(&self.buckets)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Bucket {
+    pub count: u32,
+    reserved: u32,
+    pub slots: [(u32, u32); BUCKET_CAPACITY], // (hash, location)
+}
+
+impl Bucket {
+    pub fn new() -> Self {
+        Self { count: 0, reserved: 0, slots: [(0, 0); BUCKET_CAPACITY] }
+    }
+    pub fn slots(&self) -> &[(u32, u32)] {
+        &self.slots[..(self.count as usize).min(BUCKET_CAPACITY)]
+    }
+    /// 桶已满时返回 false
+    pub fn push(&mut self, hash: u32, location: u32) -> bool {
+        if self.count as usize >= BUCKET_CAPACITY {
+            return false;
+        }
+        self.slots[self.count as usize] = (hash, location);
+        self.count += 1;
+        true
+    }
+}
+
+// FNV-1a
+pub fn name_hash(name: &[u8]) -> u32 {
+    name.iter().fold(0x811c_9dc5, |hash: u32, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// 把 (hash, location) 分配到 buckets 个桶中，有桶溢出时返回 None
+pub fn distribute(entries: &[(u32, u32)], buckets: usize) -> Option<Vec<Bucket>> {
+    let mut result = alloc::vec![Bucket::new(); buckets];
+    for (hash, location) in entries {
+        if !result[*hash as usize % buckets].push(*hash, *location) {
+            return None;
+        }
+    }
+    Some(result)
+}
+
+/// 磁盘上的目录索引，root 为根块号
+pub struct DirIndex<'a> {
+    root: u32,
+    block_device: &'a Arc<dyn BlockDevice>,
+}
+
+impl<'a> DirIndex<'a> {
+    // ----- constructor -----
+    pub fn new(root: u32, block_device: &'a Arc<dyn BlockDevice>) -> Self {
+        Self { root, block_device }
+    }
+
+    // ----- methods -----
+    pub fn read_root(&self) -> IndexRoot {
+        get_block_cache(self.root as usize, Arc::clone(self.block_device)).lock().read(0, |root: &IndexRoot| *root)
+    }
+
+    fn modify_root<V>(&self, f: impl FnOnce(&mut IndexRoot) -> V) -> V {
+        get_block_cache(self.root as usize, Arc::clone(self.block_device)).lock().modify(0, f)
+    }
+
+    /// 初始化新分配的 (已清零的) 根块，写入 magic 之前索引不可用
+    pub fn initialize(&self, buckets: u32, entries: u32) {
+        self.modify_root(|root| {
+            root.buckets = buckets;
+            root.entries = entries;
+        });
+    }
+
+    pub fn set_bucket_block(&self, bucket: usize, block: u32) {
+        self.modify_root(|root| root.bucket_blocks[bucket] = block);
+    }
+
+    pub fn set_valid(&self) {
+        self.modify_root(|root| root.magic = DIR_INDEX_MAGIC);
+    }
+
+    /// 哈希值为 hash 的桶的块号，索引不可用时返回 None
+    fn bucket_block(&self, hash: u32) -> Option<u32> {
+        let root = self.read_root();
+        if !root.is_valid() {
+            return None;
+        }
+        // 损坏的索引中桶的块号可能为 0，此时不能把 SuperBlock 当作桶
+        Some(root.bucket_blocks[hash as usize % root.buckets as usize]).filter(|block| *block != 0)
+    }
+
+    /// 哈希值为 hash 的所有目录项的位置，索引不可用时返回 None
+    pub fn lookup(&self, hash: u32) -> Option<Vec<u32>> {
+        let block = self.bucket_block(hash)?;
+        Some(get_block_cache(block as usize, Arc::clone(self.block_device)).lock().read(0, |bucket: &Bucket| {
+            bucket.slots().iter().filter(|(h, _)| *h == hash).map(|(_, location)| *location).collect()
+        }))
+    }
+
+    /// 记录一个目录项，索引不可用或桶已满时返回 false (需要重建)
+    pub fn insert(&self, hash: u32, location: u32) -> bool {
+        let Some(block) = self.bucket_block(hash) else {
+            return false;
+        };
+        let inserted = get_block_cache(block as usize, Arc::clone(self.block_device)).lock()
+            .modify(0, |bucket: &mut Bucket| bucket.push(hash, location));
+        if inserted {
+            self.modify_root(|root| root.entries += 1);
+        }
+        inserted
+    }
+
+    /// 删除一个目录项的记录，返回剩余的目录项数；索引不可用时返回 None
+    pub fn remove(&self, hash: u32, location: u32) -> Option<u32> {
+        let block = self.bucket_block(hash)?;
+        let removed = get_block_cache(block as usize, Arc::clone(self.block_device)).lock()
+            .modify(0, |bucket: &mut Bucket| {
+                let count = bucket.slots().len();
+                let pos = bucket.slots().iter().position(|slot| *slot == (hash, location))?;
+                bucket.slots[pos] = bucket.slots[count - 1];
+                bucket.count -= 1;
+                Some(())
+            });
+        removed?;
+        Some(self.modify_root(|root| {
+            root.entries = root.entries.saturating_sub(1);
+            root.entries
+        }))
+    }
+
+    /// 索引占用的所有块 (根块与已分配的桶)
+    pub fn blocks(&self) -> Vec<u32> {
+        let mut blocks = alloc::vec![self.root];
+        blocks.extend(self.read_root().bucket_blocks.iter().filter(|block| **block != 0));
+        blocks
+    }
+}
@@ -2,56 +2,213 @@
 
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use crate::block_cache::get_block_cache;
+use crate::block_cache::{get_block_cache, is_block_cached, prefetch_block_caches, BlockCache, MAX_PREFETCH};
 use crate::block_dev::BlockDevice;
-use crate::config::BLOCK_SIZE;
+use crate::config::{BLOCK_SIZE, INODE_SIZE};
+use crate::extent::{ExtentRoot, ExtentTree};
+use crate::time::{Times, Timestamp};
+use spin::Mutex;
 
 use crate::config::{INODE_DIRECT_COUNT, INODE_INDIRECT1_COUNT, INODE_INDIRECT2_COUNT};
 
 pub type DataBlock = [u8; BLOCK_SIZE as usize];
+
+/// 直接索引、一级索引、二级索引能表示的最大文件大小
+pub const MAX_FILE_SIZE: usize =
+    (INODE_DIRECT_COUNT + INODE_INDIRECT1_COUNT + INODE_INDIRECT2_COUNT) as usize * BLOCK_SIZE as usize;
+/// 使用 extent 树的 inode 的最大文件大小 (size 字段是 u32)
+pub const MAX_EXTENT_FILE_SIZE: usize = (u32::MAX / BLOCK_SIZE * BLOCK_SIZE) as usize;
 pub type IndirectBlock = [u32; (BLOCK_SIZE / 4) as usize];
 
+/// 修改索引块时使用: 把块标记为元数据，提交时为它计算校验和
+pub(crate) fn indirect_block_cache(block_id: u32, block_device: &Arc<dyn BlockDevice>) -> Arc<Mutex<BlockCache>> {
+    let block_cache = get_block_cache(block_id as usize, Arc::clone(block_device));
+    block_cache.lock().set_metadata();
+    block_cache
+}
+/// 不超过这个长度的符号链接目标直接保存在 direct 数组中，不占用数据块 (同 ext2 的 fast symlink)
+pub const INLINE_SYMLINK_MAX: u32 = INODE_DIRECT_COUNT * 4;
+/// 符号链接目标的最大长度 (PATH_MAX - 1)
+pub const SYMLINK_LENGTH_LIMIT: u32 = 4095;
+/// DiskInode 末尾保留的字，供以后增加字段
+const INODE_RESERVED_WORDS: usize = 18;
+/// flags 中的标志位: direct / indirect1 / indirect2 保存 extent 树的根节点 (见 extent.rs)
+const INODE_FLAG_EXTENTS: u32 = 1;
+
 // ----- Disk Inode -----
 
 #[repr(u32)] // should be u32
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub enum DiskInodeType {
     File,
     Directory,
+    Symlink,
+}
+
+impl TryFrom<u32> for DiskInodeType {
+    type Error = u32;
+    /// 磁盘上 type_ 字段的值，不合法 (只可能来自损坏的镜像) 时返回原值
+    fn try_from(raw: u32) -> Result<Self, u32> {
+        match raw {
+            0 => Ok(DiskInodeType::File),
+            1 => Ok(DiskInodeType::Directory),
+            2 => Ok(DiskInodeType::Symlink),
+            _ => Err(raw),
+        }
+    }
+}
+
+impl DiskInodeType {
+    /// 新建 inode 的默认权限位
+    pub fn default_mode(&self) -> u16 {
+        match self {
+            DiskInodeType::File => 0o644,
+            DiskInodeType::Directory => 0o755,
+            DiskInodeType::Symlink => 0o777,
+        }
+    }
+}
+
+/// 磁盘上的时间戳，秒数拆成两个 u32 以保持 DiskInode 按 4 字节对齐
+/// 旧镜像中这些字位于保留区，全为零
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DiskTime {
+    sec_lo: u32,
+    sec_hi: u32,
+    nsec: u32,
+}
+
+impl From<Timestamp> for DiskTime {
+    fn from(time: Timestamp) -> Self {
+        Self { sec_lo: time.sec as u32, sec_hi: (time.sec >> 32) as u32, nsec: time.nsec }
+    }
+}
+
+impl From<DiskTime> for Timestamp {
+    fn from(time: DiskTime) -> Self {
+        Self { sec: (time.sec_hi as u64) << 32 | time.sec_lo as u64, nsec: time.nsec }
+    }
 }
 
 /// a DiskInode represents an inode on disk
-/// size: 128 bytes(32 * 4), 4 inodes per block
+/// size: 256 bytes(64 * 4), 2 inodes per block
 /// this is a disk structure
 #[repr(C)]
+#[derive(Clone)]
 pub struct DiskInode {
     pub size: u32,    // 单位: Bytes
     pub direct: [u32; INODE_DIRECT_COUNT as usize],
     pub indirect1: u32,
     pub indirect2: u32,
-    type_: DiskInodeType,
+    type_: u32, // DiskInodeType，以 u32 保存: 从损坏的镜像读出的值可能不是合法的枚举值
+    pub mode: u32, // 权限位，即 st_mode & 0o7777 (含 setuid/setgid/sticky)
+    pub uid: u32,
+    pub gid: u32,
+    atime: DiskTime, // 最后一次读取
+    mtime: DiskTime, // 最后一次修改内容
+    ctime: DiskTime, // 最后一次修改内容或 inode (权限、属主、时间戳)
+    pub xattr_block: u32, // 扩展属性块 (见 xattr.rs)，0 表示没有
+    flags: u32,           // INODE_FLAG_*，旧镜像中全为零
+    reserved: [u32; INODE_RESERVED_WORDS],
 }
 
+const _: () = assert!(core::mem::size_of::<DiskInode>() == INODE_SIZE as usize);
+const _: () = assert!(core::mem::size_of::<ExtentRoot>() <= (INODE_DIRECT_COUNT as usize + 2) * 4);
+
 impl DiskInode {
     /// 因为 DiskInode 是一个磁盘结构体，所以初始化无需分配内存，直接在磁盘上修改数据内容即可
     /// 故用 `initialize` 方法来初始化一个新的 DiskInode
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    /// 三个时间戳都初始化为 time
+    pub fn initialize(&mut self, type_: DiskInodeType, mode: u16, uid: u32, gid: u32, time: Timestamp) {
         *self = Self{
             size: 0,
             direct: [0; INODE_DIRECT_COUNT as usize],
             indirect1: 0,
             indirect2: 0,
-            type_,
+            type_: type_ as u32,
+            mode: (mode & 0o7777) as u32,
+            uid,
+            gid,
+            atime: time.into(),
+            mtime: time.into(),
+            ctime: time.into(),
+            xattr_block: 0,
+            flags: 0,
+            reserved: [0; INODE_RESERVED_WORDS],
         }
     }
 
+    pub fn times(&self) -> Times {
+        Times { atime: self.atime.into(), mtime: self.mtime.into(), ctime: self.ctime.into() }
+    }
+    pub fn set_atime(&mut self, time: Timestamp) {
+        self.atime = time.into();
+    }
+    pub fn set_mtime(&mut self, time: Timestamp) {
+        self.mtime = time.into();
+    }
+    pub fn set_ctime(&mut self, time: Timestamp) {
+        self.ctime = time.into();
+    }
+
+    /// inode 的类型，type_ 字段的值不合法时返回 None
+    pub fn inode_type(&self) -> Option<DiskInodeType> {
+        DiskInodeType::try_from(self.type_).ok()
+    }
     pub fn is_dir(&self) -> bool {
-        self.type_ == DiskInodeType::Directory
+        self.inode_type() == Some(DiskInodeType::Directory)
     }
     pub fn is_file(&self) -> bool {
-        self.type_ == DiskInodeType::File
+        self.inode_type() == Some(DiskInodeType::File)
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.inode_type() == Some(DiskInodeType::Symlink)
+    }
+    /// 目标保存在 inode 内 (direct 数组) 的符号链接，size 为目标的长度，没有数据块
+    pub fn is_inline(&self) -> bool {
+        self.is_symlink() && self.size <= INLINE_SYMLINK_MAX
+    }
+    fn inline_data(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.direct.as_ptr() as *const u8, self.size as usize) }
+    }
+    /// 把符号链接的目标保存在 inode 内，data 不能超过 INLINE_SYMLINK_MAX
+    pub fn set_inline(&mut self, data: &[u8]) {
+        assert!(self.is_symlink() && self.size == 0 && data.len() <= INLINE_SYMLINK_MAX as usize);
+        self.size = data.len() as u32;
+        unsafe {
+            core::slice::from_raw_parts_mut(self.direct.as_mut_ptr() as *mut u8, data.len()).copy_from_slice(data);
+        }
+    }
+    /// 数据块由 extent 树而不是直接与间接索引记录
+    pub fn uses_extents(&self) -> bool {
+        self.flags & INODE_FLAG_EXTENTS != 0
+    }
+    /// 改用 extent 树，只能对还没有数据块的新 inode 调用
+    pub fn set_extents(&mut self) {
+        assert!(self.size == 0);
+        self.flags |= INODE_FLAG_EXTENTS;
+        self.extent_root_mut().initialize();
+    }
+    /// extent 树的根节点，与 direct / indirect1 / indirect2 占用相同的位置
+    pub fn extent_root(&self) -> &ExtentRoot {
+        unsafe { &*(self.direct.as_ptr() as *const ExtentRoot) }
+    }
+    fn extent_root_mut(&mut self) -> &mut ExtentRoot {
+        unsafe { &mut *(self.direct.as_mut_ptr() as *mut ExtentRoot) }
+    }
+    /// 最大文件大小
+    pub fn max_size(&self) -> usize {
+        if self.uses_extents() { MAX_EXTENT_FILE_SIZE } else { MAX_FILE_SIZE }
+    }
+    /// 磁盘上 type_ 字段的原始值；损坏的镜像中可能不是合法的 DiskInodeType
+    pub fn raw_type(&self) -> u32 {
+        self.type_
     }
     pub fn data_block_num(&self) -> u32 {
+        if self.is_inline() {
+            return 0;
+        }
         Self::data_block_num_(self.size)
     }
     fn data_block_num_(size: u32) -> u32 {
@@ -59,7 +216,19 @@ impl DiskInode {
     }
     /// 根据内部块编号 `inner_id`，返回对应的物理块编号。
     pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
-        if inner_id < INODE_DIRECT_COUNT {
+        self.map_blocks(inner_id, block_device).0
+    }
+    /// 最后一个数据块的块号，没有数据块时返回 None；文件增长时新的块尽量紧接着它分配
+    pub fn last_block(&self, block_device: &Arc<dyn BlockDevice>) -> Option<u32> {
+        let blocks = self.data_block_num();
+        (blocks > 0).then(|| self.get_block_id(blocks - 1, block_device))
+    }
+    /// 第 inner_id 块的物理块号，以及从它开始物理上连续的块数 (直接与间接索引总是 1)
+    fn map_blocks(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> (u32, u32) {
+        if self.uses_extents() {
+            return self.extent_root().lookup(inner_id, block_device).expect("block is not mapped by the extent tree");
+        }
+        let block_id = if inner_id < INODE_DIRECT_COUNT {
             self.direct[inner_id as usize]
         } else if inner_id < INODE_DIRECT_COUNT + INODE_INDIRECT1_COUNT {
             get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
@@ -77,7 +246,8 @@ impl DiskInode {
                 .read(0, |indirect1: &IndirectBlock| {
                     indirect1[(last % INODE_INDIRECT1_COUNT) as usize]
                 })
-        }
+        };
+        (block_id, 1)
     }
     /// number of blocks needed, include indirect1/2
     pub fn total_blocks(size: u32) -> u32 {
@@ -101,6 +271,7 @@ impl DiskInode {
         }
     }
     /// number of extra blocks needed to increase the size of the inode
+    /// 只用于直接与间接索引；extent 树需要的节点块数取决于新数据块是否连续，见 `extent_tree`
     pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
         assert!(new_size >= self.size);
         Self::total_blocks(new_size) - Self::total_blocks(self.size)
@@ -109,6 +280,11 @@ impl DiskInode {
     /// 从 disk inode 的指定偏移处读取数据到缓冲区
     /// 返回实际读取的字节数
     pub fn read_at(&self, offset: usize, buf: &mut [u8], block_device: &Arc<dyn BlockDevice>) -> usize {
+        self.read_at_ahead(offset, buf, block_device, 0)
+    }
+
+    /// 同 read_at，读到不在缓存中的块时把它与之后直到第 ahead 块 (不含) 的块一起读入缓存 (见 ReadAhead)
+    pub fn read_at_ahead(&self, offset: usize, buf: &mut [u8], block_device: &Arc<dyn BlockDevice>, ahead: u32) -> usize {
         let len = buf.len() as u32;
         let mut start = offset as u32;
         let end = (start + len).min(self.size);
@@ -116,10 +292,15 @@ impl DiskInode {
         if start >= end {
             return 0;
         }
+        if self.is_inline() {
+            buf[..(end - start) as usize].copy_from_slice(&self.inline_data()[start as usize..end as usize]);
+            return (end - start) as usize;
+        }
 
         // 计算起始数据块的索引(逻辑块号)
         let mut start_block = start / BLOCK_SIZE;
         let mut read_size = 0u32; // 已经读取的字节数
+        let mut run = (0u32, 0u32); // (下一个块的物理块号, 剩余的连续块数)，连续的块无需再查索引
 
         loop {
             // 计算当前块的结束位置
@@ -136,7 +317,13 @@ impl DiskInode {
             let dst = &mut buf[read_size as usize..(read_size + block_read_size) as usize];
 
             // 获取当前数据块的缓存
-            get_block_cache(self.get_block_id(start_block, block_device) as usize,Arc::clone(block_device))
+            if run.1 == 0 {
+                run = self.map_blocks(start_block, block_device);
+            }
+            if start_block < ahead && !is_block_cached(run.0 as usize, block_device) {
+                self.prefetch(start_block, ahead, block_device);
+            }
+            get_block_cache(run.0 as usize, Arc::clone(block_device))
                 .lock().read(0, |data_block: &DataBlock| {
                 let src = &data_block[(start % BLOCK_SIZE) as usize..(start % BLOCK_SIZE + block_read_size) as usize];
                 dst.copy_from_slice(src);
@@ -144,6 +331,7 @@ impl DiskInode {
 
             // 已读取的总字节数
             read_size += block_read_size;
+            run = (run.0 + 1, run.1 - 1);
 
             if end_current_block == end {
                 break;
@@ -157,6 +345,26 @@ impl DiskInode {
         read_size as usize
     }
 
+    /// 把文件的第 start..end 块 (最多 MAX_PREFETCH 块) 读入块缓存，磁盘上连续的块用一次请求读取
+    fn prefetch(&self, start: u32, end: u32, block_device: &Arc<dyn BlockDevice>) {
+        let end = end.min(self.data_block_num()).min(start + MAX_PREFETCH as u32);
+        let mut block = start;
+        while block < end {
+            let (first, run) = self.map_blocks(block, block_device);
+            let mut count = run.min(end - block);
+            // 直接与间接索引每次只映射一块，物理上相邻的块也合并到同一个请求中
+            while block + count < end {
+                let (next, run) = self.map_blocks(block + count, block_device);
+                if next != first + count {
+                    break;
+                }
+                count += run.min(end - block - count);
+            }
+            prefetch_block_caches(first as usize, count as usize, block_device);
+            block += count;
+        }
+    }
+
     /// 将数据写入到 disk inode 的指定偏移处
     /// 返回实际写入的字节数
     pub fn write_at(
@@ -170,16 +378,17 @@ impl DiskInode {
         assert!(start <= end);
         let mut start_block = start / BLOCK_SIZE;
         let mut write_size = 0u32;
+        let mut run = (0u32, 0u32); // 同 read_at
         loop {
             // calculate end of current block
             let mut end_current_block = (start / BLOCK_SIZE + 1) * BLOCK_SIZE;
             end_current_block = end_current_block.min(end);
             // write and update write size
             let block_write_size = end_current_block - start;
-            get_block_cache(
-                self.get_block_id(start_block, block_device) as usize,
-                Arc::clone(block_device),
-            )
+            if run.1 == 0 {
+                run = self.map_blocks(start_block, block_device);
+            }
+            get_block_cache(run.0 as usize, Arc::clone(block_device))
                 .lock()
                 .modify(0, |data_block: &mut DataBlock| {
                     let src = &buf[write_size as usize..(write_size + block_write_size) as usize];
@@ -187,6 +396,7 @@ impl DiskInode {
                     dst.copy_from_slice(src);
                 });
             write_size += block_write_size;
+            run = (run.0 + 1, run.1 - 1);
             // move to next block
             if end_current_block == end {
                 break;
@@ -224,7 +434,7 @@ impl DiskInode {
             return;
         }
         // fill indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+        indirect_block_cache(self.indirect1, block_device)
             .lock()
             .modify(0, |indirect1: &mut IndirectBlock| {
                 while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT) {
@@ -248,7 +458,7 @@ impl DiskInode {
         let a1 = total_blocks / INODE_INDIRECT1_COUNT;
         let b1 = total_blocks % INODE_INDIRECT1_COUNT;
         // alloc low-level indirect1
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+        indirect_block_cache(self.indirect2, block_device)
             .lock()
             .modify(0, |indirect2: &mut IndirectBlock| {
                 while (a0 < a1) || (a0 == a1 && b0 < b1) {
@@ -256,7 +466,7 @@ impl DiskInode {
                         indirect2[a0 as usize] = new_blocks.next().unwrap();
                     }
                     // fill current
-                    get_block_cache(indirect2[a0 as usize] as usize, Arc::clone(block_device))
+                    indirect_block_cache(indirect2[a0 as usize], block_device)
                         .lock()
                         .modify(0, |indirect1: &mut IndirectBlock| {
                             indirect1[b0 as usize] = new_blocks.next().unwrap();
@@ -271,9 +481,90 @@ impl DiskInode {
             });
     }
 
+    /// 读取 extent 树的最右路径，用于追加数据块
+    pub fn extent_tree(&self, block_device: &Arc<dyn BlockDevice>) -> ExtentTree {
+        ExtentTree::load(self.extent_root(), block_device)
+    }
+
+    /// 同 `increase_size`，用于使用 extent 树的 inode:
+    /// tree 中已追加了新的数据块，node_blocks 是为它的新节点分配的块
+    pub fn increase_size_extents(
+        &mut self,
+        new_size: u32,
+        tree: ExtentTree,
+        node_blocks: Vec<u32>,
+        block_device: &Arc<dyn BlockDevice>,
+    ) {
+        assert_eq!(tree.blocks(), Self::data_block_num_(new_size));
+        self.size = new_size;
+        tree.store(self.extent_root_mut(), node_blocks, block_device);
+    }
+
+    /// Decrease the size of current disk inode and return blocks that should be deallocated,
+    /// including index blocks that are no longer needed
+    pub fn decrease_size(&mut self, new_size: u32, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let old_blocks = self.data_block_num();
+        let new_blocks = Self::data_block_num_(new_size);
+        // 清零保留的最后一块中超出新大小的部分，之后扩大文件时这部分读出来是零
+        let tail = (new_size % BLOCK_SIZE) as usize;
+        if tail != 0 {
+            let block_id = self.get_block_id(new_blocks - 1, block_device);
+            get_block_cache(block_id as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| data_block[tail..].fill(0));
+        }
+        if self.uses_extents() {
+            self.size = new_size;
+            return self.extent_root_mut().truncate(new_blocks, block_device);
+        }
+        // 被释放的数据块
+        let mut v: Vec<u32> = (new_blocks..old_blocks)
+            .map(|inner_id| self.get_block_id(inner_id, block_device))
+            .collect();
+        // direct
+        for i in new_blocks.min(INODE_DIRECT_COUNT)..old_blocks.min(INODE_DIRECT_COUNT) {
+            self.direct[i as usize] = 0;
+        }
+        // indirect1
+        if old_blocks > INODE_DIRECT_COUNT && new_blocks <= INODE_DIRECT_COUNT {
+            v.push(self.indirect1);
+            self.indirect1 = 0;
+        }
+        // indirect2 及其下的一级索引块
+        let base = INODE_DIRECT_COUNT + INODE_INDIRECT1_COUNT;
+        if old_blocks > base {
+            let old_sub = (old_blocks - base).div_ceil(INODE_INDIRECT1_COUNT);
+            let new_sub = new_blocks.saturating_sub(base).div_ceil(INODE_INDIRECT1_COUNT);
+            indirect_block_cache(self.indirect2, block_device)
+                .lock()
+                .modify(0, |indirect2: &mut IndirectBlock| {
+                    for entry in indirect2[new_sub as usize..old_sub as usize].iter_mut() {
+                        v.push(*entry);
+                        *entry = 0;
+                    }
+                });
+            if new_blocks <= base {
+                v.push(self.indirect2);
+                self.indirect2 = 0;
+            }
+        }
+        self.size = new_size;
+        v
+    }
+
     /// Clear size to zero and return blocks that should be deallocated.
-    /// We will clear the block contents to zero later (in memory_inode.clear())
+    /// Block contents are zeroed when they are allocated again (in EasyFileSystem::alloc_data_block)
     pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        if self.is_inline() {
+            self.direct = [0; INODE_DIRECT_COUNT as usize];
+            self.size = 0;
+            return Vec::new();
+        }
+        if self.uses_extents() {
+            self.size = 0;
+            return self.extent_root_mut().truncate(0, block_device);
+        }
         let mut v: Vec<u32> = Vec::new();  // 需要释放的块编号
         let mut data_block_num = self.data_block_num();
         self.size = 0; // resize
@@ -296,7 +587,7 @@ impl DiskInode {
         }
         // indirect1
         get_block_cache(self.indirect1 as usize, Arc::clone(block_device)).lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
+            .read(0, |indirect1: &IndirectBlock| {
                 while current_blocks < data_block_num.min(INODE_INDIRECT1_COUNT) {
                     v.push(indirect1[current_blocks as usize]);
                     //indirect1[current_blocks] = 0;
@@ -317,13 +608,13 @@ impl DiskInode {
         let b1 = data_block_num % INODE_INDIRECT1_COUNT;
         get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
             .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
+            .read(0, |indirect2: &IndirectBlock| {
                 // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1 as usize) {
+                for entry in indirect2.iter().take(a1 as usize) {
                     v.push(*entry);
                     get_block_cache(*entry as usize, Arc::clone(block_device))
                         .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
+                        .read(0, |indirect1: &IndirectBlock| {
                             for entry in indirect1.iter() {
                                 v.push(*entry);
                             }
@@ -334,7 +625,7 @@ impl DiskInode {
                     v.push(indirect2[a1 as usize]);
                     get_block_cache(indirect2[a1 as usize] as usize, Arc::clone(block_device))
                         .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
+                        .read(0, |indirect1: &IndirectBlock| {
                             for entry in indirect1.iter().take(b1 as usize) {
                                 v.push(*entry);
                             }
@@ -347,55 +638,130 @@ impl DiskInode {
 }
 
 // ----- DirEntry -----
-pub const NAME_LENGTH_LIMIT: u32 = 27; // 最大允许保存长度为 27 的文件/目录名
-pub const DIRENT_SIZE: u32 = 32;
-
-/// DirEntry represents a directory entry on disk
-/// size: 32 bytes, 16 entries per block
-#[repr(C)]
+pub const NAME_LENGTH_LIMIT: u32 = 255; // 文件/目录名的最大长度
+pub const DIRENT_HEADER_SIZE: u32 = 8;
+/// 索引记录的标志位 (见 dir_index.rs)
+const DIRENT_INDEX: u8 = 1;
+
+/*
+目录的内容由整数个块组成，每块被若干变长目录项恰好铺满，目录项不跨块:
+[inode_number: u32][rec_len: u16][name_len: u8][flags: u8][name: name_len 字节][填充到 4 字节对齐]
+rec_len 是整条记录的长度，可以大于存放名字所需的长度，多出的部分是可供插入的空闲空间
+name_len 为 0 表示空闲的目录项 (根目录的 inode 编号为 0，不能用 inode_number 标记空闲)
+删除目录项时把它并入同一块中的前一项，块首的目录项被删除时标记为空闲
+目录块 0 的第一条记录是索引记录 (flags 为 DIRENT_INDEX，没有名字)，inode_number 字段保存索引根块号
+ */
+
+/// DirEntry represents a variable-length directory entry on disk
+#[derive(Clone, Debug)]
 pub struct DirEntry {
-    name: [u8; NAME_LENGTH_LIMIT as usize + 1], // +1 for `\0` terminator
     inode_number: u32,
+    rec_len: u32,
+    flags: u8,
+    name: Vec<u8>,
 }
 
 impl DirEntry {
     // ----- constructor -----
-    pub fn new_empty() -> Self {
-        Self {
-            name: [0; NAME_LENGTH_LIMIT as usize + 1],
-            inode_number: 0,
+    /// 名字为空或超出长度限制时返回 None
+    pub fn new(name: &str, inode_number: u32) -> Option<Self> {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT as usize {
+            return None;
         }
+        Some(Self {
+            inode_number,
+            rec_len: Self::min_rec_len(name.len()),
+            flags: 0,
+            name: Vec::from(name.as_bytes()),
+        })
+    }
+
+    /// 长为 rec_len 的空闲目录项
+    pub fn new_free(rec_len: u32) -> Self {
+        Self { inode_number: 0, rec_len, flags: 0, name: Vec::new() }
     }
 
-    pub fn new(name: &str, inode_number: u32) -> Self {
-        assert!(name.len() <= NAME_LENGTH_LIMIT as usize, "Name too long");
-        let mut name_bytes = [0; NAME_LENGTH_LIMIT as usize + 1];
-        name_bytes[..name.len()].copy_from_slice(name.as_bytes());
-        name_bytes[name.len()] = 0; // null terminator
+    /// 索引记录，index_root 为 0 表示目录还没有索引
+    pub fn new_index(index_root: u32) -> Self {
+        Self { inode_number: index_root, rec_len: DIRENT_HEADER_SIZE, flags: DIRENT_INDEX, name: Vec::new() }
+    }
 
-        Self {
-            name: name_bytes,
-            inode_number,
+    /// 从目录块的 offset 处解析目录项，记录损坏 (长度未对齐、越过块尾或放不下名字) 时返回 None
+    pub fn parse(block: &[u8], offset: usize) -> Option<Self> {
+        let header = block.get(offset..offset + DIRENT_HEADER_SIZE as usize)?;
+        let inode_number = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let rec_len = u16::from_le_bytes(header[4..6].try_into().unwrap()) as usize;
+        let name_len = header[6] as usize;
+        if rec_len < DIRENT_HEADER_SIZE as usize || !rec_len.is_multiple_of(4)
+            || offset + rec_len > block.len() || DIRENT_HEADER_SIZE as usize + name_len > rec_len {
+            return None;
         }
+        let name_start = offset + DIRENT_HEADER_SIZE as usize;
+        Some(Self {
+            inode_number,
+            rec_len: rec_len as u32,
+            flags: header[7],
+            name: Vec::from(&block[name_start..name_start + name_len]),
+        })
+    }
+
+    /// 把目录项写入目录块的 offset 处
+    pub fn write(&self, block: &mut [u8], offset: usize) {
+        let record = &mut block[offset..offset + self.rec_len as usize];
+        record[0..4].copy_from_slice(&self.inode_number.to_le_bytes());
+        record[4..6].copy_from_slice(&(self.rec_len as u16).to_le_bytes());
+        record[6] = self.name.len() as u8;
+        record[7] = self.flags;
+        let name_end = DIRENT_HEADER_SIZE as usize + self.name.len();
+        record[DIRENT_HEADER_SIZE as usize..name_end].copy_from_slice(&self.name);
+        record[name_end..].fill(0);
     }
 
     // ----- methods -----
-    pub fn get_name(&self) -> &str {
-        let null_pos = self.name.iter().position(|&b| b == 0).unwrap_or(NAME_LENGTH_LIMIT as usize);
-        core::str::from_utf8(&self.name[..null_pos]).expect("invalid UTF-8 bytes in inode name")
+    /// 存放长为 name_len 的名字所需的最小记录长度
+    pub fn min_rec_len(name_len: usize) -> u32 {
+        (DIRENT_HEADER_SIZE + name_len as u32).next_multiple_of(4)
+    }
+    pub fn is_free(&self) -> bool {
+        self.name.is_empty() && !self.is_index()
+    }
+    pub fn is_index(&self) -> bool {
+        self.flags & DIRENT_INDEX != 0
+    }
+    /// 普通的目录项: 既不空闲也不是索引记录
+    pub fn is_used(&self) -> bool {
+        !self.is_free() && !self.is_index()
+    }
+    /// 索引根块号，只对索引记录有意义
+    pub fn index_root(&self) -> u32 {
+        self.inode_number
+    }
+    /// 名字，不是合法的 UTF-8 时返回 None
+    pub fn get_name(&self) -> Option<&str> {
+        core::str::from_utf8(&self.name).ok()
+    }
+    /// 名字的原始字节
+    pub fn raw_name(&self) -> &[u8] {
+        &self.name
     }
     pub fn get_inode_number(&self) -> u32 {
         self.inode_number
     }
-
-    pub fn as_bytes(&self) -> &[u8] {
-        unsafe {
-            core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SIZE as usize)
-        }
+    pub fn set_inode_number(&mut self, inode_number: u32) {
+        self.inode_number = inode_number;
     }
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe {
-            core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SIZE as usize)
-        }
+    pub fn rec_len(&self) -> u32 {
+        self.rec_len
+    }
+    pub fn set_rec_len(&mut self, rec_len: u32) {
+        self.rec_len = rec_len;
+    }
+    /// 记录中除存放名字 (或索引记录头部) 外的空闲字节数
+    pub fn spare(&self) -> u32 {
+        if self.is_free() { self.rec_len } else { self.rec_len - Self::min_rec_len(self.name.len()) }
+    }
+    /// 块首的记录是否覆盖整个块且不含任何内容 (空闲，或是没有索引的索引记录)
+    pub fn is_empty_block(&self, block_size: u32) -> bool {
+        self.rec_len == block_size && self.name.is_empty() && self.inode_number == 0
     }
 }
@@ -1,14 +1,42 @@
 // fs/src/efs.rs
 
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::sync::Arc;
-use spin::Mutex;
+use alloc::vec::Vec;
+use spin::{Mutex, MutexGuard};
 use crate::bitmap::Bitmap;
-use crate::block_cache::{block_cache_sync_all, get_block_cache};
+use crate::block_cache::{dirty_block_caches, dirty_block_count, get_block_cache, register_checksums, take_checksum_errors};
 use crate::block_dev::BlockDevice;
-use crate::config::{BLOCK_SIZE, INODE_PER_BLOCK, INODE_SIZE};
-use crate::inode::Inode;
+use crate::checksum::{crc32c, table_block_checksum, ChecksumBlock, ChecksumMode, ChecksumTable, CHECKSUMS_PER_BLOCK};
+use crate::config::{BLOCK_SIZE, INODE_PER_BLOCK, INODE_SIZE, JOURNAL_BLOCKS, PREALLOC_BLOCKS};
+use crate::inode::{Inode, InodeCache};
 use crate::disk_inode::{DiskInode, DiskInodeType, DataBlock};
-use crate::super_block::SuperBlock;
+use crate::journal::{Journal, JOURNAL_MAX_BLOCKS};
+use crate::super_block::{SuperBlock, FEATURE_DATA_CSUM, FEATURE_EXTENTS, FEATURE_FREE_COUNTS, FEATURE_METADATA_CSUM};
+use crate::time::Timestamp;
+
+/// 新建文件系统时的可选特性
+#[derive(Clone, Copy, Debug)]
+pub struct FormatOptions {
+    pub journal_blocks: u32,     // 日志区块数，0 表示不使用日志
+    pub checksums: ChecksumMode, // 校验和的范围
+    pub extents: bool,           // 新建的文件与目录使用 extent 树 (见 extent.rs)，否则使用直接与间接索引
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { journal_blocks: JOURNAL_BLOCKS, checksums: ChecksumMode::Metadata, extents: false }
+    }
+}
+
+/// 文件系统的容量与空闲计数 (statfs)，块数只计数据区
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FsUsage {
+    pub blocks: u32,
+    pub free_blocks: u32,
+    pub inodes: u32,
+    pub free_inodes: u32,
+}
 
 /// Easy File System (EFS) implementation
 /// this is a structure in Memory
@@ -18,25 +46,81 @@ pub struct EasyFileSystem {
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32, // inode 区域的起始块号
     data_area_start_block: u32,  // 数据区的起始块号
+    data_area_blocks: u32,       // 数据区的块数 (位图的最后一块中可能有多余的位)
+    journal: Option<Journal>,    // 旧镜像没有日志区
+    checksums: Option<ChecksumTable>, // 旧镜像没有校验表
+    extents: bool,               // 新建的文件与目录是否使用 extent 树
+    inodes: Arc<InodeCache>,     // 内存中的 inode 表与墙上时钟，读写 inode 时无需 fs 锁
+    free_inodes: u32,            // 空闲计数，提交事务时写回 SuperBlock
+    free_blocks: u32,
+    inode_hint: u32,             // 下一次分配 inode 时开始查找的位置 (next-fit)
+    block_hint: u32,             // 下一次在新位置分配数据块时开始查找的位置 (数据区内的偏移)
+    delayed_commit: bool,        // 见 set_delayed_commit
+    dirty_inodes: BTreeMap<u32, bool>, // 当前事务中被修改的 inode -> 是否修改了内容
+    dirty_since: Option<Timestamp>, // 当前事务中第一个操作结束的时间，见 writeback
+    dirty_limit: usize,          // 脏块的高水位，见 set_dirty_limit
+    active_ops: usize,           // 进行中的操作数，见 begin_op
+    reserved_blocks: usize,      // 进行中的操作预留的块数
+    commit_pending: bool,        // 有提交在等待进行中的操作结束，新的操作先等待
+    orphans: BTreeSet<u32>,      // 已删除但仍被打开的 inode，最后一个引用被丢弃时释放 (见 free_orphans)
 }
 
+/*
+数据块的分配策略:
+- 文件增长时优先紧接着它的最后一个块继续分配 (goal)，使文件尽量连续
+- 新文件与无法原地增长的文件从 block_hint 开始查找 (next-fit)，而不是每次都从位图开头扫描
+- 无法原地增长的文件说明还有其他文件在交替写入: 新位置之后的 PREALLOC_BLOCKS 个块留给它继续增长 (只在内存中预留，
+  把 block_hint 移到窗口之后)，不写入位图，崩溃后不会泄漏；磁盘快满时 block_hint 回到开头，窗口中的块仍可被其他文件使用
+ */
+
 impl EasyFileSystem {
     // ----- constructor -----
-    /// 根据总块数和 inode 位图块数来创建文件系统
+    /// 根据总块数和 inode 位图块数来创建文件系统，磁盘末尾的 JOURNAL_BLOCKS 个块作为日志区
     pub fn create(block_device: Arc<dyn BlockDevice>, total_blocks: u32, inode_bitmap_blocks: u32) -> Arc<Mutex<Self>> {
+        Self::create_with_journal(block_device, total_blocks, inode_bitmap_blocks, JOURNAL_BLOCKS)
+    }
+
+    /// 同 `create`，但指定日志区的块数，为 0 时不使用日志
+    /// 元数据有校验和，数据块没有；文件使用直接与间接索引
+    pub fn create_with_journal(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        journal_blocks: u32,
+    ) -> Arc<Mutex<Self>> {
+        let options = FormatOptions { journal_blocks, ..FormatOptions::default() };
+        Self::create_with_options(block_device, total_blocks, inode_bitmap_blocks, options)
+    }
+
+    /// 同 `create_with_journal`，但指定所有可选的特性
+    pub fn create_with_options(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        options: FormatOptions,
+    ) -> Arc<Mutex<Self>> {
+        let FormatOptions { journal_blocks, checksums: checksum_mode, extents } = options;
+        assert!(journal_blocks == 0 || journal_blocks >= 2, "journal needs a header block and at least one log block");
         // 1. 计算各区域大小并创建位图
         let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
         let inode_num = inode_bitmap.maximum() as u32;
         let inode_area_blocks = (inode_num + INODE_PER_BLOCK - 1) / INODE_PER_BLOCK;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
-        let data_bitmap_blocks = (data_total_blocks * size_of::<u8>() as u32 * 8 + 1) / (BLOCK_SIZE * 8 + 1);
+        // 校验表位于日志区之前
+        let checksum_blocks = match checksum_mode {
+            ChecksumMode::None => 0,
+            _ => ChecksumTable::blocks_needed(total_blocks - journal_blocks),
+        };
+        let data_total_blocks = total_blocks - 1 - inode_total_blocks - journal_blocks - checksum_blocks;
+        // 每个位图块管理 4096 个数据块: 位图块数 = ceil(data_total_blocks / 4097)
+        let block_bits = BLOCK_SIZE * 8;
+        let data_bitmap_blocks = (data_total_blocks + block_bits) / (block_bits + 1);
         let data_area_blocks = data_total_blocks - data_bitmap_blocks;
-        let data_num = data_area_blocks;
         let data_bitmap = Bitmap::new(
             (1 + inode_total_blocks) as usize,
             data_bitmap_blocks as usize,
         );
+        let data_area_start_block = 1 + inode_total_blocks + data_bitmap_blocks;
 
         // 2. 初始化文件系统元数据
         let mut efs = Self {
@@ -44,85 +128,512 @@ impl EasyFileSystem {
             inode_bitmap,
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
-            data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            data_area_start_block,
+            data_area_blocks,
+            journal: (journal_blocks > 0).then(|| Journal::new(total_blocks - journal_blocks, journal_blocks)),
+            checksums: (checksum_mode != ChecksumMode::None).then(|| ChecksumTable::new(
+                data_area_start_block + data_area_blocks,
+                checksum_blocks,
+                data_area_start_block,
+                checksum_mode == ChecksumMode::All,
+            )),
+            extents,
+            inodes: Arc::new(InodeCache::new(1 + inode_bitmap_blocks)),
+            free_inodes: inode_num,
+            free_blocks: data_area_blocks,
+            inode_hint: 0,
+            block_hint: 0,
+            delayed_commit: false,
+            dirty_inodes: BTreeMap::new(),
+            dirty_since: None,
+            dirty_limit: usize::MAX,
+            active_ops: 0,
+            reserved_blocks: 0,
+            commit_pending: false,
+            orphans: BTreeSet::new(),
         };
 
-        // 3. 清空所有块
+        // 3. 清空所有块 (直接写块设备，不经过块缓存)
+        let zero_block: DataBlock = [0; BLOCK_SIZE as usize];
         for i in 0..total_blocks {
-            get_block_cache(i as usize, Arc::clone(&block_device))
-                .lock()
-                .modify(0, |data_block: &mut [u8; BLOCK_SIZE as usize]| {
-                    for byte in data_block.iter_mut() {
-                        *byte = 0;
-                    }
-                });
+            block_device.write_block(i as usize, &zero_block);
+        }
+        if let Some(table) = &efs.checksums {
+            efs.format_checksums(table);
         }
 
         // 4. 初始化 SuperBlock
         get_block_cache(0, Arc::clone(&block_device)).lock()
             .modify(0, |super_block: &mut SuperBlock| {
-                super_block.initialize(inode_num, data_num);
+                super_block.initialize(
+                    total_blocks,
+                    inode_bitmap_blocks,
+                    inode_area_blocks,
+                    data_bitmap_blocks,
+                    data_area_blocks,
+                    journal_blocks,
+                );
+                super_block.checksum_blocks = checksum_blocks;
+                super_block.features = match checksum_mode {
+                    ChecksumMode::None => 0,
+                    ChecksumMode::Metadata => FEATURE_METADATA_CSUM,
+                    ChecksumMode::All => FEATURE_METADATA_CSUM | FEATURE_DATA_CSUM,
+                };
+                if extents {
+                    super_block.features |= FEATURE_EXTENTS;
+                }
             });
 
         // 5. 创建根目录 "/" 的 inode
-        assert_eq!(efs.alloc_inode(), 0); // 分配 inode 0
+        assert_eq!(efs.alloc_inode(), Some(0)); // 分配 inode 0
         // 获取 inode 0 在磁盘上的位置
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
         // 初始化为 Directory 类型
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
             .lock()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+                disk_inode.initialize(DiskInodeType::Directory, DiskInodeType::Directory.default_mode(), 0, 0, Timestamp::default());
+                if extents {
+                    disk_inode.set_extents();
+                }
             });
 
-        // 6. 将所有缓存写回磁盘
-        block_cache_sync_all();
+        // 6. 提交: 计算校验和并写回磁盘
+        // 日志很小时初始的事务 (SuperBlock、位图、根目录与校验表) 放不进日志，此时直接写回，新镜像无需原子性
+        efs.commit_or_write_back();
 
         Arc::new(Mutex::new(efs))
     }
 
+    /// 写入初始的校验表: 此时所有块都是零，总是有校验和的块记录零块的校验和
+    /// 并为块设备启用校验
+    fn format_checksums(&self, table: &ChecksumTable) {
+        let zero_checksum = crc32c(&[0; BLOCK_SIZE as usize]);
+        for i in 0..table.blocks() {
+            let mut block: ChecksumBlock = [0; (BLOCK_SIZE / 4) as usize];
+            for (index, entry) in block[..CHECKSUMS_PER_BLOCK as usize].iter_mut().enumerate() {
+                let block_id = (i * CHECKSUMS_PER_BLOCK) as usize + index;
+                if table.always_covers(block_id) {
+                    *entry = zero_checksum;
+                }
+            }
+            block[CHECKSUMS_PER_BLOCK as usize] = table_block_checksum(&block);
+            let bytes = unsafe { core::slice::from_raw_parts(block.as_ptr() as *const u8, BLOCK_SIZE as usize) };
+            self.block_device.write_block((table.start_block() + i) as usize, bytes);
+        }
+        register_checksums(&self.block_device, *table);
+    }
+
     // ----- methods -----
-    /// Allocate a new inode
-    pub fn alloc_inode(&mut self) -> u32 {
-        self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
+    /// Allocate a new inode, return None if there is no free inode
+    pub fn alloc_inode(&mut self) -> Option<u32> {
+        let limit = self.inode_bitmap.maximum();
+        let inode_id = self.inode_bitmap.alloc(&self.block_device, self.inode_hint as usize, limit)? as u32;
+        self.inode_hint = inode_id + 1;
+        self.free_inodes -= 1;
+        Some(inode_id)
+    }
+    /// Deallocate an inode
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap.dealloc(&self.block_device, inode_id as usize);
+        self.free_inodes += 1;
     }
-    /// Allocate a new data block (contains offset!)
-    pub fn alloc_data_block(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    /// Allocate a new data block (contains offset!), return None if the disk is full
+    /// 新分配的块内容全部为零
+    pub fn alloc_data_block(&mut self) -> Option<u32> {
+        self.alloc_data_run(None, 1).map(|(block_id, _)| block_id)
+    }
+    /// 分配至多 len 个连续的数据块，尽量恰好 len 个，返回 (第一个块号, 块数)；磁盘已满时返回 None
+    /// goal 为文件最后一个数据块之后的块 (文件没有数据块时为 None)，能分配时从 goal 开始分配
+    /// 新分配的块内容全部为零
+    pub fn alloc_data_run(&mut self, goal: Option<u32>, len: u32) -> Option<(u32, u32)> {
+        let limit = self.data_area_blocks as usize;
+        let goal = goal.and_then(|goal| goal.checked_sub(self.data_area_start_block));
+        let at_goal = goal.map_or(0, |pos| self.data_bitmap.alloc_at(&self.block_device, pos as usize, len as usize, limit));
+        let (pos, len) = match goal {
+            Some(pos) if at_goal > 0 => (pos, at_goal as u32),
+            _ => {
+                let (pos, len) = self.data_bitmap.alloc_contiguous(&self.block_device, self.block_hint as usize, len as usize, limit)?;
+                // 无法原地增长的文件在新位置之后预留 PREALLOC_BLOCKS 个块
+                let reserved = if goal.is_some() { (len as u32).max(PREALLOC_BLOCKS) } else { len as u32 };
+                self.block_hint = (pos as u32 + reserved) % self.data_area_blocks;
+                (pos as u32, len as u32)
+            }
+        };
+        self.free_blocks -= len;
+        let start = pos + self.data_area_start_block;
+        // 在分配时而不是释放时清零: 清零和分配属于同一个事务，
+        // 释放数据块的事务只需修改位图，不会因为文件很大而超出日志容量
+        // 清零同时清除元数据标记，块被用作索引块时由 DiskInode 重新标记
+        for block_id in start..start + len {
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock().clear();
+        }
+        Some((start, len))
     }
     /// Deallocate a data block (contains offset!)
     pub fn dealloc_data_block(&mut self, block_id: u32) {
-        // 获取该块的缓存，并将其内容全部清零
-        get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock()
-            .modify(
-                0, |data_block: &mut DataBlock| {
-                    data_block.iter_mut().for_each(|p| { *p = 0; })
-                });
-        
         // 释放 Bitmap 中的位
         self.data_bitmap.dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize
-        )
+        );
+        self.free_blocks += 1;
+    }
+
+    // ----- free space -----
+    /// 空闲的 inode 数
+    pub fn free_inodes(&self) -> usize {
+        self.free_inodes as usize
+    }
+    /// 空闲的数据块数
+    pub fn free_data_blocks(&self) -> usize {
+        self.free_blocks as usize
+    }
+    /// 数据区的块数
+    pub fn data_blocks(&self) -> usize {
+        self.data_area_blocks as usize
+    }
+    pub fn usage(&self) -> FsUsage {
+        FsUsage {
+            blocks: self.data_area_blocks,
+            free_blocks: self.free_blocks,
+            inodes: self.inode_bitmap.maximum() as u32,
+            free_inodes: self.free_inodes,
+        }
+    }
+    /// 根据位图重新计算空闲计数: 打开没有记录空闲计数的旧镜像时，以及 fsck 直接修改位图之后
+    pub fn recount_free(&mut self) {
+        self.free_inodes = (self.inode_bitmap.maximum() - self.inode_bitmap.count_allocated(&self.block_device)) as u32;
+        // 位图最后一块中多余的位不会被分配
+        self.free_blocks = self.data_area_blocks - self.data_bitmap.count_allocated(&self.block_device) as u32;
+    }
+    /// 空闲计数与 SuperBlock 中记录的不同时写入 SuperBlock，与位图的修改属于同一事务
+    fn update_free_counts(&self) {
+        let counts = (self.free_inodes, self.free_blocks);
+        let cache = get_block_cache(0, Arc::clone(&self.block_device));
+        let mut cache = cache.lock();
+        if cache.read(0, |super_block: &SuperBlock| super_block.free_counts()) != Some(counts) {
+            cache.modify(0, |super_block: &mut SuperBlock| {
+                super_block.features |= FEATURE_FREE_COUNTS;
+                (super_block.free_inodes, super_block.free_blocks) = counts;
+            });
+        }
+    }
+
+    /// 新建的文件与目录是否使用 extent 树
+    pub fn uses_extents(&self) -> bool {
+        self.extents
+    }
+
+    // ----- clock -----
+    /// 设置墙上时钟，之后的读写与创建会维护 inode 的时间戳
+    pub fn set_clock(&mut self, clock: fn() -> Timestamp) {
+        self.inodes.set_clock(clock);
+    }
+    /// 当前时间，没有设置时钟时返回 None
+    pub fn now(&self) -> Option<Timestamp> {
+        self.inodes.now()
+    }
+
+    // ----- transaction -----
+    /// 提交当前事务: 自上次提交以来被修改的所有块
+    /// 由最后一个结束的操作 (见 end_op) 以及 fsync / sync 调用
+    /// 有进行中的操作时推迟到它们结束时提交并返回 false，保证事务总是由完整的操作组成
+    /// 事务超出日志容量时不写入任何块并返回 false (见 `Journal::commit`)，修改仍留在块缓存中
+    pub fn commit(&mut self) -> bool {
+        if self.active_ops > 0 {
+            self.commit_pending = true;
+            return false;
+        }
+        self.commit_pending = false;
+        self.update_free_counts();
+        self.update_checksums();
+        match &self.journal {
+            Some(journal) => {
+                if !journal.commit(&self.block_device) {
+                    return false;
+                }
+            }
+            // 没有日志区的旧镜像: 直接写回原位
+            None => dirty_block_caches(&self.block_device)
+                .iter()
+                .for_each(|cache| cache.lock().sync()),
+        }
+        self.dirty_inodes.clear();
+        self.dirty_since = None;
+        true
+    }
+
+    /// 等待进行中的操作结束后提交 (fsync / syncfs)，等待期间新的操作不会开始
+    /// dirty 为 false 时 (例如该 inode 没有未提交的修改) 什么都不做，返回 true
+    pub fn commit_when_idle(efs: &Mutex<Self>, dirty: impl Fn(&Self) -> bool) -> bool {
+        loop {
+            let mut fs = efs.lock();
+            if !dirty(&fs) {
+                return true;
+            }
+            if fs.active_ops == 0 {
+                return fs.commit();
+            }
+            fs.commit_pending = true;
+            drop(fs);
+            core::hint::spin_loop();
+        }
+    }
+
+    /// 同 `commit`，但事务超出日志容量时先把修改直接写回原位 (不保证原子性)
+    /// 用于 fsck 修复 (修复本身不是原子的，重建一个大目录的修改可能超出日志容量)、格式化，
+    /// 以及操作结束与后台回写时的提交: 某个操作对块数的估计有误时，之后的操作不会继续累积到一个永远无法提交的事务中
+    /// 只有 fsync / syncfs 报告提交失败 (见 `commit_when_idle`)
+    pub fn commit_or_write_back(&mut self) {
+        if self.active_ops > 0 {
+            // 同 commit: 推迟到最后一个操作结束时
+            self.commit_pending = true;
+            return;
+        }
+        self.update_free_counts();
+        self.update_checksums();
+        let capacity = self.journal.as_ref().map_or(usize::MAX, Journal::capacity);
+        if dirty_block_count(&self.block_device) > capacity {
+            dirty_block_caches(&self.block_device)
+                .iter()
+                .for_each(|cache| cache.lock().sync());
+        }
+        self.commit();
+    }
+
+    /// 延迟提交 (group commit): 操作结束时不立即提交，修改留在块缓存中，
+    /// 直到事务放不下下一个操作、或者 fsync / sync 时与之前的操作一起提交
+    /// 每个操作仍然是原子的，崩溃时丢失的是最近若干个尚未提交的操作；默认每个操作结束时立即提交
+    pub fn set_delayed_commit(&mut self, delayed: bool) {
+        self.delayed_commit = delayed;
+        if !delayed {
+            self.commit_or_write_back();
+        }
+    }
+
+    /// 开始一个最多修改 blocks 个块的操作 (或大写入中的一段)，返回的 `Op` 被丢弃时操作结束
+    /// 当前事务放不下这些块时先提交: 有进行中的操作时等待它们结束，由最后一个结束的操作提交
+    /// 调用前先获取要修改的 inode 的锁，开始之后不再等待任何 inode 锁 (见 inode.rs 中的并发控制说明)
+    pub fn begin_op(efs: &Mutex<Self>, blocks: usize) -> Op<'_> {
+        Self::wait_begin_op(efs, blocks);
+        Op { fs: efs, blocks }
+    }
+
+    fn wait_begin_op(efs: &Mutex<Self>, blocks: usize) {
+        while !efs.lock().try_begin_op(blocks) {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn try_begin_op(&mut self, blocks: usize) -> bool {
+        // 进行中的操作已修改的块同时计入脏块与预留，估计偏大
+        let fits = self.dirty_blocks() + self.reserved_blocks + blocks <= self.transaction_blocks();
+        if !fits || self.commit_pending {
+            if self.active_ops > 0 {
+                self.commit_pending = true;
+                return false;
+            }
+            self.commit_or_write_back();
+        }
+        self.active_ops += 1;
+        self.reserved_blocks += blocks;
+        true
+    }
+
+    /// 操作结束，由 `Op` 调用；最后一个结束的操作释放不再被打开的孤儿 inode，
+    /// 不是延迟提交 (或有提交在等待) 时提交
+    /// 延迟提交时记录事务变脏的时间；脏块达到高水位时由这个写者同步提交，从而限制写入的速度
+    fn end_op(&mut self, blocks: usize) {
+        self.active_ops -= 1;
+        self.reserved_blocks -= blocks;
+        if self.active_ops > 0 {
+            return;
+        }
+        self.free_orphans();
+        if !self.delayed_commit || self.commit_pending {
+            self.commit_or_write_back();
+            return;
+        }
+        let dirty = self.dirty_blocks();
+        if dirty >= self.dirty_limit {
+            self.commit_or_write_back();
+        } else if dirty > 0 && self.dirty_since.is_none() {
+            // 没有时钟时记为 0，下一次 writeback 总会提交
+            self.dirty_since = Some(self.now().unwrap_or_default());
+        }
+    }
+
+    /// 设置脏块的高水位: 延迟提交时脏块达到 blocks 个就立即提交
+    /// 不设置时只受事务容量 (transaction_blocks) 的限制
+    pub fn set_dirty_limit(&mut self, blocks: usize) {
+        self.dirty_limit = blocks.max(1);
+    }
+
+    /// 当前事务中第一个操作结束的时间，没有未提交的修改时返回 None
+    pub fn dirty_since(&self) -> Option<Timestamp> {
+        self.dirty_since
+    }
+
+    /// 后台回写: 当前事务在 expire 或更早时就已经变脏则提交，返回是否提交了
+    /// 由使用者定期调用 (如内核的定时器)，限制崩溃时丢失的修改的时间范围
+    pub fn writeback(&mut self, expire: Timestamp) -> bool {
+        if self.active_ops > 0 || self.dirty_since.is_none_or(|since| since > expire) {
+            return false;
+        }
+        self.commit_or_write_back();
+        true
+    }
+
+    // ----- orphan inodes -----
+    /// 释放 inode 及其数据块 (包括扩展属性块)
+    /// 由删除它的操作调用，或者它是孤儿时在最后一个引用被丢弃之后调用
+    pub(crate) fn free_inode(&mut self, inode_id: u32) {
+        let (block_id, block_offset) = self.get_disk_inode_pos(inode_id);
+        let data_blocks = get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock()
+            .modify(block_offset, |disk_inode: &mut DiskInode| {
+                let mut blocks = disk_inode.clear_size(&self.block_device);
+                if disk_inode.xattr_block != 0 {
+                    blocks.push(core::mem::take(&mut disk_inode.xattr_block));
+                }
+                blocks
+            });
+        for data_block in data_blocks {
+            self.dealloc_data_block(data_block);
+        }
+        self.dealloc_inode(inode_id);
+    }
+
+    /// 删除仍被打开的 inode 时调用: inode 成为孤儿，不再能由 inode ID 获取，最后一个引用被丢弃之后才释放
+    /// 孤儿只记录在内存中，释放之前崩溃时 inode 在磁盘上仍被分配，由 fsck 报告为泄漏并回收
+    pub(crate) fn add_orphan(&mut self, inode_id: u32) {
+        self.orphans.insert(inode_id);
+    }
+
+    /// 已删除但仍被打开、尚未释放的 inode，fsck 把它们视为可达的
+    pub fn orphans(&self) -> Vec<u32> {
+        self.orphans.iter().copied().collect()
+    }
+
+    /// 释放不再被打开的孤儿 inode，只在没有进行中的操作时调用 (end_op)，释放属于一个单独的事务
+    fn free_orphans(&mut self) {
+        let closed: Vec<u32> = self.orphans.iter().copied().filter(|&inode_id| !self.inodes.is_open(inode_id)).collect();
+        for inode_id in closed {
+            self.orphans.remove(&inode_id);
+            self.free_inode(inode_id);
+        }
+    }
+
+    /// Inode 的最后一个引用被丢弃: 它是孤儿且没有进行中的操作时，作为一个单独的操作释放它
+    /// 否则留给最后一个结束的操作 (见 end_op)
+    pub(crate) fn release_orphan(&mut self, inode_id: u32) {
+        if self.active_ops == 0 && self.orphans.contains(&inode_id) {
+            self.active_ops += 1;
+            self.end_op(0);
+        }
+    }
+
+    /// 记录 inode 在当前事务中被修改，data 表示修改了内容 (数据、目录项或大小)，而不只是时间戳、权限等属性
+    pub fn mark_dirty(&mut self, inode_id: u32, data: bool) {
+        *self.dirty_inodes.entry(inode_id).or_default() |= data;
+    }
+
+    /// inode 在当前事务中是否有修改，data_only 时只看内容的修改 (fdatasync)
+    pub fn is_dirty(&self, inode_id: u32, data_only: bool) -> bool {
+        self.dirty_inodes.get(&inode_id).is_some_and(|data| *data || !data_only)
+    }
+
+    /// 当前事务已修改的块数
+    pub fn dirty_blocks(&self) -> usize {
+        dirty_block_count(&self.block_device)
+    }
+
+    /// 一个事务中最多可以修改的块数
+    /// 使用校验和时每个被修改的块至多再使一个校验表块变脏，只能用一半的容量
+    pub fn transaction_blocks(&self) -> usize {
+        let capacity = self.journal.as_ref().map_or(JOURNAL_MAX_BLOCKS, |journal| journal.capacity());
+        if self.checksums.is_some() { capacity / 2 } else { capacity }
+    }
+
+    // ----- checksum -----
+    /// 校验表，旧镜像没有
+    pub fn checksum_table(&self) -> Option<ChecksumTable> {
+        self.checksums
+    }
+
+    /// 取出自上次调用以来访问到的校验和不符的块号
+    pub fn take_checksum_errors(&self) -> Vec<u32> {
+        take_checksum_errors(&self.block_device).into_iter().map(|block_id| block_id as u32).collect()
+    }
+
+    /// 在写入日志之前，更新本事务中被修改的块的校验和:
+    /// SuperBlock 更新自身的校验和，其余的块更新校验表中的记录，最后更新被修改的校验表块自身的校验和
+    /// 加载时已损坏的块保留原来的记录，之后仍然报告校验失败
+    fn update_checksums(&self) {
+        let Some(table) = &self.checksums else {
+            return;
+        };
+        for cache in dirty_block_caches(&self.block_device) {
+            let (block_id, checksum) = {
+                let mut cache = cache.lock();
+                let block_id = cache.block_id();
+                if block_id == 0 {
+                    cache.modify(0, |super_block: &mut SuperBlock| super_block.update_checksum());
+                    continue;
+                }
+                if cache.is_corrupted() {
+                    continue;
+                }
+                // 没有被标记的数据块记录为 0: 原来是索引块的块被重新分配为数据块后不再检查
+                let covered = table.always_covers(block_id) || cache.is_metadata();
+                (block_id, if covered { crc32c(cache.data()) } else { 0 })
+            };
+            let Some((table_block, index)) = table.locate(block_id) else {
+                continue;
+            };
+            let table_cache = get_block_cache(table_block, Arc::clone(&self.block_device));
+            let mut table_cache = table_cache.lock();
+            // 只在记录变化时修改，写入普通数据块不会使校验表变脏
+            if table_cache.read(0, |block: &ChecksumBlock| block[index]) != checksum {
+                table_cache.modify(0, |block: &mut ChecksumBlock| block[index] = checksum);
+            }
+        }
+        for cache in dirty_block_caches(&self.block_device) {
+            let mut cache = cache.lock();
+            if table.is_table_block(cache.block_id()) {
+                cache.modify(0, |block: &mut ChecksumBlock| {
+                    block[CHECKSUMS_PER_BLOCK as usize] = table_block_checksum(block);
+                });
+            }
+        }
+    }
+
+    /// `get_disk_inode_pos` 的逆运算，由磁盘位置得到 inode ID
+    pub fn get_inode_id(&self, block_id: u32, block_offset: usize) -> u32 {
+        (block_id - self.inode_area_start_block) * INODE_PER_BLOCK + block_offset as u32 / INODE_SIZE
     }
 
     /// 根据 inode ID 计算其在磁盘上存储的位置 (块号，偏移量)
-    /// 一个块有四个 disk_inode
+    /// 一个块有两个 disk_inode (INODE_PER_BLOCK)
     pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
-        let block_id = self.inode_area_start_block + inode_id / INODE_PER_BLOCK;
-        (
-            block_id,
-            ((inode_id % INODE_PER_BLOCK) * INODE_SIZE) as usize,
-        )
+        self.inodes.disk_inode_pos(inode_id)
     }
 
     /// 从一个已写入 efs 镜像的块设备上打开我们的 easy-fs
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+        Self::try_open(block_device).expect("Error loading EFS!")
+    }
+
+    /// 同 `open`，但魔数不匹配 (不是 efs 镜像) 或 SuperBlock 的校验和不符时返回 None 而不是 panic
+    /// 打开时重做日志中已提交的事务，之后从磁盘加载的块都会检查校验和
+    pub fn try_open(block_device: Arc<dyn BlockDevice>) -> Option<Arc<Mutex<Self>>> {
         // 读取 0 号块 (SuperBlock)
-        get_block_cache(0, Arc::clone(&block_device)).lock()
+        let mut efs = get_block_cache(0, Arc::clone(&block_device)).lock()
             .read(0, |super_block: &SuperBlock| {
-                // 检查魔数
-                assert!(super_block.is_valid(), "Error loading EFS!");
+                // 检查魔数与校验和
+                if !super_block.is_valid() || !super_block.checksum_ok() {
+                    return None;
+                }
                 // 计算 inode 位图和 inode 数据区的总块数
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
@@ -139,19 +650,86 @@ impl EasyFileSystem {
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks, // inode 区的起始块号
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks, // 数据区的起始块号
+                    data_area_blocks: super_block.data_area_blocks,
+                    journal: (super_block.journal_blocks >= 2).then(|| {
+                        Journal::new(super_block.journal_start_block(), super_block.journal_blocks)
+                    }),
+                    checksums: super_block.checksum_table(),
+                    extents: super_block.features & FEATURE_EXTENTS != 0,
+                    inodes: Arc::new(InodeCache::new(1 + super_block.inode_bitmap_blocks)),
+                    free_inodes: 0,
+                    free_blocks: 0,
+                    inode_hint: 0,
+                    block_hint: 0,
+                    delayed_commit: false,
+                    dirty_inodes: BTreeMap::new(),
+                    dirty_since: None,
+                    dirty_limit: usize::MAX,
+                    active_ops: 0,
+                    reserved_blocks: 0,
+                    commit_pending: false,
+                    orphans: BTreeSet::new(),
                 };
-                Arc::new(Mutex::new(efs))
-            })
+                Some(efs)
+            })?;
+        if let Some(journal) = &efs.journal {
+            journal.replay(&efs.block_device);
+        }
+        // 重做日志之后再读取空闲计数，旧镜像没有记录时由位图计算 (下一次提交时写入 SuperBlock)
+        let free_counts = get_block_cache(0, Arc::clone(&efs.block_device)).lock()
+            .read(0, |super_block: &SuperBlock| super_block.free_counts());
+        match free_counts {
+            Some((free_inodes, free_blocks)) => (efs.free_inodes, efs.free_blocks) = (free_inodes, free_blocks),
+            None => efs.recount_free(),
+        }
+        // 重做日志之后再启用校验: 日志中的事务已包含对应的校验表块
+        if let Some(table) = efs.checksums {
+            register_checksums(&efs.block_device, table);
+        }
+        Some(Arc::new(Mutex::new(efs)))
     }
     /// 获取根目录的 inode
-    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
-        let block_device = Arc::clone(&efs.lock().block_device);
-        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
-        Inode::new(
-            block_id,
-            block_offset,
-            Arc::clone(efs),
-            block_device,
-        )
+    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Arc<Inode> {
+        Self::get_inode(efs, 0).expect("root inode is not allocated")
+    }
+
+    /// inode ID 为 inode_id 的 inode，已在内存中时返回同一个 `Inode`
+    /// inode 超出 inode 区、未分配或已被删除 (编号可能过期，或是尚未释放的孤儿) 时返回 None
+    pub fn get_inode(efs: &Arc<Mutex<Self>>, inode_id: u32) -> Option<Arc<Inode>> {
+        // 持有 fs 锁时获取: 删除操作在 fs 锁下判断 inode 是否仍被打开
+        let fs = efs.lock();
+        if inode_id as usize >= fs.inode_bitmap.maximum()
+            || !fs.inode_bitmap.is_set(&fs.block_device, inode_id as usize)
+            || fs.orphans.contains(&inode_id) {
+            return None;
+        }
+        Some(InodeCache::get(&fs.inodes, inode_id, efs, &fs.block_device))
+    }
+}
+
+/// 进行中的操作 (见 `EasyFileSystem::begin_op`)，被丢弃时结束操作
+pub struct Op<'a> {
+    fs: &'a Mutex<EasyFileSystem>,
+    blocks: usize,
+}
+
+impl Op<'_> {
+    /// 在操作中短暂获取 fs 锁: 分配与释放、记录被修改的 inode
+    /// 不能在持有块缓存的锁时调用
+    pub fn fs(&self) -> MutexGuard<'_, EasyFileSystem> {
+        self.fs.lock()
+    }
+
+    /// 结束当前操作并开始一个最多修改 blocks 个块的新操作，用于分多个事务完成的操作 (重建目录索引)
+    pub fn restart(&mut self, blocks: usize) {
+        self.fs.lock().end_op(self.blocks);
+        EasyFileSystem::wait_begin_op(self.fs, blocks);
+        self.blocks = blocks;
+    }
+}
+
+impl Drop for Op<'_> {
+    fn drop(&mut self) {
+        self.fs.lock().end_op(self.blocks);
     }
 }
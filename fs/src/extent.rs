@@ -0,0 +1,331 @@
+// fs/src/extent.rs
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::block_cache::get_block_cache;
+use crate::block_dev::BlockDevice;
+use crate::config::BLOCK_SIZE;
+use crate::disk_inode::indirect_block_cache;
+
+pub const EXTENT_MAGIC: u16 = 0x5845; // "EX"
+/// inode 内的根节点最多记录的项数
+pub const ROOT_ENTRIES: usize = 9;
+/// 树节点块最多记录的项数
+pub const BLOCK_ENTRIES: usize = 42;
+
+// ----- Extent Tree -----
+/*
+使用 extent 的 inode (DiskInode 的 INODE_FLAG_EXTENTS) 不使用 direct / indirect1 / indirect2，
+这 30 个字保存 extent 树的根节点，文件的数据块由若干段连续的块 (extent) 组成:
+根节点: [header][entry] * 9          (116 字节，位于 DiskInode 内)
+树节点: [header][entry] * 42         (一个数据块)
+header: [magic: u16][entries: u16][depth: u16][reserved: u16]
+entry:  [logical: u32][start: u32][len: u32]
+depth 为 0 的叶节点中，entry 表示从文件的第 logical 块起的 len 块保存在从 start 开始的连续数据块中；
+depth 大于 0 的索引节点中，entry 表示从第 logical 块起的部分由块号为 start 的子节点记录 (len 不使用)
+同一节点中的 entry 按 logical 递增排列，相邻的 extent 首尾相接 (文件没有空洞)
+
+文件只在末尾增长或截断，因此树只沿最右侧的路径变化:
+追加的块与最后一个 extent 物理上相邻时直接延长它，否则在最右的叶节点中追加一项；
+叶节点已满时在最近的有空位的祖先下新建一条到叶节点的路径，根节点也满时先把根节点的内容移入新块，树高加一
+截断时释放超出新大小的 extent 与子树；根节点只剩一个子节点且放得下它的内容时，把它合并回根节点
+ */
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Extent {
+    pub logical: u32, // 第一个块在文件中的块号
+    pub start: u32,   // 叶节点: 第一个数据块的块号；索引节点: 子节点的块号
+    pub len: u32,     // 叶节点: 连续的块数；索引节点: 0
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExtentHeader {
+    pub magic: u16,
+    pub entries: u16,
+    pub depth: u16,
+    reserved: u16,
+}
+
+impl ExtentHeader {
+    fn new(entries: usize, depth: u16) -> Self {
+        Self { magic: EXTENT_MAGIC, entries: entries as u16, depth, reserved: 0 }
+    }
+}
+
+/// 位于 DiskInode 内的根节点
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExtentRoot {
+    pub header: ExtentHeader,
+    entries: [Extent; ROOT_ENTRIES],
+}
+
+/// 保存在数据块中的树节点
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ExtentBlock {
+    pub header: ExtentHeader,
+    entries: [Extent; BLOCK_ENTRIES],
+}
+
+const _: () = assert!(core::mem::size_of::<ExtentBlock>() == BLOCK_SIZE as usize);
+
+impl ExtentRoot {
+    /// 空树
+    pub fn initialize(&mut self) {
+        self.header = ExtentHeader::new(0, 0);
+        self.entries = [Extent::default(); ROOT_ENTRIES];
+    }
+    pub fn is_valid(&self) -> bool {
+        self.header.magic == EXTENT_MAGIC && self.header.entries as usize <= ROOT_ENTRIES
+    }
+    pub fn entries(&self) -> &[Extent] {
+        &self.entries[..(self.header.entries as usize).min(ROOT_ENTRIES)]
+    }
+    fn store(&mut self, depth: u16, entries: &[Extent]) {
+        self.header = ExtentHeader::new(entries.len(), depth);
+        self.entries = [Extent::default(); ROOT_ENTRIES];
+        self.entries[..entries.len()].copy_from_slice(entries);
+    }
+
+    /// 文件第 inner_id 块所在的数据块，以及从它开始 (含它) 连续的块数
+    /// inner_id 超出树记录的范围时返回 None
+    pub fn lookup(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> Option<(u32, u32)> {
+        let mut node = Node::from_root(self);
+        loop {
+            let index = node.entries.partition_point(|entry| entry.logical <= inner_id).checked_sub(1)?;
+            let entry = node.entries[index];
+            if node.depth == 0 {
+                let offset = inner_id - entry.logical;
+                return (offset < entry.len).then_some((entry.start + offset, entry.len - offset));
+            }
+            node = Node::read(entry.start, block_device);
+        }
+    }
+
+    /// 截断到 blocks 个数据块，返回被释放的数据块与树节点块
+    pub fn truncate(&mut self, blocks: u32, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        let mut freed = Vec::new();
+        let mut root = Node::from_root(self);
+        truncate_node(&mut root, blocks, block_device, &mut freed);
+        // 合并只剩一个子节点的根节点，空树的高度为 0
+        while root.depth > 0 && root.entries.len() <= 1 {
+            let Some(entry) = root.entries.first() else {
+                root.depth = 0;
+                break;
+            };
+            let child = Node::read(entry.start, block_device);
+            if child.entries.len() > ROOT_ENTRIES {
+                break;
+            }
+            freed.push(entry.start);
+            root.depth = child.depth;
+            root.entries = child.entries;
+        }
+        self.store(root.depth, &root.entries);
+        freed
+    }
+}
+
+/// 读取并检查树节点块 (供 fsck 使用)，格式不正确或深度不是 depth 时返回 None
+pub fn read_node(block_id: u32, depth: u16, block_device: &Arc<dyn BlockDevice>) -> Option<Vec<Extent>> {
+    get_block_cache(block_id as usize, Arc::clone(block_device)).lock()
+        .read(0, |block: &ExtentBlock| {
+            let header = block.header;
+            (header.magic == EXTENT_MAGIC && header.depth == depth && header.entries as usize <= BLOCK_ENTRIES)
+                .then(|| Vec::from(&block.entries[..header.entries as usize]))
+        })
+}
+
+/// 截断 node 下超出 blocks 的部分，返回 node 是否被修改 (被修改的子节点已写回)
+fn truncate_node(node: &mut Node, blocks: u32, block_device: &Arc<dyn BlockDevice>, freed: &mut Vec<u32>) -> bool {
+    let keep = node.entries.partition_point(|entry| entry.logical < blocks);
+    let mut changed = keep < node.entries.len();
+    for entry in node.entries.drain(keep..) {
+        free_subtree(&entry, node.depth, block_device, freed);
+    }
+    let Some(last) = node.entries.last_mut() else {
+        return changed;
+    };
+    if node.depth == 0 {
+        if last.logical + last.len > blocks {
+            let len = blocks - last.logical;
+            freed.extend(last.start + len..last.start + last.len);
+            last.len = len;
+            changed = true;
+        }
+    } else {
+        let mut child = Node::read(last.start, block_device);
+        if truncate_node(&mut child, blocks, block_device, freed) {
+            child.write(block_device);
+        }
+    }
+    changed
+}
+
+/// 释放 entry 记录的 extent 或子树
+fn free_subtree(entry: &Extent, depth: u16, block_device: &Arc<dyn BlockDevice>, freed: &mut Vec<u32>) {
+    if depth == 0 {
+        freed.extend(entry.start..entry.start + entry.len);
+        return;
+    }
+    for child_entry in Node::read(entry.start, block_device).entries.iter() {
+        free_subtree(child_entry, depth - 1, block_device, freed);
+    }
+    freed.push(entry.start);
+}
+
+/// 内存中的树节点
+#[derive(Default)]
+struct Node {
+    block: u32, // 所在的块号，根节点与尚未分配块的新节点为 0
+    depth: u16,
+    entries: Vec<Extent>,
+    new_children: Vec<(usize, usize)>, // (entry 下标, 子节点在 ExtentTree::nodes 中的下标): 指向新节点的 entry
+    dirty: bool,
+}
+
+impl Node {
+    fn from_root(root: &ExtentRoot) -> Self {
+        Self { depth: root.header.depth, entries: Vec::from(root.entries()), ..Self::default() }
+    }
+    fn read(block_id: u32, block_device: &Arc<dyn BlockDevice>) -> Self {
+        get_block_cache(block_id as usize, Arc::clone(block_device)).lock()
+            .read(0, |block: &ExtentBlock| Self {
+                block: block_id,
+                depth: block.header.depth,
+                entries: Vec::from(&block.entries[..(block.header.entries as usize).min(BLOCK_ENTRIES)]),
+                ..Self::default()
+            })
+    }
+    fn write(&self, block_device: &Arc<dyn BlockDevice>) {
+        indirect_block_cache(self.block, block_device).lock()
+            .modify(0, |block: &mut ExtentBlock| {
+                block.header = ExtentHeader::new(self.entries.len(), self.depth);
+                block.entries = [Extent::default(); BLOCK_ENTRIES];
+                block.entries[..self.entries.len()].copy_from_slice(&self.entries);
+            });
+    }
+}
+
+/// 在内存中向树的末尾追加 extent，之后由 `store` 写回:
+/// 先用 `append` 记录新分配的数据块，再为 `new_nodes` 个新节点分配块
+pub struct ExtentTree {
+    nodes: Vec<Node>, // nodes[0] 为根节点，其余是最右路径上的节点与新节点
+    path: Vec<usize>, // 从根到最右叶节点的路径 (nodes 的下标)
+    blocks: u32,      // 树记录的数据块数
+}
+
+impl ExtentTree {
+    // ----- constructor -----
+    /// 读取根节点与最右路径
+    pub fn load(root: &ExtentRoot, block_device: &Arc<dyn BlockDevice>) -> Self {
+        let mut tree = Self { nodes: alloc::vec![Node::from_root(root)], path: alloc::vec![0], blocks: 0 };
+        while tree.nodes.last().unwrap().depth > 0 {
+            let Some(last) = tree.nodes.last().unwrap().entries.last() else {
+                break;
+            };
+            let child = Node::read(last.start, block_device);
+            tree.path.push(tree.nodes.len());
+            tree.nodes.push(child);
+        }
+        if let Some(last) = tree.nodes.last().unwrap().entries.last() {
+            tree.blocks = last.logical + last.len;
+        }
+        tree
+    }
+    pub fn empty() -> Self {
+        Self { nodes: alloc::vec![Node::default()], path: alloc::vec![0], blocks: 0 }
+    }
+    // ----- methods -----
+    /// 树记录的数据块数
+    pub fn blocks(&self) -> u32 {
+        self.blocks
+    }
+    /// 追加从 start 开始的 len 个连续数据块
+    pub fn append(&mut self, start: u32, len: u32) {
+        let leaf = *self.path.last().unwrap();
+        let logical = self.blocks;
+        self.blocks += len;
+        if let Some(last) = self.nodes[leaf].entries.last_mut()
+            && last.start + last.len == start {
+            last.len += len;
+            self.nodes[leaf].dirty = true;
+            return;
+        }
+        let extent = Extent { logical, start, len };
+        // 路径上最低的有空位的节点，都满时树高加一
+        let level = (0..self.path.len()).rev()
+            .find(|&level| self.nodes[self.path[level]].entries.len() < Self::capacity(level))
+            .unwrap_or_else(|| self.grow());
+        let mut parent = self.path[level];
+        self.path.truncate(level + 1);
+        // 从该节点向下新建一条路径，直到叶节点
+        while self.nodes[parent].depth > 0 {
+            let child = self.nodes.len();
+            let depth = self.nodes[parent].depth - 1;
+            self.nodes.push(Node { depth, dirty: true, ..Node::default() });
+            let node = &mut self.nodes[parent];
+            node.new_children.push((node.entries.len(), child));
+            node.entries.push(Extent { logical, start: 0, len: 0 });
+            node.dirty = true;
+            self.path.push(child);
+            parent = child;
+        }
+        self.nodes[parent].entries.push(extent);
+        self.nodes[parent].dirty = true;
+    }
+
+    /// 需要分配块的新节点数
+    pub fn new_nodes(&self) -> usize {
+        self.nodes.iter().skip(1).filter(|node| node.block == 0).count()
+    }
+
+    /// 为新节点依次使用 node_blocks 中的块，写回所有被修改的节点
+    pub fn store(mut self, root: &mut ExtentRoot, node_blocks: Vec<u32>, block_device: &Arc<dyn BlockDevice>) {
+        let mut node_blocks = node_blocks.into_iter();
+        for node in self.nodes.iter_mut().skip(1).filter(|node| node.block == 0) {
+            node.block = node_blocks.next().unwrap();
+        }
+        let fixes: Vec<(usize, usize, u32)> = self.nodes.iter().enumerate()
+            .flat_map(|(index, node)| node.new_children.iter().map(move |(entry, child)| (index, *entry, *child)))
+            .map(|(index, entry, child)| (index, entry, self.nodes[child].block))
+            .collect();
+        for (index, entry, block) in fixes {
+            self.nodes[index].entries[entry].start = block;
+        }
+        for (index, node) in self.nodes.iter().enumerate().filter(|(_, node)| node.dirty) {
+            if index == 0 {
+                root.store(node.depth, &node.entries);
+            } else {
+                node.write(block_device);
+            }
+        }
+    }
+
+    fn capacity(level: usize) -> usize {
+        if level == 0 { ROOT_ENTRIES } else { BLOCK_ENTRIES }
+    }
+
+    /// 把已满的根节点的内容移入新节点，树高加一，返回根节点所在的层 (0)
+    fn grow(&mut self) -> usize {
+        let child = self.nodes.len();
+        let root = &mut self.nodes[0];
+        let moved = Node {
+            depth: root.depth,
+            entries: core::mem::take(&mut root.entries),
+            new_children: core::mem::take(&mut root.new_children),
+            dirty: true,
+            ..Node::default()
+        };
+        root.depth += 1;
+        root.entries.push(Extent { logical: moved.entries[0].logical, start: 0, len: 0 });
+        root.new_children.push((0, child));
+        root.dirty = true;
+        self.nodes.push(moved);
+        self.path.insert(1, child);
+        0
+    }
+}
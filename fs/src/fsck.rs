@@ -0,0 +1,838 @@
+// fs/src/fsck.rs
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+use crate::block_cache::get_block_cache;
+use crate::block_dev::BlockDevice;
+use crate::checksum::{crc32c, table_block_checksum, ChecksumBlock, CHECKSUMS_PER_BLOCK};
+use crate::config::{BLOCK_SIZE, INODE_DIRECT_COUNT, INODE_INDIRECT1_COUNT, INODE_PER_BLOCK};
+use crate::dir_index::{name_hash, Bucket, DirIndex};
+use crate::disk_inode::{indirect_block_cache, DataBlock, DirEntry, DiskInode, DiskInodeType, IndirectBlock};
+use crate::efs::EasyFileSystem;
+use crate::extent::{self, Extent, ExtentTree, ROOT_ENTRIES};
+use crate::super_block::SuperBlock;
+use crate::xattr;
+
+// ----- File System Checker -----
+/*
+0. 校验表块自身的校验和正确，记录了校验和的块与记录一致 (SuperBlock 的校验和在打开时检查)
+从根目录 (inode 0) 出发遍历整棵目录树:
+1. 每个可达 inode 的块指针 (direct / indirect1 / indirect2 / xattr_block) 必须指向数据区，且不能被两个地方引用
+   使用 extent 树的 inode 的节点格式正确，extent 首尾相接且恰好覆盖文件大小，节点块与数据块同样检查
+   扩展属性块的格式必须正确
+2. 文件大小与块指针一致: 超出大小的指针必须为 0
+3. 目录由整块组成、记录长度合法，目录项的名字合法、不重名，指向合法的 inode，每个 inode 只被一个目录项引用
+   目录索引 (如果有) 恰好记录了所有目录项
+4. inode_bitmap / data_bitmap 与可达的 inode / 数据块完全一致，SuperBlock 中记录的空闲计数与位图一致
+修复模式下先接受校验和不符的块的当前内容 (重新计算校验和)，再截断损坏的块指针 (extent 树用合法的前缀重建)、丢弃损坏的扩展属性块、删除非法的目录项与不一致的目录索引，最后按可达集合重建两个位图并重新计算空闲计数
+ */
+
+/// fsck 发现的问题
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// SuperBlock 中各区域大小之和与总块数不符
+    BadSuperBlock,
+    /// 校验表块自身的校验和不符，它记录的校验和不可信
+    BadChecksumTable { block: u32 },
+    /// 块的内容与校验表中记录的校验和不符
+    BadChecksum { block: u32 },
+    /// inode 的类型不是文件、目录或符号链接
+    InvalidInodeType { inode: u32, raw_type: u32 },
+    /// 第 `index` 个数据块 (或其所在的索引块) 的指针指向数据区之外
+    BadBlockPointer { inode: u32, index: u32, block: u32 },
+    /// 块已被 inode `owner` (可能就是本 inode 的其他位置) 引用；先引用它的 inode 保留该块
+    DoubleAllocated { inode: u32, index: u32, block: u32, owner: u32 },
+    /// 文件大小与块指针数量不符: 大小之外还有非零的块指针
+    StalePointer { inode: u32, size: u32 },
+    /// extent 树的节点格式错误或 extent 不连续，`block` 为节点所在的块 (0 表示 inode 内的根节点)
+    BadExtentNode { inode: u32, block: u32 },
+    /// 扩展属性块指向数据区之外、已被引用或格式错误
+    BadXattrBlock { inode: u32, block: u32 },
+    /// 文件大小超出 easy-fs 支持的最大值
+    BadSize { inode: u32, size: u32 },
+    /// 目录大小不是块大小的整数倍
+    BadDirectorySize { inode: u32, size: u32 },
+    /// 目录中偏移 `offset` 处的记录长度非法，该块中其后的目录项无法读取
+    BadDirEntry { dir: u32, offset: u32 },
+    /// 目录项的名字不是 UTF-8、包含 '/' 或为 "." / ".."
+    InvalidName { dir: u32, offset: u32 },
+    /// 目录索引的块号非法，或索引与目录项不一致
+    BadDirIndex { dir: u32 },
+    /// 同一目录中有重名的目录项
+    DuplicateName { dir: u32, name: String },
+    /// 目录项指向的 inode 编号超出 inode 区，或该 inode 的类型非法
+    DanglingEntry { dir: u32, name: String, inode: u32 },
+    /// inode 被多个目录项引用 (easy-fs 不支持硬链接)
+    MultiplyLinked { dir: u32, name: String, inode: u32 },
+    /// 可达的 inode 在 inode_bitmap 中未被标记
+    InodeNotMarked { inode: u32 },
+    /// inode_bitmap 中标记的 inode 不可达
+    InodeLeaked { inode: u32 },
+    /// 被引用的数据块在 data_bitmap 中未被标记
+    BlockNotMarked { block: u32 },
+    /// data_bitmap 中标记的数据块没有被引用
+    BlockLeaked { block: u32 },
+    /// SuperBlock 中记录的空闲 inode 数与 inode_bitmap 不符
+    BadFreeInodeCount { recorded: u32, actual: u32 },
+    /// SuperBlock 中记录的空闲数据块数与 data_bitmap 不符
+    BadFreeBlockCount { recorded: u32, actual: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::BadSuperBlock => write!(f, "superblock regions do not add up to total blocks"),
+            Problem::BadChecksumTable { block } => write!(f, "checksum table block {} is corrupt", block),
+            Problem::BadChecksum { block } => write!(f, "block {}: checksum mismatch", block),
+            Problem::InvalidInodeType { inode, raw_type } =>
+                write!(f, "inode {}: invalid type {}", inode, raw_type),
+            Problem::BadBlockPointer { inode, index, block } =>
+                write!(f, "inode {}: block {} at index {} is outside the data area", inode, block, index),
+            Problem::DoubleAllocated { inode, index, block, owner } =>
+                write!(f, "inode {}: block {} at index {} is already in use by inode {}", inode, block, index, owner),
+            Problem::StalePointer { inode, size } =>
+                write!(f, "inode {}: non-zero block pointers beyond size {}", inode, size),
+            Problem::BadExtentNode { inode, block: 0 } => write!(f, "inode {}: bad extent tree root", inode),
+            Problem::BadExtentNode { inode, block } => write!(f, "inode {}: bad extent tree node {}", inode, block),
+            Problem::BadXattrBlock { inode, block } =>
+                write!(f, "inode {}: extended attribute block {} is invalid", inode, block),
+            Problem::BadSize { inode, size } =>
+                write!(f, "inode {}: size {} exceeds the maximum file size", inode, size),
+            Problem::BadDirectorySize { inode, size } =>
+                write!(f, "inode {}: directory size {} is not a multiple of {}", inode, size, BLOCK_SIZE),
+            Problem::BadDirEntry { dir, offset } =>
+                write!(f, "directory {}: corrupt entry at offset {}", dir, offset),
+            Problem::InvalidName { dir, offset } =>
+                write!(f, "directory {}: entry at offset {} has an invalid name", dir, offset),
+            Problem::BadDirIndex { dir } => write!(f, "directory {}: index does not match its entries", dir),
+            Problem::DuplicateName { dir, name } =>
+                write!(f, "directory {}: duplicate entry \"{}\"", dir, name),
+            Problem::DanglingEntry { dir, name, inode } =>
+                write!(f, "directory {}: entry \"{}\" points to invalid inode {}", dir, name, inode),
+            Problem::MultiplyLinked { dir, name, inode } =>
+                write!(f, "directory {}: entry \"{}\" links inode {} which is already linked", dir, name, inode),
+            Problem::InodeNotMarked { inode } => write!(f, "inode {} is in use but free in inode bitmap", inode),
+            Problem::InodeLeaked { inode } => write!(f, "inode {} is allocated but unreachable", inode),
+            Problem::BlockNotMarked { block } => write!(f, "block {} is in use but free in data bitmap", block),
+            Problem::BlockLeaked { block } => write!(f, "block {} is allocated but unused", block),
+            Problem::BadFreeInodeCount { recorded, actual } =>
+                write!(f, "superblock records {} free inodes, bitmap has {}", recorded, actual),
+            Problem::BadFreeBlockCount { recorded, actual } =>
+                write!(f, "superblock records {} free blocks, bitmap has {}", recorded, actual),
+        }
+    }
+}
+
+/// fsck 的结果
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub problems: Vec<Problem>,
+    pub files: usize,
+    pub directories: usize,
+    pub data_blocks: usize, // 被引用的数据块数 (含索引块)
+    pub repaired: bool,     // 是否以修复模式运行并修改了镜像
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// 检查 (repair 为 true 时修复) 一个已打开的 easy-fs
+pub fn fsck(efs: &Arc<Mutex<EasyFileSystem>>, repair: bool) -> FsckReport {
+    let mut fs = efs.lock();
+    let block_device = Arc::clone(&fs.block_device);
+    let super_block = get_block_cache(0, Arc::clone(&block_device)).lock()
+        .read(0, |super_block: &SuperBlock| *super_block);
+    let mut checker = Checker::new(&fs, block_device, &super_block, repair);
+    if checker.layout_blocks(&super_block) != super_block.total_blocks {
+        checker.report(Problem::BadSuperBlock);
+    }
+    checker.check_checksums(&mut fs);
+    checker.walk(&mut fs);
+    checker.check_free_counts(&fs);
+    checker.check_bitmaps(&fs);
+    if repair {
+        fs.recount_free();
+        fs.commit_or_write_back();
+    }
+    // 遍历时加载到的损坏块已在上面报告
+    fs.take_checksum_errors();
+    checker.report.repaired = repair && !checker.report.problems.is_empty();
+    checker.report
+}
+
+// ----- Checker -----
+
+/// extent 树遍历的结果
+#[derive(Default)]
+struct ExtentWalk {
+    claimed: Vec<u32>,       // 已标记的块 (数据块与节点块)
+    nodes: Vec<u32>,         // 其中的节点块
+    runs: Vec<(u32, u32)>,   // 合法的前缀中连续的数据块 (起始块号, 块数)
+    blocks: u32,             // 合法的前缀中的数据块数
+    bad: bool,               // 遇到了非法的节点或块指针
+    stale: bool,             // 文件大小之外还有 extent
+}
+
+/// 块指针遍历的结果
+enum BlockWalk {
+    /// 所有块 (数据块与索引块)
+    Ok(Vec<u32>),
+    /// 第 index 个数据块处出错，之前的块已被标记为已引用
+    Bad { claimed: Vec<u32>, index: u32 },
+}
+
+struct Checker {
+    block_device: Arc<dyn BlockDevice>,
+    repair: bool,
+    inode_count: u32,
+    data_area_start_block: u32,
+    data_area_blocks: u32,
+    reached: Vec<bool>, // inode 是否可达
+    owners: Vec<Option<u32>>, // 引用数据块的 inode
+    report: FsckReport,
+}
+
+impl Checker {
+    fn new(fs: &EasyFileSystem, block_device: Arc<dyn BlockDevice>, super_block: &SuperBlock, repair: bool) -> Self {
+        let inode_count = (super_block.inode_area_blocks * INODE_PER_BLOCK)
+            .min(fs.inode_bitmap.maximum() as u32);
+        let data_area_start_block = 1 + super_block.inode_bitmap_blocks
+            + super_block.inode_area_blocks + super_block.data_bitmap_blocks;
+        Self {
+            block_device,
+            repair,
+            inode_count,
+            data_area_start_block,
+            data_area_blocks: super_block.data_area_blocks,
+            reached: vec![false; inode_count as usize],
+            owners: vec![None; super_block.data_area_blocks as usize],
+            report: FsckReport::default(),
+        }
+    }
+
+    fn layout_blocks(&self, super_block: &SuperBlock) -> u32 {
+        self.data_area_start_block + super_block.data_area_blocks + super_block.checksum_blocks + super_block.journal_blocks
+    }
+
+    fn report(&mut self, problem: Problem) {
+        self.report.problems.push(problem);
+    }
+
+    fn read_inode<V>(&self, fs: &EasyFileSystem, inode: u32, f: impl FnOnce(&DiskInode) -> V) -> V {
+        let (block_id, offset) = fs.get_disk_inode_pos(inode);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock().read(offset, f)
+    }
+
+    fn modify_inode<V>(&self, fs: &EasyFileSystem, inode: u32, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+        let (block_id, offset) = fs.get_disk_inode_pos(inode);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock().modify(offset, f)
+    }
+
+    fn read_indirect(&self, block: u32, index: u32) -> u32 {
+        get_block_cache(block as usize, Arc::clone(&self.block_device)).lock()
+            .read(0, |indirect: &IndirectBlock| indirect[index as usize])
+    }
+
+    /// 直接从块设备读取 (不经过块缓存) 并检查校验表覆盖的所有块
+    fn check_checksums(&mut self, fs: &mut EasyFileSystem) {
+        let Some(table) = fs.checksum_table() else {
+            return;
+        };
+        let mut data: DataBlock = [0; BLOCK_SIZE as usize];
+        for table_block in table.start_block()..table.start_block() + table.blocks() {
+            self.block_device.read_block(table_block as usize, &mut data);
+            let entries: ChecksumBlock =
+                core::array::from_fn(|i| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap()));
+            if table_block_checksum(&entries) != entries[CHECKSUMS_PER_BLOCK as usize] {
+                self.report(Problem::BadChecksumTable { block: table_block });
+                if !self.repair {
+                    continue; // 记录不可信，不检查它覆盖的块
+                }
+                self.accept(table_block);
+            }
+            let first = (table_block - table.start_block()) * CHECKSUMS_PER_BLOCK;
+            for (index, checksum) in entries[..CHECKSUMS_PER_BLOCK as usize].iter().enumerate() {
+                let block = first + index as u32;
+                if *checksum == 0 || table.locate(block as usize).is_none() {
+                    continue;
+                }
+                self.block_device.read_block(block as usize, &mut data);
+                if crc32c(&data) != *checksum {
+                    self.report(Problem::BadChecksum { block });
+                    if self.repair {
+                        self.accept(block);
+                    }
+                }
+            }
+        }
+        if self.repair {
+            fs.commit_or_write_back();
+        }
+    }
+
+    fn accept(&self, block: u32) {
+        get_block_cache(block as usize, Arc::clone(&self.block_device)).lock().accept();
+    }
+
+    /// 从根目录广度优先遍历
+    fn walk(&mut self, fs: &mut EasyFileSystem) {
+        let raw_type = self.read_inode(fs, 0, |disk_inode| disk_inode.raw_type());
+        if raw_type != DiskInodeType::Directory as u32 {
+            // 根目录损坏时无法继续检查
+            self.report(Problem::InvalidInodeType { inode: 0, raw_type });
+            return;
+        }
+        self.reached[0] = true;
+        let mut queue = VecDeque::from([0u32]);
+        // 已删除但仍被打开的 inode (孤儿) 不在目录树中，同样检查它们引用的块
+        for orphan in fs.orphans() {
+            if !self.reached[orphan as usize] {
+                self.reached[orphan as usize] = true;
+                queue.push_back(orphan);
+            }
+        }
+        while let Some(inode) = queue.pop_front() {
+            let is_dir = self.read_inode(fs, inode, |disk_inode| disk_inode.is_dir());
+            if is_dir {
+                self.report.directories += 1;
+            } else {
+                self.report.files += 1;
+            }
+            let (mut blocks, readable_size) = self.check_blocks(fs, inode);
+            if is_dir {
+                let (children, changed) = self.check_dirents(fs, inode, readable_size);
+                if changed {
+                    // 目录被压缩，重新计算它引用的块
+                    blocks.iter().for_each(|block| self.unclaim(*block));
+                    blocks = self.check_blocks(fs, inode).0;
+                }
+                queue.extend(children);
+            }
+            blocks.extend(self.check_xattr_block(fs, inode));
+            self.report.data_blocks += blocks.len();
+            if self.repair {
+                fs.commit_or_write_back();
+            }
+        }
+    }
+
+    fn claim(&mut self, inode: u32, block: u32) {
+        self.owners[(block - self.data_area_start_block) as usize] = Some(inode);
+    }
+
+    fn owner(&self, block: u32) -> Option<u32> {
+        self.owners[(block - self.data_area_start_block) as usize]
+    }
+
+    fn is_claimed(&self, block: u32) -> bool {
+        self.owner(block).is_some()
+    }
+
+    fn unclaim(&mut self, block: u32) {
+        self.owners[(block - self.data_area_start_block) as usize] = None;
+    }
+
+    /// 检查块指针并标记 inode 引用的块
+    /// 返回这些块，以及可以安全读取的字节数 (只检查不修复时，出错位置之后的内容不可读)
+    fn check_blocks(&mut self, fs: &EasyFileSystem, inode: u32) -> (Vec<u32>, u32) {
+        let (size, max_size) = self.read_inode(fs, inode, |disk_inode| (disk_inode.size, disk_inode.max_size() as u64));
+        if size as u64 > max_size {
+            self.report(Problem::BadSize { inode, size });
+            if !self.repair {
+                return (Vec::new(), 0);
+            }
+            self.modify_inode(fs, inode, |disk_inode| disk_inode.size = max_size as u32);
+        }
+        if self.read_inode(fs, inode, |disk_inode| disk_inode.uses_extents()) {
+            return self.check_extents(fs, inode);
+        }
+        let blocks = match self.walk_blocks(fs, inode) {
+            BlockWalk::Ok(blocks) => blocks,
+            BlockWalk::Bad { claimed, index } => {
+                if !self.repair {
+                    return (claimed, index * BLOCK_SIZE);
+                }
+                // 截断到出错位置之前，重新遍历
+                claimed.iter().for_each(|block| self.unclaim(*block));
+                self.modify_inode(fs, inode, |disk_inode| {
+                    truncate(disk_inode, &self.block_device, (index * BLOCK_SIZE).min(disk_inode.size));
+                });
+                match self.walk_blocks(fs, inode) {
+                    BlockWalk::Ok(blocks) => blocks,
+                    BlockWalk::Bad { claimed, .. } => claimed,
+                }
+            }
+        };
+        // 超出文件大小的指针
+        let size = self.read_inode(fs, inode, |disk_inode| disk_inode.size);
+        if self.has_stale_pointers(fs, inode) {
+            self.report(Problem::StalePointer { inode, size });
+            if self.repair {
+                self.modify_inode(fs, inode, |disk_inode| truncate(disk_inode, &self.block_device, size));
+            }
+        }
+        (blocks, size)
+    }
+
+    /// 同 `check_blocks`，用于使用 extent 树的 inode
+    /// 修复时用合法的前缀 (不超过文件大小) 重建整棵树
+    fn check_extents(&mut self, fs: &EasyFileSystem, inode: u32) -> (Vec<u32>, u32) {
+        let (root, size, data_blocks) = self.read_inode(fs, inode, |disk_inode| {
+            (*disk_inode.extent_root(), disk_inode.size, disk_inode.data_block_num())
+        });
+        let mut walk = ExtentWalk::default();
+        if root.is_valid() {
+            self.walk_extents(inode, root.header.depth, 0, root.entries(), data_blocks, &mut walk);
+        } else {
+            self.report(Problem::BadExtentNode { inode, block: 0 });
+            walk.bad = true;
+        }
+        if !walk.bad && walk.blocks < data_blocks {
+            // 树记录的块比文件大小少，缺少的第一个块视为空指针
+            self.report(Problem::BadBlockPointer { inode, index: walk.blocks, block: 0 });
+            walk.bad = true;
+        }
+        if walk.stale {
+            self.report(Problem::StalePointer { inode, size });
+        }
+        if !walk.bad && !walk.stale {
+            return (walk.claimed, size);
+        }
+        let readable_size = size.min(walk.blocks.saturating_mul(BLOCK_SIZE));
+        if !self.repair {
+            return (walk.claimed, readable_size);
+        }
+        // 新节点优先使用原来的节点块，不够时使用任意未被引用的块；没有空闲块时只保留根节点放得下的部分
+        let mut tree = ExtentTree::empty();
+        walk.runs.iter().for_each(|(start, len)| tree.append(*start, *len));
+        let mut node_blocks = core::mem::take(&mut walk.nodes);
+        while node_blocks.len() < tree.new_nodes() {
+            let Some(pos) = self.owners.iter().position(Option::is_none) else {
+                break;
+            };
+            let block = pos as u32 + self.data_area_start_block;
+            self.claim(inode, block);
+            walk.claimed.push(block);
+            node_blocks.push(block);
+        }
+        let mut new_size = readable_size;
+        if node_blocks.len() < tree.new_nodes() {
+            tree = ExtentTree::empty();
+            walk.runs.iter().take(ROOT_ENTRIES).for_each(|(start, len)| tree.append(*start, *len));
+            new_size = new_size.min(tree.blocks().saturating_mul(BLOCK_SIZE));
+        }
+        // 没有用到的原节点块与超出新大小的数据块不再被引用
+        let used_blocks: Vec<u32> = walk.runs.iter().flat_map(|(start, len)| *start..*start + *len)
+            .take(tree.blocks() as usize)
+            .chain(node_blocks.iter().take(tree.new_nodes()).copied())
+            .collect();
+        let mut used_sorted = used_blocks.clone();
+        used_sorted.sort_unstable();
+        for block in walk.claimed.iter().filter(|block| used_sorted.binary_search(block).is_err()) {
+            self.unclaim(*block);
+        }
+        node_blocks.truncate(tree.new_nodes());
+        self.modify_inode(fs, inode, |disk_inode| {
+            disk_inode.size = 0;
+            disk_inode.set_extents();
+            disk_inode.increase_size_extents(new_size, tree, node_blocks, &self.block_device);
+        });
+        (used_blocks, new_size)
+    }
+
+    /// 按 logical 顺序遍历 extent 树的一个节点 (node 为其块号，根节点为 0)，返回是否继续
+    fn walk_extents(&mut self, inode: u32, depth: u16, node: u32, entries: &[Extent], data_blocks: u32, walk: &mut ExtentWalk) -> bool {
+        for entry in entries {
+            if entry.logical != walk.blocks || (depth == 0 && entry.len == 0) {
+                self.report(Problem::BadExtentNode { inode, block: node });
+                walk.bad = true;
+                return false;
+            }
+            if walk.blocks >= data_blocks {
+                walk.stale = true;
+                return false;
+            }
+            if depth == 0 {
+                for offset in 0..entry.len {
+                    if walk.blocks == data_blocks {
+                        walk.stale = true;
+                        return false;
+                    }
+                    let block = entry.start.saturating_add(offset);
+                    if !self.try_claim(inode, walk.blocks, block, &mut walk.claimed) {
+                        walk.bad = true;
+                        return false;
+                    }
+                    match walk.runs.last_mut() {
+                        Some((start, len)) if *start + *len == block => *len += 1,
+                        _ => walk.runs.push((block, 1)),
+                    }
+                    walk.blocks += 1;
+                }
+                continue;
+            }
+            if !self.try_claim(inode, walk.blocks, entry.start, &mut walk.claimed) {
+                walk.bad = true;
+                return false;
+            }
+            walk.nodes.push(entry.start);
+            let children = extent::read_node(entry.start, depth - 1, &self.block_device).filter(|children| !children.is_empty());
+            let Some(children) = children else {
+                self.report(Problem::BadExtentNode { inode, block: entry.start });
+                walk.bad = true;
+                return false;
+            };
+            if !self.walk_extents(inode, depth - 1, entry.start, &children, data_blocks, walk) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 检查并标记扩展属性块，不合法时报告 (修复模式下丢弃该块)
+    fn check_xattr_block(&mut self, fs: &EasyFileSystem, inode: u32) -> Option<u32> {
+        let block = self.read_inode(fs, inode, |disk_inode| disk_inode.xattr_block);
+        if block == 0 {
+            return None;
+        }
+        let valid = self.in_data_area(block) && !self.is_claimed(block)
+            && get_block_cache(block as usize, Arc::clone(&self.block_device)).lock()
+                .read(0, |data: &DataBlock| xattr::decode(data).is_some());
+        if !valid {
+            self.report(Problem::BadXattrBlock { inode, block });
+            if self.repair {
+                self.modify_inode(fs, inode, |disk_inode| disk_inode.xattr_block = 0);
+            }
+            return None;
+        }
+        self.claim(inode, block);
+        Some(block)
+    }
+
+    /// 按数据块顺序遍历块指针，遇到第一个非法指针时停止
+    fn walk_blocks(&mut self, fs: &EasyFileSystem, inode: u32) -> BlockWalk {
+        let (data_blocks, direct, indirect1, indirect2) = self.read_inode(fs, inode, |disk_inode| {
+            (disk_inode.data_block_num(), disk_inode.direct, disk_inode.indirect1, disk_inode.indirect2)
+        });
+        let mut claimed = Vec::new();
+        let mut sub_indirect1 = 0;
+        for index in 0..data_blocks {
+            // 先检查数据块所在的索引块
+            let block = if index < INODE_DIRECT_COUNT {
+                direct[index as usize]
+            } else if index < INODE_DIRECT_COUNT + INODE_INDIRECT1_COUNT {
+                if index == INODE_DIRECT_COUNT && !self.try_claim(inode, index, indirect1, &mut claimed) {
+                    return BlockWalk::Bad { claimed, index };
+                }
+                self.read_indirect(indirect1, index - INODE_DIRECT_COUNT)
+            } else {
+                let last = index - INODE_DIRECT_COUNT - INODE_INDIRECT1_COUNT;
+                if last == 0 && !self.try_claim(inode, index, indirect2, &mut claimed) {
+                    return BlockWalk::Bad { claimed, index };
+                }
+                if last.is_multiple_of(INODE_INDIRECT1_COUNT) {
+                    sub_indirect1 = self.read_indirect(indirect2, last / INODE_INDIRECT1_COUNT);
+                    if !self.try_claim(inode, index, sub_indirect1, &mut claimed) {
+                        return BlockWalk::Bad { claimed, index };
+                    }
+                }
+                self.read_indirect(sub_indirect1, last % INODE_INDIRECT1_COUNT)
+            };
+            if !self.try_claim(inode, index, block, &mut claimed) {
+                return BlockWalk::Bad { claimed, index };
+            }
+        }
+        BlockWalk::Ok(claimed)
+    }
+
+    /// 检查块指针合法且未被引用，然后标记为已引用
+    /// 被两个 inode 引用的块按遍历 (广度优先) 顺序归先引用它的 inode，后者在修复模式下从该位置截断
+    fn try_claim(&mut self, inode: u32, index: u32, block: u32, claimed: &mut Vec<u32>) -> bool {
+        if !self.in_data_area(block) {
+            self.report(Problem::BadBlockPointer { inode, index, block });
+            return false;
+        }
+        if let Some(owner) = self.owner(block) {
+            self.report(Problem::DoubleAllocated { inode, index, block, owner });
+            return false;
+        }
+        self.claim(inode, block);
+        claimed.push(block);
+        true
+    }
+
+    fn has_stale_pointers(&self, fs: &EasyFileSystem, inode: u32) -> bool {
+        let (data_blocks, direct, indirect1, indirect2, inline) = self.read_inode(fs, inode, |disk_inode| {
+            (disk_inode.data_block_num(), disk_inode.direct, disk_inode.indirect1, disk_inode.indirect2, disk_inode.is_inline())
+        });
+        // 目标保存在 inode 内的符号链接，direct 数组中是目标而不是块指针
+        if !inline && direct.iter().skip(data_blocks as usize).any(|block| *block != 0) {
+            return true;
+        }
+        if data_blocks <= INODE_DIRECT_COUNT {
+            return indirect1 != 0 || indirect2 != 0;
+        }
+        if data_blocks <= INODE_DIRECT_COUNT + INODE_INDIRECT1_COUNT {
+            return indirect2 != 0;
+        }
+        if !self.in_data_area(indirect2) {
+            return false; // 已作为 BadBlockPointer 报告
+        }
+        let used = sub_indirect1_count(data_blocks);
+        (used..INODE_INDIRECT1_COUNT).any(|i| self.read_indirect(indirect2, i) != 0)
+    }
+
+    fn in_data_area(&self, block: u32) -> bool {
+        block >= self.data_area_start_block && block < self.data_area_start_block + self.data_area_blocks
+    }
+
+    /// 检查目录项，返回合法的子 inode 以及目录是否被修改
+    fn check_dirents(&mut self, fs: &EasyFileSystem, dir: u32, readable_size: u32) -> (Vec<u32>, bool) {
+        let size = self.read_inode(fs, dir, |disk_inode| disk_inode.size);
+        let mut changed = false;
+        if !size.is_multiple_of(BLOCK_SIZE) {
+            self.report(Problem::BadDirectorySize { inode: dir, size });
+            changed = self.repair;
+        }
+        let count = size.min(readable_size) / BLOCK_SIZE;
+        let blocks: Vec<[u8; BLOCK_SIZE as usize]> = self.read_inode(fs, dir, |disk_inode| {
+            (0..count).map(|i| {
+                let mut block = [0u8; BLOCK_SIZE as usize];
+                disk_inode.read_at((i * BLOCK_SIZE) as usize, &mut block, &self.block_device);
+                block
+            }).collect()
+        });
+
+        let mut kept = Vec::new();
+        let mut children = Vec::new();
+        let mut names: Vec<String> = Vec::new();
+        let mut index_root = 0;
+        let mut located = Vec::new(); // 所有普通目录项的 (hash, location)，用于检查索引
+        for (index, block) in blocks.iter().enumerate() {
+            let mut offset = 0;
+            while offset < BLOCK_SIZE as usize {
+                let location = index as u32 * BLOCK_SIZE + offset as u32;
+                let Some(dirent) = DirEntry::parse(block, offset) else {
+                    self.report(Problem::BadDirEntry { dir, offset: location });
+                    changed |= self.repair;
+                    break;
+                };
+                offset += dirent.rec_len() as usize;
+                if dirent.is_free() {
+                    continue;
+                }
+                if dirent.is_index() {
+                    // 索引记录只能位于目录开头
+                    if location == 0 && dirent.raw_name().is_empty() {
+                        index_root = dirent.index_root();
+                    } else {
+                        self.report(Problem::BadDirIndex { dir });
+                        changed |= self.repair;
+                    }
+                    continue;
+                }
+                located.push((name_hash(dirent.raw_name()), location));
+                let name = match dirent.get_name() {
+                    Some(name) if is_valid_name(name) => String::from(name),
+                    _ => {
+                        self.report(Problem::InvalidName { dir, offset: location });
+                        changed |= self.repair;
+                        continue;
+                    }
+                };
+                if names.contains(&name) {
+                    self.report(Problem::DuplicateName { dir, name });
+                    changed |= self.repair;
+                    continue;
+                }
+                let inode = dirent.get_inode_number();
+                let valid_type = inode < self.inode_count
+                    && self.read_inode(fs, inode, |disk_inode| disk_inode.inode_type().is_some());
+                if !valid_type {
+                    self.report(Problem::DanglingEntry { dir, name, inode });
+                    changed |= self.repair;
+                    continue;
+                }
+                if self.reached[inode as usize] {
+                    self.report(Problem::MultiplyLinked { dir, name, inode });
+                    changed |= self.repair;
+                    continue;
+                }
+                self.reached[inode as usize] = true;
+                children.push(inode);
+                names.push(name);
+                kept.push(dirent);
+            }
+        }
+
+        // 目录需要重建时目录项的位置会改变，旧索引被丢弃
+        if index_root != 0 && !changed {
+            match self.check_index(dir, index_root, located) {
+                Some(index_blocks) => self.report.data_blocks += index_blocks,
+                None => {
+                    self.report(Problem::BadDirIndex { dir });
+                    changed |= self.repair;
+                }
+            }
+        }
+
+        if changed {
+            // 重建目录: 以没有索引的索引记录开头，保留的目录项按原顺序紧凑地写回，每块的最后一项延伸到块尾
+            // 索引在之后插入目录项时重新建立
+            let mut groups: Vec<Vec<DirEntry>> = Vec::new();
+            let mut used = BLOCK_SIZE;
+            if !kept.is_empty() {
+                kept.insert(0, DirEntry::new_index(0));
+            }
+            for mut dirent in kept {
+                let rec_len = DirEntry::min_rec_len(dirent.raw_name().len());
+                dirent.set_rec_len(rec_len);
+                if used + rec_len > BLOCK_SIZE {
+                    groups.push(Vec::new());
+                    used = 0;
+                }
+                used += rec_len;
+                groups.last_mut().unwrap().push(dirent);
+            }
+            self.modify_inode(fs, dir, |disk_inode| {
+                for (index, group) in groups.iter_mut().enumerate() {
+                    let mut block = [0u8; BLOCK_SIZE as usize];
+                    let mut offset = 0;
+                    let last = group.len() - 1;
+                    for (i, dirent) in group.iter_mut().enumerate() {
+                        if i == last {
+                            dirent.set_rec_len(BLOCK_SIZE - offset);
+                        }
+                        dirent.write(&mut block, offset as usize);
+                        offset += dirent.rec_len();
+                    }
+                    disk_inode.write_at(index * BLOCK_SIZE as usize, &block, &self.block_device);
+                }
+                truncate(disk_inode, &self.block_device, groups.len() as u32 * BLOCK_SIZE);
+            });
+        }
+        (children, changed)
+    }
+
+    /// 检查目录索引并标记它占用的块，返回块数；块号非法或索引与目录项不一致时返回 None (不标记任何块)
+    /// 尚不可用 (重建被中断) 的索引只检查块号
+    fn check_index(&mut self, dir: u32, root: u32, mut located: Vec<(u32, u32)>) -> Option<usize> {
+        if !self.in_data_area(root) || self.is_claimed(root) {
+            return None;
+        }
+        let index = DirIndex::new(root, &self.block_device);
+        let index_root = index.read_root();
+        let blocks = index.blocks();
+        for (i, block) in blocks.iter().enumerate() {
+            if !self.in_data_area(*block) || self.is_claimed(*block) || blocks[..i].contains(block) {
+                return None;
+            }
+        }
+        if index_root.is_valid() {
+            let buckets = index_root.buckets as usize;
+            if index_root.entries as usize != located.len() || index_root.bucket_blocks[..buckets].contains(&0) {
+                return None;
+            }
+            let mut indexed = Vec::new();
+            for (i, block) in index_root.bucket_blocks[..buckets].iter().enumerate() {
+                let bucket = get_block_cache(*block as usize, Arc::clone(&self.block_device)).lock()
+                    .read(0, |bucket: &Bucket| *bucket);
+                if bucket.slots().len() != bucket.count as usize
+                    || bucket.slots().iter().any(|(hash, _)| *hash as usize % buckets != i) {
+                    return None;
+                }
+                indexed.extend_from_slice(bucket.slots());
+            }
+            indexed.sort_unstable();
+            located.sort_unstable();
+            if indexed != located {
+                return None;
+            }
+        }
+        blocks.iter().for_each(|block| self.claim(dir, *block));
+        Some(blocks.len())
+    }
+
+    /// 对比位图与可达集合，修复模式下按可达集合重建位图
+    fn check_bitmaps(&mut self, fs: &EasyFileSystem) {
+        for inode in 0..self.inode_count {
+            let marked = fs.inode_bitmap.is_set(&self.block_device, inode as usize);
+            let reached = self.reached[inode as usize];
+            if marked != reached {
+                self.report(if reached { Problem::InodeNotMarked { inode } } else { Problem::InodeLeaked { inode } });
+                if self.repair {
+                    fs.inode_bitmap.set(&self.block_device, inode as usize, reached);
+                }
+            }
+        }
+        for pos in 0..self.data_area_blocks {
+            let marked = fs.data_bitmap.is_set(&self.block_device, pos as usize);
+            let claimed = self.owners[pos as usize].is_some();
+            if marked != claimed {
+                let block = pos + self.data_area_start_block;
+                self.report(if claimed { Problem::BlockNotMarked { block } } else { Problem::BlockLeaked { block } });
+                if self.repair {
+                    fs.data_bitmap.set(&self.block_device, pos as usize, claimed);
+                }
+            }
+        }
+    }
+
+    /// 比较 SuperBlock 中记录的空闲计数与 (修复之前的) 位图，旧镜像没有记录时不检查
+    /// 修复时在重建位图之后重新计算
+    fn check_free_counts(&mut self, fs: &EasyFileSystem) {
+        let recorded = get_block_cache(0, Arc::clone(&self.block_device)).lock()
+            .read(0, |super_block: &SuperBlock| super_block.free_counts());
+        let Some((recorded_inodes, recorded_blocks)) = recorded else {
+            return;
+        };
+        let free_inodes = (fs.inode_bitmap.maximum() - fs.inode_bitmap.count_allocated(&self.block_device)) as u32;
+        let free_blocks = self.data_area_blocks - fs.data_bitmap.count_allocated(&self.block_device) as u32;
+        if recorded_inodes != free_inodes {
+            self.report(Problem::BadFreeInodeCount { recorded: recorded_inodes, actual: free_inodes });
+        }
+        if recorded_blocks != free_blocks {
+            self.report(Problem::BadFreeBlockCount { recorded: recorded_blocks, actual: free_blocks });
+        }
+    }
+}
+
+/// 合法的目录项名: 非空、不含 '/'，且不是 "." 或 ".."
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('/') && name != "." && name != ".."
+}
+
+/// 二级索引下使用的一级索引块个数
+fn sub_indirect1_count(data_blocks: u32) -> u32 {
+    let remaining = data_blocks.saturating_sub(INODE_DIRECT_COUNT + INODE_INDIRECT1_COUNT);
+    remaining.div_ceil(INODE_INDIRECT1_COUNT)
+}
+
+/// 将大小设为 new_size 并清零超出大小的块指针；被丢弃的块由重建位图时释放
+fn truncate(disk_inode: &mut DiskInode, block_device: &Arc<dyn BlockDevice>, new_size: u32) {
+    disk_inode.size = new_size;
+    let data_blocks = disk_inode.data_block_num();
+    if !disk_inode.is_inline() {
+        disk_inode.direct.iter_mut().skip(data_blocks as usize).for_each(|block| *block = 0);
+    }
+    if data_blocks <= INODE_DIRECT_COUNT {
+        disk_inode.indirect1 = 0;
+    }
+    if data_blocks <= INODE_DIRECT_COUNT + INODE_INDIRECT1_COUNT {
+        disk_inode.indirect2 = 0;
+    } else {
+        let used = sub_indirect1_count(data_blocks) as usize;
+        indirect_block_cache(disk_inode.indirect2, block_device).lock()
+            .modify(0, |indirect2: &mut IndirectBlock| {
+                indirect2.iter_mut().skip(used).for_each(|block| *block = 0);
+            });
+    }
+}
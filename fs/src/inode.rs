@@ -1,31 +1,194 @@
 // fs/src/inode.rs
 
+use alloc::collections::BTreeMap;
 use alloc::string::String;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use spin::{Mutex, MutexGuard};
-use crate::block_cache::get_block_cache;
+use spin::{Mutex, RwLock};
+use crate::block_cache::{get_block_cache, take_checksum_errors};
 use crate::block_dev::BlockDevice;
-use crate::disk_inode::{DirEntry, DiskInode, DiskInodeType, DIRENT_SIZE};
-use crate::efs::EasyFileSystem;
+use crate::config::{BLOCK_SIZE, INODE_PER_BLOCK, INODE_SIZE};
+use crate::dir_index::{distribute, name_hash, Bucket, DirIndex, INDEX_MIN_BLOCKS, MAX_BUCKETS};
+use crate::disk_inode::{DataBlock, DirEntry, DiskInode, DiskInodeType, INLINE_SYMLINK_MAX, NAME_LENGTH_LIMIT};
+use crate::disk_inode::SYMLINK_LENGTH_LIMIT;
+use crate::efs::{EasyFileSystem, FsUsage, Op};
+use crate::readahead::ReadAhead;
+use crate::time::{Times, Timestamp};
+use crate::xattr::{self, XattrError, XattrMode, Xattrs, XATTR_NAME_MAX};
 
 
+// ----- Memory Inode -----
+
+/// 目录项在目录中的位置
+#[derive(Clone, Copy)]
+struct DirentPos {
+    block: u32,          // 目录中的块序号
+    offset: usize,       // 块内偏移
+    prev: Option<usize>, // 同一块中前一条记录的块内偏移
+}
+
+impl DirentPos {
+    /// 目录项在目录中的字节偏移，即索引中记录的位置
+    fn location(&self) -> u32 {
+        self.block * BLOCK_SIZE + self.offset as u32
+    }
+}
+
+/// `Inode::rename` 的方式，对应 renameat2 的 flags
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenameMode {
+    Replace,   // 目标已存在时原子地替换它
+    NoReplace, // RENAME_NOREPLACE: 目标已存在时失败
+    Exchange,  // RENAME_EXCHANGE: 原子地交换源与目标，两者都必须存在
+}
+
+/// `Inode::rename` 失败的原因
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenameError {
+    NotFound, // 源不存在，或交换时目标不存在
+    Exists,   // NoReplace 时目标已存在
+    IsDir,    // 用文件替换目录
+    NotDir,   // 用目录替换文件
+    NotEmpty, // 被替换的目录非空
+    Invalid,  // 新名字不合法，或把目录移动到它自身或其子目录中
+    NoSpace,  // 目标目录无法扩容
+}
+
+/// inode 的权限位 (mode & 0o7777) 与属主
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Permissions {
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// relatime: atime 早于 mtime/ctime 或已超过这么久 (秒) 时，读取才更新 atime
+const ATIME_UPDATE_INTERVAL: u64 = 24 * 60 * 60;
+
+/// 一次写入除数据块外最多修改的块数 (见 write_chunk_blocks)
+const METADATA_BLOCKS: usize = 11;
+
+/// 写入以外的操作 (创建、删除、重命名、修改属性等) 最多修改的块数的估计，用于 begin_op
+/// 目录扩容或重建索引时可能超出，重建索引自己会分批提交
+const OP_BLOCKS: usize = 2 * METADATA_BLOCKS;
+
+/// 内容 (或目录项) 被修改: 更新 mtime 与 ctime
+fn touch(disk_inode: &mut DiskInode, now: Option<Timestamp>) {
+    if let Some(now) = now {
+        disk_inode.set_mtime(now);
+        disk_inode.set_ctime(now);
+    }
+}
+
+// ----- Inode Cache -----
+/*
+并发控制:
+- 同一个 inode 在内存中只有一个 `Inode` (由 InodeCache 按 inode ID 去重)，它的读写锁保护 disk inode 及其数据块
+  (文件内容、目录项、目录索引、extent 树节点、扩展属性块)
+- 只读的操作 (read_at、find_inode、ls、size ...) 只持有 inode 的读锁，不获取 fs 锁，多个读者可以并行
+- 修改磁盘的操作先获取 inode 的写锁，再开始一个操作 (`EasyFileSystem::begin_op`)；fs 锁只在分配、释放块与提交时短暂持有，
+  修改不同文件的写者 (包括向数据块复制数据) 可以并行
+- 日志中只有一个运行中的事务，提交等到没有进行中的操作时才进行，所以事务总是由完整的操作组成；
+  begin_op 为操作预留日志空间，放不下时等待进行中的操作结束，日志容量限制了可以同时进行的操作数
+- 删除与重命名要同时持有几个 inode 的锁，由 InodeCache 的 rename 锁串行化，按旧目录、新目录、被删除的 inode 的顺序获取；
+  其他操作只持有一个 inode 的锁。开始操作之后不再等待任何 inode 锁
+- 加锁顺序: rename 锁 → inode 锁 → fs 锁 → 块缓存的锁、inode 表的锁。持有块缓存的锁时不获取 fs 锁，
+  需要分配或释放块时修改 disk inode 的副本，完成后再写回 (见 update_disk_inode)
+- 被删除的 inode 仍被打开 (内存中还有它的 `Inode`) 时成为孤儿，不再能由 inode ID 获取，
+  最后一个引用被丢弃之后才释放，之后编号才会被新文件重用
+ */
+
+/// 内存中的 inode 表，以及读写 inode 时不需要 fs 锁就能使用的文件系统信息
+pub struct InodeCache {
+    inode_area_start_block: u32,
+    clock: RwLock<Option<fn() -> Timestamp>>, // 墙上时钟，用于 inode 的时间戳
+    inodes: Mutex<BTreeMap<u32, Weak<Inode>>>, // inode ID -> 仍被使用的 Inode
+    rename_lock: Mutex<()>, // 串行化删除与重命名，见上方的并发控制说明
+}
+
+impl InodeCache {
+    // ----- constructor -----
+    pub fn new(inode_area_start_block: u32) -> Self {
+        Self {
+            inode_area_start_block,
+            clock: RwLock::new(None),
+            inodes: Mutex::new(BTreeMap::new()),
+            rename_lock: Mutex::new(()),
+        }
+    }
+    // ----- methods -----
+    /// 根据 inode ID 计算其在磁盘上存储的位置 (块号，偏移量)
+    pub fn disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
+        let block_id = self.inode_area_start_block + inode_id / INODE_PER_BLOCK;
+        (block_id, ((inode_id % INODE_PER_BLOCK) * INODE_SIZE) as usize)
+    }
+
+    pub fn set_clock(&self, clock: fn() -> Timestamp) {
+        *self.clock.write() = Some(clock);
+    }
+    /// 当前时间，没有设置时钟时返回 None
+    pub fn now(&self) -> Option<Timestamp> {
+        self.clock.read().map(|clock| clock())
+    }
+
+    /// inode ID 为 inode_id 的 Inode，已在内存中时返回同一个，否则新建并记录在表中
+    pub fn get(cache: &Arc<Self>, inode_id: u32, fs: &Arc<Mutex<EasyFileSystem>>, block_device: &Arc<dyn BlockDevice>)
+        -> Arc<Inode> {
+        let mut inodes = cache.inodes.lock();
+        if let Some(inode) = inodes.get(&inode_id).and_then(Weak::upgrade) {
+            return inode;
+        }
+        let inode = Arc::new(Inode::new(inode_id, Arc::clone(cache), Arc::clone(fs), Arc::clone(block_device)));
+        inodes.insert(inode_id, Arc::downgrade(&inode));
+        inode
+    }
+
+    /// inode 是否仍被使用 (内存中有它的 Inode)，不会创建新的引用
+    pub fn is_open(&self, inode_id: u32) -> bool {
+        self.inodes.lock().get(&inode_id).is_some_and(|inode| inode.strong_count() > 0)
+    }
+}
+
 // ----- Memory Inode -----
 
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
+    lock: RwLock<()>, // 见上方的并发控制说明
+    cache: Arc<InodeCache>,
     fs: Arc<Mutex<EasyFileSystem>>,
     block_device: Arc<dyn BlockDevice>,
 }
 
+impl Drop for Inode {
+    /// 最后一个引用被丢弃时从 inode 表中移除；表中的记录可能已被同一 inode 新建的 Inode 替换，此时保留
+    /// 已被删除的 inode (孤儿) 此时释放；fs 锁被占用 (可能就是当前线程) 时留给之后结束的操作
+    fn drop(&mut self) {
+        {
+            let mut inodes = self.cache.inodes.lock();
+            if inodes.get(&self.inode_id).is_some_and(|inode| inode.strong_count() == 0) {
+                inodes.remove(&self.inode_id);
+            }
+        }
+        if let Some(mut fs) = self.fs.try_lock() {
+            fs.release_orphan(self.inode_id);
+        }
+    }
+}
+
 impl Inode {
     // ----- constructor -----
-    pub fn new(block_id: u32, block_offset: usize, fs: Arc<Mutex<EasyFileSystem>>, block_device: Arc<dyn BlockDevice>)
+    /// 只由 InodeCache 调用，保证同一个 inode 只有一个 Inode
+    fn new(inode_id: u32, cache: Arc<InodeCache>, fs: Arc<Mutex<EasyFileSystem>>, block_device: Arc<dyn BlockDevice>)
         -> Self {
+        let (block_id, block_offset) = cache.disk_inode_pos(inode_id);
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
+            lock: RwLock::new(()),
+            cache,
             fs,
             block_device,
         }
@@ -33,6 +196,16 @@ impl Inode {
 
     // ----- methods -----
 
+    /// inode ID 为 inode_id 的 Inode (见 `InodeCache::get`)
+    fn get_inode(&self, inode_id: u32) -> Arc<Inode> {
+        InodeCache::get(&self.cache, inode_id, &self.fs, &self.block_device)
+    }
+
+    /// disk inode 的副本: 读者用它访问数据块，不必在整个读取期间持有 inode 所在块的缓存锁
+    fn disk_inode(&self) -> DiskInode {
+        self.read_disk_inode(DiskInode::clone)
+    }
+
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(
             self.block_id,
@@ -47,173 +220,1020 @@ impl Inode {
         ).lock().modify(self.block_offset, f)
     }
 
+    /// 修改 disk inode 的副本，f 返回后写回；f 中可以获取 fs 锁分配或释放块，而不必持有 inode 所在块的缓存锁
+    /// 调用者持有 inode 的写锁，副本在写回之前不会过期
+    fn update_disk_inode<V>(&self, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+        let mut disk_inode = self.disk_inode();
+        let v = f(&mut disk_inode);
+        self.modify_disk_inode(|stored| *stored = disk_inode);
+        v
+    }
+
     fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
         assert!(disk_inode.is_dir());
-        // 目录中条目数量
-        let file_count = disk_inode.size / DIRENT_SIZE;
-        let mut dirent = DirEntry::new_empty(); // 用于读取
-        // 遍历目录中的每个条目
-        for i in 0..file_count {
-            // 从 disk inode 中读取目录条目数据
-            assert_eq!(
-                disk_inode.read_at(
-                    (DIRENT_SIZE * i) as usize,
-                    dirent.as_bytes_mut(),
-                    &self.block_device,
-                ) as u32,
-                DIRENT_SIZE
-            );
-            if dirent.get_name() == name {
-                // 若找到
-                return Some(dirent.get_inode_number());
-            }
-        }
-        None
+        self.lookup_dirent(name, disk_inode).map(|(_, dirent)| dirent.get_inode_number())
     }
 
     /// find an inode by name in the current directory inode(root inode)
+    /// 同一个文件总是返回同一个 Inode
     pub fn find_inode(&self, name: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode: &DiskInode| {
-            // 尝试在目录中查找指定名称的文件的 inode ID
-            let inode_id_option = self.find_inode_id(name, disk_inode);
-            if inode_id_option.is_none() {
-                return None;
-            }
-            // 找到 inode ID
-            let inode_id = inode_id_option.unwrap();
-            // 获取 inode 在磁盘上的位置(块ID, 块内偏移)
-            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-            // 创建一个新的 Inode 实例并返回
-            Some(Arc::new(Self::new(
-                block_id,
-                block_offset,
-                self.fs.clone(),
-                self.block_device.clone(),
-            )))
+        let _guard = self.lock.read();
+        // 尝试在目录中查找指定名称的文件的 inode ID
+        let inode_id = self.find_inode_id(name, &self.disk_inode())?;
+        Some(self.get_inode(inode_id))
+    }
+
+    /// inode ID，即 inode 在 inode 区中的编号，根目录为 0
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+
+    /// 取出文件系统上自上次调用以来校验和不符的块号 (见 `EasyFileSystem::take_checksum_errors`)
+    /// 记录保存在块缓存中，不需要获取文件系统的锁，每次读写之后调用也不会使读者串行
+    pub fn take_checksum_errors(&self) -> Vec<u32> {
+        take_checksum_errors(&self.block_device).into_iter().map(|block_id| block_id as u32).collect()
+    }
+
+    /// 文件系统的容量与空闲计数 (见 `EasyFileSystem::usage`)
+    pub fn fs_usage(&self) -> FsUsage {
+        self.fs.lock().usage()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    pub fn is_file(&self) -> bool {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.is_file())
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+
+    pub fn permissions(&self) -> Permissions {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| Permissions {
+            mode: disk_inode.mode as u16,
+            uid: disk_inode.uid,
+            gid: disk_inode.gid,
         })
     }
 
+    /// 修改权限位与属主 (chmod / chown)，mode 中 0o7777 以外的位被忽略
+    pub fn set_permissions(&self, perm: Permissions) {
+        let _guard = self.lock.write();
+        let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+        let now = self.cache.now();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.mode = (perm.mode & 0o7777) as u32;
+            disk_inode.uid = perm.uid;
+            disk_inode.gid = perm.gid;
+            if let Some(now) = now {
+                disk_inode.set_ctime(now);
+            }
+        });
+        op.fs().mark_dirty(self.inode_id, false);
+    }
 
-    /// ls, only root inode can use it
+    pub fn times(&self) -> Times {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.times())
+    }
+
+    /// 修改 atime/mtime (utimensat)，None 表示不变；ctime 更新为当前时间
+    pub fn set_times(&self, atime: Option<Timestamp>, mtime: Option<Timestamp>) {
+        let _guard = self.lock.write();
+        let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+        let now = self.cache.now();
+        self.modify_disk_inode(|disk_inode| {
+            if let Some(atime) = atime {
+                disk_inode.set_atime(atime);
+            }
+            if let Some(mtime) = mtime {
+                disk_inode.set_mtime(mtime);
+            }
+            if let Some(now) = now {
+                disk_inode.set_ctime(now);
+            }
+        });
+        op.fs().mark_dirty(self.inode_id, false);
+    }
+
+    /// 文件大小 (Bytes)，对目录而言是目录项占用的字节数
+    pub fn size(&self) -> usize {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+
+    /// 最大文件大小: 使用 extent 树的 inode 只受 size 字段的限制，否则为 MAX_FILE_SIZE
+    pub fn max_size(&self) -> usize {
+        let _guard = self.lock.read();
+        self.read_disk_inode(|disk_inode| disk_inode.max_size())
+    }
+
+    /// ls, only directory inodes can use it
+    /// 不是 UTF-8 的名字 (只可能来自损坏的镜像) 中的非法字节被替换为 U+FFFD
     pub fn ls(&self) -> Vec<String> {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            let file_count = disk_inode.size / DIRENT_SIZE;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::new_empty();
-                assert_eq!(
-                    disk_inode.read_at(
-                        (i * DIRENT_SIZE) as usize, // offset
-                        dirent.as_bytes_mut(),      // slice
-                        &self.block_device,
-                    ) as u32,
-                    DIRENT_SIZE,
-                );
-                v.push(String::from(dirent.get_name()));
-            }
-            v
-        })
+        let _guard = self.lock.read();
+        let mut v: Vec<String> = Vec::new();
+        self.find_dirent(&self.disk_inode(), |_, dirent| -> Option<()> {
+            v.push(String::from_utf8_lossy(dirent.raw_name()).into_owned());
+            None
+        });
+        v
     }
 
-    /// create, only root inode can use it
+    /// create a regular file, only directory inodes can use it
+    /// create / create_dir / create_symlink 新建的 inode 属于 root，权限位为该类型的默认值
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
-        let mut fs = self.fs.lock();
+        self.create_with(name, Self::root_owned(DiskInodeType::File))
+    }
+
+    /// create a sub directory, only directory inodes can use it
+    pub fn create_dir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_dir_with(name, Self::root_owned(DiskInodeType::Directory))
+    }
+
+    /// 创建指向 target 的符号链接，target 为空或超过 SYMLINK_LENGTH_LIMIT 时返回 None
+    /// 符号链接的内容 (用 read_at 读取) 就是 target，不检查 target 是否存在
+    pub fn create_symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        self.create_symlink_with(name, target, Self::root_owned(DiskInodeType::Symlink))
+    }
+
+    /// 以给定的权限位与属主新建文件，与目录项在同一个事务中写入
+    pub fn create_with(&self, name: &str, perm: Permissions) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File, perm, &[])
+    }
+
+    pub fn create_dir_with(&self, name: &str, perm: Permissions) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory, perm, &[])
+    }
+
+    pub fn create_symlink_with(&self, name: &str, target: &str, perm: Permissions) -> Option<Arc<Inode>> {
+        if target.is_empty() || target.len() > SYMLINK_LENGTH_LIMIT as usize {
+            return None;
+        }
+        self.create_inode(name, DiskInodeType::Symlink, perm, target.as_bytes())
+    }
+
+    fn root_owned(type_: DiskInodeType) -> Permissions {
+        Permissions { mode: type_.default_mode(), uid: 0, gid: 0 }
+    }
+
+    /// 新建 inode 并在当前目录中添加目录项，data 是符号链接的目标 (其他类型为空)
+    /// inode 的内容与目录项在同一个事务中写入
+    fn create_inode(&self, name: &str, type_: DiskInodeType, perm: Permissions, data: &[u8]) -> Option<Arc<Inode>> {
+        // 文件名为空或过长
+        let mut dirent = DirEntry::new(name, 0)?;
+        let _guard = self.lock.write();
 
         // 检查同名文件
-        if self.modify_disk_inode(|root_inode| {
+        if self.read_disk_inode(|root_inode| {
             assert!(root_inode.is_dir());
             self.find_inode_id(name, root_inode)
         }).is_some() {
             return None;
         }
 
+        let mut op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
         // 分配新的 inode
-        let new_inode_id = fs.alloc_inode();
+        let (new_inode_id, extents) = {
+            let mut fs = op.fs();
+            (fs.alloc_inode()?, fs.uses_extents())
+        };
 
-        // 获取新 inode 在磁盘上的位置(块ID, 块内偏移)
-        let (new_inode_block_id, new_inode_block_offset)
-            = fs.get_disk_inode_pos(new_inode_id);
+        // 在副本上初始化新的 inode 为 File/Directory/Symlink Type，分配完数据块后再写回
+        let mut new_inode = self.read_inode_by_id(new_inode_id, DiskInode::clone);
+        new_inode.initialize(type_, perm.mode, perm.uid, perm.gid, self.cache.now().unwrap_or_default());
+        // 符号链接总是使用直接索引 (短的目标保存在 direct 数组中)
+        if extents && !new_inode.is_symlink() {
+            new_inode.set_extents();
+        }
+        // 短的符号链接目标保存在 inode 内，长的写入数据块
+        let written = if data.len() > INLINE_SYMLINK_MAX as usize {
+            self.increase_size(data.len() as u32, &mut new_inode, &op)
+                && new_inode.write_at(0, data, &self.block_device) == data.len()
+        } else {
+            if !data.is_empty() {
+                new_inode.set_inline(data);
+            }
+            true
+        };
+        self.modify_inode_by_id(new_inode_id, |stored| *stored = new_inode);
+        if !written {
+            op.fs().free_inode(new_inode_id);
+            return None;
+        }
 
-        // 初始化新的 inode 为 File Type
-        get_block_cache(
-            new_inode_block_id as usize,
-            Arc::clone(&self.block_device)
-        ).lock().modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-            // 将新inode初始化为文件类型
-            new_inode.initialize(DiskInodeType::File);
+        // 修改当前目录inode，添加新文件的目录项
+        dirent.set_inode_number(new_inode_id);
+        let now = self.cache.now();
+        let location = self.update_disk_inode(|root_inode| {
+            let location = self.insert_dirent(root_inode, dirent, &op);
+            if location.is_some() {
+                touch(root_inode, now);
+            }
+            location
         });
+        let Some(location) = location else {
+            // 目录无法扩容，归还刚分配的 inode (及符号链接的数据块)
+            op.fs().free_inode(new_inode_id);
+            return None;
+        };
+        self.index_insert(name_hash(name.as_bytes()), location, &mut op);
+        let mut fs = op.fs();
+        fs.mark_dirty(self.inode_id, true);
+        fs.mark_dirty(new_inode_id, true);
+        drop(fs);
 
-        // 修改当前目录inode，添加新文件的目录项
-        self.modify_disk_inode(|root_inode| {
-            let file_count = root_inode.size / DIRENT_SIZE;
-            // 添加新条目后的目录大小
-            let new_size = (file_count + 1) * DIRENT_SIZE;
-            // 扩容
-            self.increase_size(new_size, root_inode, &mut fs);
-            // 创建并写入目录
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                (file_count * DIRENT_SIZE) as usize,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-        });
-
-        // 获取新创建的inode在磁盘上的位置
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+        Some(self.get_inode(new_inode_id))
     }
 
     /// 清空文件内容并释放文件占用的数据块
     pub fn clear(&self) {
-        let mut fs = self.fs.lock();
-        self.modify_disk_inode(|disk_inode| {
+        let _guard = self.lock.write();
+        let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+        let data_blocks_dealloc = self.modify_disk_inode(|disk_inode| {
             let size = disk_inode.size;
+            let extents = disk_inode.uses_extents();
             let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
-            assert_eq!(data_blocks_dealloc.len(), DiskInode::total_blocks(size) as usize);
-            for data_block in data_blocks_dealloc.into_iter() {
-                fs.dealloc_data_block(data_block);
+            // extent 树的节点块数取决于数据块是否连续，无法由大小算出
+            assert!(extents || data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
+            data_blocks_dealloc
+        });
+        let mut fs = op.fs();
+        for data_block in data_blocks_dealloc.into_iter() {
+            fs.dealloc_data_block(data_block);
+        }
+        fs.mark_dirty(self.inode_id, true);
+    }
+
+    /// 删除当前目录中的文件或空目录，释放其 inode 与数据块
+    /// 文件仍被打开时 inode 在最后一个引用被丢弃之后才释放，在此之前仍可读写
+    /// 不存在或目录非空时返回 false
+    pub fn unlink(&self, name: &str) -> bool {
+        let _rename = self.cache.rename_lock.lock();
+        let _guard = self.lock.write();
+        // 找到目录项的位置
+        let found = self.read_disk_inode(|dir_inode| {
+            assert!(dir_inode.is_dir());
+            self.lookup_dirent(name, dir_inode)
+        });
+        let Some((pos, dirent)) = found else {
+            return false;
+        };
+        let target = self.get_inode(dirent.get_inode_number());
+        let _target_guard = target.lock.write();
+        if target.read_disk_inode(|disk_inode| disk_inode.is_dir() && disk_inode.size > 0) {
+            return false;
+        }
+
+        let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+        self.detach_dirent(pos, name_hash(name.as_bytes()), &op);
+        let now = self.cache.now();
+        self.modify_disk_inode(|dir_inode| touch(dir_inode, now));
+        let mut fs = op.fs();
+        fs.mark_dirty(self.inode_id, true);
+        target.release(&mut fs);
+        true
+    }
+
+    /// 把 old_dir 中的目录项 old_name 移动到 new_dir 中并改名为 new_name，old_dir 与 new_dir 可以是同一目录
+    /// 所有修改在同一个事务中提交，崩溃后只会看到 rename 之前或之后的状态
+    /// easy-fs 的目录不保存 ".." (父目录由路径决定)，移动目录时无需更新
+    pub fn rename(old_dir: &Inode, old_name: &str, new_dir: &Inode, new_name: &str, mode: RenameMode)
+        -> Result<(), RenameError> {
+        assert!(Arc::ptr_eq(&old_dir.fs, &new_dir.fs));
+        let mut new_dirent = DirEntry::new(new_name, 0).ok_or(RenameError::Invalid)?;
+        // 持有 rename 锁时没有其他删除与重命名，依次获取两个目录的写锁不会死锁
+        let _rename = old_dir.cache.rename_lock.lock();
+        let _old_guard = old_dir.lock.write();
+        let _new_guard = (new_dir.inode_id != old_dir.inode_id).then(|| new_dir.lock.write());
+        let source = old_dir.read_disk_inode(|dir_inode| {
+            assert!(dir_inode.is_dir());
+            old_dir.lookup_dirent(old_name, dir_inode)
+        });
+        let (source_pos, source) = source.ok_or(RenameError::NotFound)?;
+        let source_location = source_pos.location();
+        let target = new_dir.read_disk_inode(|dir_inode| {
+            assert!(dir_inode.is_dir());
+            new_dir.lookup_dirent(new_name, dir_inode)
+        });
+        let source_id = source.get_inode_number();
+        let source_is_dir = old_dir.read_inode_by_id(source_id, |disk_inode| disk_inode.is_dir());
+
+        // 被替换的 inode: 检查它是否为空目录，以及删除它时需要持有它的锁
+        // 它是 old_dir 自身时 (如把 a/x 移动为 a) 锁已经持有，检查总会失败
+        let victim = target.as_ref()
+            .filter(|(_, target)| mode == RenameMode::Replace && target.get_inode_number() != source_id)
+            .map(|(_, target)| new_dir.get_inode(target.get_inode_number()));
+        let _victim_guard = victim.as_ref()
+            .filter(|victim| victim.inode_id != old_dir.inode_id)
+            .map(|victim| victim.lock.write());
+
+        // 检查目标
+        let mut target_is_dir = false;
+        match &target {
+            // 同一个目录项 (easy-fs 没有硬链接)，什么也不做
+            Some((_, target)) if target.get_inode_number() == source_id => return Ok(()),
+            Some(_) if mode == RenameMode::NoReplace => return Err(RenameError::Exists),
+            Some((_, target)) => {
+                let (is_dir, size) = old_dir.read_inode_by_id(target.get_inode_number(), |disk_inode| {
+                    (disk_inode.is_dir(), disk_inode.size)
+                });
+                target_is_dir = is_dir;
+                if mode == RenameMode::Replace {
+                    match (source_is_dir, is_dir) {
+                        (false, true) => return Err(RenameError::IsDir),
+                        (true, false) => return Err(RenameError::NotDir),
+                        (true, true) if size > 0 => return Err(RenameError::NotEmpty),
+                        _ => {}
+                    }
+                }
+            }
+            None if mode == RenameMode::Exchange => return Err(RenameError::NotFound),
+            None => {}
+        }
+
+        // 目录不能移动到它自身或其子目录中
+        let (old_dir_id, new_dir_id) = (old_dir.inode_id, new_dir.inode_id);
+        if old_dir_id != new_dir_id {
+            if source_is_dir && old_dir.subtree_contains(source_id, new_dir_id) {
+                return Err(RenameError::Invalid);
+            }
+            if let Some((_, target)) = &target
+                && mode == RenameMode::Exchange && target_is_dir
+                && old_dir.subtree_contains(target.get_inode_number(), old_dir_id) {
+                return Err(RenameError::Invalid);
+            }
+        }
+
+        let mut op = EasyFileSystem::begin_op(&old_dir.fs, OP_BLOCKS);
+        match target {
+            // 交换两个目录项指向的 inode，目录项的位置不变
+            Some((pos, target)) if mode == RenameMode::Exchange => {
+                old_dir.set_dirent_inode(source_location, target.get_inode_number());
+                new_dir.set_dirent_inode(pos.location(), source_id);
+            }
+            // 目标目录项改为指向源 inode，再删除源目录项并释放被替换的 inode
+            Some((pos, _)) => {
+                new_dir.set_dirent_inode(pos.location(), source_id);
+                let source_pos = old_dir.read_disk_inode(|dir_inode| old_dir.dirent_at(dir_inode, source_location)).unwrap().0;
+                old_dir.detach_dirent(source_pos, name_hash(old_name.as_bytes()), &op);
+                victim.as_ref().unwrap().release(&mut op.fs());
             }
+            // 先插入新目录项，再删除源目录项: 目录无法扩容时不做任何修改
+            None => {
+                new_dirent.set_inode_number(source_id);
+                let location = new_dir.update_disk_inode(|dir_inode| new_dir.insert_dirent(dir_inode, new_dirent, &op));
+                let Some(location) = location else {
+                    return Err(RenameError::NoSpace);
+                };
+                // 插入可能拆分了同一块中位于源目录项之前的记录，重新定位源目录项
+                let source_pos = old_dir.read_disk_inode(|dir_inode| old_dir.dirent_at(dir_inode, source_location)).unwrap().0;
+                old_dir.detach_dirent(source_pos, name_hash(old_name.as_bytes()), &op);
+                // 重建索引时的第一个事务已包含上面所有的修改
+                new_dir.index_insert(name_hash(new_name.as_bytes()), location, &mut op);
+            }
+        }
+        let now = old_dir.cache.now();
+        old_dir.modify_disk_inode(|dir_inode| touch(dir_inode, now));
+        new_dir.modify_disk_inode(|dir_inode| touch(dir_inode, now));
+        let mut fs = op.fs();
+        fs.mark_dirty(old_dir.inode_id, true);
+        fs.mark_dirty(new_dir.inode_id, true);
+        Ok(())
+    }
+
+    /// 读取 inode ID 为 inode_id 的 disk inode
+    /// 不能在持有同一 inode 块的缓存锁时调用 (一个块中有多个 inode)
+    fn read_inode_by_id<V>(&self, inode_id: u32, f: impl FnOnce(&DiskInode) -> V) -> V {
+        let (block_id, block_offset) = self.cache.disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock().read(block_offset, f)
+    }
+
+    fn modify_inode_by_id<V>(&self, inode_id: u32, f: impl FnOnce(&mut DiskInode) -> V) -> V {
+        let (block_id, block_offset) = self.cache.disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock().modify(block_offset, f)
+    }
+
+    /// 目录项已被删除: 没有其他引用时立即释放 inode，仍被打开时成为孤儿 (见 Drop for Inode)
+    /// 在 fs 锁下判断，期间不会出现新的引用 (见 `EasyFileSystem::get_inode`)；调用者自己持有一个引用
+    fn release(self: &Arc<Self>, fs: &mut EasyFileSystem) {
+        if Arc::strong_count(self) > 1 {
+            fs.add_orphan(self.inode_id);
+        } else {
+            fs.free_inode(self.inode_id);
+        }
+    }
+
+    /// 以 inode ID 为 dir 的目录为根的目录树中是否有 inode ID 为 target 的 inode (包括 dir 自身)
+    /// 读取每个目录时持有它的读锁: 持有 rename 锁时目录树的形状不变，但目录的内容可能正被创建文件的写者修改
+    fn subtree_contains(&self, dir: u32, target: u32) -> bool {
+        let mut stack = alloc::vec![dir];
+        while let Some(inode_id) = stack.pop() {
+            if inode_id == target {
+                return true;
+            }
+            if !self.read_inode_by_id(inode_id, |disk_inode| disk_inode.is_dir()) {
+                continue;
+            }
+            let inode = self.get_inode(inode_id);
+            let _guard = inode.lock.read();
+            inode.find_dirent(&inode.disk_inode(), |_, dirent| -> Option<()> {
+                stack.push(dirent.get_inode_number());
+                None
+            });
+        }
+        false
+    }
+
+    // ----- sync -----
+    /// fsync: 把该 inode 的修改 (内容与属性) 写入磁盘
+    /// 日志中只有一个运行中的事务，只能等进行中的操作结束后与它们的修改一起整体提交；
+    /// 该 inode 没有未提交的修改时什么都不做
+    /// 事务超出日志容量、无法提交时返回 false (见 `EasyFileSystem::commit`)，sync_data 与 sync_fs 相同
+    pub fn sync_all(&self) -> bool {
+        EasyFileSystem::commit_when_idle(&self.fs, |fs| fs.is_dirty(self.inode_id, false))
+    }
+
+    /// fdatasync: 同 sync_all，但只修改了时间戳、权限等属性时不提交
+    pub fn sync_data(&self) -> bool {
+        EasyFileSystem::commit_when_idle(&self.fs, |fs| fs.is_dirty(self.inode_id, true))
+    }
+
+    /// syncfs: 提交所在文件系统的当前事务
+    pub fn sync_fs(&self) -> bool {
+        EasyFileSystem::commit_when_idle(&self.fs, |_| true)
+    }
+
+    /// 后台回写，见 `EasyFileSystem::writeback`
+    pub fn writeback(&self, expire: Timestamp) -> bool {
+        self.fs.lock().writeback(expire)
+    }
+
+    /// 见 `EasyFileSystem::set_delayed_commit`
+    pub fn set_delayed_commit(&self, delayed: bool) {
+        self.fs.lock().set_delayed_commit(delayed);
+    }
+
+    /// 见 `EasyFileSystem::set_dirty_limit`
+    pub fn set_dirty_limit(&self, blocks: usize) {
+        self.fs.lock().set_dirty_limit(blocks);
+    }
+
+    // ----- extended attributes -----
+
+    /// 扩展属性 name 的值，不存在时返回 None
+    pub fn get_xattr(&self, name: &str) -> Option<Vec<u8>> {
+        let _guard = self.lock.read();
+        let xattrs = self.read_xattrs(&self.disk_inode());
+        xattrs.into_iter().find(|(key, _)| key == name).map(|(_, value)| value)
+    }
+
+    /// 所有扩展属性的名字
+    pub fn list_xattr(&self) -> Vec<String> {
+        let _guard = self.lock.read();
+        let xattrs = self.read_xattrs(&self.disk_inode());
+        xattrs.into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// 设置扩展属性 name 的值，mode 决定属性已存在或不存在时的行为
+    pub fn set_xattr(&self, name: &str, value: &[u8], mode: XattrMode) -> Result<(), XattrError> {
+        if name.is_empty() || name.len() > XATTR_NAME_MAX {
+            return Err(XattrError::Invalid);
+        }
+        let _guard = self.lock.write();
+        let mut xattrs = self.read_disk_inode(|disk_inode| self.read_xattrs(disk_inode));
+        match (xattrs.iter_mut().find(|(key, _)| key == name), mode) {
+            (Some(_), XattrMode::Create) => return Err(XattrError::Exists),
+            (None, XattrMode::Replace) => return Err(XattrError::NotFound),
+            (Some((_, old)), _) => *old = value.to_vec(),
+            (None, _) => xattrs.push((String::from(name), value.to_vec())),
+        }
+        let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+        self.write_xattrs(&xattrs, &op)?;
+        op.fs().mark_dirty(self.inode_id, false);
+        Ok(())
+    }
+
+    /// 删除扩展属性 name，删除最后一个属性时释放属性块
+    pub fn remove_xattr(&self, name: &str) -> Result<(), XattrError> {
+        let _guard = self.lock.write();
+        let mut xattrs = self.read_disk_inode(|disk_inode| self.read_xattrs(disk_inode));
+        let pos = xattrs.iter().position(|(key, _)| key == name).ok_or(XattrError::NotFound)?;
+        xattrs.remove(pos);
+        let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+        self.write_xattrs(&xattrs, &op)?;
+        op.fs().mark_dirty(self.inode_id, false);
+        Ok(())
+    }
+
+    /// 读出 inode 的全部扩展属性，属性块损坏时视为没有属性 (由 fsck 报告)
+    fn read_xattrs(&self, disk_inode: &DiskInode) -> Xattrs {
+        if disk_inode.xattr_block == 0 {
+            return Vec::new();
+        }
+        get_block_cache(disk_inode.xattr_block as usize, Arc::clone(&self.block_device)).lock()
+            .read(0, |block: &DataBlock| xattr::decode(block))
+            .unwrap_or_default()
+    }
+
+    /// 写回全部扩展属性并更新 ctime，按需分配或释放属性块
+    fn write_xattrs(&self, xattrs: &Xattrs, op: &Op) -> Result<(), XattrError> {
+        if !xattr::fits(xattrs) {
+            return Err(XattrError::NoSpace);
+        }
+        let mut block_id = self.read_disk_inode(|disk_inode| disk_inode.xattr_block);
+        if xattrs.is_empty() {
+            if block_id != 0 {
+                op.fs().dealloc_data_block(block_id);
+                block_id = 0;
+            }
+        } else {
+            if block_id == 0 {
+                let allocated = op.fs().alloc_data_block();
+                block_id = allocated.ok_or(XattrError::NoSpace)?;
+            }
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device)).lock()
+                .modify(0, |block: &mut DataBlock| xattr::encode(xattrs, block));
+        }
+        let now = self.cache.now();
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.xattr_block = block_id;
+            if let Some(now) = now {
+                disk_inode.set_ctime(now);
+            }
+        });
+        Ok(())
+    }
+
+    // ----- directory entries -----
+
+    /// 读取目录的第 index 个块
+    fn read_dir_block(&self, disk_inode: &DiskInode, index: u32) -> [u8; BLOCK_SIZE as usize] {
+        let mut block = [0u8; BLOCK_SIZE as usize];
+        disk_inode.read_at((index * BLOCK_SIZE) as usize, &mut block, &self.block_device);
+        block
+    }
+
+    /// 依次访问目录中的普通目录项，f 返回 Some 时停止遍历
+    /// 损坏的记录 (由 fsck 报告) 及同一块中位于其后的记录被跳过
+    fn find_dirent<V>(&self, disk_inode: &DiskInode, mut f: impl FnMut(DirentPos, &DirEntry) -> Option<V>) -> Option<V> {
+        for index in 0..disk_inode.size.div_ceil(BLOCK_SIZE) {
+            let block = self.read_dir_block(disk_inode, index);
+            let mut offset = 0;
+            let mut prev = None;
+            while let Some(dirent) = DirEntry::parse(&block, offset) {
+                if dirent.is_used()
+                    && let Some(v) = f(DirentPos { block: index, offset, prev }, &dirent) {
+                    return Some(v);
+                }
+                prev = Some(offset);
+                offset += dirent.rec_len() as usize;
+            }
+        }
+        None
+    }
+
+    /// 位于 location 处的普通目录项
+    fn dirent_at(&self, disk_inode: &DiskInode, location: u32) -> Option<(DirentPos, DirEntry)> {
+        let index = location / BLOCK_SIZE;
+        if index >= disk_inode.size.div_ceil(BLOCK_SIZE) {
+            return None;
+        }
+        let block = self.read_dir_block(disk_inode, index);
+        let target = (location % BLOCK_SIZE) as usize;
+        let mut offset = 0;
+        let mut prev = None;
+        while offset < target {
+            prev = Some(offset);
+            offset += DirEntry::parse(&block, offset)?.rec_len() as usize;
+        }
+        let dirent = DirEntry::parse(&block, offset).filter(|dirent| offset == target && dirent.is_used())?;
+        Some((DirentPos { block: index, offset, prev }, dirent))
+    }
+
+    /// 按名字查找目录项，有可用的索引时只读取哈希值相同的目录项
+    fn lookup_dirent(&self, name: &str, disk_inode: &DiskInode) -> Option<(DirentPos, DirEntry)> {
+        if name.len() > NAME_LENGTH_LIMIT as usize {
+            return None;
+        }
+        let name = name.as_bytes();
+        if let Some(locations) = self.dir_index(disk_inode).and_then(|index| index.lookup(name_hash(name))) {
+            return locations.into_iter().find_map(|location| {
+                self.dirent_at(disk_inode, location).filter(|(_, dirent)| dirent.raw_name() == name)
+            });
+        }
+        self.find_dirent(disk_inode, |pos, dirent| (dirent.raw_name() == name).then(|| (pos, dirent.clone())))
+    }
+
+    /// 把目录项放入第一个足够大的空闲空间，没有时在目录末尾追加一个块，返回目录项的位置
+    /// 新目录的第一个块以索引记录开头
+    /// 追加块时磁盘空间不足返回 None
+    fn insert_dirent(&self, disk_inode: &mut DiskInode, mut dirent: DirEntry, op: &Op) -> Option<u32> {
+        let needed = dirent.rec_len();
+        let blocks = disk_inode.size.div_ceil(BLOCK_SIZE);
+        for index in 0..blocks {
+            let mut block = self.read_dir_block(disk_inode, index);
+            let mut offset = 0;
+            while let Some(mut record) = DirEntry::parse(&block, offset) {
+                let rec_len = record.rec_len();
+                if record.spare() >= needed {
+                    let mut location = offset;
+                    if record.is_free() {
+                        dirent.set_rec_len(rec_len);
+                    } else {
+                        // 拆分: 原记录只保留存放名字所需的长度
+                        let used = rec_len - record.spare();
+                        record.set_rec_len(used);
+                        record.write(&mut block, offset);
+                        dirent.set_rec_len(rec_len - used);
+                        location += used as usize;
+                    }
+                    dirent.write(&mut block, location);
+                    disk_inode.write_at((index * BLOCK_SIZE) as usize, &block, &self.block_device);
+                    return Some(index * BLOCK_SIZE + location as u32);
+                }
+                offset += rec_len as usize;
+            }
+        }
+        let size = blocks * BLOCK_SIZE;
+        if !self.increase_size(size + BLOCK_SIZE, disk_inode, op) {
+            return None;
+        }
+        let mut block = [0u8; BLOCK_SIZE as usize];
+        let mut offset = 0;
+        if blocks == 0 {
+            let index_record = DirEntry::new_index(0);
+            index_record.write(&mut block, 0);
+            offset = index_record.rec_len();
+        }
+        dirent.set_rec_len(BLOCK_SIZE - offset);
+        dirent.write(&mut block, offset as usize);
+        disk_inode.write_at(size as usize, &block, &self.block_device);
+        Some(size + offset)
+    }
+
+    /// 删除 pos 处的目录项
+    fn remove_dirent(&self, disk_inode: &mut DiskInode, pos: DirentPos) {
+        let mut block = self.read_dir_block(disk_inode, pos.block);
+        let rec_len = DirEntry::parse(&block, pos.offset).unwrap().rec_len();
+        match pos.prev {
+            // 并入前一项
+            Some(prev) => {
+                let mut prev_dirent = DirEntry::parse(&block, prev).unwrap();
+                prev_dirent.set_rec_len(prev_dirent.rec_len() + rec_len);
+                prev_dirent.write(&mut block, prev);
+            }
+            None => DirEntry::new_free(rec_len).write(&mut block, pos.offset),
+        }
+        disk_inode.write_at((pos.block * BLOCK_SIZE) as usize, &block, &self.block_device);
+    }
+
+    /// 把 location 处的目录项改为指向 inode_id，目录项的位置与名字不变 (索引无需修改)
+    fn set_dirent_inode(&self, location: u32, inode_id: u32) {
+        self.modify_disk_inode(|disk_inode| {
+            let index = location / BLOCK_SIZE;
+            let offset = (location % BLOCK_SIZE) as usize;
+            let mut block = self.read_dir_block(disk_inode, index);
+            let mut dirent = DirEntry::parse(&block, offset).unwrap();
+            dirent.set_inode_number(inode_id);
+            dirent.write(&mut block, offset);
+            disk_inode.write_at((index * BLOCK_SIZE) as usize, &block, &self.block_device);
+        });
+    }
+
+    /// 删除 pos 处名字哈希值为 hash 的目录项并更新索引，释放目录末尾空出的块
+    fn detach_dirent(&self, pos: DirentPos, hash: u32, op: &Op) {
+        self.modify_disk_inode(|dir_inode| self.remove_dirent(dir_inode, pos));
+        self.index_remove(hash, pos.location(), op);
+        let freed = self.modify_disk_inode(|dir_inode| self.trim_dir(dir_inode));
+        let mut fs = op.fs();
+        for data_block in freed {
+            fs.dealloc_data_block(data_block);
+        }
+    }
+
+    /// 释放目录末尾不含任何内容的块，返回被释放的数据块
+    /// 因此空目录的大小总是 0
+    fn trim_dir(&self, disk_inode: &mut DiskInode) -> Vec<u32> {
+        let mut blocks = disk_inode.size.div_ceil(BLOCK_SIZE);
+        while blocks > 0 && DirEntry::parse(&self.read_dir_block(disk_inode, blocks - 1), 0)
+            .is_some_and(|dirent| dirent.is_empty_block(BLOCK_SIZE)) {
+            blocks -= 1;
+        }
+        disk_inode.decrease_size((blocks * BLOCK_SIZE).min(disk_inode.size), &self.block_device)
+    }
+
+    // ----- directory index -----
+
+    /// 目录块 0 开头的索引记录，旧镜像中的目录没有索引记录
+    fn index_record(&self, disk_inode: &DiskInode) -> Option<DirEntry> {
+        if disk_inode.size < BLOCK_SIZE {
+            return None;
+        }
+        DirEntry::parse(&self.read_dir_block(disk_inode, 0), 0).filter(|record| record.is_index())
+    }
+
+    /// 目录的索引 (可能尚不可用)，没有索引时返回 None
+    fn dir_index(&self, disk_inode: &DiskInode) -> Option<DirIndex<'_>> {
+        self.index_record(disk_inode)
+            .filter(|record| record.index_root() != 0)
+            .map(|record| DirIndex::new(record.index_root(), &self.block_device))
+    }
+
+    fn set_index_root(&self, root: u32) {
+        self.modify_disk_inode(|disk_inode| {
+            let mut block = self.read_dir_block(disk_inode, 0);
+            // 保留记录长度 (其后可能并入了空闲空间)
+            let mut record = DirEntry::parse(&block, 0).unwrap();
+            record.set_inode_number(root);
+            record.write(&mut block, 0);
+            disk_inode.write_at(0, &block, &self.block_device);
         });
     }
 
+    /// 在索引中记录新的目录项；索引不可用或桶已满时，足够大的目录重建索引
+    fn index_insert(&self, hash: u32, location: u32, op: &mut Op) {
+        let (inserted, blocks) = self.read_disk_inode(|disk_inode| {
+            let inserted = self.dir_index(disk_inode).is_some_and(|index| index.insert(hash, location));
+            (inserted, disk_inode.size.div_ceil(BLOCK_SIZE))
+        });
+        if !inserted && blocks >= INDEX_MIN_BLOCKS {
+            self.build_index(op);
+        }
+    }
+
+    /// 从索引中删除目录项，目录变空时释放索引
+    fn index_remove(&self, hash: u32, location: u32, op: &Op) {
+        let empty = self.read_disk_inode(|disk_inode| {
+            let remaining = self.dir_index(disk_inode)?.remove(hash, location);
+            // 索引不可用时只能遍历目录
+            Some(remaining.map_or_else(|| self.find_dirent(disk_inode, |_, _| Some(())).is_none(), |n| n == 0))
+        });
+        if empty == Some(true) {
+            self.drop_index(op);
+        }
+    }
+
+    /// 释放目录的索引 (保留索引记录)，目录没有索引记录时返回 false
+    fn drop_index(&self, op: &Op) -> bool {
+        let Some(record) = self.read_disk_inode(|disk_inode| self.index_record(disk_inode)) else {
+            return false;
+        };
+        if record.index_root() != 0 {
+            let blocks = DirIndex::new(record.index_root(), &self.block_device).blocks();
+            let mut fs = op.fs();
+            for block in blocks {
+                fs.dealloc_data_block(block);
+            }
+            drop(fs);
+            self.set_index_root(0);
+        }
+        true
+    }
+
+    /// 为目录重新建立索引，分多个事务完成: 当前操作先结束，之后的每一批是一个新的操作
+    /// 目录没有索引记录、目录项过多或磁盘空间不足时，目录保持没有索引
+    fn build_index(&self, op: &mut Op) {
+        // 1. 释放旧索引
+        if !self.drop_index(op) {
+            return;
+        }
+        // 2. 按哈希值分桶，平均每桶 16 项，有桶溢出时加倍
+        let entries = self.read_disk_inode(|disk_inode| {
+            let mut entries = Vec::new();
+            self.find_dirent(disk_inode, |pos, dirent| -> Option<()> {
+                entries.push((name_hash(dirent.raw_name()), pos.location()));
+                None
+            });
+            entries
+        });
+        let mut buckets = entries.len().div_ceil(16).clamp(1, MAX_BUCKETS);
+        let table = loop {
+            match distribute(&entries, buckets) {
+                Some(table) => break table,
+                None if buckets < MAX_BUCKETS => buckets = (buckets * 2).min(MAX_BUCKETS),
+                None => return,
+            }
+        };
+        // 3. 分配根块并挂到索引记录上，此时索引还不可用
+        let root = op.fs().alloc_data_block();
+        let Some(root) = root else {
+            return;
+        };
+        let index = DirIndex::new(root, &self.block_device);
+        index.initialize(buckets as u32, entries.len() as u32);
+        self.set_index_root(root);
+        // 4. 分批写入各个桶
+        let chunk_blocks = Self::write_chunk_blocks(&op.fs());
+        let batch_blocks = chunk_blocks + METADATA_BLOCKS;
+        op.restart(batch_blocks);
+        for (i, bucket) in table.iter().enumerate() {
+            let block = op.fs().alloc_data_block();
+            let Some(block) = block else {
+                self.drop_index(op);
+                return;
+            };
+            get_block_cache(block as usize, Arc::clone(&self.block_device)).lock()
+                .modify(0, |data: &mut Bucket| *data = *bucket);
+            index.set_bucket_block(i, block);
+            if (i + 1) % chunk_blocks == 0 {
+                op.restart(batch_blocks);
+            }
+        }
+        // 5. 写入 magic，索引生效
+        index.set_valid();
+    }
+
     /// 从文件的指定偏移位置读取数据到缓冲区，实质上是 disk inode 的读取操作
+    /// 读到数据时按 relatime 的规则更新 atime，避免每次读取都写一次 inode
+    /// 读取时只持有读锁；需要更新 atime 时读完再获取写锁
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            disk_inode.read_at(offset, buf, &self.block_device)
-        })
+        self.read(offset, buf, None)
+    }
+
+    /// 同 read_at，ra 是打开的文件记录的访问模式，顺序读取时预读之后的块
+    pub fn read_at_ahead(&self, offset: usize, buf: &mut [u8], ra: &mut ReadAhead) -> usize {
+        self.read(offset, buf, Some(ra))
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8], ra: Option<&mut ReadAhead>) -> usize {
+        let ahead = ra.map_or(0, |ra| ra.on_read(offset, buf.len()));
+        let (len, times) = {
+            let _guard = self.lock.read();
+            let disk_inode = self.disk_inode();
+            (disk_inode.read_at_ahead(offset, buf, &self.block_device, ahead), disk_inode.times())
+        };
+        if let Some(now) = self.cache.now()
+            && len > 0
+            && now != times.atime
+            && (times.atime <= times.mtime || times.atime <= times.ctime
+                || now.sec >= times.atime.sec + ATIME_UPDATE_INTERVAL) {
+            let _guard = self.lock.write();
+            let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+            self.modify_disk_inode(|disk_inode| disk_inode.set_atime(now));
+            op.fs().mark_dirty(self.inode_id, false);
+        }
+        len
     }
 
     /// 写入数据到 inode 的指定偏移处，实质上是 disk inode 的写入操作
+    /// 大的写入被拆分为多个事务，每个事务原子地完成一段的扩容与写入
+    /// 磁盘空间不足时停止写入，返回已写入的字节数
     // 注意: write_at 之前先调用 increase_size 扩容
+    /// 符号链接的内容在创建后不能修改，返回 0
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
-        let mut fs = self.fs.lock();
-        self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
-        })
+        let _guard = self.lock.write();
+        if self.read_disk_inode(|disk_inode| disk_inode.is_symlink()) {
+            return 0;
+        }
+        let chunk_size = Self::write_chunk_blocks(&self.fs.lock()) * BLOCK_SIZE as usize;
+        let now = self.cache.now();
+        // 写入位置超出文件末尾时，先把文件扩展到 offset (中间的块全为零)
+        if !self.extend_to(offset) {
+            return 0;
+        }
+        let mut written = 0;
+        for chunk in buf.chunks(chunk_size) {
+            let chunk_offset = offset + written;
+            let op = EasyFileSystem::begin_op(&self.fs, chunk.len().div_ceil(BLOCK_SIZE as usize) + 1 + METADATA_BLOCKS);
+            let len = self.update_disk_inode(|disk_inode| {
+                if !self.increase_size((chunk_offset + chunk.len()) as u32, disk_inode, &op) {
+                    return 0;
+                }
+                touch(disk_inode, now);
+                disk_inode.write_at(chunk_offset, chunk, &self.block_device)
+            });
+            op.fs().mark_dirty(self.inode_id, true);
+            drop(op);
+            written += len;
+            if len < chunk.len() {
+                break;
+            }
+        }
+        written
+    }
+
+    /// 把文件截断或扩展到 new_size，扩展的部分全为零
+    /// 超出最大文件大小或磁盘空间不足时返回 false，后者文件可能已被扩展了一部分；符号链接不能截断
+    pub fn truncate(&self, new_size: usize) -> bool {
+        let _guard = self.lock.write();
+        let (size, max_size, is_symlink) = self.read_disk_inode(|disk_inode| {
+            (disk_inode.size as usize, disk_inode.max_size(), disk_inode.is_symlink())
+        });
+        if new_size > max_size || is_symlink {
+            return false;
+        }
+        if new_size >= size {
+            return self.extend_to(new_size);
+        }
+        let op = EasyFileSystem::begin_op(&self.fs, OP_BLOCKS);
+        let now = self.cache.now();
+        let data_blocks_dealloc = self.modify_disk_inode(|disk_inode| {
+            touch(disk_inode, now);
+            disk_inode.decrease_size(new_size as u32, &self.block_device)
+        });
+        let mut fs = op.fs();
+        for data_block in data_blocks_dealloc {
+            fs.dealloc_data_block(data_block);
+        }
+        fs.mark_dirty(self.inode_id, true);
+        true
+    }
+
+    /// 分段 (每段一个操作) 把文件扩展到 new_size，同时更新 mtime；调用者持有 inode 的写锁
+    fn extend_to(&self, new_size: usize) -> bool {
+        let chunk_size = Self::write_chunk_blocks(&self.fs.lock()) * BLOCK_SIZE as usize;
+        loop {
+            let size = self.read_disk_inode(|disk_inode| disk_inode.size as usize);
+            if size >= new_size {
+                return true;
+            }
+            let size = new_size.min(size + chunk_size);
+            let op = EasyFileSystem::begin_op(&self.fs, chunk_size / BLOCK_SIZE as usize + 1 + METADATA_BLOCKS);
+            let now = self.cache.now();
+            let extended = self.update_disk_inode(|disk_inode| {
+                touch(disk_inode, now);
+                self.increase_size(size as u32, disk_inode, &op)
+            });
+            op.fs().mark_dirty(self.inode_id, true);
+            drop(op);
+            if !extended {
+                return false;
+            }
+        }
+    }
+
+    /// 一个写入事务最多写入的数据块数
+    // 一段未对齐的写入最多跨越 n + 1 个数据块，另外还会修改 METADATA_BLOCKS 个块:
+    // inode 所在块、数据位图 (至多 2 块)、indirect1、indirect2 及其下的 indirect1 (至多 2 块)
+    // extent 树通常只修改最右的叶节点，新建节点时还有到根节点的路径；以及记录空闲计数的 SuperBlock
+    fn write_chunk_blocks(fs: &EasyFileSystem) -> usize {
+        fs.transaction_blocks().saturating_sub(METADATA_BLOCKS).max(1)
     }
 
     /// 增加文件大小，必要时分配新的数据块
-    fn increase_size(&self, new_size: u32, disk_inode: &mut DiskInode, fs: &mut MutexGuard<EasyFileSystem>) {
+    /// 空间不足时归还已分配的块并返回 false
+    /// disk_inode 是副本 (见 update_disk_inode)，分配期间持有 fs 锁
+    fn increase_size(&self, new_size: u32, disk_inode: &mut DiskInode, op: &Op) -> bool {
         if new_size < disk_inode.size {
-            return; // 无需扩容
+            return true; // 无需扩容
+        }
+        let mut fs = op.fs();
+        if disk_inode.uses_extents() {
+            return self.increase_size_extents(new_size, disk_inode, &mut fs);
         }
         let blocks_needed = disk_inode.blocks_num_needed(new_size);
         let mut v: Vec<u32> = Vec::new();
-        // 分配所需的数据块
-        for _ in 0..blocks_needed {
-            v.push(fs.alloc_data_block());
+        // 分配所需的数据块 (与其间的索引块)，尽量紧接着文件原来的最后一个块
+        let mut goal = disk_inode.last_block(&self.block_device).map(|block_id| block_id + 1);
+        while (v.len() as u32) < blocks_needed {
+            let Some((start, len)) = fs.alloc_data_run(goal, blocks_needed - v.len() as u32) else {
+                for block_id in v {
+                    fs.dealloc_data_block(block_id);
+                }
+                return false;
+            };
+            v.extend(start..start + len);
+            goal = Some(start + len);
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
+        true
+    }
+
+    /// 同 `increase_size`，用于使用 extent 树的 inode: 尽量分配连续的数据块，再为树的新节点分配块
+    fn increase_size_extents(&self, new_size: u32, disk_inode: &mut DiskInode, fs: &mut EasyFileSystem) -> bool {
+        let mut tree = disk_inode.extent_tree(&self.block_device);
+        let mut allocated: Vec<u32> = Vec::new();
+        let mut remaining = new_size.div_ceil(BLOCK_SIZE) - tree.blocks();
+        let mut goal = disk_inode.last_block(&self.block_device).map(|block_id| block_id + 1);
+        while remaining > 0 {
+            let Some((start, len)) = fs.alloc_data_run(goal, remaining) else {
+                allocated.into_iter().for_each(|block_id| fs.dealloc_data_block(block_id));
+                return false;
+            };
+            tree.append(start, len);
+            allocated.extend(start..start + len);
+            remaining -= len;
+            goal = Some(start + len);
+        }
+        let mut node_blocks = Vec::new();
+        for _ in 0..tree.new_nodes() {
+            match fs.alloc_data_block() {
+                Some(block_id) => node_blocks.push(block_id),
+                None => {
+                    allocated.into_iter().chain(node_blocks).for_each(|block_id| fs.dealloc_data_block(block_id));
+                    return false;
+                }
+            }
+        }
+        disk_inode.increase_size_extents(new_size, tree, node_blocks, &self.block_device);
+        true
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,151 @@
+// fs/src/journal.rs
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::block_cache::{dirty_block_caches, get_block_cache};
+use crate::block_dev::BlockDevice;
+use crate::config::{BLOCK_SIZE, JOURNAL_MAGIC};
+
+/// 一个事务最多能记录的块数，受限于日志头中块号数组的长度
+pub const JOURNAL_MAX_BLOCKS: usize = (BLOCK_SIZE as usize - 12) / 4; // 125
+
+// ----- Write-ahead Journal -----
+/*
+日志区位于磁盘末尾，共 journal_blocks 个块:
+[日志头][块副本0][块副本1]...[块副本n-1]
+
+一个 Inode 操作 (create / write_at / clear ...) 修改的所有块构成一个事务，提交过程:
+1. 把所有被修改的块写入日志区的副本位置
+2. 写入日志头 (提交记录)，此后事务视为已提交
+3. 把被修改的块写回原位 (checkpoint)
+4. 清空日志头
+在 2 之前崩溃，事务整体丢失；在 2 之后崩溃，EasyFileSystem::open 时由 replay 重做
+ */
+
+/// 日志头，恰好占用一个块
+#[repr(C)]
+struct JournalHeader {
+    magic: u32,
+    count: u32,    // 事务包含的块数
+    checksum: u32, // 块号与块副本的校验和，用于识别未写完的事务
+    block_ids: [u32; JOURNAL_MAX_BLOCKS],
+}
+
+impl JournalHeader {
+    fn empty() -> Self {
+        Self {
+            magic: 0,
+            count: 0,
+            checksum: 0,
+            block_ids: [0; JOURNAL_MAX_BLOCKS],
+        }
+    }
+    fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, BLOCK_SIZE as usize) }
+    }
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, BLOCK_SIZE as usize) }
+    }
+}
+
+// FNV-1a
+fn checksum(mut hash: u32, bytes: &[u8]) -> u32 {
+    for byte in bytes {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+const CHECKSUM_INIT: u32 = 0x811c_9dc5;
+
+/// 内存中的日志区描述
+pub struct Journal {
+    start_block: u32, // 日志头所在块号
+    blocks: u32,      // 日志区总块数 (含日志头)
+}
+
+impl Journal {
+    // ----- constructor -----
+    pub fn new(start_block: u32, blocks: u32) -> Self {
+        assert!(blocks >= 2);
+        Self { start_block, blocks }
+    }
+    // ----- methods -----
+    /// 一个事务最多能包含的块数
+    pub fn capacity(&self) -> usize {
+        (self.blocks as usize - 1).min(JOURNAL_MAX_BLOCKS)
+    }
+
+    fn write_header(&self, block_device: &Arc<dyn BlockDevice>, header: &JournalHeader) {
+        block_device.write_block(self.start_block as usize, header.as_bytes());
+    }
+
+    /// 提交块设备上所有被修改过的块缓存
+    /// 事务超出日志容量时不写入任何块 (修改留在块缓存中) 并返回 false: 直接写回原位不是原子的，
+    /// 崩溃后会留下写了一半的操作，由调用者决定 (见 `EasyFileSystem::commit_or_write_back`)。
+    /// Inode 的操作会把大的写入拆分为多个事务，超出容量说明操作对块数的估计有误
+    pub fn commit(&self, block_device: &Arc<dyn BlockDevice>) -> bool {
+        let dirty = dirty_block_caches(block_device);
+        if dirty.is_empty() {
+            return true;
+        }
+        if dirty.len() > self.capacity() {
+            return false;
+        }
+        // 1. 写入块副本
+        let mut header = JournalHeader::empty();
+        let mut sum = CHECKSUM_INIT;
+        for (i, cache) in dirty.iter().enumerate() {
+            let cache = cache.lock();
+            header.block_ids[i] = cache.block_id() as u32;
+            sum = checksum(sum, &header.block_ids[i].to_le_bytes());
+            sum = checksum(sum, cache.data());
+            block_device.write_block(self.start_block as usize + 1 + i, cache.data());
+        }
+        // 2. 写入提交记录
+        header.magic = JOURNAL_MAGIC;
+        header.count = dirty.len() as u32;
+        header.checksum = sum;
+        self.write_header(block_device, &header);
+        // 3. checkpoint
+        dirty.iter().for_each(|cache| cache.lock().sync());
+        // 4. 事务完成，清空日志
+        self.write_header(block_device, &JournalHeader::empty());
+        true
+    }
+
+    /// 重做日志中已提交但可能未写回原位的事务，返回重做的块数
+    pub fn replay(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        let mut header = JournalHeader::empty();
+        block_device.read_block(self.start_block as usize, header.as_bytes_mut());
+        if header.magic != JOURNAL_MAGIC {
+            return 0;
+        }
+        let count = header.count as usize;
+        let mut blocks: Vec<[u8; BLOCK_SIZE as usize]> = Vec::new();
+        let mut sum = CHECKSUM_INIT;
+        if count <= self.capacity() {
+            for i in 0..count {
+                let mut data = [0u8; BLOCK_SIZE as usize];
+                block_device.read_block(self.start_block as usize + 1 + i, &mut data);
+                sum = checksum(sum, &header.block_ids[i].to_le_bytes());
+                sum = checksum(sum, &data);
+                blocks.push(data);
+            }
+        }
+        let replayed = if count <= self.capacity() && sum == header.checksum {
+            // 经由块缓存写回，避免缓存中残留旧数据
+            for (block_id, data) in header.block_ids.iter().zip(blocks.iter()) {
+                let cache = get_block_cache(*block_id as usize, Arc::clone(block_device));
+                let mut cache = cache.lock();
+                cache.modify(0, |block: &mut [u8; BLOCK_SIZE as usize]| block.copy_from_slice(data));
+                cache.sync();
+            }
+            count
+        } else {
+            0 // 日志头损坏或校验和不符，丢弃
+        };
+        self.write_header(block_device, &JournalHeader::empty());
+        replayed
+    }
+}
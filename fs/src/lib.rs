@@ -4,13 +4,26 @@ pub mod block_dev;
 pub mod super_block;
 pub mod config;
 pub mod block_cache;
+pub mod checksum;
+pub mod extent;
 pub mod bitmap;
 pub mod inode;
 pub mod efs;
+pub mod journal;
+pub mod readahead;
+pub mod fsck;
+pub mod time;
+pub mod xattr;
+mod dir_index;
 mod disk_inode;
 
 extern crate alloc;
 
 pub use block_dev::BlockDevice;
-pub use inode::Inode;
-pub use efs::EasyFileSystem;
\ No newline at end of file
+pub use inode::{Inode, Permissions, RenameError, RenameMode};
+pub use efs::{EasyFileSystem, FormatOptions, FsUsage};
+pub use checksum::ChecksumMode;
+pub use readahead::ReadAhead;
+pub use time::{Times, Timestamp};
+pub use xattr::{XattrError, XattrMode, XATTR_NAME_MAX, XATTR_SIZE_MAX};
+pub use disk_inode::{MAX_EXTENT_FILE_SIZE, MAX_FILE_SIZE, NAME_LENGTH_LIMIT, SYMLINK_LENGTH_LIMIT};
\ No newline at end of file
@@ -0,0 +1,50 @@
+// fs/src/readahead.rs
+// 顺序读取的预读
+// 每个打开的文件 (内核中的 OSInode) 各自记录访问模式: 读取紧接着上一次读取的末尾时视为顺序读取，
+// 除了这次要读的块，再把之后的 window 个块一起读入块缓存；连续的顺序读取使窗口加倍，随机读取时关闭预读
+// 预读在 DiskInode::read_at_ahead 中进行，磁盘上连续的块用一次多块请求 (BlockDevice::read_blocks) 读取
+
+use crate::block_cache::MAX_PREFETCH;
+use crate::config::BLOCK_SIZE;
+
+/// 第一次顺序读取时预读的块数
+const INITIAL_WINDOW: u32 = 4;
+/// 预读窗口的上限: 一次读取一个块时，这个块与预读的块正好是一次预读请求
+const MAX_WINDOW: u32 = MAX_PREFETCH as u32 - 1;
+
+pub struct ReadAhead {
+    next_offset: usize, // 上一次读取的末尾，下一次从这里开始读取即为顺序读取
+    ahead: u32,         // 已经安排预读到的块 (文件内的块号，不含)
+    window: u32,        // 当前的预读窗口，0 表示不预读
+}
+
+impl ReadAhead {
+    // ----- constructor -----
+    /// 从文件开头读取视为顺序读取
+    pub const fn new() -> Self {
+        Self { next_offset: 0, ahead: 0, window: 0 }
+    }
+    // ----- methods -----
+    /// 读取 [offset, offset + len) 之前调用，返回可以预读到的块 (文件内的块号，不含)
+    pub(crate) fn on_read(&mut self, offset: usize, len: usize) -> u32 {
+        let end = (offset + len).div_ceil(BLOCK_SIZE as usize) as u32;
+        let sequential = offset == self.next_offset;
+        self.next_offset = offset + len;
+        if !sequential {
+            // 随机读取: 只把这次要读的块一起读入
+            self.window = 0;
+            self.ahead = end;
+        } else if end > self.ahead {
+            // 读到了还没有预读的块，扩大窗口继续预读
+            self.window = (self.window * 2).clamp(INITIAL_WINDOW, MAX_WINDOW);
+            self.ahead = end + self.window;
+        }
+        self.ahead
+    }
+}
+
+impl Default for ReadAhead {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,6 +1,13 @@
 // fs/src/super_block.rs
 
-use crate::config::{BLOCK_SIZE, EFS_MAGIC, INODE_PER_BLOCK};
+use crate::checksum::{crc32c, ChecksumMode, ChecksumTable};
+use crate::config::EFS_MAGIC;
+
+/// features 中的标志位，旧镜像中这些字位于块的空闲部分，全为零
+pub const FEATURE_METADATA_CSUM: u32 = 1; // SuperBlock、位图、inode 区与索引块有校验和
+pub const FEATURE_DATA_CSUM: u32 = 2;     // 数据块也有校验和
+pub const FEATURE_EXTENTS: u32 = 4;       // 新建的文件与目录使用 extent 树
+pub const FEATURE_FREE_COUNTS: u32 = 8;   // free_inodes 与 free_blocks 有效
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -11,6 +18,12 @@ pub struct SuperBlock {
     pub inode_area_blocks: u32,   // inode区占用块数
     pub data_bitmap_blocks: u32,  // 数据位图占用块数
     pub data_area_blocks: u32,    // 数据区占用块数
+    pub journal_blocks: u32,      // 磁盘末尾日志区占用块数，0 表示没有日志
+    pub features: u32,            // FEATURE_* 标志位
+    pub checksum_blocks: u32,     // 日志区之前的校验表占用块数 (见 checksum.rs)
+    pub checksum: u32,            // SuperBlock 自身的校验和 (计算时视为 0)
+    pub free_inodes: u32,         // 空闲的 inode 数，与位图在同一事务中更新
+    pub free_blocks: u32,         // 空闲的数据块数
 }
 
 impl SuperBlock {
@@ -23,18 +36,27 @@ impl SuperBlock {
             inode_area_blocks: 0,
             data_bitmap_blocks: 0,
             data_area_blocks: 0,
+            journal_blocks: 0,
+            features: 0,
+            checksum_blocks: 0,
+            checksum: 0,
+            free_inodes: 0,
+            free_blocks: 0,
         }
     }
     // ----- methods -----
-    pub fn initialize(&mut self, num_inode: u32, num_dnode: u32) {
-        let inode_bitmap_blocks = (num_inode / 8 - 1) / BLOCK_SIZE + 1;
-        let inode_area_blocks = (num_inode - 1) / INODE_PER_BLOCK + 1;
-        let data_bitmap_blocks = (num_dnode / 8 - 1) / BLOCK_SIZE + 1;
-        let data_area_blocks = num_dnode;
-        
-        let total_blocks = 1 + 
-            inode_bitmap_blocks + inode_area_blocks + data_bitmap_blocks + data_area_blocks;
-
+    /// 各区域的大小由 `EasyFileSystem::create` 计算，这里原样记录，
+    /// 保证 `EasyFileSystem::open` 得到与创建时相同的布局
+    /// 不使用校验和；需要时之后再设置 features 与 checksum_blocks
+    pub fn initialize(
+        &mut self,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        inode_area_blocks: u32,
+        data_bitmap_blocks: u32,
+        data_area_blocks: u32,
+        journal_blocks: u32,
+    ) {
         *self = Self {
             magic: EFS_MAGIC,
             total_blocks,
@@ -42,9 +64,67 @@ impl SuperBlock {
             inode_area_blocks,
             data_bitmap_blocks,
             data_area_blocks,
+            journal_blocks,
+            features: 0,
+            checksum_blocks: 0,
+            checksum: 0,
+            free_inodes: 0,
+            free_blocks: 0,
         }
     }
+    /// 日志区的起始块号
+    pub fn journal_start_block(&self) -> u32 {
+        self.total_blocks - self.journal_blocks
+    }
     pub fn is_valid(&self) -> bool {
         self.magic == EFS_MAGIC
     }
-}
\ No newline at end of file
+    /// 记录的 (空闲 inode 数, 空闲数据块数)，旧镜像没有记录时返回 None
+    pub fn free_counts(&self) -> Option<(u32, u32)> {
+        (self.features & FEATURE_FREE_COUNTS != 0).then_some((self.free_inodes, self.free_blocks))
+    }
+
+    // ----- checksum -----
+    pub fn checksum_mode(&self) -> ChecksumMode {
+        if self.features & FEATURE_DATA_CSUM != 0 {
+            ChecksumMode::All
+        } else if self.features & FEATURE_METADATA_CSUM != 0 {
+            ChecksumMode::Metadata
+        } else {
+            ChecksumMode::None
+        }
+    }
+    /// 校验表的位置，没有使用校验和时返回 None
+    pub fn checksum_table(&self) -> Option<ChecksumTable> {
+        let data_area_start = 1 + self.inode_bitmap_blocks + self.inode_area_blocks + self.data_bitmap_blocks;
+        (self.checksum_mode() != ChecksumMode::None).then(|| ChecksumTable::new(
+            self.journal_start_block() - self.checksum_blocks,
+            self.checksum_blocks,
+            data_area_start,
+            self.checksum_mode() == ChecksumMode::All,
+        ))
+    }
+    /// 没有空闲计数的镜像只计算到 checksum 为止，与加入空闲计数之前创建的镜像保持一致
+    fn compute_checksum(&self) -> u32 {
+        let mut super_block = *self;
+        super_block.checksum = 0;
+        let len = match self.free_counts() {
+            Some(_) => core::mem::size_of::<Self>(),
+            None => core::mem::offset_of!(Self, free_inodes),
+        };
+        let bytes = unsafe {
+            core::slice::from_raw_parts(&super_block as *const _ as *const u8, len)
+        };
+        crc32c(bytes)
+    }
+    /// 每次修改后 (提交事务时) 调用
+    pub fn update_checksum(&mut self) {
+        if self.checksum_mode() != ChecksumMode::None {
+            self.checksum = self.compute_checksum();
+        }
+    }
+    /// 校验和是否正确，没有使用校验和时总是正确
+    pub fn checksum_ok(&self) -> bool {
+        self.checksum_mode() == ChecksumMode::None || self.checksum == self.compute_checksum()
+    }
+}
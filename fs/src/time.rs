@@ -0,0 +1,19 @@
+// fs/src/time.rs
+// 文件时间戳
+// easy-fs 自身没有时钟，由使用者 (内核、fs-fuse) 通过 `EasyFileSystem::set_clock` 提供墙上时间
+// 没有设置时钟时不维护时间戳，新建的 inode 时间为 0
+
+/// 自 Unix 纪元 (1970-01-01 00:00:00 UTC) 以来的时间
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Timestamp {
+    pub sec: u64,
+    pub nsec: u32,
+}
+
+/// inode 的访问时间、内容修改时间与状态 (inode) 改变时间
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Times {
+    pub atime: Timestamp,
+    pub mtime: Timestamp,
+    pub ctime: Timestamp,
+}
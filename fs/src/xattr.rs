@@ -0,0 +1,94 @@
+// fs/src/xattr.rs
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::config::BLOCK_SIZE;
+use crate::disk_inode::DataBlock;
+
+pub const XATTR_MAGIC: u32 = 0x52545841; // "XATR"
+/// 扩展属性名字的最大长度
+pub const XATTR_NAME_MAX: usize = 255;
+const HEADER_SIZE: usize = 8;
+const ENTRY_HEADER_SIZE: usize = 4;
+/// 只有一个扩展属性时，名字与值的最大总长度
+pub const XATTR_SIZE_MAX: usize = BLOCK_SIZE as usize - HEADER_SIZE - ENTRY_HEADER_SIZE;
+
+// ----- Extended Attributes -----
+/*
+一个 inode 的所有扩展属性保存在一个数据块中，DiskInode 的 xattr_block 字段记录其块号，0 表示没有扩展属性
+块: [magic][count][条目] * count
+条目: [name_len: u8][reserved: u8][value_len: u16][name][value]，整个条目按 4 字节对齐
+扩展属性只用于保存少量元数据 (如测试编号、校验和)，放不进一个块的属性在设置时失败
+ */
+
+/// 扩展属性 (名字, 值) 的列表，按设置的先后排列
+pub type Xattrs = Vec<(String, Vec<u8>)>;
+
+/// set_xattr 的方式，由 setxattr 的 flags 决定
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum XattrMode {
+    Set,     // 不存在时新建，已存在时替换
+    Create,  // XATTR_CREATE: 已存在时返回 Exists
+    Replace, // XATTR_REPLACE: 不存在时返回 NotFound
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum XattrError {
+    NotFound, // 属性不存在
+    Exists,   // Create 时属性已存在
+    Invalid,  // 名字为空或过长
+    NoSpace,  // 属性块放不下，或没有空闲的数据块
+}
+
+fn entry_size(name: &str, value: &[u8]) -> usize {
+    (ENTRY_HEADER_SIZE + name.len() + value.len()).next_multiple_of(4)
+}
+
+/// 编码后是否放得进一个块
+pub fn fits(xattrs: &Xattrs) -> bool {
+    HEADER_SIZE + xattrs.iter().map(|(name, value)| entry_size(name, value)).sum::<usize>() <= BLOCK_SIZE as usize
+}
+
+/// 解析属性块，格式错误 (魔数不对、条目越界或名字不是 UTF-8) 时返回 None
+pub fn decode(block: &DataBlock) -> Option<Xattrs> {
+    let word = |pos: usize| u32::from_le_bytes(block[pos..pos + 4].try_into().unwrap());
+    if word(0) != XATTR_MAGIC {
+        return None;
+    }
+    let mut xattrs = Vec::new();
+    let mut pos = HEADER_SIZE;
+    for _ in 0..word(4) {
+        if pos + ENTRY_HEADER_SIZE > block.len() {
+            return None;
+        }
+        let name_len = block[pos] as usize;
+        let value_len = u16::from_le_bytes([block[pos + 2], block[pos + 3]]) as usize;
+        let name_start = pos + ENTRY_HEADER_SIZE;
+        let value_start = name_start + name_len;
+        if name_len == 0 || value_start + value_len > block.len() {
+            return None;
+        }
+        let name = core::str::from_utf8(&block[name_start..value_start]).ok()?;
+        xattrs.push((String::from(name), block[value_start..value_start + value_len].to_vec()));
+        pos += entry_size(name, &block[value_start..value_start + value_len]);
+    }
+    Some(xattrs)
+}
+
+/// 把属性写入块中，调用者保证 `fits(xattrs)`
+pub fn encode(xattrs: &Xattrs, block: &mut DataBlock) {
+    assert!(fits(xattrs));
+    block.fill(0);
+    block[0..4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+    block[4..8].copy_from_slice(&(xattrs.len() as u32).to_le_bytes());
+    let mut pos = HEADER_SIZE;
+    for (name, value) in xattrs {
+        block[pos] = name.len() as u8;
+        block[pos + 2..pos + 4].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        let name_start = pos + ENTRY_HEADER_SIZE;
+        block[name_start..name_start + name.len()].copy_from_slice(name.as_bytes());
+        let value_start = name_start + name.len();
+        block[value_start..value_start + value.len()].copy_from_slice(value);
+        pos += entry_size(name, value);
+    }
+}
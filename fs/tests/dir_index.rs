@@ -0,0 +1,130 @@
+// fs/tests/dir_index.rs
+// 目录索引的测试与查找性能基准，基准的结果需要 `cargo test --release -- --nocapture` 查看
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use easy_fs::fsck::{fsck, Problem};
+use easy_fs::{BlockDevice, EasyFileSystem};
+
+const BLOCK_SZ: usize = 512;
+const TOTAL_BLOCKS: u32 = 16384;
+const FILES: usize = 4095; // 一个 inode 位图块最多 4096 个 inode，其中一个是根目录
+
+/// 内存中的磁盘；克隆得到的设备共享数据，但块缓存按设备区分，相当于重新挂载
+#[derive(Clone)]
+struct MemDisk(Arc<Mutex<Vec<u8>>>);
+
+impl BlockDevice for MemDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0.lock().unwrap()[block_id * BLOCK_SZ..(block_id + 1) * BLOCK_SZ]);
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.0.lock().unwrap()[block_id * BLOCK_SZ..(block_id + 1) * BLOCK_SZ].copy_from_slice(buf);
+    }
+}
+
+impl MemDisk {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(vec![0; TOTAL_BLOCKS as usize * BLOCK_SZ])))
+    }
+    fn reopen(&self) -> Arc<spin::Mutex<EasyFileSystem>> {
+        EasyFileSystem::open(Arc::new(self.clone()))
+    }
+    fn read_u32(&self, block_id: u32, offset: usize) -> u32 {
+        let pos = block_id as usize * BLOCK_SZ + offset;
+        u32::from_le_bytes(self.0.lock().unwrap()[pos..pos + 4].try_into().unwrap())
+    }
+    fn write_u32(&self, block_id: u32, offset: usize, value: u32) {
+        let pos = block_id as usize * BLOCK_SZ + offset;
+        self.0.lock().unwrap()[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    /// 根目录的索引根块号: 根目录 inode 位于 inode 区 (块 2) 开头，direct[0] 在偏移 4 处；
+    /// 目录块 0 的第一条记录是索引记录，其 inode_number 字段为索引根块号
+    fn index_root(&self) -> u32 {
+        self.read_u32(self.read_u32(2, 4), 0)
+    }
+}
+
+fn file_name(i: usize) -> String {
+    format!("file-{:04}-{}", i, "x".repeat(i % 23))
+}
+
+/// 创建有 FILES 个文件的根目录
+fn populate() -> (MemDisk, Arc<spin::Mutex<EasyFileSystem>>) {
+    let disk = MemDisk::new();
+    let efs = EasyFileSystem::create(Arc::new(disk.clone()), TOTAL_BLOCKS, 1);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    for i in 0..FILES {
+        root_inode.create(&file_name(i)).unwrap();
+    }
+    (disk, efs)
+}
+
+#[test]
+fn dir_index_test() {
+    let (disk, efs) = populate();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_ne!(disk.index_root(), 0);
+    assert!(root_inode.create(&file_name(0)).is_none());
+    assert!((0..FILES).all(|i| root_inode.find_inode(&file_name(i)).is_some()));
+    assert!(root_inode.find_inode("missing").is_none());
+    assert!(fsck(&efs, false).is_clean());
+
+    // 删除一半，再用新名字填回删除后空出的位置
+    for i in (0..FILES).step_by(2) {
+        assert!(root_inode.unlink(&file_name(i)));
+    }
+    for i in (0..FILES).step_by(2) {
+        root_inode.create(&file_name(i + FILES)).unwrap();
+    }
+    let expected = |i: usize| if i.is_multiple_of(2) { file_name(i + FILES) } else { file_name(i) };
+    assert!((0..FILES).all(|i| root_inode.find_inode(&file_name(i)).is_none() == (i.is_multiple_of(2))));
+    assert!(fsck(&efs, false).is_clean());
+
+    // 重新打开后索引仍然可用
+    let efs = disk.reopen();
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!((0..FILES).all(|i| root_inode.find_inode(&expected(i)).is_some()));
+
+    // 索引的目录项计数被破坏: fsck 报告并丢弃索引，之后退回顺序查找，下一次创建时重建索引
+    disk.write_u32(disk.index_root(), 8, 1);
+    let efs = disk.reopen();
+    assert!(fsck(&efs, false).problems.contains(&Problem::BadDirIndex { dir: 0 }));
+    assert!(fsck(&efs, true).repaired);
+    assert!(fsck(&efs, false).is_clean());
+    assert_eq!(disk.index_root(), 0);
+    let root_inode = EasyFileSystem::root_inode(&efs);
+    assert!((0..FILES).all(|i| root_inode.find_inode(&expected(i)).is_some()));
+    assert!(root_inode.unlink(&expected(7)));
+    root_inode.create("rebuilt").unwrap();
+    assert_ne!(disk.index_root(), 0);
+    assert!(root_inode.find_inode("rebuilt").is_some());
+    assert!(fsck(&efs, false).is_clean());
+
+    // 删除所有文件后索引被释放，目录大小回到 0
+    for name in root_inode.ls() {
+        assert!(root_inode.unlink(&name));
+    }
+    assert_eq!(root_inode.size(), 0);
+    assert!(fsck(&efs, false).is_clean());
+}
+
+#[test]
+fn dir_lookup_bench() {
+    let (disk, _) = populate();
+    // 每 8 个文件查找一个，控制顺序查找的耗时
+    let bench = |efs: Arc<spin::Mutex<EasyFileSystem>>| -> Duration {
+        let root_inode = EasyFileSystem::root_inode(&efs);
+        let start = Instant::now();
+        for i in (0..FILES).step_by(8) {
+            assert!(root_inode.find_inode(&file_name(i)).is_some());
+        }
+        start.elapsed() / FILES.div_ceil(8) as u32
+    };
+    let indexed = bench(disk.reopen());
+    // 清除索引根块的 magic: 索引视为重建被中断，查找退回顺序扫描
+    disk.write_u32(disk.index_root(), 0, 0);
+    let linear = bench(disk.reopen());
+    // 耗时只打印 (cargo test -- --nocapture)，不作断言: 结果受机器负载影响
+    println!("lookup in a directory of {} files: indexed {:?}/op, linear scan {:?}/op", FILES, indexed, linear);
+}
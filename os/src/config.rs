@@ -2,6 +2,8 @@
 
 // task
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
+pub const USER_STACK_SIZE: usize = 4096 * 8; // argv/envp/auxv 也放在用户栈上
+pub const USER_MMAP_BASE: usize = 0x10_0000_0000; // mmap 区域的起始地址，heap (brk) 不能超过它
 
 // trampoline
 pub const TRAMPOLINE_START_ADDRESS: usize = usize::MAX - PAGE_SIZE + 1;
@@ -17,11 +19,15 @@ pub const KERNEL_HEAP_SIZE: usize = 0x300000;
 // uart and sbi
 pub const TEST_DEVICE_ADDR: usize = 0x100000; // shutdown devic, QEMU 测试设备地址
 
+pub const RTC_BASE_ADDR: usize = 0x101000; // Goldfish RTC，墙上时钟
+pub const RTC_SIZE: usize = 0x1000;
+
 pub const UART0_BASE_ADDR: usize = 0x10000000;
 pub const UART0_SIZE: usize = 0x100;
 
 pub const VIRTIO0_BASE_ADDR: usize = 0x10001000;
 pub const VIRTIO0_SIZE: usize = 0x1000; // 4KB
+pub const VIRTIO_MMIO_COUNT: usize = 8; // QEMU virt 有 8 个 virtio-mmio 槽位，依次相隔 VIRTIO0_SIZE
 
 pub const CLINT_BASE:     usize = 0x2000000;
 pub const CLINT_SIZE: usize = 0x10000;  // 64KB
@@ -36,6 +42,9 @@ pub const PPN_WIDTH: usize = PA_WIDTH - PAGE_SIZE_BITS;
 
 pub const VA_WIDTH: usize = 39;
 
+// syscall
+pub const PATH_MAX: usize = 4096; // 用户传入的路径/字符串的最大长度
+
 // Return (bottom, top) of a kernel stack in kernel space
 // 次高空间为内核栈
 // 分配 KERNEL_STACK_SIZE + 1 PAGE 作为每个用户的内核栈
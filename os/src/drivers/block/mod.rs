@@ -4,16 +4,64 @@ mod virtio_blk;
 
 pub use virtio_blk::VirtIOBlock;
 
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use easy_fs::BlockDevice;
 use lazy_static::*;
+use crate::config::{VIRTIO0_BASE_ADDR, VIRTIO0_SIZE, VIRTIO_MMIO_COUNT};
 
 pub type BlockDeviceImpl = VirtIOBlock;
 
-const BLOCK_SIZE: usize = 512;
+pub const BLOCK_SIZE: usize = 512;
+
+// virtio-mmio 寄存器
+const VIRTIO_MAGIC: u32 = 0x74726976; // "virt"
+const VIRTIO_DEVICE_ID_BLOCK: u32 = 2;
+
+pub struct BlockDeviceInfo {
+    pub name: String,                  // vda, vdb, ...
+    pub device: Arc<dyn BlockDevice>,
+    pub num_blocks: usize,             // 容量，单位为 BLOCK_SIZE 字节的块
+}
 
 lazy_static! {
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(VirtIOBlock::new());
+    /// 所有 virtio 块设备，按 virtio-mmio 槽位顺序依次命名为 vda, vdb, ...
+    pub static ref BLOCK_DEVICES: Vec<BlockDeviceInfo> = probe_virtio_blk();
+    /// 第一块磁盘，根文件系统所在的设备
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> =
+        BLOCK_DEVICES.first().expect("[kernel] no virtio block device").device.clone();
+}
+
+// 扫描 virtio-mmio 槽位，找出所有块设备
+fn probe_virtio_blk() -> Vec<BlockDeviceInfo> {
+    let mut devices = Vec::new();
+    for slot in 0..VIRTIO_MMIO_COUNT {
+        let base_addr = VIRTIO0_BASE_ADDR + slot * VIRTIO0_SIZE;
+        // offset 0: magic value, offset 8: device id (0 表示该槽位没有设备)
+        // offset 0x100: 设备配置空间，块设备的第一个字段是以 512 字节为单位的容量 (u64)
+        let (magic, device_id, num_blocks) = unsafe {
+            (
+                (base_addr as *const u32).read_volatile(),
+                ((base_addr + 8) as *const u32).read_volatile(),
+                ((base_addr + 0x100) as *const u32).read_volatile() as usize
+                    | (((base_addr + 0x104) as *const u32).read_volatile() as usize) << 32,
+            )
+        };
+        if magic == VIRTIO_MAGIC && device_id == VIRTIO_DEVICE_ID_BLOCK {
+            let name = format!("vd{}", (b'a' + devices.len() as u8) as char);
+            let device: Arc<dyn BlockDevice> = Arc::new(VirtIOBlock::new(base_addr));
+            devices.push(BlockDeviceInfo { name, device, num_blocks });
+        }
+    }
+    devices
+}
+
+/// 根据设备名 ("vda" 或 "/dev/vda") 找到块设备
+pub fn block_device_by_name(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    let name = name.strip_prefix("/dev/").unwrap_or(name);
+    BLOCK_DEVICES.iter().find(|info| info.name == name).map(|info| info.device.clone())
 }
 
 pub fn block_device_test() {
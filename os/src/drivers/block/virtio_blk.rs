@@ -5,8 +5,8 @@ use crate::mm::address::{PhyAddr, PhyPageNum, VirAddr};
 use crate::sync::UPSafeCell;
 use alloc::vec::Vec;
 use lazy_static::*;
-use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
-use crate::config::VIRTIO0_BASE_ADDR;
+use virtio_drivers::{BlkResp, Hal, RespStatus, VirtIOBlk, VirtIOHeader};
+use easy_fs::config::BLOCK_SIZE;
 use crate::mm::KERNEL_SPACE;
 
 // frame_alloc 得到的物理页帧都会被保存在全局的 QUEUE_FRAMES 中
@@ -56,6 +56,9 @@ impl Hal for VirtioHal {
 
 
 // ----- VirtIOBlk -----
+/// 同时提交给设备的最多请求数: 队列有 16 个描述符，每个请求占用 3 个 (请求头、数据、状态)
+const MAX_INFLIGHT: usize = 4;
+
 /// Wrapper for VirtIO block device driver, implements the `BlockDevice` trait,
 /// provides thread-safe block-level storage read/write interface for interacting with virtual block devices.
 pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
@@ -66,6 +69,32 @@ impl BlockDevice for VirtIOBlock {
             .read_block(block_id, buf)
             .expect("Error when reading VirtIOBlk");
     }
+    /// 读取连续的多个块 (预读): 一次提交多个请求再等待它们全部完成，而不是逐块同步读取
+    fn read_blocks(&self, block_id: usize, buf: &mut [u8]) {
+        let mut blk = self.0.exclusive_access();
+        let mut resps = [BlkResp::default(), BlkResp::default(), BlkResp::default(), BlkResp::default()];
+        let chunk = MAX_INFLIGHT * BLOCK_SIZE as usize;
+        for (i, bufs) in buf.chunks_mut(chunk).enumerate() {
+            let base = block_id + i * MAX_INFLIGHT;
+            let mut inflight = 0;
+            for ((j, block), resp) in bufs.chunks_mut(BLOCK_SIZE as usize).enumerate().zip(resps.iter_mut()) {
+                // 设备写入 block 和 resp，它们在请求完成之前都不会被移动或释放
+                unsafe { blk.read_block_nb(base + j, block, resp) }
+                    .expect("Error when reading VirtIOBlk");
+                inflight += 1;
+            }
+            while inflight > 0 {
+                if blk.pop_used().is_ok() {
+                    inflight -= 1;
+                } else {
+                    core::hint::spin_loop();
+                }
+            }
+            for resp in resps.iter().take(bufs.len() / BLOCK_SIZE as usize) {
+                assert!(resp.status() == RespStatus::Ok, "Error when reading VirtIOBlk");
+            }
+        }
+    }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
         self.0.exclusive_access()
             .write_block(block_id, buf)
@@ -74,10 +103,11 @@ impl BlockDevice for VirtIOBlock {
 }
 
 impl VirtIOBlock {
-    pub fn new() -> Self {
+    /// `base_addr`: 设备的 virtio-mmio 寄存器基址
+    pub fn new(base_addr: usize) -> Self {
         unsafe {
             Self(UPSafeCell::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0_BASE_ADDR as *mut VirtIOHeader)).unwrap(),
+                VirtIOBlk::<VirtioHal>::new(&mut *(base_addr as *mut VirtIOHeader)).unwrap(),
             ))
         }
     }
@@ -1,5 +1,7 @@
 // os/src/drivers/mod.rs
 
 mod block;
+mod rtc;
 
-pub use block::BLOCK_DEVICE;
+pub use block::{BLOCK_DEVICE, BLOCK_DEVICES, BLOCK_SIZE, block_device_by_name};
+pub use rtc::rtc_time_ns;
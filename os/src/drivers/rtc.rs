@@ -0,0 +1,20 @@
+// os/src/drivers/rtc.rs
+// QEMU virt 上的 Goldfish RTC，提供自 Unix 纪元以来的纳秒数
+// https://github.com/qemu/qemu/blob/master/hw/rtc/goldfish_rtc.c
+
+use core::ptr::read_volatile;
+use crate::config::RTC_BASE_ADDR;
+
+// 寄存器偏移量
+const TIME_LOW: usize = 0x00;  // 读取时锁存 TIME_HIGH
+const TIME_HIGH: usize = 0x04;
+
+/// 当前的墙上时间，单位为纳秒
+pub fn rtc_time_ns() -> u64 {
+    unsafe {
+        // 必须先读低 32 位，之后读到的高 32 位与之属于同一时刻
+        let low = read_volatile((RTC_BASE_ADDR + TIME_LOW) as *const u32) as u64;
+        let high = read_volatile((RTC_BASE_ADDR + TIME_HIGH) as *const u32) as u64;
+        (high << 32) | low
+    }
+}
@@ -0,0 +1,219 @@
+// os/src/fs/devfs.rs
+// devfs: 设备文件系统，挂载在 /dev
+//   null     读到 EOF，写入的数据被丢弃
+//   zero     读到全 0，写入的数据被丢弃
+//   random   伪随机数 (xorshift64*，以开机后的时钟为种子)，urandom 与之相同
+//   console  可读可写的终端，tty 与之相同
+//   vda ...  virtio 块设备的原始内容，按字节偏移读写
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use easy_fs::block_cache::invalidate_block_cache;
+use crate::config::PAGE_SIZE;
+use crate::drivers::{BLOCK_DEVICES, BLOCK_SIZE};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EFBIG, EINVAL, ENOTDIR};
+use crate::timer::get_time;
+use super::File;
+use super::stdio::Console;
+use super::vfs::{FileSystem, FsStats, InodeType, VfsInode, TMPFS_MAGIC};
+
+// ----- random -----
+lazy_static! {
+    static ref RANDOM_STATE: UPSafeCell<u64> = unsafe { UPSafeCell::new(0) };
+}
+
+/// 用伪随机数填满 buf，不适用于密码学用途
+pub fn fill_random(buf: &mut [u8]) {
+    let mut state = RANDOM_STATE.exclusive_access();
+    if *state == 0 {
+        *state = get_time() as u64 | 1; // 第一次使用时播种，种子不能为 0
+    }
+    for chunk in buf.chunks_mut(8) {
+        *state ^= *state >> 12;
+        *state ^= *state << 25;
+        *state ^= *state >> 27;
+        let value = state.wrapping_mul(0x2545F4914F6CDD1D);
+        chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+    }
+}
+
+// ----- character devices -----
+struct NullDev;
+struct ZeroDev;
+struct RandomDev;
+
+impl File for NullDev {
+    fn readable(&self) -> bool { true }
+    fn writable(&self) -> bool { true }
+    fn read(&self, _buf: UserBuffer) -> Result<usize, isize> { Ok(0) }
+    fn write(&self, buf: UserBuffer) -> Result<usize, isize> { Ok(buf.len()) }
+}
+
+impl File for ZeroDev {
+    fn readable(&self) -> bool { true }
+    fn writable(&self) -> bool { true }
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, isize> {
+        for slice in buf.buffers.iter_mut() {
+            slice.fill(0);
+        }
+        Ok(buf.len())
+    }
+    fn write(&self, buf: UserBuffer) -> Result<usize, isize> { Ok(buf.len()) }
+}
+
+impl File for RandomDev {
+    fn readable(&self) -> bool { true }
+    fn writable(&self) -> bool { true }
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, isize> {
+        for slice in buf.buffers.iter_mut() {
+            fill_random(slice);
+        }
+        Ok(buf.len())
+    }
+    fn write(&self, buf: UserBuffer) -> Result<usize, isize> { Ok(buf.len()) }
+}
+
+// ----- DevFs -----
+pub struct DevFs {
+    root: Arc<DevInode>,
+}
+
+impl DevFs {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self { root: Arc::new(DevInode::Root) })
+    }
+}
+
+impl FileSystem for DevFs {
+    fn fs_type(&self) -> &'static str {
+        "devfs"
+    }
+    fn root_inode(&self) -> Arc<dyn VfsInode> {
+        self.root.clone()
+    }
+    fn statfs(&self) -> FsStats {
+        // 与 Linux 的 devtmpfs 一样报告为 tmpfs
+        FsStats { magic: TMPFS_MAGIC, block_size: PAGE_SIZE, ..FsStats::default() }
+    }
+}
+
+// ----- DevInode -----
+#[derive(Clone, Copy)]
+enum DevInode {
+    Root,
+    Null,
+    Zero,
+    Random,
+    Console,
+    Block(usize), // BLOCK_DEVICES 中的下标
+}
+
+const CHAR_DEVICES: [(&str, DevInode); 6] = [
+    ("null", DevInode::Null),
+    ("zero", DevInode::Zero),
+    ("random", DevInode::Random),
+    ("urandom", DevInode::Random),
+    ("console", DevInode::Console),
+    ("tty", DevInode::Console),
+];
+
+impl DevInode {
+    // 块设备按块读写，不足一块的部分先读出整块再修改 (read-modify-write)
+    // 读写前先把已挂载的文件系统写回，使设备上的内容是最新的；写入的块在 easy-fs 块缓存中的旧内容随后失效，
+    // 否则挂载的文件系统会继续读到旧数据，之后修改这些块时还会把整块旧内容写回、覆盖这次的写入
+    // offset + len 溢出 (lseek 到很大的偏移) 时返回 None
+    fn block_io(index: usize, offset: usize, len: usize, mut f: impl FnMut(&mut [u8; BLOCK_SIZE], usize, usize, usize) -> bool) -> Option<usize> {
+        let info = &BLOCK_DEVICES[index];
+        let end = offset.checked_add(len)?.min(info.num_blocks * BLOCK_SIZE);
+        super::sync();
+        let mut pos = offset;
+        let mut block = [0u8; BLOCK_SIZE];
+        while pos < end {
+            let block_id = pos / BLOCK_SIZE;
+            let block_offset = pos % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - block_offset).min(end - pos);
+            info.device.read_block(block_id, &mut block);
+            if f(&mut block, block_offset, pos - offset, n) {
+                info.device.write_block(block_id, &block);
+                invalidate_block_cache(block_id, &info.device);
+            }
+            pos += n;
+        }
+        Some(end.saturating_sub(offset))
+    }
+}
+
+impl VfsInode for DevInode {
+    fn inode_type(&self) -> InodeType {
+        match self {
+            DevInode::Root => InodeType::Dir,
+            DevInode::Block(_) => InodeType::BlockDevice,
+            _ => InodeType::CharDevice,
+        }
+    }
+    fn ino(&self) -> usize {
+        match *self {
+            DevInode::Root => 1,
+            DevInode::Null => 2,
+            DevInode::Zero => 3,
+            DevInode::Random => 4,
+            DevInode::Console => 5,
+            DevInode::Block(index) => 16 + index,
+        }
+    }
+    fn size(&self) -> usize {
+        match *self {
+            DevInode::Block(index) => BLOCK_DEVICES[index].num_blocks * BLOCK_SIZE,
+            _ => 0,
+        }
+    }
+    // 字符设备的读写由 device() 返回的 File 完成，只有块设备按偏移读写
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        match *self {
+            DevInode::Block(index) => Self::block_io(index, offset, buf.len(), |block, block_offset, buf_offset, n| {
+                buf[buf_offset..buf_offset + n].copy_from_slice(&block[block_offset..block_offset + n]);
+                false
+            }).ok_or(EINVAL),
+            _ => Ok(0),
+        }
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, isize> {
+        match *self {
+            DevInode::Block(index) => Self::block_io(index, offset, buf.len(), |block, block_offset, buf_offset, n| {
+                block[block_offset..block_offset + n].copy_from_slice(&buf[buf_offset..buf_offset + n]);
+                true
+            }).ok_or(EFBIG),
+            _ => Ok(0),
+        }
+    }
+    fn device(&self) -> Option<Arc<dyn File>> {
+        match self {
+            DevInode::Null => Some(Arc::new(NullDev)),
+            DevInode::Zero => Some(Arc::new(ZeroDev)),
+            DevInode::Random => Some(Arc::new(RandomDev)),
+            DevInode::Console => Some(Arc::new(Console)),
+            _ => None,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn VfsInode>> {
+        if !matches!(self, DevInode::Root) {
+            return None;
+        }
+        let inode = CHAR_DEVICES.iter().find(|(dev_name, _)| *dev_name == name).map(|(_, inode)| *inode)
+            .or_else(|| BLOCK_DEVICES.iter().position(|info| info.name == name).map(DevInode::Block))?;
+        Some(Arc::new(inode))
+    }
+    fn readdir(&self) -> Result<Vec<String>, isize> {
+        if !matches!(self, DevInode::Root) {
+            return Err(ENOTDIR);
+        }
+        let mut names: Vec<String> = CHAR_DEVICES.iter().map(|(name, _)| String::from(*name)).collect();
+        names.extend(BLOCK_DEVICES.iter().map(|info| info.name.clone()));
+        Ok(names)
+    }
+}
@@ -0,0 +1,237 @@
+// os/src/fs/efs.rs
+// 将 easy-fs 接入 VFS
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use easy_fs::config::{BLOCK_SIZE, EFS_MAGIC};
+use easy_fs::{BlockDevice, EasyFileSystem, Inode, ReadAhead, RenameError, Timestamp, XattrError, NAME_LENGTH_LIMIT, SYMLINK_LENGTH_LIMIT};
+use crate::syscall::errno::{EEXIST, EFBIG, EINVAL, EIO, EISDIR, ENAMETOOLONG, ENODATA, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, EPERM, ERANGE, EXDEV};
+use crate::timer::{realtime, TimeSpec};
+use super::vfs::{FileSystem, FsStats, InodeTimes, InodeType, Permissions, RenameMode, VfsInode, XattrMode};
+use super::writeback;
+
+// ----- EasyFs -----
+pub struct EasyFs {
+    root: Arc<Inode>,
+}
+
+impl EasyFs {
+    /// 打开块设备上的 easy-fs，块设备上不是 easy-fs 镜像时返回 None
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Option<Arc<Self>> {
+        let efs = EasyFileSystem::try_open(block_device)?;
+        efs.lock().set_clock(|| to_timestamp(realtime()));
+        // 默认每个操作在返回前提交；开启 delayed_commit 时由 fsync/sync 或后台回写 (见 writeback) 提交
+        efs.lock().set_delayed_commit(writeback::delayed_commit());
+        efs.lock().set_dirty_limit(writeback::dirty_limit() / BLOCK_SIZE as usize);
+        Some(Arc::new(Self {
+            root: EasyFileSystem::root_inode(&efs),
+        }))
+    }
+}
+
+impl FileSystem for EasyFs {
+    fn fs_type(&self) -> &'static str {
+        "easyfs"
+    }
+    fn root_inode(&self) -> Arc<dyn VfsInode> {
+        self.root.clone()
+    }
+    /// 提交日志中的当前事务，不能直接写回块缓存，否则未完成的事务会绕过日志写到原位
+    /// 事务超出日志容量、没有提交时返回 EIO
+    fn sync(&self) -> Result<(), isize> {
+        if self.root.sync_fs() { Ok(()) } else { Err(EIO) }
+    }
+    fn writeback(&self, expire: TimeSpec) {
+        self.root.writeback(to_timestamp(expire));
+    }
+    fn set_delayed_commit(&self, delayed: bool) {
+        self.root.set_delayed_commit(delayed);
+    }
+    fn set_dirty_limit(&self, bytes: usize) {
+        self.root.set_dirty_limit(bytes / BLOCK_SIZE as usize);
+    }
+    /// 空闲计数保存在 SuperBlock 中，不需要扫描位图
+    fn statfs(&self) -> FsStats {
+        let usage = self.root.fs_usage();
+        FsStats {
+            magic: EFS_MAGIC as u64,
+            block_size: BLOCK_SIZE as usize,
+            blocks: usage.blocks as u64,
+            free_blocks: usage.free_blocks as u64,
+            files: usage.inodes as u64,
+            free_files: usage.free_inodes as u64,
+        }
+    }
+}
+
+// ----- easy-fs Inode -----
+fn to_efs(perm: Permissions) -> easy_fs::Permissions {
+    easy_fs::Permissions { mode: perm.mode, uid: perm.uid, gid: perm.gid }
+}
+
+fn to_timestamp(time: TimeSpec) -> Timestamp {
+    Timestamp { sec: time.tv_sec as u64, nsec: time.tv_nsec as u32 }
+}
+
+fn to_timespec(time: Timestamp) -> TimeSpec {
+    TimeSpec { tv_sec: time.sec as usize, tv_nsec: time.nsec as usize }
+}
+
+fn xattr_errno(err: XattrError) -> isize {
+    match err {
+        XattrError::NotFound => ENODATA,
+        XattrError::Exists => EEXIST,
+        XattrError::Invalid => ERANGE,
+        XattrError::NoSpace => ENOSPC,
+    }
+}
+
+/// 报告访问到的校验和不符的块 (内容已经损坏，只能由 fsck 修复)，返回是否有这样的块
+fn report_checksum_errors(inode: &Inode) -> bool {
+    let errors = inode.take_checksum_errors();
+    for block in errors.iter() {
+        println_red!("[kernel] easy-fs: block {} checksum mismatch", block);
+    }
+    !errors.is_empty()
+}
+
+/// 执行一次读写，期间访问到损坏的块时返回 EIO，不把损坏的内容交给用户 (写入可能已部分完成)
+/// 内核不会在文件系统操作中间切换任务，这期间记录的块都属于这次读写；
+/// 之前的操作 (例如查找目录) 留下的记录先报告掉，不算在这次读写上
+fn checked(inode: &Inode, f: impl FnOnce() -> usize) -> Result<usize, isize> {
+    report_checksum_errors(inode);
+    let len = f();
+    if report_checksum_errors(inode) { Err(EIO) } else { Ok(len) }
+}
+
+impl VfsInode for Inode {
+    fn inode_type(&self) -> InodeType {
+        if self.is_dir() {
+            InodeType::Dir
+        } else if self.is_symlink() {
+            InodeType::Symlink
+        } else {
+            InodeType::File
+        }
+    }
+    fn ino(&self) -> usize {
+        self.inode_id() as usize
+    }
+    fn size(&self) -> usize {
+        Inode::size(self)
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        checked(self, || Inode::read_at(self, offset, buf))
+    }
+    fn read_at_ahead(&self, offset: usize, buf: &mut [u8], ra: &mut ReadAhead) -> Result<usize, isize> {
+        checked(self, || Inode::read_at_ahead(self, offset, buf, ra))
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, isize> {
+        checked(self, || Inode::write_at(self, offset, buf))
+    }
+    fn permissions(&self) -> Permissions {
+        let perm = Inode::permissions(self);
+        Permissions { mode: perm.mode, uid: perm.uid, gid: perm.gid }
+    }
+    fn set_permissions(&self, perm: Permissions) -> Result<(), isize> {
+        Inode::set_permissions(self, to_efs(perm));
+        Ok(())
+    }
+    fn times(&self) -> InodeTimes {
+        let times = Inode::times(self);
+        InodeTimes { atime: to_timespec(times.atime), mtime: to_timespec(times.mtime), ctime: to_timespec(times.ctime) }
+    }
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> Result<(), isize> {
+        Inode::set_times(self, atime.map(to_timestamp), mtime.map(to_timestamp));
+        Ok(())
+    }
+    fn truncate(&self, size: usize) -> Result<(), isize> {
+        if size > Inode::max_size(self) {
+            return Err(EFBIG);
+        }
+        if Inode::truncate(self, size) { Ok(()) } else { Err(ENOSPC) }
+    }
+    fn fsync(&self, data_only: bool) -> Result<(), isize> {
+        let synced = if data_only { self.sync_data() } else { self.sync_all() };
+        if synced { Ok(()) } else { Err(EIO) }
+    }
+
+    fn get_xattr(&self, name: &str) -> Result<Vec<u8>, isize> {
+        Inode::get_xattr(self, name).ok_or(ENODATA)
+    }
+    fn list_xattr(&self) -> Result<Vec<String>, isize> {
+        Ok(Inode::list_xattr(self))
+    }
+    fn set_xattr(&self, name: &str, value: &[u8], mode: XattrMode) -> Result<(), isize> {
+        let mode = match mode {
+            XattrMode::Set => easy_fs::XattrMode::Set,
+            XattrMode::Create => easy_fs::XattrMode::Create,
+            XattrMode::Replace => easy_fs::XattrMode::Replace,
+        };
+        Inode::set_xattr(self, name, value, mode).map_err(xattr_errno)
+    }
+    fn remove_xattr(&self, name: &str) -> Result<(), isize> {
+        Inode::remove_xattr(self, name).map_err(xattr_errno)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn VfsInode>> {
+        self.find_inode(name).map(|inode| inode as Arc<dyn VfsInode>)
+    }
+    fn create(&self, name: &str, type_: InodeType, perm: Permissions) -> Result<Arc<dyn VfsInode>, isize> {
+        if name.len() > NAME_LENGTH_LIMIT as usize {
+            return Err(ENAMETOOLONG);
+        }
+        if self.find_inode(name).is_some() {
+            return Err(EEXIST);
+        }
+        let inode = match type_ {
+            InodeType::File => self.create_with(name, to_efs(perm)),
+            InodeType::Dir => self.create_dir_with(name, to_efs(perm)),
+            _ => return Err(EPERM), // easy-fs 不支持设备文件，符号链接由 symlink 创建
+        };
+        inode.map(|inode| inode as Arc<dyn VfsInode>).ok_or(ENOSPC)
+    }
+    fn symlink(&self, name: &str, target: &str, perm: Permissions) -> Result<Arc<dyn VfsInode>, isize> {
+        if name.len() > NAME_LENGTH_LIMIT as usize || target.len() > SYMLINK_LENGTH_LIMIT as usize {
+            return Err(ENAMETOOLONG);
+        }
+        if self.find_inode(name).is_some() {
+            return Err(EEXIST);
+        }
+        self.create_symlink_with(name, target, to_efs(perm)).map(|inode| inode as Arc<dyn VfsInode>).ok_or(ENOSPC)
+    }
+    fn readdir(&self) -> Result<Vec<String>, isize> {
+        Ok(self.ls())
+    }
+    fn unlink(&self, name: &str) -> Result<(), isize> {
+        let inode = self.find_inode(name).ok_or(ENOENT)?;
+        if inode.is_dir() && Inode::size(&inode) > 0 {
+            return Err(ENOTEMPTY);
+        }
+        if Inode::unlink(self, name) { Ok(()) } else { Err(ENOENT) }
+    }
+    fn rename(&self, old_name: &str, new_dir: &Arc<dyn VfsInode>, new_name: &str, mode: RenameMode)
+        -> Result<(), isize> {
+        if new_name.len() > NAME_LENGTH_LIMIT as usize {
+            return Err(ENAMETOOLONG);
+        }
+        let new_dir: &dyn Any = new_dir.as_ref();
+        let new_dir = new_dir.downcast_ref::<Inode>().ok_or(EXDEV)?;
+        let mode = match mode {
+            RenameMode::Replace => easy_fs::RenameMode::Replace,
+            RenameMode::NoReplace => easy_fs::RenameMode::NoReplace,
+            RenameMode::Exchange => easy_fs::RenameMode::Exchange,
+        };
+        Inode::rename(self, old_name, new_dir, new_name, mode).map_err(|err| match err {
+            RenameError::NotFound => ENOENT,
+            RenameError::Exists => EEXIST,
+            RenameError::IsDir => EISDIR,
+            RenameError::NotDir => ENOTDIR,
+            RenameError::NotEmpty => ENOTEMPTY,
+            RenameError::Invalid => EINVAL,
+            RenameError::NoSpace => ENOSPC,
+        })
+    }
+}
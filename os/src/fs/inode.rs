@@ -1,141 +1,251 @@
 // os/src/fs/inode.rs
 
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use lazy_static::lazy_static;
+use easy_fs::ReadAhead;
 use crate::mm::page_table::UserBuffer;
-use easy_fs::{EasyFileSystem, Inode};
-use crate::drivers::BLOCK_DEVICE;
 use crate::fs::File;
 use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EEXIST, EINVAL, EISDIR, ELOOP, ENOENT, ENOTDIR, ENOTTY};
+use super::mount::{find_mount, lookup_parent, lookup_path, lookup_path_nofollow, resolve_path};
+use super::path::split_parent;
+use super::perm::{check_access, check_dir_change, new_permissions, MAY_READ, MAY_WRITE};
+use super::vfs::{FileSystem, InodeType, VfsInode};
 
 // ----- OSInode -----
 pub struct OSInode {
     readable: bool,
     writable: bool,
+    append: bool,
+    path: String, // 打开时的绝对路径
+    _fs: Arc<dyn FileSystem>, // 所在的文件系统，文件打开期间不能卸载 (见 umount)
+    device: Option<Arc<dyn File>>, // 字符设备，读写直接交给设备
     inner: UPSafeCell<OSInodeInner>,
 }
 
 pub struct OSInodeInner {
-    offset: usize,
-    inode: Arc<Inode>,
+    offset: usize, // 对目录而言是下一个要读的目录项的序号
+    inode: Arc<dyn VfsInode>,
+    ra: ReadAhead, // 这个打开的文件的访问模式，顺序读取时预读
 }
 impl File for OSInode {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    /// 出错时返回之前已经读到的字节数，一个字节都没有读到时才返回错误
+    fn read(&self, mut buf: UserBuffer) -> Result<usize, isize> {
+        if let Some(device) = &self.device {
+            return device.read(buf);
+        }
         let mut inner = self.inner.exclusive_access();
+        if inner.inode.inode_type() == InodeType::Dir {
+            return Ok(0);
+        }
         let mut total_read_size = 0usize;
+        let inner = &mut *inner;
         for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
+            let read_size = match inner.inode.read_at_ahead(inner.offset, *slice, &mut inner.ra) {
+                Ok(read_size) => read_size,
+                Err(errno) if total_read_size == 0 => return Err(errno),
+                Err(_) => break,
+            };
             if read_size == 0 {
                 break;
             }
             inner.offset += read_size;
             total_read_size += read_size;
         }
-        total_read_size
+        Ok(total_read_size)
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    /// 同 read，出错时返回之前已经写入的字节数
+    fn write(&self, buf: UserBuffer) -> Result<usize, isize> {
+        if let Some(device) = &self.device {
+            return device.write(buf);
+        }
         let mut inner = self.inner.exclusive_access();
+        if self.append {
+            inner.offset = inner.inode.size();
+        }
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
+            let write_size = match inner.inode.write_at(inner.offset, *slice) {
+                Ok(write_size) => write_size,
+                Err(errno) if total_write_size == 0 => return Err(errno),
+                Err(_) => break,
+            };
             inner.offset += write_size;
             total_write_size += write_size;
+            if write_size < slice.len() {
+                break; // no space left on device
+            }
         }
-        total_write_size
+        Ok(total_write_size)
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        match &self.device {
+            Some(device) => device.ioctl(cmd, arg),
+            None => -ENOTTY,
+        }
+    }
+    fn inode(&self) -> Option<Arc<dyn VfsInode>> {
+        Some(self.inner.exclusive_access().inode.clone())
+    }
+    fn truncate(&self, len: usize) -> Result<(), isize> {
+        let inner = self.inner.exclusive_access();
+        if !self.writable || inner.inode.inode_type() != InodeType::File {
+            return Err(EINVAL);
+        }
+        inner.inode.truncate(len)
+    }
+    fn path(&self) -> Option<String> {
+        Some(self.path.clone())
+    }
+    fn getdents64(&self, buf: &mut [u8]) -> Result<usize, isize> {
+        let mut inner = self.inner.exclusive_access();
+        let dir = inner.inode.clone();
+        if dir.inode_type() != InodeType::Dir {
+            return Err(ENOTDIR);
+        }
+        let mut names = Vec::from([String::from("."), String::from("..")]);
+        names.extend(dir.readdir()?);
+        // ".." 按打开时的路径查找父目录: 挂载的文件系统的根目录得到被覆盖的挂载点的父目录，"/" 得到它自己
+        // 目录在打开之后被移动或删除、原来的父目录找不到时退回目录自身
+        let parent_ino = if inner.offset <= 1 {
+            lookup_path(split_parent(&self.path).0).map_or(dir.ino(), |parent| parent.ino())
+        } else {
+            dir.ino()
+        };
+        let mut written = 0;
+        while inner.offset < names.len() {
+            let name = &names[inner.offset];
+            let (ino, d_type) = match name.as_str() {
+                "." => (dir.ino(), InodeType::Dir.dirent_type()),
+                ".." => (parent_ino, InodeType::Dir.dirent_type()),
+                _ => match dir.lookup(name) {
+                    Some(inode) => (inode.ino(), inode.inode_type().dirent_type()),
+                    None => { inner.offset += 1; continue; } // 读目录期间被删除
+                },
+            };
+            // struct linux_dirent64: d_ino(8) d_off(8) d_reclen(2) d_type(1) d_name(NUL 结尾)，按 8 字节对齐
+            let reclen = (19 + name.len() + 1 + 7) & !7;
+            if written + reclen > buf.len() {
+                if written == 0 {
+                    return Err(EINVAL); // 缓冲区连一个目录项都放不下
+                }
+                break;
+            }
+            let record = &mut buf[written..written + reclen];
+            record.fill(0);
+            record[0..8].copy_from_slice(&(ino as u64).to_le_bytes());
+            record[8..16].copy_from_slice(&((inner.offset + 1) as u64).to_le_bytes());
+            record[16..18].copy_from_slice(&(reclen as u16).to_le_bytes());
+            record[18] = d_type;
+            record[19..19 + name.len()].copy_from_slice(name.as_bytes());
+            written += reclen;
+            inner.offset += 1;
+        }
+        Ok(written)
     }
 }
 
 impl OSInode {
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+    pub fn new(readable: bool, writable: bool, append: bool, path: String, inode: Arc<dyn VfsInode>) -> Self {
         Self {
             readable,
             writable,
+            append,
+            _fs: find_mount(&path).0,
+            path,
+            device: inode.device(),
             inner: unsafe { 
-                UPSafeCell::new(OSInodeInner { offset: 0, inode }) 
+                UPSafeCell::new(OSInodeInner { offset: 0, inode, ra: ReadAhead::new() }) 
             },
         }
     }
-    pub fn read_data(&self) -> Vec<u8> {
+    /// 读出从当前偏移到文件末尾的全部内容 (exec 加载 ELF)，顺序读取会触发预读
+    pub fn read_data(&self) -> Result<Vec<u8>, isize> {
         let mut inner = self.inner.exclusive_access();
+        let inner = &mut *inner;
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
         loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
+            let len = inner.inode.read_at_ahead(inner.offset, &mut buffer, &mut inner.ra)?;
             if len == 0 {
                 break;
             }
             inner.offset += len;
             v.extend_from_slice(&buffer[..len]);
         }
-        v
+        Ok(v)
     }
 }
 
-// ----- Root Inode -----
-lazy_static! {
-    pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
-        Arc::new(EasyFileSystem::root_inode(&efs))
-    };
-}
-
 // ----- OpenFlags -----
+// 取值与 Linux (asm-generic/fcntl.h) 一致
 bitflags! {
     pub struct OpenFlags: u32 {
         const RD_ONLY = 0;
         const WR_ONLY = 1 << 0;
         const RDWR = 1 << 1;
-        const CREATE = 1 << 9; // allow creating a new file, always returns an empty file
-        const TRUNC = 1 << 10; // clear file and return an empty one (only for existing files)
+        const CREATE = 0o100;        // create the file if it does not exist
+        const EXCL = 0o200;          // with CREATE: fail if the file exists
+        const TRUNC = 0o1000;        // clear an existing regular file opened for writing
+        const APPEND = 0o2000;       // every write goes to the end of the file
+        const DIRECTORY = 0o200000;  // fail if the path is not a directory
+        const NOFOLLOW = 0o400000;   // fail if the last component is a symbolic link
     }
 }
 
 impl OpenFlags {
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
-            (true, false)
-        } else if self.contains(Self::WR_ONLY) {
+        if self.contains(Self::WR_ONLY) {
             (false, true)
-        } else {
+        } else if self.contains(Self::RDWR) {
             (true, true)
+        } else {
+            (true, false)
         }
     }
 }
 
 // ----- kernel function to open a file -----
 
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+/// 打开 path (规范化的绝对路径) 对应的文件或目录，记录的路径中不含符号链接
+/// 新建的文件权限为 mode 去掉 umask，打开已有的文件时按 flags 检查读写权限
+pub fn open_file(path: &str, flags: OpenFlags, mode: u16) -> Result<Arc<OSInode>, isize> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find_inode(name) {
-            // file exists, clear data
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
-            // create new file
-            let new_inode_option = ROOT_INODE.create(name);
-            if let Some(inode) = new_inode_option {
-                // successfully created new file
-                Some(Arc::new(OSInode::new(readable, writable, inode)))
-            } else {
-                // fail
-                None
+    // O_CREAT | O_EXCL 与 O_NOFOLLOW 不跟随最后一个分量的符号链接
+    let follow = !flags.contains(OpenFlags::NOFOLLOW) && !flags.contains(OpenFlags::CREATE | OpenFlags::EXCL);
+    let path = resolve_path(path, follow)?;
+    let inode = match lookup_path_nofollow(&path) {
+        Ok(inode) => {
+            if flags.contains(OpenFlags::CREATE | OpenFlags::EXCL) {
+                return Err(EEXIST);
             }
-        }
-    } else {
-        let inode_option = ROOT_INODE.find_inode(name);
-        if let Some(inode) = inode_option {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
+            if inode.inode_type() == InodeType::Symlink {
+                return Err(ELOOP);
             }
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
-            None
+            let access = if readable { MAY_READ } else { 0 } | if writable { MAY_WRITE } else { 0 };
+            check_access(&inode, access)?;
+            inode
+        }
+        Err(ENOENT) if flags.contains(OpenFlags::CREATE) => {
+            // create new file, 新文件总是可以按 flags 打开
+            let (parent, name) = lookup_parent(&path)?;
+            check_dir_change(&parent, None)?;
+            parent.create(&name, InodeType::File, new_permissions(mode))?
         }
+        Err(errno) => return Err(errno),
+    };
+    if inode.inode_type() == InodeType::Dir {
+        if writable {
+            return Err(EISDIR);
+        }
+    } else if flags.contains(OpenFlags::DIRECTORY) {
+        return Err(ENOTDIR);
     }
+    if flags.contains(OpenFlags::TRUNC) && writable && inode.inode_type() == InodeType::File {
+        inode.truncate(0)?;
+    }
+    Ok(Arc::new(OSInode::new(readable, writable, flags.contains(OpenFlags::APPEND), path, inode)))
 }
-
@@ -1,17 +1,54 @@
 // os/src/fs/mod.rs
 
+pub mod devfs;
+mod efs;
 mod inode;
+mod mount;
+pub mod path;
+pub mod perm;
+mod procfs;
 mod stdio;
+mod tmpfs;
+mod vfs;
+pub mod writeback;
 
-pub use inode::ROOT_INODE;
 pub use inode::{OSInode, OpenFlags, open_file};
-pub use stdio::{Stdin, Stdout, Stderr};
+pub use mount::{
+    init, is_mount_point, lookup_parent, lookup_path, lookup_path_nofollow, mount, new_filesystem, rename, resolve_path,
+    root_inode, statfs, sync, syncfs, umount,
+};
+pub use stdio::{Console, Stdin, Stdout, Stderr};
+pub use vfs::{FileSystem, FsStats, InodeType, Permissions, RenameMode, VfsInode, XattrMode, S_ISGID, S_ISUID};
 pub use crate::mm::UserBuffer;
+use alloc::string::String;
+use alloc::sync::Arc;
+use crate::syscall::errno::{EINVAL, ENOTDIR, ENOTTY};
 
 /// `File` trait
 pub trait File: Send + Sync {
     fn readable(&self) -> bool;
     fn writable(&self) -> bool;
-    fn read(&self, buf: UserBuffer) -> usize;
-    fn write(&self, buf: UserBuffer) -> usize;
-}
\ No newline at end of file
+    /// 返回读写的字节数，出错时返回 errno (例如 EIO)
+    fn read(&self, buf: UserBuffer) -> Result<usize, isize>;
+    fn write(&self, buf: UserBuffer) -> Result<usize, isize>;
+    /// device specific control, only terminals support it for now
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> isize {
+        -ENOTTY
+    }
+    /// the absolute path of a file opened from the file system, used by the *at syscalls
+    fn path(&self) -> Option<String> {
+        None
+    }
+    /// fill `buf` with `struct linux_dirent64` records, only directories support it
+    fn getdents64(&self, _buf: &mut [u8]) -> Result<usize, isize> {
+        Err(ENOTDIR)
+    }
+    /// the inode behind a file opened from the file system, used by fstat/futimens
+    fn inode(&self) -> Option<Arc<dyn VfsInode>> {
+        None
+    }
+    /// change the size of a regular file (ftruncate)
+    fn truncate(&self, _len: usize) -> Result<(), isize> {
+        Err(EINVAL)
+    }
+}
@@ -0,0 +1,304 @@
+// os/src/fs/mount.rs
+// 挂载表与路径解析
+// 挂载点以规范化的绝对路径记录，解析路径时先找到最长匹配的挂载点，再从该文件系统的根目录逐级 lookup
+// 遇到符号链接时把路径中到链接为止的部分替换为链接目标，重新规范化后从头解析
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use crate::drivers::block_device_by_name;
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EACCES, EBUSY, EINVAL, ELOOP, ENAMETOOLONG, ENODEV, ENOENT, ENOTDIR, EXDEV};
+use super::devfs::DevFs;
+use super::efs::EasyFs;
+use super::path::{normalize, split_parent, NAME_MAX};
+use super::perm::{check_dir_change, permitted, MAY_EXEC};
+use super::procfs::ProcFs;
+use super::tmpfs::TmpFs;
+use super::vfs::{FileSystem, FsStats, InodeType, Permissions, RenameMode, VfsInode};
+use crate::task::all_tasks;
+use crate::task::cred::current_credentials;
+use crate::timer::TimeSpec;
+
+/// 一次路径解析中最多跟随的符号链接数，超过时返回 ELOOP
+const MAX_SYMLINKS: usize = 40;
+
+// ----- MountTable -----
+struct MountPoint {
+    path: String,   // 挂载点，规范化的绝对路径
+    source: String, // 设备名 (如 "vda") 或文件系统类型名
+    fs: Arc<dyn FileSystem>,
+}
+
+lazy_static! {
+    // 启动时将第一块磁盘上的 easy-fs 挂载为根目录
+    static ref MOUNT_TABLE: UPSafeCell<Vec<MountPoint>> = unsafe {
+        let source = String::from("vda");
+        let block_device = block_device_by_name(&source).expect("[kernel] no block device for the root fs");
+        let root_fs = EasyFs::open(block_device).expect("[kernel] Error loading EFS as the root fs");
+        UPSafeCell::new(vec![MountPoint { path: String::from("/"), source, fs: root_fs }])
+    };
+}
+
+/// path 是否位于目录 dir 之下 (不含 dir 自身)，两者都是规范化的绝对路径
+fn is_under(path: &str, dir: &str) -> bool {
+    path.len() > dir.len() && path.starts_with(dir) && (dir == "/" || path.as_bytes()[dir.len()] == b'/')
+}
+
+/// 找到 path 所在的文件系统，返回 (该文件系统, path 在其中的剩余部分)
+pub(super) fn find_mount(path: &str) -> (Arc<dyn FileSystem>, String) {
+    let table = MOUNT_TABLE.exclusive_access();
+    let mount_point = table.iter()
+        .filter(|mp| {
+            mp.path == "/" || path == mp.path
+                || (path.starts_with(mp.path.as_str()) && path.as_bytes()[mp.path.len()] == b'/')
+        })
+        .max_by_key(|mp| mp.path.len())
+        .unwrap(); // "/" 总是匹配
+    let rest = if mount_point.path == "/" { path } else { &path[mount_point.path.len()..] };
+    (mount_point.fs.clone(), String::from(rest))
+}
+
+/// 逐级查找规范化的绝对路径 path，follow 为 false 时不跟随最后一个分量的符号链接
+/// 返回 (解析掉符号链接后的路径, 对应的 inode)，只有最后一个分量不存在时 inode 为 None
+/// 与其他路径一样，链接目标中的 ".." 按字面处理；经过的每个目录都需要当前进程的搜索权限
+fn walk(path: &str, follow: bool) -> Result<(String, Option<Arc<dyn VfsInode>>), isize> {
+    let cred = current_credentials();
+    let mut path = String::from(path);
+    let mut links = 0;
+    'resolve: loop {
+        // 不能在持有挂载表时调用文件系统的方法 (procfs 等可能会读挂载表)
+        let (fs, rest) = find_mount(&path);
+        let names: Vec<&str> = rest.split('/').filter(|name| !name.is_empty()).collect();
+        let mut inode = fs.root_inode();
+        for (i, name) in names.iter().enumerate() {
+            if inode.inode_type() != InodeType::Dir {
+                return Err(ENOTDIR);
+            }
+            if !permitted(&cred, &inode, MAY_EXEC) {
+                return Err(EACCES);
+            }
+            if name.len() > NAME_MAX {
+                return Err(ENAMETOOLONG);
+            }
+            let last = i + 1 == names.len();
+            inode = match inode.lookup(name) {
+                Some(child) => child,
+                None if last => return Ok((path, None)),
+                None => return Err(ENOENT),
+            };
+            if inode.inode_type() == InodeType::Symlink && (follow || !last) {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(ELOOP);
+                }
+                let target = inode.readlink()?;
+                if target.is_empty() {
+                    return Err(ENOENT);
+                }
+                // 相对目标基于链接所在的目录
+                let mount_point = &path[..path.len() - rest.len()];
+                let dir = normalize("/", &format!("{}/{}", mount_point, names[..i].join("/")));
+                path = normalize(&dir, &format!("{}/{}", target, names[i + 1..].join("/")));
+                continue 'resolve;
+            }
+        }
+        return Ok((path, Some(inode)));
+    }
+}
+
+/// 解析 path 中的符号链接，得到不含符号链接的规范化绝对路径
+/// follow 为 false 时保留最后一个分量 (不论它是否是符号链接)，最后一个分量可以不存在
+pub fn resolve_path(path: &str, follow: bool) -> Result<String, isize> {
+    walk(path, follow).map(|(path, _)| path)
+}
+
+/// 根据规范化的绝对路径找到对应的 inode，跟随路径中所有的符号链接
+pub fn lookup_path(path: &str) -> Result<Arc<dyn VfsInode>, isize> {
+    walk(path, true)?.1.ok_or(ENOENT)
+}
+
+/// 同 lookup_path，但最后一个分量是符号链接时返回符号链接本身
+pub fn lookup_path_nofollow(path: &str) -> Result<Arc<dyn VfsInode>, isize> {
+    walk(path, false)?.1.ok_or(ENOENT)
+}
+
+/// 找到 path 的父目录，返回 (父目录 inode, 最后一个分量)
+pub fn lookup_parent(path: &str) -> Result<(Arc<dyn VfsInode>, String), isize> {
+    let (parent, name) = split_parent(path);
+    let parent_inode = lookup_path(parent)?;
+    if parent_inode.inode_type() != InodeType::Dir {
+        return Err(ENOTDIR);
+    }
+    Ok((parent_inode, String::from(name)))
+}
+
+/// path (规范化的绝对路径) 所在文件系统的容量与使用情况，跟随路径中所有的符号链接
+pub fn statfs(path: &str) -> Result<FsStats, isize> {
+    let (path, inode) = walk(path, true)?;
+    inode.ok_or(ENOENT)?;
+    Ok(find_mount(&path).0.statfs())
+}
+
+/// sync: 把所有已挂载的文件系统写回存储设备
+/// sync(2) 没有错误返回值，写回失败的文件系统只打印警告，不影响其他文件系统
+pub fn sync() {
+    // 写回时不持有挂载表，块设备 I/O 期间可能切换到其他任务
+    let mount_points: Vec<_> = MOUNT_TABLE.exclusive_access().iter().map(|mp| (mp.path.clone(), mp.fs.clone())).collect();
+    for (path, fs) in mount_points {
+        if fs.sync().is_err() {
+            println_red!("[kernel] sync: failed to write back {}", path);
+        }
+    }
+}
+
+/// 后台回写所有已挂载的文件系统中过期的修改，见 writeback::tick
+pub fn writeback(expire: TimeSpec) {
+    let filesystems: Vec<_> = MOUNT_TABLE.exclusive_access().iter().map(|mp| mp.fs.clone()).collect();
+    for fs in filesystems {
+        fs.writeback(expire);
+    }
+}
+
+/// 开启或关闭所有已挂载的文件系统的延迟提交
+pub fn set_delayed_commit(delayed: bool) {
+    let filesystems: Vec<_> = MOUNT_TABLE.exclusive_access().iter().map(|mp| mp.fs.clone()).collect();
+    for fs in filesystems {
+        fs.set_delayed_commit(delayed);
+    }
+}
+
+/// 设置所有已挂载的文件系统的脏数据高水位
+pub fn set_dirty_limit(bytes: usize) {
+    let filesystems: Vec<_> = MOUNT_TABLE.exclusive_access().iter().map(|mp| mp.fs.clone()).collect();
+    for fs in filesystems {
+        fs.set_dirty_limit(bytes);
+    }
+}
+
+/// syncfs: 把 path (规范化的绝对路径) 所在的文件系统写回存储设备，写回失败时返回 EIO
+pub fn syncfs(path: &str) -> Result<(), isize> {
+    let (path, inode) = walk(path, true)?;
+    inode.ok_or(ENOENT)?;
+    find_mount(&path).0.sync()
+}
+
+pub fn root_inode() -> Arc<dyn VfsInode> {
+    MOUNT_TABLE.exclusive_access()[0].fs.root_inode()
+}
+
+pub fn is_mount_point(path: &str) -> bool {
+    MOUNT_TABLE.exclusive_access().iter().any(|mp| mp.path == path)
+}
+
+/// 根据文件系统类型和来源创建一个新的文件系统实例
+pub fn new_filesystem(fs_type: &str, source: &str) -> Result<Arc<dyn FileSystem>, isize> {
+    match fs_type {
+        "easyfs" => {
+            let block_device = block_device_by_name(source).ok_or(ENODEV)?;
+            // 同一块磁盘不能同时挂载两次，否则两份位图会互相覆盖
+            // 按解析出的块设备比较: "/dev/vda" 与 "vda" 是同一块磁盘
+            if MOUNT_TABLE.exclusive_access().iter().any(|mp| {
+                mp.fs.fs_type() == fs_type
+                    && block_device_by_name(&mp.source).is_some_and(|device| Arc::ptr_eq(&device, &block_device))
+            }) {
+                return Err(EBUSY);
+            }
+            Ok(EasyFs::open(block_device).ok_or(EINVAL)?)
+        }
+        "tmpfs" => Ok(TmpFs::new()),
+        "procfs" | "proc" => Ok(ProcFs::new()),
+        "devfs" => Ok(DevFs::new()),
+        _ => Err(ENODEV),
+    }
+}
+
+/// 将 fs 挂载到 path (规范化的绝对路径)，挂载点必须是已存在的目录
+pub fn mount(path: &str, source: &str, fs: Arc<dyn FileSystem>) -> Result<(), isize> {
+    if lookup_path(path)?.inode_type() != InodeType::Dir {
+        return Err(ENOTDIR);
+    }
+    let mut table = MOUNT_TABLE.exclusive_access();
+    if table.iter().any(|mp| mp.path == path) {
+        return Err(EBUSY);
+    }
+    table.push(MountPoint { path: String::from(path), source: String::from(source), fs });
+    Ok(())
+}
+
+/// 卸载挂载在 path 上的文件系统，以下情况返回 EBUSY:
+/// 根文件系统、其下还有挂载点、其中还有打开的文件 (OSInode 持有所在的文件系统) 或进程的工作目录
+/// 先写回再从挂载表中移除，写回失败时 (EIO) 保持挂载
+pub fn umount(path: &str) -> Result<(), isize> {
+    if path == "/" {
+        return Err(EBUSY);
+    }
+    // 不能在持有挂载表时访问进程
+    let cwd_busy = all_tasks().iter().any(|task| {
+        let inner = task.inner_exclusive_access();
+        !inner.is_zombie() && (inner.cwd == path || is_under(&inner.cwd, path))
+    });
+    let fs = {
+        let table = MOUNT_TABLE.exclusive_access();
+        let mount_point = table.iter().find(|mp| mp.path == path).ok_or(EINVAL)?;
+        // 挂载表本身持有一个引用
+        if cwd_busy || Arc::strong_count(&mount_point.fs) > 1 || table.iter().any(|mp| is_under(&mp.path, path)) {
+            return Err(EBUSY);
+        }
+        mount_point.fs.clone()
+    };
+    // 写回时不持有挂载表，块设备 I/O 期间可能切换到其他任务
+    fs.sync()?;
+    let mut table = MOUNT_TABLE.exclusive_access();
+    // 写回期间其他任务可能打开了其中的文件，或者已经卸载了它
+    let index = table.iter().position(|mp| Arc::ptr_eq(&mp.fs, &fs)).ok_or(EINVAL)?;
+    if Arc::strong_count(&fs) > 2 {
+        return Err(EBUSY);
+    }
+    table.remove(index);
+    Ok(())
+}
+
+/// 将 old_path 重命名为 new_path (规范化的绝对路径)，两者必须位于同一文件系统
+/// 挂载点和其下有挂载点的目录不能被移动或替换
+pub fn rename(old_path: &str, new_path: &str, mode: RenameMode) -> Result<(), isize> {
+    let busy = MOUNT_TABLE.exclusive_access().iter().any(|mp| {
+        [old_path, new_path].iter().any(|path| mp.path == *path || is_under(&mp.path, path))
+    });
+    if busy {
+        return Err(EBUSY);
+    }
+    // 目录不能移动到它自身之下；交换时反过来也不行
+    if is_under(new_path, old_path) || (mode == RenameMode::Exchange && is_under(old_path, new_path)) {
+        return Err(EINVAL);
+    }
+    let (old_fs, _) = find_mount(old_path);
+    let (new_fs, _) = find_mount(new_path);
+    if !Arc::ptr_eq(&old_fs, &new_fs) {
+        return Err(EXDEV);
+    }
+    let (old_parent, old_name) = lookup_parent(old_path)?;
+    let (new_parent, new_name) = lookup_parent(new_path)?;
+    if old_name.len() > NAME_MAX || new_name.len() > NAME_MAX {
+        return Err(ENAMETOOLONG);
+    }
+    check_dir_change(&old_parent, Some(&old_name))?;
+    check_dir_change(&new_parent, Some(&new_name))?;
+    old_parent.rename(&old_name, &new_parent, &new_name, mode)
+}
+
+/// 启动时挂载的文件系统，挂载点不存在时在根文件系统上创建
+pub fn init() {
+    for (path, fs_type) in [("/tmp", "tmpfs"), ("/proc", "procfs"), ("/dev", "devfs")] {
+        if let Err(ENOENT) = lookup_path(path) {
+            let (parent, name) = lookup_parent(path).unwrap();
+            parent.create(&name, InodeType::Dir, Permissions::root(0o755)).expect("[kernel] cannot create mount point");
+        }
+        let fs = new_filesystem(fs_type, fs_type).unwrap();
+        mount(path, fs_type, fs).unwrap();
+        println!("[kernel] Mounted {} on {}", fs_type, path);
+    }
+}
@@ -0,0 +1,40 @@
+// os/src/fs/path.rs
+// 路径处理：内核内部统一使用规范化的绝对路径 (以 '/' 开头，不含 "."、".." 和多余的 '/')
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// 路径中单个分量的最大长度
+pub const NAME_MAX: usize = 255;
+/// 路径 (含末尾的 '\0') 的最大长度，也是符号链接目标的长度上限
+pub const PATH_MAX: usize = 4096;
+
+/// 将 path 规范化为绝对路径，相对路径基于 cwd (cwd 本身必须是规范化的绝对路径)
+pub fn normalize(cwd: &str, path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    let full = if path.starts_with('/') { [path, ""] } else { [cwd, path] };
+    for part in full.iter().flat_map(|s| s.split('/')) {
+        match part {
+            "" | "." => {}
+            ".." => { components.pop(); } // 根目录的 ".." 仍是根目录
+            name => components.push(name),
+        }
+    }
+    let mut result = String::new();
+    for name in components {
+        result.push('/');
+        result.push_str(name);
+    }
+    if result.is_empty() {
+        result.push('/');
+    }
+    result
+}
+
+/// 将规范化的绝对路径拆分为 (父目录, 最后一个分量)
+/// "/a/b" -> ("/a", "b")，"/a" -> ("/", "a")，"/" -> ("/", "")
+pub fn split_parent(path: &str) -> (&str, &str) {
+    let pos = path.rfind('/').unwrap_or(0);
+    let parent = if pos == 0 { "/" } else { &path[..pos] };
+    (parent, &path[pos + 1..])
+}
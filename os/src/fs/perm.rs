@@ -0,0 +1,67 @@
+// os/src/fs/perm.rs
+// 文件权限检查
+// 与 Unix 相同: 按 euid/egid 是否是 inode 的属主/属组选择一组 rwx 位，root 不受读写限制
+
+use alloc::sync::Arc;
+use crate::syscall::errno::{EACCES, EPERM};
+use crate::task::cred::{current_credentials, Credentials};
+use crate::task::processor::current_task;
+use super::vfs::{InodeType, Permissions, VfsInode, S_ISVTX};
+
+pub const MAY_EXEC: u16 = 0o1; // 对目录而言是搜索，即经过该目录查找目录项
+pub const MAY_WRITE: u16 = 0o2;
+pub const MAY_READ: u16 = 0o4;
+
+/// 初始进程的 umask，之后由子进程继承
+pub const DEFAULT_UMASK: u16 = 0o022;
+
+/// cred 能否以 access (MAY_* 的组合) 方式访问 inode
+/// root 可以读写任何文件、搜索任何目录，但执行文件要求至少有一个执行位
+pub fn permitted(cred: &Credentials, inode: &Arc<dyn VfsInode>, access: u16) -> bool {
+    let perm = inode.permissions();
+    if cred.is_root() {
+        return access & MAY_EXEC == 0 || inode.inode_type() == InodeType::Dir || perm.mode & 0o111 != 0;
+    }
+    let bits = if cred.euid == perm.uid {
+        perm.mode >> 6
+    } else if cred.egid == perm.gid {
+        perm.mode >> 3
+    } else {
+        perm.mode
+    };
+    bits & access == access
+}
+
+/// 当前进程能否以 access 方式访问 inode，不能时返回 EACCES
+pub fn check_access(inode: &Arc<dyn VfsInode>, access: u16) -> Result<(), isize> {
+    if permitted(&current_credentials(), inode, access) { Ok(()) } else { Err(EACCES) }
+}
+
+/// 在目录 dir 中新建、删除或改名目录项之前的检查，需要 dir 的写和搜索权限
+/// 删除或改名已有的目录项 name 时，若 dir 设置了 sticky 位，还要求是 dir 或该目录项的属主 (或 root)
+pub fn check_dir_change(dir: &Arc<dyn VfsInode>, name: Option<&str>) -> Result<(), isize> {
+    let cred = current_credentials();
+    if !permitted(&cred, dir, MAY_WRITE | MAY_EXEC) {
+        return Err(EACCES);
+    }
+    let dir_perm = dir.permissions();
+    if dir_perm.mode & S_ISVTX == 0 || cred.is_root() || cred.euid == dir_perm.uid {
+        return Ok(());
+    }
+    match name.and_then(|name| dir.lookup(name)) {
+        Some(child) if child.permissions().uid != cred.euid => Err(EPERM),
+        _ => Ok(()),
+    }
+}
+
+/// 当前进程新建的 inode 的权限: 去掉 mode 中 umask 的位，属于当前的 euid/egid
+pub fn new_permissions(mode: u16) -> Permissions {
+    let (cred, umask) = match current_task() {
+        Some(task) => {
+            let inner = task.inner_exclusive_access();
+            (inner.cred, inner.umask)
+        }
+        None => (Credentials::ROOT, DEFAULT_UMASK),
+    };
+    Permissions { mode: mode & 0o7777 & !umask, uid: cred.euid, gid: cred.egid }
+}
@@ -0,0 +1,220 @@
+// os/src/fs/procfs.rs
+// procfs: 将内核状态以文本文件的形式导出给用户程序 (ps、top 等)
+// 文件内容在每次读取时现场生成，不占用存储空间
+//   /proc/meminfo       物理页帧与内核堆的使用情况
+//   /proc/uptime        开机以来的秒数
+//   /proc/<pid>/status  进程名、状态、父进程、退出码
+//   /proc/<pid>/maps    进程地址空间中的各个 MapArea
+//   /proc/sys/vm/*      后台回写的参数 (见 writeback)，root 可以写入新的值
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use crate::config::{CLOCK_FREQ, PAGE_SIZE};
+use crate::mm::area::MapPermission;
+use crate::mm::frame_allocator::{frame_remaining, frame_total};
+use crate::mm::heap_allocator::heap_stats;
+use crate::syscall::errno::EINVAL;
+use crate::task::{all_tasks, find_task, TaskStatus};
+use crate::timer::get_time;
+use super::vfs::{FileSystem, FsStats, InodeType, VfsInode, PROC_SUPER_MAGIC};
+use super::writeback::{set_sysctl, sysctl, SYSCTLS};
+
+// ----- ProcFs -----
+pub struct ProcFs;
+
+impl ProcFs {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self)
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn fs_type(&self) -> &'static str {
+        "procfs"
+    }
+    fn root_inode(&self) -> Arc<dyn VfsInode> {
+        Arc::new(ProcInode::Root)
+    }
+    fn statfs(&self) -> FsStats {
+        FsStats { magic: PROC_SUPER_MAGIC, block_size: PAGE_SIZE, ..FsStats::default() }
+    }
+}
+
+// ----- ProcInode -----
+#[derive(Clone, Copy)]
+enum ProcInode {
+    Root,
+    MemInfo,
+    Uptime,
+    PidDir(usize),
+    Status(usize),
+    Maps(usize),
+    Sys,
+    SysVm,
+    Sysctl(usize), // SYSCTLS 中的下标
+}
+
+impl ProcInode {
+    /// 生成文件内容，进程已经不存在时返回 None
+    fn content(&self) -> Option<String> {
+        match *self {
+            ProcInode::MemInfo => Some(meminfo()),
+            ProcInode::Uptime => {
+                let centisecs = get_time() / (CLOCK_FREQ / 100);
+                Some(format!("{}.{:02} 0.00\n", centisecs / 100, centisecs % 100))
+            }
+            ProcInode::Status(pid) => status(pid),
+            ProcInode::Maps(pid) => maps(pid),
+            ProcInode::Sysctl(index) => sysctl(SYSCTLS[index]).map(|value| format!("{}\n", value)),
+            _ => None,
+        }
+    }
+}
+
+impl VfsInode for ProcInode {
+    fn inode_type(&self) -> InodeType {
+        match self {
+            ProcInode::Root | ProcInode::PidDir(_) | ProcInode::Sys | ProcInode::SysVm => InodeType::Dir,
+            _ => InodeType::File,
+        }
+    }
+    fn ino(&self) -> usize {
+        match *self {
+            ProcInode::Root => 1,
+            ProcInode::MemInfo => 2,
+            ProcInode::Uptime => 3,
+            ProcInode::PidDir(pid) => (pid + 1) << 2,
+            ProcInode::Status(pid) => ((pid + 1) << 2) | 1,
+            ProcInode::Maps(pid) => ((pid + 1) << 2) | 2,
+            // 低两位为 3 的编号没有被进程使用
+            ProcInode::Sys => (1 << 2) | 3,
+            ProcInode::SysVm => (2 << 2) | 3,
+            ProcInode::Sysctl(index) => ((index + 3) << 2) | 3,
+        }
+    }
+    // 内容是动态生成的，与 Linux 一样报告大小为 0
+    fn size(&self) -> usize {
+        0
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        let content = match self.content() {
+            Some(content) => content,
+            None => return Ok(0),
+        };
+        let bytes = content.as_bytes();
+        if offset >= bytes.len() {
+            return Ok(0);
+        }
+        let len = buf.len().min(bytes.len() - offset);
+        buf[..len].copy_from_slice(&bytes[offset..offset + len]);
+        Ok(len)
+    }
+    // 只有 sysctl 可以写入: 一个十进制数，末尾可以有换行；无法解析时返回 EINVAL
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> Result<usize, isize> {
+        let ProcInode::Sysctl(index) = *self else {
+            return Ok(0);
+        };
+        let value = core::str::from_utf8(buf).ok().and_then(|s| s.trim().parse().ok());
+        match value {
+            Some(value) if set_sysctl(SYSCTLS[index], value) => Ok(buf.len()),
+            _ => Err(EINVAL),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn VfsInode>> {
+        let inode = match (*self, name) {
+            (ProcInode::Root, "meminfo") => ProcInode::MemInfo,
+            (ProcInode::Root, "uptime") => ProcInode::Uptime,
+            (ProcInode::Root, "sys") => ProcInode::Sys,
+            (ProcInode::Sys, "vm") => ProcInode::SysVm,
+            (ProcInode::SysVm, name) => ProcInode::Sysctl(SYSCTLS.iter().position(|sysctl| *sysctl == name)?),
+            (ProcInode::Root, "self") => ProcInode::PidDir(crate::task::processor::current_task()?.get_pid()),
+            (ProcInode::Root, pid) => {
+                let pid = pid.parse().ok()?;
+                find_task(pid)?;
+                ProcInode::PidDir(pid)
+            }
+            (ProcInode::PidDir(pid), "status") => ProcInode::Status(pid),
+            (ProcInode::PidDir(pid), "maps") => ProcInode::Maps(pid),
+            _ => return None,
+        };
+        Some(Arc::new(inode))
+    }
+    fn readdir(&self) -> Result<Vec<String>, isize> {
+        match self {
+            ProcInode::Root => {
+                let mut names = vec![String::from("meminfo"), String::from("uptime"), String::from("self"), String::from("sys")];
+                names.extend(all_tasks().iter().map(|task| task.get_pid().to_string()));
+                Ok(names)
+            }
+            ProcInode::PidDir(_) => Ok(vec![String::from("status"), String::from("maps")]),
+            ProcInode::Sys => Ok(vec![String::from("vm")]),
+            ProcInode::SysVm => Ok(SYSCTLS.iter().map(|name| String::from(*name)).collect()),
+            _ => Err(crate::syscall::errno::ENOTDIR),
+        }
+    }
+}
+
+// ----- file contents -----
+
+fn meminfo() -> String {
+    let frames_total = frame_total();
+    let frames_free = frame_remaining();
+    let (heap_total, heap_user, heap_allocated) = heap_stats();
+    let kb = |pages: usize| pages * PAGE_SIZE / 1024;
+    let mut s = String::new();
+    writeln!(s, "MemTotal:       {:>8} kB", kb(frames_total)).unwrap();
+    writeln!(s, "MemFree:        {:>8} kB", kb(frames_free)).unwrap();
+    writeln!(s, "MemUsed:        {:>8} kB", kb(frames_total - frames_free)).unwrap();
+    writeln!(s, "FramesTotal:    {:>8}", frames_total).unwrap();
+    writeln!(s, "FramesFree:     {:>8}", frames_free).unwrap();
+    writeln!(s, "KernelHeapTotal:{:>8} kB", heap_total / 1024).unwrap();
+    writeln!(s, "KernelHeapUsed: {:>8} kB", heap_user / 1024).unwrap();         // 用户请求的字节数
+    writeln!(s, "KernelHeapAlloc:{:>8} kB", heap_allocated / 1024).unwrap();    // 含内部碎片
+    s
+}
+
+fn status(pid: usize) -> Option<String> {
+    let task = find_task(pid)?;
+    let inner = task.inner_exclusive_access();
+    let state = match inner.task_status {
+        TaskStatus::Ready => "R (ready)",
+        TaskStatus::Running => "R (running)",
+        TaskStatus::Zombie => "Z (zombie)",
+    };
+    // INITPROC 没有父进程，与 Linux 的 init 一样报告 PPid 为 0
+    let ppid = inner.parent.as_ref().and_then(|parent| parent.upgrade()).map_or(0, |parent| parent.get_pid());
+    let vm_pages: usize = inner.memory_set.areas.iter()
+        .map(|area| area.vpn_range.end.0 - area.vpn_range.start.0)
+        .sum();
+    let mut s = String::new();
+    writeln!(s, "Name:\t{}", inner.name).unwrap();
+    writeln!(s, "State:\t{}", state).unwrap();
+    writeln!(s, "Pid:\t{}", pid).unwrap();
+    writeln!(s, "PPid:\t{}", ppid).unwrap();
+    writeln!(s, "ExitCode:\t{}", inner.exit_code).unwrap();
+    writeln!(s, "Children:\t{}", inner.children.len()).unwrap();
+    writeln!(s, "VmSize:\t{} kB", vm_pages * PAGE_SIZE / 1024).unwrap();
+    Some(s)
+}
+
+fn maps(pid: usize) -> Option<String> {
+    let task = find_task(pid)?;
+    let inner = task.inner_exclusive_access();
+    let mut s = String::new();
+    for area in inner.memory_set.areas.iter() {
+        let perm = area.map_perm;
+        let flag = |bit: MapPermission, c: char| if perm.contains(bit) { c } else { '-' };
+        writeln!(
+            s, "{:08x}-{:08x} {}{}{}p 00000000 00:00 0",
+            area.vpn_range.start.0 * PAGE_SIZE,
+            area.vpn_range.end.0 * PAGE_SIZE,
+            flag(MapPermission::R, 'r'), flag(MapPermission::W, 'w'), flag(MapPermission::X, 'x'),
+        ).unwrap();
+    }
+    Some(s)
+}
@@ -1,10 +1,42 @@
 // os/src/fs/stdio.rs
 
-use crate::sbi::console_getchar;
+use crate::sbi::{console_getchar, console_putchar};
 use crate::task::suspend_current_and_run_next;
 use crate::mm::UserBuffer;
+use crate::mm::page_table::copy_obj_to_user;
+use crate::syscall::errno::ENOTTY;
+use crate::task::processor::current_user_satp;
 use super::File;
 
+// ----- terminal ioctl -----
+const TCGETS: usize = 0x5401;
+const TIOCGWINSZ: usize = 0x5413;
+
+// struct winsize
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct WinSize {
+    ws_row: u16,
+    ws_col: u16,
+    ws_xpixel: u16,
+    ws_ypixel: u16,
+}
+
+// 串口终端的 ioctl: 报告固定的 24x80 窗口，libc 据此判断 isatty 并选择行缓冲
+fn tty_ioctl(cmd: usize, arg: usize) -> isize {
+    match cmd {
+        TIOCGWINSZ => {
+            let winsize = WinSize { ws_row: 24, ws_col: 80, ws_xpixel: 0, ws_ypixel: 0 };
+            match copy_obj_to_user(current_user_satp(), arg as *mut WinSize, &winsize) {
+                Ok(()) => 0,
+                Err(errno) => -errno,
+            }
+        }
+        TCGETS => 0, // termios 保持用户缓冲区原样
+        _ => -ENOTTY,
+    }
+}
+
 pub struct Stdin;
 pub struct Stdout;
 pub struct Stderr;
@@ -16,9 +48,11 @@ impl File for Stdin {
     fn writable(&self) -> bool {
         false
     }
-    /// 从标准输入读取一个字符
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1); // 确保只读取一个字节
+    /// 从标准输入读取一个字符，缓冲区更长时也只返回一个字节
+    fn read(&self, mut user_buf: UserBuffer) -> Result<usize, isize> {
+        if user_buf.len() == 0 {
+            return Ok(0);
+        }
         // 循环等待输入
         let mut ch: usize;
         loop {
@@ -36,11 +70,14 @@ impl File for Stdin {
         unsafe {
             user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
         }
-        1
+        Ok(1)
     }
 
-    fn write(&self, _user_buf: UserBuffer) -> usize {
-        panic!("Cannot write to stdin!");
+    fn write(&self, _user_buf: UserBuffer) -> Result<usize, isize> {
+        Ok(0) // not writable, sys_write has already returned EBADF
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        tty_ioctl(cmd, arg)
     }
 }
 
@@ -51,16 +88,15 @@ impl File for Stdout {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _user_buf: UserBuffer) -> usize {
-        panic!("Cannot read from stdout!");
+    fn read(&self, _user_buf: UserBuffer) -> Result<usize, isize> {
+        Ok(0) // not readable, sys_read has already returned EBADF
     }
     /// 将用户缓冲区的内容输出到标准输出
-    fn write(&self, user_buf: UserBuffer) -> usize {
-        // 遍历所有缓冲区并输出内容
-        for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
-        }
-        user_buf.len()
+    fn write(&self, user_buf: UserBuffer) -> Result<usize, isize> {
+        Ok(write_bytes(&user_buf))
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        tty_ioctl(cmd, arg)
     }
 }
 
@@ -71,15 +107,46 @@ impl File for Stderr {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _user_buf: UserBuffer) -> usize {
-        panic!("Cannot read from stderr!");
+    fn read(&self, _user_buf: UserBuffer) -> Result<usize, isize> {
+        Ok(0) // not readable, sys_read has already returned EBADF
     }
     /// 将用户缓冲区的内容输出到标准错误
-    fn write(&self, user_buf: UserBuffer) -> usize {
-        // 遍历所有缓冲区并输出内容
-        for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+    fn write(&self, user_buf: UserBuffer) -> Result<usize, isize> {
+        Ok(write_bytes(&user_buf))
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        tty_ioctl(cmd, arg)
+    }
+}
+
+// ----- Console -----
+// 可读可写的终端 (/dev/console、/dev/tty)，可以被重新打开
+pub struct Console;
+
+impl File for Console {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, user_buf: UserBuffer) -> Result<usize, isize> {
+        Stdin.read(user_buf)
+    }
+    fn write(&self, user_buf: UserBuffer) -> Result<usize, isize> {
+        Ok(write_bytes(&user_buf))
+    }
+    fn ioctl(&self, cmd: usize, arg: usize) -> isize {
+        tty_ioctl(cmd, arg)
+    }
+}
+
+// 按字节原样输出，不要求内容是合法的 UTF-8
+fn write_bytes(user_buf: &UserBuffer) -> usize {
+    for buffer in user_buf.buffers.iter() {
+        for &byte in buffer.iter() {
+            console_putchar(byte as usize);
         }
-        user_buf.len()
     }
-}
\ No newline at end of file
+    user_buf.len()
+}
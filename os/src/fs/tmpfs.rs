@@ -0,0 +1,270 @@
+// os/src/fs/tmpfs.rs
+// tmpfs: 完全位于内存中的文件系统，重启后内容消失
+// 目录保存在内核堆中，文件数据按页保存在 frame_alloc 分配的物理页帧中，不占用内核堆
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::config::PAGE_SIZE;
+use crate::mm::frame_allocator::{frame_alloc, FrameTracker};
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EEXIST, EFBIG, EINVAL, ENODATA, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY, EPERM};
+use crate::timer::{realtime, TimeSpec};
+use super::vfs::{FileSystem, FsStats, InodeTimes, InodeType, Permissions, VfsInode, XattrMode, S_ISVTX, TMPFS_MAGIC};
+
+// 所有 tmpfs 实例共用一个计数器分配 inode 编号，根目录为 1
+static NEXT_INO: AtomicUsize = AtomicUsize::new(2);
+
+// ----- TmpFs -----
+pub struct TmpFs {
+    root: Arc<TmpInode>,
+}
+
+impl TmpFs {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            // 与 Linux 的 /tmp 一样，所有用户都可以在根目录中创建文件，但只能删除自己的文件
+            root: TmpInode::new(1, InodeType::Dir, Permissions::root(S_ISVTX | 0o777)),
+        })
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn fs_type(&self) -> &'static str {
+        "tmpfs"
+    }
+    fn root_inode(&self) -> Arc<dyn VfsInode> {
+        self.root.clone()
+    }
+    fn statfs(&self) -> FsStats {
+        FsStats { magic: TMPFS_MAGIC, block_size: PAGE_SIZE, ..FsStats::default() }
+    }
+}
+
+// ----- TmpInode -----
+pub struct TmpInode {
+    ino: usize,
+    type_: InodeType,
+    inner: UPSafeCell<TmpInodeInner>,
+}
+
+struct TmpInodeInner {
+    perm: Permissions,
+    times: InodeTimes,
+    size: usize,
+    pages: Vec<FrameTracker>,                    // 文件数据，第 i 页保存 [i * PAGE_SIZE, (i + 1) * PAGE_SIZE)
+    children: BTreeMap<String, Arc<TmpInode>>,   // 目录项
+    xattrs: BTreeMap<String, Vec<u8>>,           // 扩展属性
+}
+
+impl TmpInode {
+    fn new(ino: usize, type_: InodeType, perm: Permissions) -> Arc<Self> {
+        let now = realtime();
+        let inner = TmpInodeInner {
+            perm,
+            times: InodeTimes { atime: now, mtime: now, ctime: now },
+            size: 0,
+            pages: Vec::new(),
+            children: BTreeMap::new(),
+            xattrs: BTreeMap::new(),
+        };
+        Arc::new(Self { ino, type_, inner: unsafe { UPSafeCell::new(inner) } })
+    }
+}
+
+impl TmpInodeInner {
+    /// 内容 (对目录而言是目录项) 被修改: 更新 mtime 与 ctime
+    fn touch(&mut self) {
+        let now = realtime();
+        self.times.mtime = now;
+        self.times.ctime = now;
+    }
+
+    /// 将文件大小调整为 new_size，扩大时补零，物理页不足时返回 false 且不做修改
+    fn resize(&mut self, new_size: usize) -> bool {
+        let page_count = new_size.div_ceil(PAGE_SIZE);
+        if new_size < self.size {
+            self.pages.truncate(page_count);
+            // 清零最后一页中 new_size 之后的部分，之后再扩大时这些字节应读出 0
+            if let Some(page) = self.pages.last() {
+                let tail = new_size % PAGE_SIZE;
+                if tail != 0 {
+                    page.ppn.as_raw_bytes()[tail..].fill(0);
+                }
+            }
+        } else {
+            let mut new_pages = Vec::new();
+            for _ in self.pages.len()..page_count {
+                match frame_alloc() {
+                    Some(frame) => new_pages.push(frame), // frame_alloc 得到的页已清零
+                    None => return false,                 // new_pages 被 drop，归还已分配的页
+                }
+            }
+            self.pages.extend(new_pages);
+        }
+        self.size = new_size;
+        true
+    }
+}
+
+impl VfsInode for TmpInode {
+    fn inode_type(&self) -> InodeType {
+        self.type_
+    }
+    fn ino(&self) -> usize {
+        self.ino
+    }
+    fn size(&self) -> usize {
+        self.inner.exclusive_access().size
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, isize> {
+        let mut inner = self.inner.exclusive_access();
+        // lseek 可以把偏移设置得很大，offset + buf.len() 可能溢出
+        let end = offset.checked_add(buf.len()).ok_or(EINVAL)?.min(inner.size);
+        inner.times.atime = realtime();
+        let mut pos = offset;
+        while pos < end {
+            let page_offset = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(end - pos);
+            let page = inner.pages[pos / PAGE_SIZE].ppn.as_raw_bytes();
+            buf[pos - offset..pos - offset + len].copy_from_slice(&page[page_offset..page_offset + len]);
+            pos += len;
+        }
+        Ok(end.saturating_sub(offset))
+    }
+    // 内存不足时不写入任何数据，返回 0
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, isize> {
+        let mut inner = self.inner.exclusive_access();
+        let end = offset.checked_add(buf.len()).ok_or(EFBIG)?;
+        if end > inner.size && !inner.resize(end) {
+            return Ok(0);
+        }
+        let mut pos = offset;
+        while pos < end {
+            let page_offset = pos % PAGE_SIZE;
+            let len = (PAGE_SIZE - page_offset).min(end - pos);
+            let page = inner.pages[pos / PAGE_SIZE].ppn.as_raw_bytes();
+            page[page_offset..page_offset + len].copy_from_slice(&buf[pos - offset..pos - offset + len]);
+            pos += len;
+        }
+        inner.touch();
+        Ok(buf.len())
+    }
+    fn permissions(&self) -> Permissions {
+        self.inner.exclusive_access().perm
+    }
+    fn set_permissions(&self, perm: Permissions) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        inner.perm = perm;
+        inner.times.ctime = realtime();
+        Ok(())
+    }
+    fn times(&self) -> InodeTimes {
+        self.inner.exclusive_access().times
+    }
+    fn set_times(&self, atime: Option<TimeSpec>, mtime: Option<TimeSpec>) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        if let Some(atime) = atime {
+            inner.times.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            inner.times.mtime = mtime;
+        }
+        inner.times.ctime = realtime();
+        Ok(())
+    }
+    fn truncate(&self, size: usize) -> Result<(), isize> {
+        if self.type_ != InodeType::File {
+            return Err(EINVAL);
+        }
+        let mut inner = self.inner.exclusive_access();
+        if !inner.resize(size) {
+            return Err(ENOSPC);
+        }
+        inner.touch();
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str) -> Result<Vec<u8>, isize> {
+        self.inner.exclusive_access().xattrs.get(name).cloned().ok_or(ENODATA)
+    }
+    fn list_xattr(&self) -> Result<Vec<String>, isize> {
+        Ok(self.inner.exclusive_access().xattrs.keys().cloned().collect())
+    }
+    fn set_xattr(&self, name: &str, value: &[u8], mode: XattrMode) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        match (inner.xattrs.contains_key(name), mode) {
+            (true, XattrMode::Create) => return Err(EEXIST),
+            (false, XattrMode::Replace) => return Err(ENODATA),
+            _ => {}
+        }
+        inner.xattrs.insert(String::from(name), value.to_vec());
+        inner.times.ctime = realtime();
+        Ok(())
+    }
+    fn remove_xattr(&self, name: &str) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        inner.xattrs.remove(name).ok_or(ENODATA)?;
+        inner.times.ctime = realtime();
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn VfsInode>> {
+        let inner = self.inner.exclusive_access();
+        inner.children.get(name).map(|inode| inode.clone() as Arc<dyn VfsInode>)
+    }
+    fn create(&self, name: &str, type_: InodeType, perm: Permissions) -> Result<Arc<dyn VfsInode>, isize> {
+        if self.type_ != InodeType::Dir {
+            return Err(ENOTDIR);
+        }
+        if type_ != InodeType::File && type_ != InodeType::Dir {
+            return Err(EPERM);
+        }
+        let mut inner = self.inner.exclusive_access();
+        if inner.children.contains_key(name) {
+            return Err(EEXIST);
+        }
+        let inode = TmpInode::new(NEXT_INO.fetch_add(1, Ordering::Relaxed), type_, perm);
+        inner.children.insert(String::from(name), inode.clone());
+        inner.touch();
+        Ok(inode)
+    }
+    fn symlink(&self, name: &str, target: &str, perm: Permissions) -> Result<Arc<dyn VfsInode>, isize> {
+        if self.type_ != InodeType::Dir {
+            return Err(ENOTDIR);
+        }
+        let mut inner = self.inner.exclusive_access();
+        if inner.children.contains_key(name) {
+            return Err(EEXIST);
+        }
+        let inode = TmpInode::new(NEXT_INO.fetch_add(1, Ordering::Relaxed), InodeType::Symlink, perm);
+        if inode.write_at(0, target.as_bytes())? < target.len() {
+            return Err(ENOSPC);
+        }
+        inner.children.insert(String::from(name), inode.clone());
+        inner.touch();
+        Ok(inode)
+    }
+    fn readdir(&self) -> Result<Vec<String>, isize> {
+        if self.type_ != InodeType::Dir {
+            return Err(ENOTDIR);
+        }
+        Ok(self.inner.exclusive_access().children.keys().cloned().collect())
+    }
+    // 已打开的文件在关闭前仍可读写，最后一个引用消失时释放其物理页
+    fn unlink(&self, name: &str) -> Result<(), isize> {
+        if self.type_ != InodeType::Dir {
+            return Err(ENOTDIR);
+        }
+        let mut inner = self.inner.exclusive_access();
+        let child = inner.children.get(name).ok_or(ENOENT)?;
+        if !child.inner.exclusive_access().children.is_empty() {
+            return Err(ENOTEMPTY);
+        }
+        inner.children.remove(name);
+        inner.touch();
+        Ok(())
+    }
+}
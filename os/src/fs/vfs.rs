@@ -0,0 +1,241 @@
+// os/src/fs/vfs.rs
+// 虚拟文件系统 (VFS) 接口
+// 每种文件系统实现 FileSystem (整个文件系统) 和 VfsInode (其中的文件/目录)，
+// 内核的其余部分 (OSInode、路径解析、系统调用) 只通过这两个 trait 访问文件系统
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use easy_fs::ReadAhead;
+use crate::config::PAGE_SIZE;
+use crate::syscall::errno::{EINVAL, ENOTDIR, EOPNOTSUPP, EPERM};
+use crate::timer::TimeSpec;
+use super::File;
+
+// ----- InodeType -----
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InodeType {
+    File,
+    Dir,
+    CharDevice,
+    BlockDevice,
+    Symlink,
+}
+
+impl InodeType {
+    /// d_type in `struct linux_dirent64`
+    pub fn dirent_type(&self) -> u8 {
+        match self {
+            InodeType::CharDevice => 2,  // DT_CHR
+            InodeType::Dir => 4,         // DT_DIR
+            InodeType::BlockDevice => 6, // DT_BLK
+            InodeType::File => 8,        // DT_REG
+            InodeType::Symlink => 10,    // DT_LNK
+        }
+    }
+    /// st_mode 中的文件类型位 (S_IFMT)
+    pub fn file_mode(&self) -> u32 {
+        match self {
+            InodeType::CharDevice => 0o020000,  // S_IFCHR
+            InodeType::Dir => 0o040000,         // S_IFDIR
+            InodeType::BlockDevice => 0o060000, // S_IFBLK
+            InodeType::File => 0o100000,        // S_IFREG
+            InodeType::Symlink => 0o120000,     // S_IFLNK
+        }
+    }
+}
+
+// ----- Permissions -----
+/// inode 的权限位与属主
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Permissions {
+    pub mode: u16, // 低 12 位: setuid/setgid/sticky 与 rwxrwxrwx
+    pub uid: u32,
+    pub gid: u32,
+}
+
+pub const S_ISUID: u16 = 0o4000;
+pub const S_ISGID: u16 = 0o2000;
+pub const S_ISVTX: u16 = 0o1000; // sticky: 目录中的目录项只能由其属主 (或目录属主、root) 删除或改名
+
+impl Permissions {
+    /// 属于 root 的 inode
+    pub fn root(mode: u16) -> Self {
+        Self { mode, uid: 0, gid: 0 }
+    }
+}
+
+// ----- InodeTimes -----
+/// inode 的访问时间、内容修改时间与状态改变时间 (墙上时间)
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct InodeTimes {
+    pub atime: TimeSpec,
+    pub mtime: TimeSpec,
+    pub ctime: TimeSpec,
+}
+
+// ----- RenameMode -----
+/// rename 的方式，由 renameat2 的 flags 决定
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenameMode {
+    Replace,   // 目标已存在时原子地替换它
+    NoReplace, // RENAME_NOREPLACE: 目标已存在时返回 EEXIST
+    Exchange,  // RENAME_EXCHANGE: 原子地交换两者，两者都必须存在
+}
+
+// ----- XattrMode -----
+/// 设置扩展属性的方式，由 setxattr 的 flags 决定
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum XattrMode {
+    Set,     // 不存在时新建，已存在时替换
+    Create,  // XATTR_CREATE: 已存在时返回 EEXIST
+    Replace, // XATTR_REPLACE: 不存在时返回 ENODATA
+}
+
+// ----- FsStats -----
+/// 文件系统的容量与使用情况 (statfs)，块数以 block_size 为单位
+/// 不限制容量的文件系统 (tmpfs、procfs、devfs) 的块数与 inode 数全部为 0
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FsStats {
+    pub magic: u64, // f_type，与 Linux 中同类文件系统的魔数相同
+    pub block_size: usize,
+    pub blocks: u64,
+    pub free_blocks: u64,
+    pub files: u64,
+    pub free_files: u64,
+}
+
+pub const TMPFS_MAGIC: u64 = 0x01021994;
+pub const PROC_SUPER_MAGIC: u64 = 0x9fa0;
+
+// ----- FileSystem -----
+/// 一个可以被挂载的文件系统实例
+pub trait FileSystem: Send + Sync {
+    /// 文件系统类型名，即 mount 的 fstype 参数
+    fn fs_type(&self) -> &'static str;
+    fn root_inode(&self) -> Arc<dyn VfsInode>;
+    /// 将缓存的数据写回存储设备，卸载、sync/syncfs 与关机时调用
+    /// 写回失败时返回 errno (EIO)
+    fn sync(&self) -> Result<(), isize> {
+        Ok(())
+    }
+    /// 后台回写: 写回在 expire (墙上时间) 或更早时就已经被修改的缓存数据
+    fn writeback(&self, _expire: TimeSpec) {}
+    /// 延迟提交: 修改留在缓存中，由 sync 或后台回写写回；关闭时立即写回已缓存的修改
+    fn set_delayed_commit(&self, _delayed: bool) {}
+    /// 缓存中脏数据的高水位 (字节)，达到时由写者同步写回
+    fn set_dirty_limit(&self, _bytes: usize) {}
+    /// 容量与使用情况
+    fn statfs(&self) -> FsStats {
+        FsStats { block_size: PAGE_SIZE, ..FsStats::default() }
+    }
+}
+
+// ----- VfsInode -----
+/// 文件系统中的一个 inode
+/// 目录的方法 (lookup/create/readdir) 即 dentry 操作，普通文件使用默认实现
+/// 实现 rename 的文件系统可以把另一个目录转换 (`&dyn Any`) 为自己的 inode 类型
+pub trait VfsInode: Any + Send + Sync {
+    fn inode_type(&self) -> InodeType;
+    /// inode 编号，同一文件系统内唯一
+    fn ino(&self) -> usize;
+    fn size(&self) -> usize;
+    /// 读写出错时返回 errno，例如读到损坏的块时返回 EIO
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, isize>;
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, isize>;
+    /// 通过打开的文件读取，ra 记录该文件的访问模式，顺序读取时可以预读之后的数据
+    /// 不需要预读的文件系统 (数据在内存中) 使用默认实现
+    fn read_at_ahead(&self, offset: usize, buf: &mut [u8], _ra: &mut ReadAhead) -> Result<usize, isize> {
+        self.read_at(offset, buf)
+    }
+    fn truncate(&self, _size: usize) -> Result<(), isize> {
+        Err(EINVAL)
+    }
+    /// fsync/fdatasync: 把该 inode 的修改写入存储设备，data_only 时可以不写只修改了属性的部分
+    /// 不需要写回的文件系统 (内存中的 tmpfs、procfs 等) 什么都不做；写回失败时返回 errno (EIO)
+    fn fsync(&self, _data_only: bool) -> Result<(), isize> {
+        Ok(())
+    }
+    /// 权限位与属主，默认为属于 root 的常见权限
+    fn permissions(&self) -> Permissions {
+        Permissions::root(match self.inode_type() {
+            InodeType::Dir => 0o755,
+            InodeType::File => 0o644,
+            InodeType::Symlink => 0o777,
+            InodeType::CharDevice => 0o666,
+            InodeType::BlockDevice => 0o600,
+        })
+    }
+    /// chmod/chown，不保存权限的文件系统返回 EPERM
+    fn set_permissions(&self, _perm: Permissions) -> Result<(), isize> {
+        Err(EPERM)
+    }
+    /// 时间戳，不记录时间的文件系统 (devfs、procfs) 全部为 0
+    fn times(&self) -> InodeTimes {
+        InodeTimes::default()
+    }
+    /// utimensat: 修改 atime/mtime，None 表示不变，ctime 更新为当前时间
+    fn set_times(&self, _atime: Option<TimeSpec>, _mtime: Option<TimeSpec>) -> Result<(), isize> {
+        Err(EPERM)
+    }
+    /// 字符设备打开后的读写直接交给返回的 File，普通文件返回 None
+    fn device(&self) -> Option<Arc<dyn File>> {
+        None
+    }
+    /// 符号链接的目标，默认读出符号链接的全部内容；不是符号链接时返回 EINVAL
+    fn readlink(&self) -> Result<String, isize> {
+        if self.inode_type() != InodeType::Symlink {
+            return Err(EINVAL);
+        }
+        let mut buf = vec![0u8; self.size()];
+        let len = self.read_at(0, &mut buf)?;
+        buf.truncate(len);
+        String::from_utf8(buf).map_err(|_| EINVAL)
+    }
+
+    // ----- extended attributes -----
+    /// 扩展属性 name 的值，不存在时返回 ENODATA；不支持扩展属性的文件系统返回 EOPNOTSUPP
+    fn get_xattr(&self, _name: &str) -> Result<Vec<u8>, isize> {
+        Err(EOPNOTSUPP)
+    }
+    /// 所有扩展属性的名字
+    fn list_xattr(&self) -> Result<Vec<String>, isize> {
+        Err(EOPNOTSUPP)
+    }
+    fn set_xattr(&self, _name: &str, _value: &[u8], _mode: XattrMode) -> Result<(), isize> {
+        Err(EOPNOTSUPP)
+    }
+    fn remove_xattr(&self, _name: &str) -> Result<(), isize> {
+        Err(EOPNOTSUPP)
+    }
+
+    // ----- dentry operations -----
+    /// 在目录中查找名为 name 的目录项
+    fn lookup(&self, _name: &str) -> Option<Arc<dyn VfsInode>> {
+        None
+    }
+    /// 在目录中新建一个属主与权限为 perm 的文件或子目录，同名目录项已存在时返回 EEXIST
+    fn create(&self, _name: &str, _type_: InodeType, _perm: Permissions) -> Result<Arc<dyn VfsInode>, isize> {
+        Err(ENOTDIR)
+    }
+    /// 在目录中新建指向 target 的符号链接，不支持符号链接的文件系统返回 EPERM
+    fn symlink(&self, _name: &str, _target: &str, _perm: Permissions) -> Result<Arc<dyn VfsInode>, isize> {
+        Err(EPERM)
+    }
+    /// 目录中所有目录项的名字 (不含 "." 和 "..")
+    fn readdir(&self) -> Result<Vec<String>, isize> {
+        Err(ENOTDIR)
+    }
+    /// 删除目录项 name，name 是目录时要求其为空目录
+    fn unlink(&self, _name: &str) -> Result<(), isize> {
+        Err(ENOTDIR)
+    }
+    /// 把目录项 old_name 移动到目录 new_dir 中并改名为 new_name
+    /// new_dir 与 self 位于同一文件系统 (由调用者保证)，不支持 rename 的文件系统返回 EPERM
+    fn rename(&self, _old_name: &str, _new_dir: &Arc<dyn VfsInode>, _new_name: &str, _mode: RenameMode)
+        -> Result<(), isize> {
+        Err(EPERM)
+    }
+}
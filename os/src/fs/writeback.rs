@@ -0,0 +1,107 @@
+// os/src/fs/writeback.rs
+// 后台回写
+// 默认 easy-fs 的每个操作在返回前提交，崩溃时不丢失已完成的操作，没有需要回写的数据
+// delayed_commit 设为 1 时使用延迟提交 (group commit): 操作的修改先留在块缓存中 (日志中运行的事务)，
+// 由 fsync/sync 或这里提交，减少写日志的次数，代价是崩溃时会丢失最近的修改
+// 每 dirty_writeback_centisecs 在时钟中断中检查一次，事务变脏已超过 dirty_expire_centisecs 就提交
+// 崩溃时最多丢失大约 expire + writeback 这么长时间内的修改，程序不需要每次都调用 fsync
+// 脏数据达到 dirty_bytes 时由写者自己同步提交 (见 `EasyFileSystem::end_op`)，写得越快被拖慢得越多
+// 参数通过 /proc/sys/vm/ 下的同名文件读写，与 Linux 的 sysctl 相同 (delayed_commit 是这里特有的)
+
+use lazy_static::lazy_static;
+use crate::config::CLOCK_FREQ;
+use crate::sync::UPSafeCell;
+use crate::timer::{get_time, realtime, TimeSpec};
+use super::mount;
+
+const NANO_PER_SEC: usize = 1_000_000_000;
+const NANO_PER_CENTISEC: usize = 10_000_000;
+
+// ----- sysctl -----
+/// 可以通过 /proc/sys/vm/ 读写的参数
+pub const SYSCTLS: [&str; 4] = ["delayed_commit", "dirty_expire_centisecs", "dirty_writeback_centisecs", "dirty_bytes"];
+
+struct Writeback {
+    delayed_commit: bool,       // 延迟提交，默认关闭
+    expire_centisecs: usize,    // 修改在缓存中最多停留的时间
+    writeback_centisecs: usize, // 两次检查的间隔，为 0 时关闭后台回写
+    dirty_bytes: usize,         // 脏数据的高水位
+    next_check: usize,          // 下一次检查的时间 (时钟周期)
+}
+
+lazy_static! {
+    // 比 Linux 的默认值 (30 秒、5 秒) 短: QEMU 常常被直接关掉，不经过正常关机
+    static ref WRITEBACK: UPSafeCell<Writeback> = unsafe {
+        UPSafeCell::new(Writeback {
+            delayed_commit: false,
+            expire_centisecs: 300,
+            writeback_centisecs: 100,
+            dirty_bytes: 16 * 1024,
+            next_check: 0,
+        })
+    };
+}
+
+/// 读取参数 name，不存在时返回 None
+pub fn sysctl(name: &str) -> Option<usize> {
+    let writeback = WRITEBACK.exclusive_access();
+    match name {
+        "delayed_commit" => Some(writeback.delayed_commit as usize),
+        "dirty_expire_centisecs" => Some(writeback.expire_centisecs),
+        "dirty_writeback_centisecs" => Some(writeback.writeback_centisecs),
+        "dirty_bytes" => Some(writeback.dirty_bytes),
+        _ => None,
+    }
+}
+
+/// 设置参数 name，不存在时返回 false；delayed_commit 与 dirty_bytes 立即应用到所有已挂载的文件系统
+pub fn set_sysctl(name: &str, value: usize) -> bool {
+    let mut writeback = WRITEBACK.exclusive_access();
+    match name {
+        "delayed_commit" => {
+            writeback.delayed_commit = value != 0;
+            drop(writeback);
+            mount::set_delayed_commit(value != 0);
+        }
+        "dirty_expire_centisecs" => writeback.expire_centisecs = value,
+        "dirty_writeback_centisecs" => {
+            writeback.writeback_centisecs = value;
+            writeback.next_check = get_time().saturating_add(value.saturating_mul(CLOCK_FREQ / 100));
+        }
+        "dirty_bytes" => {
+            writeback.dirty_bytes = value;
+            drop(writeback);
+            mount::set_dirty_limit(value);
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// 是否使用延迟提交，挂载新的文件系统时使用
+pub fn delayed_commit() -> bool {
+    WRITEBACK.exclusive_access().delayed_commit
+}
+
+/// 脏数据的高水位 (字节)，挂载新的文件系统时使用
+pub fn dirty_limit() -> usize {
+    WRITEBACK.exclusive_access().dirty_bytes
+}
+
+// ----- writeback -----
+/// 时钟中断时调用 (此时内核没有持有任何锁)，到了检查的时间就回写所有文件系统中过期的修改
+pub fn tick() {
+    let mut writeback = WRITEBACK.exclusive_access();
+    let now = get_time();
+    if writeback.writeback_centisecs == 0 || now < writeback.next_check {
+        return;
+    }
+    // 写入的值可以任意大，饱和而不是溢出
+    writeback.next_check = now.saturating_add(writeback.writeback_centisecs.saturating_mul(CLOCK_FREQ / 100));
+    let max_age = writeback.expire_centisecs.saturating_mul(NANO_PER_CENTISEC);
+    drop(writeback);
+    // 在这个时间 (墙上时间) 或更早时就已经变脏的事务过期了
+    let time = realtime();
+    let expire = (time.tv_sec * NANO_PER_SEC + time.tv_nsec).saturating_sub(max_age);
+    mount::writeback(TimeSpec { tv_sec: expire / NANO_PER_SEC, tv_nsec: expire % NANO_PER_SEC });
+}
@@ -28,7 +28,6 @@ mod drivers;
 
 use core::arch::{asm, global_asm};
 use riscv::register::{mepc, mideleg, mstatus, pmpaddr0, pmpcfg0, satp, sie, sstatus};
-use crate::fs::ROOT_INODE;
 
 global_asm!(include_str!("boot.s"));
 // global_asm!(include_str!("link_app.s"));
@@ -91,7 +90,7 @@ unsafe fn rust_boot() {
 
 pub fn list_apps() {
     println!("===== List of Apps =====");
-    for app in ROOT_INODE.ls() {
+    for app in fs::root_inode().readdir().unwrap() {
         println!("{}", app);
     }
     println!("========================");
@@ -104,6 +103,9 @@ pub fn rust_main() -> ! {
         sie::set_sext();
         sie::set_stimer();
         sie::set_ssoft(); // 使能S模式下的软件中断 this is necessary!
+        // sstatus.FS = Initial，允许用户程序 (如 musl libc) 使用浮点寄存器
+        // 之后 app_init_context 读到的 sstatus 都带有该位，trap 时保存/恢复 f0~f31
+        asm!("csrs sstatus, {fs}", fs = in(reg) 1usize << 13);
     }
     // init bss & uart
     clear_bss();
@@ -114,6 +116,7 @@ pub fn rust_main() -> ! {
     println_green!("[kernel] Hello, Rust kernel!");
     mm::init();
     trap::init();
+    fs::init();
     list_apps();
     // mm::remap_test();
 
@@ -104,28 +104,49 @@ impl MapArea {
     }
     
     // ----- other methods -----
-    // data: start-aligned but maybe with shorter length
+    // data: placed at `offset` bytes from the start of the area, maybe with shorter length
     // assume that all frames were cleared before
     // 将给定的 data 按页面拷贝到内存区间对应的物理地址
-    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
+    // ELF 段的起始地址不一定页对齐，此时 offset 为段起始地址的页内偏移
+    pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8], offset: usize) {
         assert_eq!(self.map_type, MapType::Framed);
+        assert!(offset < PAGE_SIZE);
         let mut start: usize = 0;
+        let mut page_offset = offset;
         let data_len = data.len();
+        if data_len == 0 {
+            return;
+        }
         for cur_vpn in self.vpn_range.iter() {
-            let src = &data[start..data_len.min(start + PAGE_SIZE)];
+            let src = &data[start..data_len.min(start + PAGE_SIZE - page_offset)];
             let dst = &mut page_table
                 .translate_vpn(cur_vpn)
                 .unwrap() // PageTableEntry
                 .get_ppn() // PhyPageNum
-                .as_raw_bytes()[..src.len()];
+                .as_raw_bytes()[page_offset..page_offset + src.len()];
             dst.copy_from_slice(src); // 把这部分数据拷贝到目标物理页
-            start += PAGE_SIZE;
+            start += src.len();
+            page_offset = 0;
             if start >= data_len {
                 break;
             }
         }
     }
 
+    // 从 at 处把区域一分为二: self 保留 [start, at)，返回 [at, end)
+    // 已分配的物理页随之转移
+    pub fn split_off(&mut self, at: VirPageNum) -> Self {
+        assert!(at >= self.vpn_range.start && at <= self.vpn_range.end);
+        let tail = Self {
+            vpn_range: Range::new(at, self.vpn_range.end),
+            frames: self.frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+        };
+        self.vpn_range.end = at;
+        tail
+    }
+
     // heap area: change brk
     pub fn shrink_to(&mut self, page_table: &mut PageTable, new_end: VirPageNum) {
         assert_eq!(self.map_type, MapType::Framed);
@@ -139,8 +160,7 @@ impl MapArea {
     }
     pub fn grow_to(&mut self, page_table: &mut PageTable, new_end: VirPageNum) {
         assert_eq!(self.map_type, MapType::Framed);
-        assert!(new_end >= self.vpn_range.start);
-        assert!(new_end <= self.vpn_range.end);
+        assert!(new_end >= self.vpn_range.end);
         let old_end = self.vpn_range.end;
         self.vpn_range.end = new_end;
         for i in old_end.0..new_end.0 {
@@ -80,6 +80,11 @@ impl FrameAllocator for StackFrameAllocator {
 }
 
 impl StackFrameAllocator {
+    // 剩余可分配的物理页数
+    fn remaining(&self) -> usize {
+        self.end.0 - self.current.0 + self.recycled.len()
+    }
+
     fn new_with_range(start: usize, end: usize) -> StackFrameAllocator {
         StackFrameAllocator {
             current: PhyAddr::from(start).ceil(),
@@ -124,4 +129,16 @@ pub fn frame_alloc() -> Option<FrameTracker> {
 
 pub(crate) fn frame_dealloc(ppn: PhyPageNum) {
     FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
-}
\ No newline at end of file
+}
+
+pub fn frame_remaining() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().remaining()
+}
+
+// 物理页帧总数
+pub fn frame_total() -> usize {
+    unsafe extern "C" {
+        fn ekernel();
+    }
+    PhyAddr::from(MEMORY_END).floor().0 - PhyAddr::from(ekernel as usize).ceil().0
+}
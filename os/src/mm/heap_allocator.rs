@@ -29,6 +29,11 @@ pub fn init_heap() {
     }
 }
 
+// 内核堆的使用情况 (总量, 用户请求量, 实际分配量)，单位为字节
+pub fn heap_stats() -> (usize, usize, usize) {
+    HEAP_ALLOCATOR.stats()
+}
+
 #[allow(unused)]
 pub fn heap_test() {
     use alloc::boxed::Box;
@@ -2,16 +2,32 @@
 // implementation of MapArea and MemorySet
 
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::arch::asm;
 use lazy_static::lazy_static;
 use riscv::register::satp;
-use crate::config::{CLINT_BASE, CLINT_SIZE, MEMORY_END, PAGE_SIZE, TEST_DEVICE_ADDR, TRAMPOLINE_START_ADDRESS, TRAP_CONTEXT_ADDRESS, UART0_BASE_ADDR, UART0_SIZE, VIRTIO0_BASE_ADDR, VIRTIO0_SIZE};
+use crate::config::{CLINT_BASE, CLINT_SIZE, MEMORY_END, PAGE_SIZE, RTC_BASE_ADDR, RTC_SIZE, TEST_DEVICE_ADDR, TRAMPOLINE_START_ADDRESS, TRAP_CONTEXT_ADDRESS, UART0_BASE_ADDR, UART0_SIZE, USER_MMAP_BASE, USER_STACK_SIZE, VA_WIDTH, VIRTIO0_BASE_ADDR, VIRTIO0_SIZE, VIRTIO_MMIO_COUNT};
 use crate::mm::address::{PhyAddr, VirAddr, VirPageNum};
 use crate::mm::area::{MapArea, MapPermission};
+use crate::mm::frame_allocator::frame_remaining;
 use crate::mm::area::MapType::{Framed, Identical};
 use crate::mm::page_table::{PTEFlags, PageTable, PageTableEntry};
 use crate::sync::UPSafeCell;
+use crate::syscall::errno::ENOEXEC;
+
+// ----- auxiliary vector -----
+// 用户栈上 auxv 的类型 (include/uapi/linux/auxvec.h)
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_FLAGS: usize = 8;
+pub const AT_ENTRY: usize = 9;
+pub const AT_RANDOM: usize = 25;
+pub const AT_EXECFN: usize = 31;
 
 // ----- MemorySet -----
 pub struct MemorySet {
@@ -22,31 +38,38 @@ pub struct MemorySet {
 
 impl MemorySet {
     // ----- change brk (Heap) -----
+    // heap 区的末尾可能已被 munmap 或 MAP_FIXED 截掉 (区域被拆分)，此时以区域当前的末尾为准，
+    // 而不是 program_brk；找不到 heap 区 (起始页也被 munmap)、新增的页已被占用或物理页不足时返回 false
     pub fn shrink_to(&mut self, start: VirAddr, new_end: VirAddr) -> bool {
         let start_vpn = start.floor();
-        let mut found = false;
-        for area in self.areas.iter_mut() {
-            // find the Heap Area
-            if area.vpn_range.start == start_vpn {
-                area.shrink_to(&mut self.page_table, new_end.ceil());
-                found = true;
-                break;
-            }
+        // find the Heap Area
+        let Some(area) = self.areas.iter_mut().find(|area| area.vpn_range.start == start_vpn) else {
+            return false;
+        };
+        let new_end = new_end.ceil().max(start_vpn);
+        if new_end < area.vpn_range.end {
+            area.shrink_to(&mut self.page_table, new_end);
         }
-        found
+        true
     }
     
     pub fn grow_to(&mut self, start: VirAddr, new_end: VirAddr) -> bool {
-        let mut found = false;
-        for area in self.areas.iter_mut() {
-            // find the Heap Area
-            if area.vpn_range.start == start.floor() {
-                area.grow_to(&mut self.page_table, new_end.ceil());
-                found = true;
-                break;
-            }
+        let start_vpn = start.floor();
+        let new_end = new_end.ceil();
+        // find the Heap Area
+        let Some(idx) = self.areas.iter().position(|area| area.vpn_range.start == start_vpn) else {
+            return false;
+        };
+        let end = self.areas[idx].vpn_range.end;
+        if new_end <= end {
+            return true;
+        }
+        // 新增的页不能与 mmap 出来的区域重叠，且要有足够的物理页
+        if !self.is_range_free(end, new_end) || new_end.0 - end.0 > frame_remaining() {
+            return false;
         }
-        found
+        self.areas[idx].grow_to(&mut self.page_table, new_end);
+        true
     }
     
     
@@ -82,7 +105,12 @@ impl MemorySet {
     // ----- methods -----
     // map a new MapArea to the MemorySet
     // 'data' as the initial data (when map_type is Framed)
-    pub fn map_area(&mut self, mut area: MapArea, data: Option<&[u8]>) {
+    pub fn map_area(&mut self, area: MapArea, data: Option<&[u8]>) {
+        self.map_area_with_offset(area, data, 0);
+    }
+
+    // same as map_area, but 'data' starts at 'offset' bytes into the first page
+    pub fn map_area_with_offset(&mut self, mut area: MapArea, data: Option<&[u8]>, offset: usize) {
         println_gray!(
             "[mem] Map area of [{:#x}, {:#x})",
             area.vpn_range.start.0,
@@ -90,11 +118,66 @@ impl MemorySet {
         );
         area.map_page_table(&mut self.page_table); // this step we'll alloc Frames
         if let Some(data) = data {
-            area.copy_data(&mut self.page_table, data);
+            area.copy_data(&mut self.page_table, data, offset);
         }
         self.areas.push(area);
     }
 
+    // 解除 [start_vpn, end_vpn) 范围内的所有映射 (munmap 语义)
+    // 与该范围部分重叠的区域会被切分，只释放重叠的部分
+    pub fn remove_range(&mut self, start_vpn: VirPageNum, end_vpn: VirPageNum) {
+        let mut kept: Vec<MapArea> = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            let range = area.vpn_range;
+            if range.end <= start_vpn || range.start >= end_vpn || range.start == range.end {
+                kept.push(area); // 不重叠
+                continue;
+            }
+            // [range.start, start_vpn) 保留
+            let mut middle = if range.start < start_vpn {
+                let middle = area.split_off(start_vpn);
+                kept.push(area);
+                middle
+            } else {
+                area
+            };
+            // [end_vpn, range.end) 保留
+            if end_vpn < middle.vpn_range.end {
+                kept.push(middle.split_off(end_vpn));
+            }
+            middle.unmap_page_table(&mut self.page_table);
+        }
+        self.areas = kept;
+    }
+
+    // [start_vpn, end_vpn) 是否没有被任何区域占用
+    pub fn is_range_free(&self, start_vpn: VirPageNum, end_vpn: VirPageNum) -> bool {
+        self.areas.iter().all(|area| {
+            area.vpn_range.end <= start_vpn || area.vpn_range.start >= end_vpn
+        })
+    }
+
+    // 从 USER_MMAP_BASE 开始寻找一段长为 page_count 页的空闲虚拟地址 (first fit)
+    pub fn find_free_range(&self, page_count: usize) -> Option<VirPageNum> {
+        let limit = VirAddr::from(TRAP_CONTEXT_ADDRESS).floor();
+        let mut start = VirAddr::from(USER_MMAP_BASE).floor();
+        loop {
+            let end = VirPageNum(start.0 + page_count);
+            if end > limit {
+                return None;
+            }
+            // 与 [start, end) 重叠的区域中结束得最晚的一个
+            let blocker = self.areas.iter()
+                .filter(|area| area.vpn_range.end > start && area.vpn_range.start < end)
+                .map(|area| area.vpn_range.end)
+                .max();
+            match blocker {
+                Some(blocker_end) => start = blocker_end,
+                None => return Some(start),
+            }
+        }
+    }
+
     // unmap a MapArea from the MemorySet
     pub fn unmap_area_with_start_vpn(&mut self, start_vpn: VirPageNum) {
         let mut target_idx: Option<usize> = None;
@@ -201,6 +284,15 @@ impl MemorySet {
             ), None
         );
 
+        // RTC (Real-Time Clock)
+        println!("[kernel] Mapping memory-mapped registers (RTC) [{:#x}, {:#x})", RTC_BASE_ADDR, RTC_BASE_ADDR + RTC_SIZE);
+        result.map_area(
+            MapArea::new_with_address(
+                RTC_BASE_ADDR.into(), (RTC_BASE_ADDR + RTC_SIZE).into(),
+                Identical, MapPermission::R | MapPermission::W
+            ), None
+        );
+
         // UART (Universal Asynchronous Receiver/Transmitter)
         println!("[kernel] Mapping memory-mapped registers (UART) [{:#x}, {:#x})", UART0_BASE_ADDR, UART0_BASE_ADDR + UART0_SIZE);
         result.map_area(
@@ -211,10 +303,11 @@ impl MemorySet {
         );
         
         // VirtIO (Virtual Input/Output)
-        println!("[kernel] Mapping VirtIO device [{:#x}, {:#x})", VIRTIO0_BASE_ADDR, VIRTIO0_BASE_ADDR + VIRTIO0_SIZE);
+        let virtio_end = VIRTIO0_BASE_ADDR + VIRTIO_MMIO_COUNT * VIRTIO0_SIZE;
+        println!("[kernel] Mapping VirtIO devices [{:#x}, {:#x})", VIRTIO0_BASE_ADDR, virtio_end);
         result.map_area(
             MapArea::new_with_address(
-                VIRTIO0_BASE_ADDR.into(), (VIRTIO0_BASE_ADDR + VIRTIO0_SIZE).into(),
+                VIRTIO0_BASE_ADDR.into(), virtio_end.into(),
                 Identical, MapPermission::R | MapPermission::W
             ), None
         );
@@ -254,24 +347,54 @@ impl MemorySet {
     | ...                         |
     (高地址)
      */
-    // also returns `user_sp` and `entry point`.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    // also returns `user_sp`, `entry point` and the auxiliary vector describing the ELF
+    // (AT_PHDR, AT_PHENT, AT_PHNUM, AT_PAGESZ, AT_ENTRY ...), AT_RANDOM is added by the stack builder.
+    // 不是合法的 ELF 文件时返回 Err(ENOEXEC)
+    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, usize, usize, Vec<(usize, usize)>), isize> {
+        // headers of elf (U)
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(|_| ENOEXEC)?;
+        let elf_header = elf.header;
+        let magic = elf_header.pt1.magic;
+        if magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return Err(ENOEXEC); // invalid elf
+        }
+        let ph_count = elf_header.pt2.ph_count(); // program header count
+
+        // 先检查所有 Load 段，避免建立了一半的地址空间
+        for i in 0..ph_count {
+            let ph = elf.program_header(i).map_err(|_| ENOEXEC)?;
+            if ph.get_type().map_err(|_| ENOEXEC)? == xmas_elf::program::Type::Load {
+                let file_end = ph.offset().checked_add(ph.file_size()).ok_or(ENOEXEC)?;
+                let mem_end = ph.virtual_addr().checked_add(ph.mem_size()).ok_or(ENOEXEC)?;
+                if file_end > elf_data.len() as u64
+                    || ph.file_size() > ph.mem_size()
+                    || mem_end > (1u64 << (VA_WIDTH - 1)) {
+                    return Err(ENOEXEC);
+                }
+            }
+        }
+
         let mut result = Self::new_bare();
 
         // trampoline
         result.map_trampoline();
 
-        // headers of elf (U)
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count(); // program header count
         let mut max_end_vpn = VirPageNum(0); // 最大结束虚拟页号，用于后续确定用户栈的位置
+        let ph_offset = elf_header.pt2.ph_offset() as usize;
+        let mut phdr_va = 0usize; // program headers 在用户地址空间中的位置 (AT_PHDR)
         for i in 0..ph_count {
             let ph = elf.program_header(i).unwrap();
+            if ph.get_type().unwrap() == xmas_elf::program::Type::Phdr {
+                phdr_va = ph.virtual_addr() as usize;
+            }
             // 只处理 Load 类型的段
             if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
+                // 没有 PT_PHDR 时，用包含 program headers 的 Load 段推算
+                let (offset, file_size) = (ph.offset() as usize, ph.file_size() as usize);
+                if phdr_va == 0 && offset <= ph_offset && ph_offset < offset + file_size {
+                    phdr_va = ph.virtual_addr() as usize + (ph_offset - offset);
+                }
+
                 // 该段的起始和结束
                 let start_va: VirAddr = (ph.virtual_addr() as usize).into();
                 let end_va: VirAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
@@ -290,13 +413,14 @@ impl MemorySet {
                 );
 
                 // update max_end_vpn
-                max_end_vpn = map_area.vpn_range.end;
+                max_end_vpn = max_end_vpn.max(map_area.vpn_range.end);
 
                 // map area
-                result.map_area(
+                result.map_area_with_offset(
                     map_area,
                     // 只映射该段数据区(文件偏移到偏移+文件大小)
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize])
+                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
+                    start_va.page_offset(),
                 );
             }
         }
@@ -305,9 +429,9 @@ impl MemorySet {
         let max_end_va: VirAddr = max_end_vpn.into(); // ELF 段映射结束
         let mut user_stack_bottom: usize = max_end_va.into();
 
-        // guard page and stack page
+        // guard page and stack pages
         user_stack_bottom += PAGE_SIZE;
-        let user_stack_top: usize = user_stack_bottom + PAGE_SIZE;
+        let user_stack_top: usize = user_stack_bottom + USER_STACK_SIZE;
         println_gray!("[mem] Mapping user stack [{:#x}, {:#x})", user_stack_bottom, user_stack_top);
         result.map_area(
             MapArea::new_with_address(
@@ -316,6 +440,14 @@ impl MemorySet {
             ), None
         );
 
+        // map an empty heap area right above the user stack, brk grows it
+        result.map_area(
+            MapArea::new_with_address(
+                user_stack_top.into(), user_stack_top.into(),
+                Framed, MapPermission::R | MapPermission::W | MapPermission::U
+            ), None
+        );
+
         // map TrapContext
         result.map_area(
             MapArea::new_with_address(
@@ -324,7 +456,18 @@ impl MemorySet {
             ), None
         );
 
-        (result, user_stack_top, elf.header.pt2.entry_point() as usize)
+        let entry_point = elf.header.pt2.entry_point() as usize;
+        let auxv = vec![
+            (AT_PHDR, phdr_va),
+            (AT_PHENT, elf_header.pt2.ph_entry_size() as usize),
+            (AT_PHNUM, ph_count as usize),
+            (AT_PAGESZ, PAGE_SIZE),
+            (AT_BASE, 0), // no dynamic linker
+            (AT_FLAGS, 0),
+            (AT_ENTRY, entry_point),
+        ];
+
+        Ok((result, user_stack_top, entry_point, auxv))
     }
 
     pub fn activate(&self) {
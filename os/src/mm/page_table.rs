@@ -3,12 +3,12 @@
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use core::ptr::null;
 use bitflags::*;
 use super::address::{PhyAddr, PhyPageNum, VirAddr, VirPageNum};
 
-use crate::config::{PAGE_SIZE_BITS, PA_WIDTH};
+use crate::config::{PAGE_SIZE, PAGE_SIZE_BITS, PATH_MAX, PA_WIDTH, VA_WIDTH};
 use crate::mm::frame_allocator::FrameTracker;
+use crate::syscall::errno::{EFAULT, EINVAL, ENAMETOOLONG};
 
 // bit0-7: PTEFlags
 // bit8/bit9: RSW (Reserved for software)
@@ -195,17 +195,38 @@ impl PageTable {
     }
 }
 
-// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(satp_token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
+// ----- access user memory -----
+// 所有 translated_* / copy_*_user 都会检查用户指针: 页表项必须有效且带有 U 标志，
+// 并具有所需的 R/W 权限，否则返回 Err(EFAULT)，而不是让内核 panic
+
+// 找到 va 所在页并检查权限，返回对应的物理页号
+fn translate_user_page(page_table: &PageTable, va: VirAddr, need: PTEFlags) -> Result<PhyPageNum, isize> {
+    let pte = page_table.translate_vpn(va.floor()).ok_or(EFAULT)?;
+    if !pte.is_valid() || !pte.get_flags().contains(need | PTEFlags::U) {
+        return Err(EFAULT);
+    }
+    Ok(pte.get_ppn())
+}
+
+// 把用户空间 [ptr, ptr + len) 切分成若干个物理页内的切片
+fn translate_user_range(satp_token: usize, ptr: usize, len: usize, need: PTEFlags)
+    -> Result<Vec<&'static mut [u8]>, isize> {
     let mut result = Vec::new();
+    if len == 0 {
+        return Ok(result);
+    }
     let page_table = PageTable::from_satp_token(satp_token);
-    let mut start = ptr as usize;
-    let end = start + len;
-    
+    let mut start = ptr;
+    let end = ptr.checked_add(len).ok_or(EFAULT)?;
+    // 用户地址必须落在 Sv39 的低半部分
+    if end > (1usize << (VA_WIDTH - 1)) {
+        return Err(EFAULT);
+    }
+
     while start < end {
         let start_va = VirAddr::from(start);
         let mut start_vpn = start_va.floor();
-        let ppn = page_table.translate_vpn(start_vpn).unwrap().get_ppn();
+        let ppn = translate_user_page(&page_table, start_va, need)?;
         start_vpn.0 += 1; // next page
         let mut end_va: VirAddr = start_vpn.into();
         end_va = end_va.min(VirAddr::from(end));
@@ -218,34 +239,94 @@ pub fn translated_byte_buffer(satp_token: usize, ptr: *const u8, len: usize) ->
         }
         start = end_va.into();
     }
-    result
+    Ok(result)
+}
+
+// translate a user pointer to a u8 Vec (readable by user) through page table
+pub fn translated_byte_buffer(satp_token: usize, ptr: *const u8, len: usize) -> Result<Vec<&'static mut [u8]>, isize> {
+    translate_user_range(satp_token, ptr as usize, len, PTEFlags::R)
+}
+
+// translate a user pointer to a u8 Vec (writable by user), the kernel will write into it
+pub fn translated_byte_buffer_mut(satp_token: usize, ptr: *mut u8, len: usize) -> Result<Vec<&'static mut [u8]>, isize> {
+    translate_user_range(satp_token, ptr as usize, len, PTEFlags::W)
+}
+
+// 从用户空间 src 拷贝 dst.len() 字节到内核缓冲区 dst
+pub fn copy_from_user(satp_token: usize, src: *const u8, dst: &mut [u8]) -> Result<(), isize> {
+    let buffers = translated_byte_buffer(satp_token, src, dst.len())?;
+    let mut copied = 0;
+    for buffer in buffers {
+        dst[copied..copied + buffer.len()].copy_from_slice(buffer);
+        copied += buffer.len();
+    }
+    Ok(())
+}
+
+// 从内核缓冲区 src 拷贝 src.len() 字节到用户空间 dst
+pub fn copy_to_user(satp_token: usize, dst: *mut u8, src: &[u8]) -> Result<(), isize> {
+    let buffers = translated_byte_buffer_mut(satp_token, dst, src.len())?;
+    let mut copied = 0;
+    for buffer in buffers {
+        let len = buffer.len();
+        buffer.copy_from_slice(&src[copied..copied + len]);
+        copied += len;
+    }
+    Ok(())
+}
+
+// 读取用户空间中的一个 T (按字节拷贝，允许跨页)
+pub fn copy_obj_from_user<T: Copy>(satp_token: usize, src: *const T) -> Result<T, isize> {
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let dst = unsafe {
+        core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, core::mem::size_of::<T>())
+    };
+    copy_from_user(satp_token, src as *const u8, dst)?;
+    Ok(unsafe { value.assume_init() })
+}
+
+// 将一个 T 写入用户空间 (按字节拷贝，允许跨页)
+pub fn copy_obj_to_user<T: Copy>(satp_token: usize, dst: *mut T, value: &T) -> Result<(), isize> {
+    let src = unsafe {
+        core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    copy_to_user(satp_token, dst as *mut u8, src)
 }
 
 // 根据 ptr 找到要执行的应用名，返回 String
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+// 字符串长度不能超过 PATH_MAX，不是合法的 UTF-8 时返回 EINVAL
+pub fn translated_str(token: usize, ptr: *const u8) -> Result<String, isize> {
     let page_table = PageTable::from_satp_token(token);
-    let mut string = String::new();
+    let mut bytes = Vec::new();
     let mut va = ptr as usize;
     loop {
-        let pa = page_table.translate_va(VirAddr::from(va)).unwrap();
-        let ch: u8 = unsafe { *(pa.0 as *const u8) };
+        let ppn = translate_user_page(&page_table, VirAddr::from(va), PTEFlags::R)?;
+        let ch: u8 = ppn.as_raw_bytes()[VirAddr::from(va).page_offset()];
         if ch == 0 {
             break; // 结束符
-        } else {
-            string.push(ch as char);
-            va += 1;
         }
+        if bytes.len() >= PATH_MAX {
+            return Err(ENAMETOOLONG);
+        }
+        bytes.push(ch);
+        va = va.checked_add(1).ok_or(EFAULT)?;
     }
-    string
+    String::from_utf8(bytes).map_err(|_| EINVAL)
 }
 
 // translate a ptr and return a mutable reference
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
+// T 不能跨越页边界，否则返回 EFAULT (跨页对象请使用 copy_obj_to_user)
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Result<&'static mut T, isize> {
     let page_table = PageTable::from_satp_token(token);
-    let va = ptr as usize;
-    let phys_addr = page_table.translate_va(VirAddr::from(va)).unwrap();
+    let va = VirAddr::from(ptr as usize);
+    if va.page_offset() + core::mem::size_of::<T>() > PAGE_SIZE
+        || (ptr as usize) % core::mem::align_of::<T>() != 0 {
+        return Err(EFAULT);
+    }
+    let ppn = translate_user_page(&page_table, va, PTEFlags::W)?;
+    let phys_addr = usize::from(PhyAddr::from(ppn)) + va.page_offset();
     unsafe {
-        (phys_addr.0 as *mut T).as_mut().unwrap()
+        Ok((phys_addr as *mut T).as_mut().unwrap())
     }
 }
 
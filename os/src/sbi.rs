@@ -20,6 +20,11 @@ pub fn shutdown(failure: bool) -> ! {
     const SHUTDOWN_CODE: u32 = 0x5555;  // 正常关机
     const FAILURE_CODE: u32 = 0x3333;   // 错误关机
 
+    // 正常关机前把所有文件系统写回磁盘；panic 时可能持有文件系统的锁，不写回
+    if !failure {
+        crate::fs::sync();
+    }
+
     unsafe {
         if !failure {
             // 正常关机
@@ -1,104 +1,785 @@
 // os/src/syscall/fs
 
-use crate::fs::UserBuffer;
-use crate::mm::page_table::translated_byte_buffer;
-use crate::sbi::console_getchar;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::fs::{is_mount_point, lookup_parent, lookup_path, lookup_path_nofollow, mount, new_filesystem, open_file, rename, resolve_path, statfs, sync, syncfs, umount, FsStats, InodeType, OpenFlags, Permissions, RenameMode, UserBuffer, VfsInode, XattrMode, S_ISGID, S_ISUID};
+use crate::config::PAGE_SIZE;
+use crate::fs::path::{normalize, NAME_MAX, PATH_MAX};
+use crate::fs::perm::{check_access, check_dir_change, new_permissions, permitted, MAY_EXEC, MAY_READ, MAY_WRITE};
+use crate::mm::page_table::{copy_from_user, copy_obj_from_user, copy_obj_to_user, copy_to_user, translated_byte_buffer, translated_byte_buffer_mut, translated_str};
+use crate::syscall::errno::{E2BIG, EACCES, EBADF, EBUSY, EFAULT, EINVAL, EISDIR, ENAMETOOLONG, ENODATA, ENOENT, ENOTDIR, EOPNOTSUPP, EPERM, ERANGE};
+use crate::task::cred::current_credentials;
 use crate::task::processor::{current_task, current_user_satp};
-use crate::task::suspend_current_and_run_next;
+use crate::timer::{realtime, TimeSpec};
 
-const FD_STDIN: usize = 0;
-const FD_STDOUT: usize = 1;
-const FD_STDERR: usize = 2;
+const IOV_MAX: usize = 1024;
+const AT_FDCWD: isize = -100; // *at 系列调用中表示相对于当前工作目录
+const AT_SYMLINK_NOFOLLOW: usize = 0x100; // fchownat/newfstatat/utimensat: 不跟随最后一个分量的符号链接
+const AT_REMOVEDIR: usize = 0x200; // unlinkat: 删除目录而不是文件
+const RENAME_NOREPLACE: u32 = 1; // renameat2: 目标已存在时失败
+const RENAME_EXCHANGE: u32 = 2;  // renameat2: 原子地交换两者
+const UTIME_NOW: usize = (1 << 30) - 1;  // utimensat: 设置为当前时间
+const UTIME_OMIT: usize = (1 << 30) - 2; // utimensat: 保持不变
+
+// struct iovec, used by readv/writev
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IoVec {
+    pub base: usize,
+    pub len: usize,
+}
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_satp(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
+    let token = current_user_satp();
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -EBADF;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.writable() {
+            return -EBADF;
         }
-        FD_STDIN => {
-            panic!("Cannot write to stdin!");
+        let file = file.clone();
+        drop(inner);
+        match translated_byte_buffer(token, buf, len) {
+            Ok(buffers) => match file.write(UserBuffer::new(buffers)) {
+                Ok(len) => len as isize,
+                Err(errno) => -errno,
+            },
+            Err(_) => -EFAULT,
         }
-        FD_STDERR => {
-            let buffers = translated_byte_buffer(current_user_satp(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
-            }
-            len as isize
+    } else {
+        -EBADF
+    }
+}
+
+
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+    let token = current_user_satp();
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -EBADF;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        if !file.readable() {
+            return -EBADF;
         }
-        _ => {
-            let token = current_user_satp();
-            let task = current_task().unwrap();
-            let inner = task.inner_exclusive_access();
-            if fd >= inner.fd_table.len() {
-                return -1;
-            }
-            if let Some(file) = &inner.fd_table[fd] {
-                let file = file.clone();
-                drop(inner);
-                file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-            } else {
-                -1
-            }
+        let file = file.clone();
+        drop(inner);
+        match translated_byte_buffer_mut(token, buf as *mut u8, len) {
+            Ok(buffers) => match file.read(UserBuffer::new(buffers)) {
+                Ok(len) => len as isize,
+                Err(errno) => -errno,
+            },
+            Err(_) => -EFAULT,
         }
+    } else {
+        -EBADF
     }
 }
 
+pub fn sys_close(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
+        return -EBADF;
+    }
+    inner.fd_table[fd].take(); // take() will drop the file descriptor, and replace it with None
+    0
+}
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDIN => {
-            assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-            let mut c: usize;
-            loop {
-                c = console_getchar();
-                if c == 0 {
-                    suspend_current_and_run_next();
-                    continue;
-                } else {
-                    break;
-                }
-            }
-            let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_user_satp(), buf, len);
-            unsafe {
-                buffers[0].as_mut_ptr().write_volatile(ch);
-            }
-            1
+// 依次处理每个 iovec，返回总字节数；某一段出错时若已传输过数据则返回已传输的字节数
+fn for_each_iovec(iov: *const IoVec, iovcnt: usize, mut f: impl FnMut(IoVec) -> isize) -> isize {
+    if iovcnt > IOV_MAX {
+        return -EINVAL;
+    }
+    let token = current_user_satp();
+    let mut total: isize = 0;
+    for i in 0..iovcnt {
+        let vec = match copy_obj_from_user(token, iov.wrapping_add(i)) {
+            Ok(vec) => vec,
+            Err(errno) => return -errno,
+        };
+        if vec.len == 0 {
+            continue;
         }
-        FD_STDOUT => {
-            panic!("Cannot read from stdout!");
+        let ret = f(vec);
+        if ret < 0 {
+            return if total > 0 { total } else { ret };
         }
-        FD_STDERR => {
-            panic!("Cannot read from stderr!");
+        total += ret;
+        if (ret as usize) < vec.len {
+            break; // short read/write
         }
-        _ => {
-            let token = current_user_satp();
+    }
+    total
+}
+
+pub fn sys_readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    for_each_iovec(iov, iovcnt, |vec| sys_read(fd, vec.base as *const u8, vec.len))
+}
+
+pub fn sys_writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    for_each_iovec(iov, iovcnt, |vec| sys_write(fd, vec.base as *const u8, vec.len))
+}
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -EBADF;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        let file = file.clone();
+        drop(inner);
+        file.ioctl(cmd, arg)
+    } else {
+        -EBADF
+    }
+}
+
+// ----- path & directory syscalls -----
+
+// 将 *at 系列调用的 (dirfd, path) 解析为规范化的绝对路径
+// 中间分量的符号链接被解析，最后一个分量保持原样，是否跟随由各个调用决定
+fn resolve_at(dirfd: isize, path: *const u8) -> Result<String, isize> {
+    let path = translated_str(current_user_satp(), path)?;
+    if path.is_empty() {
+        return Err(ENOENT);
+    }
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let path = if path.starts_with('/') {
+        normalize("/", &path)
+    } else if dirfd == AT_FDCWD {
+        normalize(&inner.cwd, &path)
+    } else {
+        match inner.fd_table.get(dirfd as usize) {
+            Some(Some(file)) => normalize(&file.path().ok_or(ENOTDIR)?, &path),
+            _ => return Err(EBADF),
+        }
+    };
+    // 解析符号链接时会访问文件系统 (如 procfs)，不能持有任务的锁
+    drop(inner);
+    resolve_path(&path, false)
+}
+
+pub fn sys_openat(dirfd: isize, path: *const u8, flags: u32, mode: u32) -> isize {
+    let path = match resolve_at(dirfd, path) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    match open_file(&path, OpenFlags::from_bits_truncate(flags), mode as u16) {
+        Ok(inode) => {
             let task = current_task().unwrap();
-            let inner = task.inner_exclusive_access();
-            if fd >= inner.fd_table.len() {
-                return -1;
-            }
-            if let Some(file) = &inner.fd_table[fd] {
-                let file = file.clone();
-                drop(inner);
-                file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
-            } else {
-                -1
+            let mut inner = task.inner_exclusive_access();
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(inode);
+            fd as isize
+        }
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> isize {
+    let result = resolve_at(dirfd, path)
+        .and_then(|path| lookup_parent(&path))
+        .and_then(|(parent, name)| {
+            check_dir_change(&parent, None)?;
+            // 目录不使用 setuid/setgid 位
+            parent.create(&name, InodeType::Dir, new_permissions(mode as u16 & 0o1777))
+        });
+    match result {
+        Ok(_) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_unlinkat(dirfd: isize, path: *const u8, flags: usize) -> isize {
+    let result = resolve_at(dirfd, path).and_then(|path| {
+        if is_mount_point(&path) {
+            return Err(EBUSY);
+        }
+        let (parent, name) = lookup_parent(&path)?;
+        check_dir_change(&parent, Some(&name))?;
+        let is_dir = parent.lookup(&name).ok_or(ENOENT)?.inode_type() == InodeType::Dir;
+        match (flags & AT_REMOVEDIR != 0, is_dir) {
+            (true, false) => Err(ENOTDIR),
+            (false, true) => Err(EISDIR),
+            _ => parent.unlink(&name),
+        }
+    });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_renameat2(old_dirfd: isize, old_path: *const u8, new_dirfd: isize, new_path: *const u8, flags: u32) -> isize {
+    let mode = match flags {
+        0 => RenameMode::Replace,
+        RENAME_NOREPLACE => RenameMode::NoReplace,
+        RENAME_EXCHANGE => RenameMode::Exchange,
+        _ => return -EINVAL, // 两者不能同时使用，不支持 RENAME_WHITEOUT
+    };
+    let result = resolve_at(old_dirfd, old_path).and_then(|old_path| {
+        let new_path = resolve_at(new_dirfd, new_path)?;
+        rename(&old_path, &new_path, mode)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_symlinkat(target: *const u8, new_dirfd: isize, link_path: *const u8) -> isize {
+    let result = translated_str(current_user_satp(), target).and_then(|target| {
+        if target.is_empty() {
+            return Err(ENOENT);
+        }
+        if target.len() >= PATH_MAX {
+            return Err(ENAMETOOLONG);
+        }
+        let (parent, name) = lookup_parent(&resolve_at(new_dirfd, link_path)?)?;
+        check_dir_change(&parent, None)?;
+        // 符号链接的权限总是 rwxrwxrwx，访问时检查的是链接目标的权限
+        parent.symlink(&name, &target, Permissions { mode: 0o777, ..new_permissions(0) })
+    });
+    match result {
+        Ok(_) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// 成功时返回链接目标的长度，目标被截断到 size 字节且不以 '\0' 结尾
+pub fn sys_readlinkat(dirfd: isize, path: *const u8, buf: *mut u8, size: usize) -> isize {
+    if size == 0 {
+        return -EINVAL;
+    }
+    let result = resolve_at(dirfd, path)
+        .and_then(|path| lookup_path_nofollow(&path))
+        .and_then(|inode| inode.readlink())
+        .and_then(|target| {
+            let len = target.len().min(size);
+            copy_to_user(current_user_satp(), buf, &target.as_bytes()[..len]).map(|_| len)
+        });
+    match result {
+        Ok(len) => len as isize,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    match file.truncate(len) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_sync() -> isize {
+    sync();
+    0
+}
+
+// fsync/fdatasync 只支持文件系统中的文件，管道、终端等返回 EINVAL
+pub fn sys_fsync(fd: usize) -> isize {
+    fsync(fd, false)
+}
+
+pub fn sys_fdatasync(fd: usize) -> isize {
+    fsync(fd, true)
+}
+
+fn fsync(fd: usize, data_only: bool) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    match file.inode().ok_or(EINVAL).and_then(|inode| inode.fsync(data_only)) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// 与 fstatfs 一样按打开时的路径查找所在的文件系统
+pub fn sys_syncfs(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    match file.path().ok_or(EINVAL).and_then(|path| syncfs(&path)) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    let mut kernel_buf = vec![0u8; len.min(PAGE_SIZE)];
+    let result = file.getdents64(&mut kernel_buf)
+        .and_then(|n| copy_to_user(current_user_satp(), buf, &kernel_buf[..n]).map(|_| n));
+    match result {
+        Ok(n) => n as isize,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_chdir(path: *const u8) -> isize {
+    // 工作目录中不含符号链接
+    let path = match resolve_at(AT_FDCWD, path).and_then(|path| resolve_path(&path, true)) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    match lookup_path(&path) {
+        Ok(inode) if inode.inode_type() == InodeType::Dir => {
+            if let Err(errno) = check_access(&inode, MAY_EXEC) {
+                return -errno;
             }
+            current_task().unwrap().inner_exclusive_access().cwd = path;
+            0
         }
+        Ok(_) => -ENOTDIR,
+        Err(errno) => -errno,
     }
 }
 
-pub fn sys_close(fd: usize) -> isize {
+// 成功时返回写入的字节数 (含末尾的 '\0')
+pub fn sys_getcwd(buf: *mut u8, size: usize) -> isize {
+    let mut cwd = current_task().unwrap().inner_exclusive_access().cwd.clone();
+    cwd.push('\0');
+    if cwd.len() > size {
+        return -ERANGE;
+    }
+    match copy_to_user(current_user_satp(), buf, cwd.as_bytes()) {
+        Ok(()) => cwd.len() as isize,
+        Err(errno) => -errno,
+    }
+}
+
+// mount(source, target, fstype, flags, data)，不支持 flags 和 data；只有 root 可以挂载
+pub fn sys_mount(source: *const u8, target: *const u8, fs_type: *const u8, _flags: usize, _data: *const u8) -> isize {
+    if !current_credentials().is_root() {
+        return -EPERM;
+    }
+    let token = current_user_satp();
+    let result = translated_str(token, source).and_then(|source| {
+        let fs_type = translated_str(token, fs_type)?;
+        let target = resolve_path(&resolve_at(AT_FDCWD, target)?, true)?;
+        let fs = new_filesystem(&fs_type, &source)?;
+        mount(&target, &source, fs)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_umount2(target: *const u8, _flags: usize) -> isize {
+    if !current_credentials().is_root() {
+        return -EPERM;
+    }
+    let result = resolve_at(AT_FDCWD, target)
+        .and_then(|target| resolve_path(&target, true))
+        .and_then(|target| umount(&target));
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// ----- permissions -----
+
+// fchmodat(dirfd, path, mode, flags)，只有文件的属主和 root 可以修改权限
+pub fn sys_fchmodat(dirfd: isize, path: *const u8, mode: u32, _flags: usize) -> isize {
+    let result = resolve_at(dirfd, path)
+        .and_then(|path| lookup_path(&path))
+        .and_then(|inode| {
+            let cred = current_credentials();
+            let mut perm = inode.permissions();
+            if !cred.is_root() && cred.euid != perm.uid {
+                return Err(EPERM);
+            }
+            perm.mode = mode as u16 & 0o7777;
+            // 不属于文件的属组时不能设置 setgid 位
+            if !cred.is_root() && cred.egid != perm.gid {
+                perm.mode &= !S_ISGID;
+            }
+            inode.set_permissions(perm)
+        });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// fchownat(dirfd, path, owner, group, flags)，owner 或 group 为 -1 时保持不变
+// 只有 root 可以修改属主；属主可以把属组改为自己的 egid
+pub fn sys_fchownat(dirfd: isize, path: *const u8, owner: u32, group: u32, flags: usize) -> isize {
+    let result = resolve_at(dirfd, path)
+        .and_then(|path| {
+            if flags & AT_SYMLINK_NOFOLLOW != 0 { lookup_path_nofollow(&path) } else { lookup_path(&path) }
+        })
+        .and_then(|inode| {
+            let cred = current_credentials();
+            let mut perm = inode.permissions();
+            let uid = if owner == u32::MAX { perm.uid } else { owner };
+            let gid = if group == u32::MAX { perm.gid } else { group };
+            let allowed = cred.is_root()
+                || (cred.euid == perm.uid && uid == perm.uid && (gid == perm.gid || gid == cred.egid));
+            if !allowed {
+                return Err(EPERM);
+            }
+            perm.uid = uid;
+            perm.gid = gid;
+            // 修改属主后 setuid/setgid 程序不再以原来的用户运行
+            if inode.inode_type() != InodeType::Dir {
+                perm.mode &= !(S_ISUID | S_ISGID);
+            }
+            inode.set_permissions(perm)
+        });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// 设置新的 umask，返回原来的 umask
+pub fn sys_umask(mask: u32) -> isize {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
-    if fd >= inner.fd_table.len() || inner.fd_table[fd].is_none() {
-        return -1;
+    let old = inner.umask;
+    inner.umask = mask as u16 & 0o777;
+    old as isize
+}
+
+// ----- file status -----
+
+// struct stat (asm-generic, riscv64)
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Stat {
+    dev: u64,
+    ino: u64,
+    mode: u32,
+    nlink: u32,
+    uid: u32,
+    gid: u32,
+    rdev: u64,
+    __pad1: u64,
+    size: i64,
+    blksize: i32,
+    __pad2: i32,
+    blocks: i64,
+    atime: TimeSpec,
+    mtime: TimeSpec,
+    ctime: TimeSpec,
+    __unused: [u32; 2],
+}
+
+impl Stat {
+    fn new(inode: &Arc<dyn VfsInode>) -> Self {
+        let perm = inode.permissions();
+        let times = inode.times();
+        let size = inode.size();
+        Self {
+            ino: inode.ino() as u64,
+            mode: inode.inode_type().file_mode() | perm.mode as u32,
+            nlink: 1,
+            uid: perm.uid,
+            gid: perm.gid,
+            size: size as i64,
+            blksize: PAGE_SIZE as i32,
+            blocks: size.div_ceil(512) as i64,
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.ctime,
+            ..Self::default()
+        }
     }
-    inner.fd_table[fd].take(); // take() will drop the file descriptor, and replace it with None
-    0
-}
\ No newline at end of file
+}
+
+// fd 对应的 inode，不是从文件系统打开的文件 (标准输入输出、管道) 返回 None
+fn file_inode(fd: usize) -> Result<Option<Arc<dyn VfsInode>>, isize> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return Err(EBADF),
+    };
+    drop(inner);
+    Ok(file.inode())
+}
+
+pub fn sys_fstat(fd: usize, statbuf: *mut Stat) -> isize {
+    let result = file_inode(fd).and_then(|inode| {
+        // 没有 inode 的文件视为属于 root 的字符设备
+        let stat = match inode {
+            Some(inode) => Stat::new(&inode),
+            None => Stat { mode: InodeType::CharDevice.file_mode() | 0o620, nlink: 1, ..Stat::default() },
+        };
+        copy_obj_to_user(current_user_satp(), statbuf, &stat)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// newfstatat(dirfd, path, statbuf, flags)，支持 AT_SYMLINK_NOFOLLOW (即 lstat)
+pub fn sys_newfstatat(dirfd: isize, path: *const u8, statbuf: *mut Stat, flags: usize) -> isize {
+    let result = resolve_at(dirfd, path)
+        .and_then(|path| {
+            if flags & AT_SYMLINK_NOFOLLOW != 0 { lookup_path_nofollow(&path) } else { lookup_path(&path) }
+        })
+        .and_then(|inode| copy_obj_to_user(current_user_satp(), statbuf, &Stat::new(&inode)));
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// struct statfs (asm-generic, riscv64)
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct StatFs {
+    f_type: i64,
+    f_bsize: i64,
+    f_blocks: u64,
+    f_bfree: u64,
+    f_bavail: u64, // 普通用户可用的块数，easy-fs 不为 root 保留空间，与 f_bfree 相同
+    f_files: u64,
+    f_ffree: u64,
+    f_fsid: [i32; 2],
+    f_namelen: i64,
+    f_frsize: i64,
+    f_flags: i64,
+    f_spare: [i64; 4],
+}
+
+impl StatFs {
+    fn new(stats: FsStats) -> Self {
+        Self {
+            f_type: stats.magic as i64,
+            f_bsize: stats.block_size as i64,
+            f_blocks: stats.blocks,
+            f_bfree: stats.free_blocks,
+            f_bavail: stats.free_blocks,
+            f_files: stats.files,
+            f_ffree: stats.free_files,
+            f_namelen: NAME_MAX as i64,
+            f_frsize: stats.block_size as i64,
+            ..Self::default()
+        }
+    }
+}
+
+pub fn sys_statfs(path: *const u8, buf: *mut StatFs) -> isize {
+    let result = resolve_at(AT_FDCWD, path)
+        .and_then(|path| statfs(&path))
+        .and_then(|stats| copy_obj_to_user(current_user_satp(), buf, &StatFs::new(stats)));
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// 只支持从文件系统打开的文件 (按打开时的路径查找所在的文件系统)
+pub fn sys_fstatfs(fd: usize, buf: *mut StatFs) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -EBADF,
+    };
+    drop(inner);
+    let result = file.path().ok_or(EINVAL)
+        .and_then(|path| statfs(&path))
+        .and_then(|stats| copy_obj_to_user(current_user_satp(), buf, &StatFs::new(stats)));
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// utimensat(dirfd, path, times, flags)，path 为 NULL 时修改 dirfd 本身 (futimens)
+// times 为 NULL 表示两者都设置为当前时间，tv_nsec 可以是 UTIME_NOW 或 UTIME_OMIT
+// 设置为当前时间要求是属主或有写权限，设置为指定的时间要求是属主 (或 root)
+pub fn sys_utimensat(dirfd: isize, path: *const u8, times: *const [TimeSpec; 2], flags: usize) -> isize {
+    match utimensat(dirfd, path, times, flags) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+fn utimensat(dirfd: isize, path: *const u8, times: *const [TimeSpec; 2], flags: usize) -> Result<(), isize> {
+    let inode = if path.is_null() {
+        file_inode(dirfd as usize)?.ok_or(EPERM)?
+    } else {
+        let path = resolve_at(dirfd, path)?;
+        if flags & AT_SYMLINK_NOFOLLOW != 0 { lookup_path_nofollow(&path)? } else { lookup_path(&path)? }
+    };
+    let times = if times.is_null() {
+        [TimeSpec { tv_sec: 0, tv_nsec: UTIME_NOW }; 2]
+    } else {
+        copy_obj_from_user(current_user_satp(), times)?
+    };
+    let special = |time: &TimeSpec| time.tv_nsec == UTIME_NOW || time.tv_nsec == UTIME_OMIT;
+    if times.iter().any(|time| time.tv_nsec >= 1_000_000_000 && !special(time)) {
+        return Err(EINVAL);
+    }
+    if times.iter().all(|time| time.tv_nsec == UTIME_OMIT) {
+        return Ok(());
+    }
+    let cred = current_credentials();
+    if !cred.is_root() && cred.euid != inode.permissions().uid {
+        if !times.iter().all(special) {
+            return Err(EPERM);
+        }
+        if !permitted(&cred, &inode, MAY_WRITE) {
+            return Err(EACCES);
+        }
+    }
+    let now = realtime();
+    let [atime, mtime] = times.map(|time| match time.tv_nsec {
+        UTIME_OMIT => None,
+        UTIME_NOW => Some(now),
+        _ => Some(time),
+    });
+    inode.set_times(atime, mtime)
+}
+
+// ----- extended attributes -----
+
+const XATTR_CREATE: usize = 1;  // setxattr: 属性已存在时失败
+const XATTR_REPLACE: usize = 2; // setxattr: 属性不存在时失败
+const XATTR_NAME_MAX: usize = 255;
+const XATTR_SIZE_MAX: usize = 65536;
+
+// 用户传入的属性名，长度必须为 1 ~ XATTR_NAME_MAX
+fn xattr_name(name: *const u8) -> Result<String, isize> {
+    let name = translated_str(current_user_satp(), name)?;
+    if name.is_empty() || name.len() > XATTR_NAME_MAX {
+        return Err(ERANGE);
+    }
+    Ok(name)
+}
+
+// 按名字空间检查对属性 name 的访问，access 为 MAY_READ 或 MAY_WRITE
+// user.* 只能用于普通文件和目录，按文件的读写权限检查；trusted.* 只有 root 可以访问
+// 与 Linux 相同，不允许访问时读取返回 ENODATA (如同属性不存在)，修改返回 EPERM
+fn check_xattr_access(inode: &Arc<dyn VfsInode>, name: &str, access: u16) -> Result<(), isize> {
+    let cred = current_credentials();
+    let denied = if access == MAY_WRITE { EPERM } else { ENODATA };
+    if name.starts_with("trusted.") {
+        return if cred.is_root() { Ok(()) } else { Err(denied) };
+    }
+    if !name.starts_with("user.") {
+        return Err(EOPNOTSUPP);
+    }
+    if !matches!(inode.inode_type(), InodeType::File | InodeType::Dir) {
+        return Err(denied);
+    }
+    if permitted(&cred, inode, access) { Ok(()) } else { Err(EACCES) }
+}
+
+// 把属性值 (或属性名列表) 复制到用户缓冲区，size 为 0 时只返回所需的长度
+fn copy_xattr_out(buf: *mut u8, size: usize, data: &[u8]) -> Result<usize, isize> {
+    if size == 0 {
+        return Ok(data.len());
+    }
+    if size < data.len() {
+        return Err(ERANGE);
+    }
+    copy_to_user(current_user_satp(), buf, data).map(|_| data.len())
+}
+
+// setxattr(path, name, value, size, flags)
+pub fn sys_setxattr(path: *const u8, name: *const u8, value: *const u8, size: usize, flags: usize) -> isize {
+    let mode = match flags {
+        0 => XattrMode::Set,
+        XATTR_CREATE => XattrMode::Create,
+        XATTR_REPLACE => XattrMode::Replace,
+        _ => return -EINVAL,
+    };
+    if size > XATTR_SIZE_MAX {
+        return -E2BIG;
+    }
+    let result = xattr_name(name).and_then(|name| {
+        let inode = resolve_at(AT_FDCWD, path).and_then(|path| lookup_path(&path))?;
+        check_xattr_access(&inode, &name, MAY_WRITE)?;
+        let mut data = vec![0u8; size];
+        copy_from_user(current_user_satp(), value, &mut data)?;
+        inode.set_xattr(&name, &data, mode)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// getxattr(path, name, value, size)，成功时返回属性值的长度
+pub fn sys_getxattr(path: *const u8, name: *const u8, value: *mut u8, size: usize) -> isize {
+    let result = xattr_name(name).and_then(|name| {
+        let inode = resolve_at(AT_FDCWD, path).and_then(|path| lookup_path(&path))?;
+        check_xattr_access(&inode, &name, MAY_READ)?;
+        copy_xattr_out(value, size, &inode.get_xattr(&name)?)
+    });
+    match result {
+        Ok(len) => len as isize,
+        Err(errno) => -errno,
+    }
+}
+
+// listxattr(path, list, size)，list 中依次是以 '\0' 结尾的属性名，成功时返回总长度
+// 不支持扩展属性的文件系统返回空列表，trusted.* 只对 root 可见
+pub fn sys_listxattr(path: *const u8, list: *mut u8, size: usize) -> isize {
+    let result = resolve_at(AT_FDCWD, path)
+        .and_then(|path| lookup_path(&path))
+        .and_then(|inode| {
+            let cred = current_credentials();
+            let names: Vec<u8> = inode.list_xattr().unwrap_or_default().iter()
+                .filter(|name| cred.is_root() || !name.starts_with("trusted."))
+                .flat_map(|name| name.bytes().chain([0]))
+                .collect();
+            copy_xattr_out(list, size, &names)
+        });
+    match result {
+        Ok(len) => len as isize,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_removexattr(path: *const u8, name: *const u8) -> isize {
+    let result = xattr_name(name).and_then(|name| {
+        let inode = resolve_at(AT_FDCWD, path).and_then(|path| lookup_path(&path))?;
+        check_xattr_access(&inode, &name, MAY_WRITE)?;
+        inode.remove_xattr(&name)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
@@ -1,35 +1,134 @@
 // os/src/syscall/mod.rs
+// syscall ids follow Linux RISC-V (asm-generic/unistd.h)
+const SYSCALL_SETXATTR: usize = 5;
+const SYSCALL_GETXATTR: usize = 8;
+const SYSCALL_LISTXATTR: usize = 11;
+const SYSCALL_REMOVEXATTR: usize = 14;
+const SYSCALL_GETCWD: usize = 17;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_MKDIRAT: usize = 34;
+const SYSCALL_UNLINKAT: usize = 35;
+const SYSCALL_SYMLINKAT: usize = 36;
+const SYSCALL_UMOUNT2: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_STATFS: usize = 43;
+const SYSCALL_FSTATFS: usize = 44;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_FCHMODAT: usize = 53;
+const SYSCALL_FCHOWNAT: usize = 54;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_READV: usize = 65;
+const SYSCALL_WRITEV: usize = 66;
+const SYSCALL_READLINKAT: usize = 78;
+const SYSCALL_NEWFSTATAT: usize = 79;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
+const SYSCALL_FDATASYNC: usize = 83;
+const SYSCALL_UTIMENSAT: usize = 88;
 
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_SET_TID_ADDRESS: usize = 96;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_REBOOT: usize = 142;
+const SYSCALL_SETGID: usize = 144;
+const SYSCALL_SETUID: usize = 146;
+const SYSCALL_UNAME: usize = 160;
+const SYSCALL_UMASK: usize = 166;
 const SYSCALL_GET_TIME: usize = 169;
-const SYSCALL_SBRK: usize = 214;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_SYNCFS: usize = 267;
+const SYSCALL_RENAMEAT2: usize = 276;
 
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETEUID: usize = 175;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_GETEGID: usize = 177;
+const SYSCALL_GETTID: usize = 178;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 
+pub mod errno;
 mod fs;
 mod process;
 
+use errno::ENOSYS;
 use fs::*;
 use process::*;
+use crate::timer::TimeSpec;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
+        SYSCALL_SETXATTR => { sys_setxattr(args[0] as *const u8, args[1] as *const u8, args[2] as *const u8, args[3], args[4]) }
+        SYSCALL_GETXATTR => { sys_getxattr(args[0] as *const u8, args[1] as *const u8, args[2] as *mut u8, args[3]) }
+        SYSCALL_LISTXATTR => { sys_listxattr(args[0] as *const u8, args[1] as *mut u8, args[2]) }
+        SYSCALL_REMOVEXATTR => { sys_removexattr(args[0] as *const u8, args[1] as *const u8) }
+        SYSCALL_GETCWD => { sys_getcwd(args[0] as *mut u8, args[1]) }
+        SYSCALL_IOCTL => { sys_ioctl(args[0], args[1], args[2]) }
+        SYSCALL_MKDIRAT => { sys_mkdirat(args[0] as isize, args[1] as *const u8, args[2] as u32) }
+        SYSCALL_UNLINKAT => { sys_unlinkat(args[0] as isize, args[1] as *const u8, args[2]) }
+        SYSCALL_SYMLINKAT => { sys_symlinkat(args[0] as *const u8, args[1] as isize, args[2] as *const u8) }
+        SYSCALL_UMOUNT2 => { sys_umount2(args[0] as *const u8, args[1]) }
+        SYSCALL_MOUNT => { sys_mount(args[0] as *const u8, args[1] as *const u8, args[2] as *const u8, args[3], args[4] as *const u8) }
+        SYSCALL_RENAMEAT2 => { sys_renameat2(args[0] as isize, args[1] as *const u8, args[2] as isize, args[3] as *const u8, args[4] as u32) }
+        SYSCALL_STATFS => { sys_statfs(args[0] as *const u8, args[1] as *mut StatFs) }
+        SYSCALL_FSTATFS => { sys_fstatfs(args[0], args[1] as *mut StatFs) }
+        SYSCALL_FTRUNCATE => { sys_ftruncate(args[0], args[1]) }
+        SYSCALL_CHDIR => { sys_chdir(args[0] as *const u8) }
+        SYSCALL_FCHMODAT => { sys_fchmodat(args[0] as isize, args[1] as *const u8, args[2] as u32, args[3]) }
+        SYSCALL_FCHOWNAT => { sys_fchownat(args[0] as isize, args[1] as *const u8, args[2] as u32, args[3] as u32, args[4]) }
+        SYSCALL_OPENAT => { sys_openat(args[0] as isize, args[1] as *const u8, args[2] as u32, args[3] as u32) }
+        SYSCALL_CLOSE => { sys_close(args[0]) }
+        SYSCALL_GETDENTS64 => { sys_getdents64(args[0], args[1] as *mut u8, args[2]) }
         SYSCALL_READ => { sys_read(args[0], args[1] as *const u8, args[2]) }
         SYSCALL_WRITE => { sys_write(args[0], args[1] as *const u8, args[2]) }
+        SYSCALL_READV => { sys_readv(args[0], args[1] as *const IoVec, args[2]) }
+        SYSCALL_WRITEV => { sys_writev(args[0], args[1] as *const IoVec, args[2]) }
+        SYSCALL_READLINKAT => { sys_readlinkat(args[0] as isize, args[1] as *const u8, args[2] as *mut u8, args[3]) }
+        SYSCALL_NEWFSTATAT => { sys_newfstatat(args[0] as isize, args[1] as *const u8, args[2] as *mut Stat, args[3]) }
+        SYSCALL_FSTAT => { sys_fstat(args[0], args[1] as *mut Stat) }
+        SYSCALL_SYNC => { sys_sync() }
+        SYSCALL_FSYNC => { sys_fsync(args[0]) }
+        SYSCALL_FDATASYNC => { sys_fdatasync(args[0]) }
+        SYSCALL_SYNCFS => { sys_syncfs(args[0]) }
+        SYSCALL_UTIMENSAT => { sys_utimensat(args[0] as isize, args[1] as *const u8, args[2] as *const [TimeSpec; 2], args[3]) }
         SYSCALL_EXIT => { sys_exit(args[0] as i32) }
+        SYSCALL_EXIT_GROUP => { sys_exit(args[0] as i32) } // single-threaded: same as exit
+        SYSCALL_SET_TID_ADDRESS => { sys_set_tid_address(args[0] as *mut i32) }
+        SYSCALL_CLOCK_GETTIME => { sys_clock_gettime(args[0], args[1] as *mut TimeSpec) }
         SYSCALL_YIELD => { sys_yield() }
+        SYSCALL_REBOOT => { sys_reboot(args[0] as u32, args[1] as u32, args[2] as u32) }
+        SYSCALL_SETGID => { sys_setgid(args[0] as u32) }
+        SYSCALL_SETUID => { sys_setuid(args[0] as u32) }
+        SYSCALL_UNAME => { sys_uname(args[0] as *mut UtsName) }
+        SYSCALL_UMASK => { sys_umask(args[0] as u32) }
         SYSCALL_GET_TIME => { sys_get_time() }
-        SYSCALL_SBRK => { sys_sbrk(args[0] as i32) }
+        SYSCALL_BRK => { sys_brk(args[0]) }
+        SYSCALL_MUNMAP => { sys_munmap(args[0], args[1]) }
+        SYSCALL_MMAP => { sys_mmap(args[0], args[1], args[2], args[3], args[4] as isize, args[5]) }
         SYSCALL_GETPID => { sys_getpid() }
+        SYSCALL_GETTID => { sys_getpid() } // single-threaded: tid == pid
+        SYSCALL_GETUID => { sys_getuid() }
+        SYSCALL_GETEUID => { sys_geteuid() }
+        SYSCALL_GETGID => { sys_getgid() }
+        SYSCALL_GETEGID => { sys_getegid() }
         SYSCALL_FORK => { sys_fork() }
-        SYSCALL_EXEC => { sys_exec(args[0] as *const u8) }
+        SYSCALL_EXEC => { sys_exec(args[0] as *const u8, args[1] as *const usize, args[2] as *const usize) }
         SYSCALL_WAITPID => { sys_waitpid(args[0] as isize, args[1] as *mut i32) }
-        _ => core::panic!("Unsupported syscall id {}", syscall_id),
+        _ => {
+            println_red!("[kernel] Unsupported syscall id {}", syscall_id);
+            -ENOSYS
+        }
     }
-}
\ No newline at end of file
+}
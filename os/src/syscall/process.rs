@@ -1,11 +1,35 @@
 // os/src/syscall/syscall.rs
 
+use alloc::string::String;
 use alloc::sync::Arc;
-use crate::fs::{open_file, OpenFlags};
-use crate::mm::page_table::{translated_refmut, translated_str};
+use alloc::vec::Vec;
+use crate::config::PAGE_SIZE;
+use crate::fs::{lookup_path, InodeType, OSInode};
+use crate::fs::path::{normalize, split_parent};
+use crate::fs::perm::{check_access, MAY_EXEC};
+use crate::mm::address::{VirAddr, VirPageNum};
+use crate::mm::area::{MapArea, MapPermission};
+use crate::mm::area::MapType::Framed;
+use crate::mm::frame_allocator::frame_remaining;
+use crate::mm::page_table::{copy_obj_from_user, copy_obj_to_user, translated_str};
+use crate::syscall::errno::{E2BIG, EACCES, EAGAIN, ECHILD, EINVAL, ENODEV, ENOMEM, EPERM};
 use crate::task::{exit_current_and_run_next, suspend_current_and_run_next};
+use crate::task::cred::current_credentials;
 use crate::task::processor::{current_task, current_user_satp};
 use crate::task::task_manager::add_task;
+use crate::timer::{monotonic_time, realtime, TimeSpec};
+
+const ARG_MAX: usize = 256; // argv/envp 中字符串的最大个数
+const USER_SPACE_END: usize = 1 << 38; // Sv39 用户地址空间的上界
+
+// mmap prot & flags
+const PROT_READ: usize = 0x1;
+const PROT_WRITE: usize = 0x2;
+const PROT_EXEC: usize = 0x4;
+const MAP_SHARED: usize = 0x01;
+const MAP_PRIVATE: usize = 0x02;
+const MAP_FIXED: usize = 0x10;
+const MAP_ANONYMOUS: usize = 0x20;
 
 // SYSCALL_EXIT 93;
 pub fn sys_exit(exit_code: i32) -> ! {
@@ -30,14 +54,143 @@ pub fn sys_get_time() -> isize {
     crate::timer::get_time() as isize
 }
 
-pub fn sys_sbrk(size: i32) -> isize {
-    -1
-    // todo
-    // if let Some(old_brk) = change_program_brk(size) {
-    //     old_brk as isize
-    // } else {
-    //     -1
-    // }
+// SYSCALL_BRK 214
+// 将 program break 设置为 addr，返回新的 break
+// addr 为 0 或无法调整时返回当前的 break (Linux 语义，由 libc 判断是否成功)
+pub fn sys_brk(addr: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if addr != 0 {
+        inner.change_program_brk(addr);
+    }
+    inner.program_brk as isize
+}
+
+// SYSCALL_MMAP 222
+// 只支持匿名映射 (MAP_ANONYMOUS)，返回映射的起始地址
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, _fd: isize, _offset: usize) -> isize {
+    if len == 0 || addr % PAGE_SIZE != 0 {
+        return -EINVAL;
+    }
+    if flags & MAP_ANONYMOUS == 0 {
+        return -ENODEV; // file mappings are not supported yet
+    }
+    if flags & (MAP_SHARED | MAP_PRIVATE) == 0 {
+        return -EINVAL;
+    }
+    // RISC-V 的叶子页表项必须可读/可写/可执行之一，无法表达 PROT_NONE
+    if prot & (PROT_READ | PROT_WRITE | PROT_EXEC) == 0 {
+        return -EINVAL;
+    }
+    let mut map_perm = MapPermission::U;
+    if prot & (PROT_READ | PROT_WRITE) != 0 { map_perm |= MapPermission::R; } // W 必须同时有 R
+    if prot & PROT_WRITE != 0 { map_perm |= MapPermission::W; }
+    if prot & PROT_EXEC != 0  { map_perm |= MapPermission::X; }
+
+    let page_count = match len.checked_add(PAGE_SIZE - 1) {
+        Some(end) => end / PAGE_SIZE,
+        None => return -ENOMEM,
+    };
+    if page_count > frame_remaining() {
+        return -ENOMEM;
+    }
+
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let hint = VirAddr::from(addr).floor();
+    let hint_end = VirPageNum(hint.0 + page_count);
+    let in_user_space = addr != 0 && usize::from(hint_end) <= USER_SPACE_END;
+    let start_vpn = if flags & MAP_FIXED != 0 {
+        if !in_user_space {
+            return -EINVAL;
+        }
+        // MAP_FIXED: 先解除该范围内原有的映射
+        inner.memory_set.remove_range(hint, hint_end);
+        hint
+    } else if in_user_space && inner.memory_set.is_range_free(hint, hint_end) {
+        hint
+    } else {
+        match inner.memory_set.find_free_range(page_count) {
+            Some(vpn) => vpn,
+            None => return -ENOMEM,
+        }
+    };
+    inner.memory_set.map_area(
+        MapArea::new_with_pagenum(start_vpn, VirPageNum(start_vpn.0 + page_count), Framed, map_perm),
+        None,
+    );
+    usize::from(start_vpn) as isize
+}
+
+// SYSCALL_MUNMAP 215
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    if addr % PAGE_SIZE != 0 || len == 0 {
+        return -EINVAL;
+    }
+    let end = match addr.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => end,
+        _ => return -EINVAL,
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.memory_set.remove_range(VirAddr::from(addr).floor(), VirAddr::from(end).ceil());
+    0
+}
+
+// SYSCALL_SET_TID_ADDRESS 96
+// 没有线程，只返回 tid (== pid)
+pub fn sys_set_tid_address(_tidptr: *mut i32) -> isize {
+    sys_getpid()
+}
+
+// SYSCALL_UNAME 160
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UtsName {
+    sysname: [u8; 65],
+    nodename: [u8; 65],
+    release: [u8; 65],
+    version: [u8; 65],
+    machine: [u8; 65],
+    domainname: [u8; 65],
+}
+
+pub fn sys_uname(buf: *mut UtsName) -> isize {
+    fn field(s: &str) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes[..s.len()].copy_from_slice(s.as_bytes());
+        bytes
+    }
+    let uts = UtsName {
+        sysname: field("Linux"), // libc 和用户程序按 Linux 处理
+        nodename: field("acore"),
+        release: field("5.10.0-acore"),
+        version: field("#1 ACore"),
+        machine: field("riscv64"),
+        domainname: field("(none)"),
+    };
+    match copy_obj_to_user(current_user_satp(), buf, &uts) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// SYSCALL_CLOCK_GETTIME 113
+const CLOCK_REALTIME: usize = 0;
+const CLOCK_REALTIME_COARSE: usize = 5;
+const CLOCK_BOOTTIME: usize = 7; // 支持的时钟编号为 0 (CLOCK_REALTIME) ~ 7 (CLOCK_BOOTTIME)
+
+// CLOCK_REALTIME 来自 RTC，其余时钟 (MONOTONIC、BOOTTIME 等) 都从开机时刻开始计时
+pub fn sys_clock_gettime(clock_id: usize, tp: *mut TimeSpec) -> isize {
+    let ts = match clock_id {
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => realtime(),
+        id if id <= CLOCK_BOOTTIME => monotonic_time(),
+        _ => return -EINVAL,
+    };
+    match copy_obj_to_user(current_user_satp(), tp, &ts) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
 }
 
 pub fn sys_getpid() -> isize {
@@ -59,23 +212,139 @@ pub fn sys_fork() -> isize {
     // then trap_return
 }
 
-pub fn sys_exec(path: *const u8) -> isize {
+// SYSCALL_EXEC 221 (execve)
+// argv 为 NULL 时以 path 作为 argv[0]，envp 为 NULL 时环境变量为空
+pub fn sys_exec(path: *const u8, argv: *const usize, envp: *const usize) -> isize {
     let token = current_user_satp();
-    let path = translated_str(token, path);
-    if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RD_ONLY) {
-        let data = app_inode.read_data(); // read all data from the file
-        // elf data in `data`
-        current_task().unwrap().exec(data.as_slice()); 
-        0
-    } else {
-        -1
+    let path = match translated_str(token, path) {
+        Ok(path) => path,
+        Err(errno) => return -errno,
+    };
+    let mut args = match translated_str_array(token, argv) {
+        Ok(args) => args,
+        Err(errno) => return -errno,
+    };
+    if argv.is_null() {
+        args.push(path.clone());
+    }
+    let envs = match translated_str_array(token, envp) {
+        Ok(envs) => envs,
+        Err(errno) => return -errno,
+    };
+    let task = current_task().unwrap();
+    let path = normalize(&task.inner_exclusive_access().cwd, &path);
+    // 执行只需要执行权限，不需要读权限
+    let app_inode = match lookup_path(&path) {
+        Ok(app_inode) => app_inode,
+        Err(errno) => return -errno,
+    };
+    if app_inode.inode_type() != InodeType::File {
+        return -EACCES;
     }
+    if let Err(errno) = check_access(&app_inode, MAY_EXEC) {
+        return -errno;
+    }
+    let perm = app_inode.permissions();
+    // read all data from the file
+    let data = match OSInode::new(true, false, false, path.clone(), app_inode).read_data() {
+        Ok(data) => data,
+        Err(errno) => return -errno,
+    };
+    // elf data in `data`
+    match task.exec(data.as_slice(), args, envs) {
+        Ok(()) => {
+            let mut inner = task.inner_exclusive_access();
+            inner.name = String::from(split_parent(&path).1);
+            inner.cred = inner.cred.exec(perm); // setuid/setgid 程序以文件的属主/属组运行
+            0
+        }
+        Err(errno) => -errno,
+    }
+}
+
+// ----- user & group ids -----
+
+pub fn sys_getuid() -> isize {
+    current_credentials().uid as isize
+}
+
+pub fn sys_geteuid() -> isize {
+    current_credentials().euid as isize
+}
+
+pub fn sys_getgid() -> isize {
+    current_credentials().gid as isize
+}
+
+pub fn sys_getegid() -> isize {
+    current_credentials().egid as isize
+}
+
+pub fn sys_setuid(uid: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.cred.setuid(uid) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+pub fn sys_setgid(gid: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    match inner.cred.setgid(gid) {
+        Ok(()) => 0,
+        Err(errno) => -errno,
+    }
+}
+
+// reboot magic & cmd
+const LINUX_REBOOT_MAGIC1: u32 = 0xfee1_dead;
+const LINUX_REBOOT_MAGIC2: u32 = 0x2812_1969;
+const LINUX_REBOOT_CMD_POWER_OFF: u32 = 0x4321_fedc;
+
+// 只支持关机: 先把所有文件系统写回磁盘，再关闭 QEMU
+pub fn sys_reboot(magic1: u32, magic2: u32, cmd: u32) -> isize {
+    if magic1 != LINUX_REBOOT_MAGIC1 || magic2 != LINUX_REBOOT_MAGIC2 {
+        return -EINVAL;
+    }
+    if current_credentials().euid != 0 {
+        return -EPERM;
+    }
+    match cmd {
+        LINUX_REBOOT_CMD_POWER_OFF => {
+            println!("[kernel] Power off");
+            crate::sbi::shutdown(false)
+        }
+        _ => -EINVAL,
+    }
+}
+
+// 读取以 NULL 结尾的字符串指针数组 (argv/envp)
+fn translated_str_array(token: usize, mut ptr: *const usize) -> Result<Vec<String>, isize> {
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return Ok(strings);
+    }
+    loop {
+        let str_ptr: usize = copy_obj_from_user(token, ptr)?;
+        if str_ptr == 0 {
+            break;
+        }
+        if strings.len() >= ARG_MAX {
+            return Err(E2BIG);
+        }
+        strings.push(translated_str(token, str_ptr as *const u8)?);
+        ptr = ptr.wrapping_add(1);
+    }
+    Ok(strings)
 }
 
 // 回收子进程的资源, 并将 exit_code 写入 exit_code_ptr
-// 如果没有符合 pid 的子进程，返回 -1
-// 如果子进程仍在运行，返回 -2
-// 这是一个立即返回的系统调用，用户库中的 wait_pid 会在返回 -2 时调用 yield_ 来实现阻塞，并在最外层使用 loop 直至子进程退出
+// 如果没有符合 pid 的子进程，返回 -ECHILD
+// 如果子进程仍在运行，返回 -EAGAIN
+// 如果 exit_code_ptr 不可写，返回 -EFAULT 且不回收子进程
+// 这是一个立即返回的系统调用，用户库中的 wait_pid 会在返回 -EAGAIN 时调用 yield_ 来实现阻塞，并在最外层使用 loop 直至子进程退出
 pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
     let task = current_task().unwrap();
 
@@ -93,7 +362,7 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
     }
 
     if !has_child {
-        return -1;
+        return -ECHILD;
     }
 
     // 寻找已经结束的子进程
@@ -115,19 +384,20 @@ pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
     }
 
     if let Some(idx) = found_idx {
+        // 先写回退出码，指针非法时子进程仍留给下一次 waitpid
+        if !exit_code_ptr.is_null() {
+            let exit_code = inner.children[idx].inner_exclusive_access().exit_code;
+            if let Err(errno) = copy_obj_to_user(inner.memory_set.to_satp(), exit_code_ptr, &exit_code) {
+                return -errno;
+            }
+        }
         let child = inner.children.remove(idx);
         // confirm that child will be deallocated after removing from children list
         assert_eq!(Arc::strong_count(&child), 1);
         let found_pid = child.get_pid();
-
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ stop exclusively accessing child PCB
-
-        *translated_refmut(inner.memory_set.to_satp(), exit_code_ptr) = exit_code;
         found_pid as isize
     } else {
-        -2
+        -EAGAIN
     }
     // ---- stop exclusively accessing current PCB automatically
 }
\ No newline at end of file
@@ -0,0 +1,70 @@
+// os/src/task/cred.rs
+// 进程的用户与组 (credentials)
+// real id 表示进程属于谁，effective id 用于权限检查，saved id 使 setuid 程序可以在两者之间切换
+
+use crate::fs::{Permissions, S_ISGID, S_ISUID};
+use crate::syscall::errno::EPERM;
+use super::processor::current_task;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Credentials {
+    pub uid: u32,
+    pub euid: u32,
+    pub suid: u32,
+    pub gid: u32,
+    pub egid: u32,
+    pub sgid: u32,
+}
+
+impl Credentials {
+    pub const ROOT: Self = Self { uid: 0, euid: 0, suid: 0, gid: 0, egid: 0, sgid: 0 };
+
+    pub fn is_root(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// exec 程序文件 (权限为 perm) 之后的 credentials
+    /// 设置了 setuid/setgid 位的程序以文件的属主/属组运行，saved id 记录新的 effective id
+    pub fn exec(&self, perm: Permissions) -> Self {
+        let mut cred = *self;
+        if perm.mode & S_ISUID != 0 {
+            cred.euid = perm.uid;
+        }
+        if perm.mode & S_ISGID != 0 {
+            cred.egid = perm.gid;
+        }
+        cred.suid = cred.euid;
+        cred.sgid = cred.egid;
+        cred
+    }
+
+    /// setuid: root 同时修改三者 (永久放弃权限)，其他用户只能把 euid 切换为 real 或 saved uid
+    pub fn setuid(&mut self, uid: u32) -> Result<(), isize> {
+        if self.is_root() {
+            self.uid = uid;
+            self.suid = uid;
+        } else if uid != self.uid && uid != self.suid {
+            return Err(EPERM);
+        }
+        self.euid = uid;
+        Ok(())
+    }
+
+    /// setgid: 规则与 setuid 相同，由 euid 是否为 root 决定
+    pub fn setgid(&mut self, gid: u32) -> Result<(), isize> {
+        if self.is_root() {
+            self.gid = gid;
+            self.sgid = gid;
+        } else if gid != self.gid && gid != self.sgid {
+            return Err(EPERM);
+        }
+        self.egid = gid;
+        Ok(())
+    }
+}
+
+/// 当前进程的 credentials，没有当前进程 (内核初始化时) 视为 root
+/// 会短暂借用当前任务的 inner，调用者不能持有它
+pub fn current_credentials() -> Credentials {
+    current_task().map_or(Credentials::ROOT, |task| task.inner_exclusive_access().cred)
+}
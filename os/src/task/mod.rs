@@ -4,16 +4,20 @@ mod switch;
 mod context;
 mod task;
 mod pid;
+pub mod cred;
 pub(crate) mod task_manager;
 pub(crate) mod processor;
 pub(crate) use processor::run_tasks;
+pub use task::{TaskControlBlock, TaskStatus};
 
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 use lazy_static::lazy_static;
 pub use context::TaskContext;
 use crate::fs::{open_file, OpenFlags};
 use crate::task::processor::{schedule, take_current_task};
-use crate::task::task::{TaskControlBlock, TaskStatus};
 use crate::task::task_manager::{add_task, fetch_task};
 
 // ----- INIT_PORC -----
@@ -22,8 +26,10 @@ use crate::task::task_manager::{add_task, fetch_task};
 lazy_static! {
     // the init process
     pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
-        let inode = open_file("initproc", OpenFlags::RD_ONLY).unwrap();
-        TaskControlBlock::new_from_elf(inode.read_data().as_slice())
+        let inode = open_file("/initproc", OpenFlags::RD_ONLY, 0).unwrap();
+        let task = TaskControlBlock::new_from_elf(inode.read_data().unwrap().as_slice());
+        task.inner_exclusive_access().name = String::from("initproc");
+        task
     });
 }
 
@@ -43,6 +49,11 @@ pub fn suspend_current_and_run_next() {
 
 pub fn exit_current_and_run_next(exit_code: i32) {
     let task = take_current_task().unwrap();
+    // initproc 退出后没有进程能回收孤儿，也不会再有进程运行: 写回文件系统后关机
+    if task.get_pid() == INITPROC.get_pid() {
+        println!("[kernel] initproc exited with code {}, shutting down", exit_code);
+        crate::sbi::shutdown(false);
+    }
     let mut inner = task.inner_exclusive_access();
     inner.task_status = TaskStatus::Zombie;
     inner.exit_code = exit_code;
@@ -86,4 +97,22 @@ pub fn run_initproc() {
 // todo
 // pub fn change_program_brk(size: i32) -> Option<usize> {
 //     TASK_MANAGER.change_current_program_brk(size)
-// }
\ No newline at end of file
+// }
+
+// ----- process list -----
+/// 所有进程 (包括未被回收的僵尸进程)，按 pid 排序
+/// 孤儿进程会被过继给 INITPROC，因此从 INITPROC 出发遍历进程树即可找到所有进程
+pub fn all_tasks() -> Vec<Arc<TaskControlBlock>> {
+    let mut tasks = Vec::new();
+    let mut stack = vec![INITPROC.clone()];
+    while let Some(task) = stack.pop() {
+        stack.extend(task.inner_exclusive_access().children.iter().cloned());
+        tasks.push(task);
+    }
+    tasks.sort_by_key(|task| task.get_pid());
+    tasks
+}
+
+pub fn find_task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    all_tasks().into_iter().find(|task| task.get_pid() == pid)
+}
@@ -1,17 +1,23 @@
 // os/src/task/task.rs
 
+use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use core::cell::RefMut;
 use crate::mm::address::{PhyPageNum, VirAddr};
 use crate::mm::KERNEL_SPACE;
-use crate::mm::memory_set::MemorySet;
-use crate::config::TRAP_CONTEXT_ADDRESS;
+use crate::mm::memory_set::{MemorySet, AT_EXECFN, AT_NULL, AT_RANDOM};
+use crate::mm::page_table::copy_to_user;
+use crate::config::{TRAP_CONTEXT_ADDRESS, USER_MMAP_BASE, USER_STACK_SIZE};
+use crate::syscall::errno::E2BIG;
+use crate::fs::devfs::fill_random;
 use crate::fs::{File, Stdin, Stdout, Stderr};
+use crate::fs::perm::DEFAULT_UMASK;
 use crate::sync::UPSafeCell;
 use crate::task::pid::{pid_alloc, KernelStack, PidHandle};
 use crate::trap::{trap_handler, TrapContext};
+use super::cred::Credentials;
 use super::TaskContext;
 
 #[derive(Copy, Clone, PartialEq)]
@@ -37,6 +43,10 @@ pub struct TaskControlBlockInner {
     pub children: Vec<Arc<TaskControlBlock>>,   // 子进程的强引用列表
 
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>, // 文件描述符表
+    pub cwd: String,              // 当前工作目录，规范化的绝对路径
+    pub name: String,             // 进程名，即 exec 的文件名
+    pub cred: Credentials,        // 进程的用户与组
+    pub umask: u16,               // 新建文件时要去掉的权限位
 }
 
 impl TaskControlBlockInner {
@@ -49,6 +59,40 @@ impl TaskControlBlockInner {
     pub fn is_zombie(&self) -> bool {
         self.task_status == TaskStatus::Zombie
     }
+
+    // 分配最小的空闲文件描述符
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+
+    // change the location of the program break to `new_brk`. return None if failed.
+    // heap 区为 [heap_bottom, program_brk)，最高不能超过 mmap 区域
+    pub fn change_program_brk(&mut self, new_brk: usize) -> Option<usize> {
+        if new_brk < self.heap_bottom || new_brk > USER_MMAP_BASE {
+            return None;
+        }
+        let old_end = VirAddr::from(self.program_brk).ceil();
+        let new_end = VirAddr::from(new_brk).ceil();
+        // grow_to / shrink_to, 调整 heap 区
+        // 新增的页与 mmap 出来的区域重叠、或物理页不足时 grow_to 失败 (见 MemorySet::grow_to)
+        let result = if new_end < old_end {
+            self.memory_set.shrink_to(VirAddr(self.heap_bottom), VirAddr(new_brk))
+        } else {
+            self.memory_set.grow_to(VirAddr(self.heap_bottom), VirAddr(new_brk))
+        };
+        // success, or not
+        if result {
+            self.program_brk = new_brk;
+            Some(new_brk)
+        } else {
+            None
+        }
+    }
 }
 
 // ----- Task Control Block -----
@@ -79,7 +123,10 @@ impl TaskControlBlock {
     // ----- new, exec, fork -----
     pub fn new_from_elf(elf_data: &[u8]) -> Self {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_stack_top, entry_point, auxv) = MemorySet::from_elf(elf_data)
+            .expect("[TCB] invalid elf data");
+        let (user_sp, argv_base) = init_user_stack(&memory_set, user_stack_top, &[], &[], auxv)
+            .expect("[TCB] failed to init user stack");
 
         // user trap 存放上下文的物理页
         let trap_ctx_ppn = memory_set.translate(VirAddr::from(TRAP_CONTEXT_ADDRESS).into()) // PageTableEntry
@@ -105,17 +152,21 @@ impl TaskControlBlock {
             // 任务上下文，设置切换回 trap_return，初始时 sp 为 kernel_stack_top [注意: 这里是内核栈顶]
             task_ctx: TaskContext::goto_trap_return(kernel_stack_top),
 
-            memory_set,         // 进程的内存空间布局
-            trap_ctx_ppn,       // 存放上下文的物理页
-            base_size: user_sp, // 数据不可能超过 user_sp (用户栈顶)
-            heap_bottom: user_sp,
-            program_brk: user_sp,
+            memory_set,                // 进程的内存空间布局
+            trap_ctx_ppn,              // 存放上下文的物理页
+            base_size: user_stack_top, // 数据不可能超过 user_stack_top (用户栈顶)
+            heap_bottom: user_stack_top,
+            program_brk: user_stack_top,
 
             exit_code: 0,
             parent: None,
             children: Vec::new(),
 
             fd_table,
+            cwd: String::from("/"),
+            name: String::new(),
+            cred: Credentials::ROOT, // 初始进程以 root 运行
+            umask: DEFAULT_UMASK,
         };
 
         let task_control_block = Self {
@@ -134,13 +185,18 @@ impl TaskControlBlock {
             kernel_stack_top,                          // 内核栈顶 (切回用户态时保存)
             trap_handler as usize,                     // trap_handler 地址
         );
+        trap_ctx.x[10] = 0;         // a0: argc
+        trap_ctx.x[11] = argv_base; // a1: argv
 
         task_control_block
     }
 
-    pub fn exec(&self, elf_data: &[u8]) {
+    // 替换当前进程的地址空间，elf_data 非法时保持原进程不变并返回 errno
+    // argv/envp 按 Linux 的初始栈布局放在新的用户栈上
+    pub fn exec(&self, elf_data: &[u8], argv: Vec<String>, envp: Vec<String>) -> Result<(), isize> {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_stack_top, entry_point, auxv) = MemorySet::from_elf(elf_data)?;
+        let (user_sp, argv_base) = init_user_stack(&memory_set, user_stack_top, &argv, &envp, auxv)?;
 
         // user trap 存放上下文的物理页
         let trap_ctx_ppn = memory_set.translate(VirAddr::from(TRAP_CONTEXT_ADDRESS).into()) // PageTableEntry
@@ -155,6 +211,11 @@ impl TaskControlBlock {
         // 更新 trap_ctx_ppn
         inner.trap_ctx_ppn = trap_ctx_ppn;
 
+        // 新程序的 heap 紧接在用户栈之上
+        inner.base_size = user_stack_top;
+        inner.heap_bottom = user_stack_top;
+        inner.program_brk = user_stack_top;
+
         let trap_ctx = inner.get_trap_ctx();
 
         *trap_ctx = TrapContext::app_init_context(
@@ -164,6 +225,9 @@ impl TaskControlBlock {
             self.kernel_stack.get_kernel_top(),        // 内核栈顶 (切回用户态时保存)
             trap_handler as usize,                     // trap_handler 地址
         );
+        trap_ctx.x[10] = argv.len(); // a0: argc
+        trap_ctx.x[11] = argv_base;  // a1: argv
+        Ok(())
         // 函数结束时自动释放 inner
     }
 
@@ -213,6 +277,10 @@ impl TaskControlBlock {
                     children: Vec::new(),                   // 初始化为空
                     
                     fd_table: new_fd_table,                 // 继承父进程的文件描述符表
+                    cwd: parent_inner.cwd.clone(),          // 继承
+                    name: parent_inner.name.clone(),        // 继承
+                    cred: parent_inner.cred,                // 继承
+                    umask: parent_inner.umask,              // 继承
                 })
             },
         });
@@ -228,33 +296,82 @@ impl TaskControlBlock {
         task_control_block
         // 函数结束时自动释放父子进程 TCB 的独占访问
     }
+}
 
+// ----- initial user stack -----
+// 在 memory_set 的用户栈上按 Linux 的约定放置 argc/argv/envp/auxv，返回 (sp, argv 的地址)
+/*
+(高地址)
++-----------------------------+  <-  user_stack_top
+| argv/envp 字符串, AT_RANDOM  |
++-----------------------------+  (16 字节对齐)
+| auxv (type, value)... AT_NULL|
+| envp[0..] NULL              |
+| argv[0..] NULL              |
+| argc                        |
++-----------------------------+  <-  sp
+(低地址)
+ */
+fn init_user_stack(
+    memory_set: &MemorySet,
+    user_stack_top: usize,
+    argv: &[String],
+    envp: &[String],
+    mut auxv: Vec<(usize, usize)>,
+) -> Result<(usize, usize), isize> {
+    let token = memory_set.to_satp();
+    let mut sp = user_stack_top;
+    // 栈空间不足 (写到 guard page) 时视为参数过长
+    let push_bytes = |sp: &mut usize, bytes: &[u8]| -> Result<usize, isize> {
+        *sp = sp.checked_sub(bytes.len()).ok_or(E2BIG)?;
+        copy_to_user(token, *sp as *mut u8, bytes).map_err(|_| E2BIG)?;
+        Ok(*sp)
+    };
+
+    // 字符串, 以 '\0' 结尾
+    let push_strings = |sp: &mut usize, strings: &[String]| -> Result<Vec<usize>, isize> {
+        let mut ptrs = Vec::new();
+        for string in strings.iter() {
+            push_bytes(sp, &[0])?;
+            ptrs.push(push_bytes(sp, string.as_bytes())?);
+        }
+        Ok(ptrs)
+    };
+    let envp_ptrs = push_strings(&mut sp, envp)?;
+    let argv_ptrs = push_strings(&mut sp, argv)?;
+
+    // AT_RANDOM: 16 字节的随机数，libc 用它初始化 stack canary
+    let mut random = [0u8; 16];
+    fill_random(&mut random);
+    let random_ptr = push_bytes(&mut sp, &random)?;
+    auxv.push((AT_RANDOM, random_ptr));
+    if let Some(&execfn) = argv_ptrs.first() {
+        auxv.push((AT_EXECFN, execfn));
+    }
+    auxv.push((AT_NULL, 0));
+
+    // 指针区: argc + argv + NULL + envp + NULL + auxv，保证最终的 sp 16 字节对齐
+    let word = size_of::<usize>();
+    let words = 1 + argv_ptrs.len() + 1 + envp_ptrs.len() + 1 + auxv.len() * 2;
+    sp = (sp - words * word) & !0xf;
+    if sp < user_stack_top - USER_STACK_SIZE {
+        return Err(E2BIG);
+    }
 
-    // change the location of the program break. return None if failed.
-    pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
-        None
-        // todo
-        // let old_brk = self.program_brk;
-        // let new_brk = self.program_brk as isize + size as isize; // size may be negative!
-        // 
-        // // 下界安全性检查
-        // if new_brk < self.heap_bottom as isize {
-        //     return None;
-        // }
-        // 
-        // // grow_to / shrink_to, 调整 heap 区
-        // let result = if size < 0 {
-        //     self.memory_set.shrink_to(VirAddr(self.heap_bottom), VirAddr(new_brk as usize))
-        // } else {
-        //     self.memory_set.grow_to(VirAddr(self.heap_bottom), VirAddr(new_brk as usize))
-        // };
-        // // success, or not
-        // if result {
-        //     self.program_brk = new_brk as usize;
-        //     Some(old_brk)
-        // } else {
-        //     None
-        // }
+    let mut table: Vec<usize> = Vec::with_capacity(words);
+    table.push(argv_ptrs.len());
+    table.extend_from_slice(&argv_ptrs);
+    table.push(0);
+    table.extend_from_slice(&envp_ptrs);
+    table.push(0);
+    for (key, value) in auxv {
+        table.push(key);
+        table.push(value);
     }
-}
+    let table_bytes = unsafe {
+        core::slice::from_raw_parts(table.as_ptr() as *const u8, table.len() * word)
+    };
+    copy_to_user(token, sp as *mut u8, table_bytes).map_err(|_| E2BIG)?;
 
+    Ok((sp, sp + word))
+}
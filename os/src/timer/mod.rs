@@ -8,6 +8,7 @@ use riscv::register::{mie, mscratch, mstatus, mtvec};
 const TICKS_PER_SEC: usize = 500; // interrupt frequency
 const TIME_INTERVAL: usize = CLOCK_FREQ / TICKS_PER_SEC; // timer interval in seconds
 const MICRO_PER_SEC: usize = 1_000_000;
+const NANO_PER_SEC: usize = 1_000_000_000;
 
 global_asm!(include_str!("m_trap.s"));
 
@@ -35,6 +36,30 @@ pub fn get_time_us() -> usize {
     get_time() / (CLOCK_FREQ / MICRO_PER_SEC)
 }
 
+// ----- TimeSpec -----
+/// struct timespec
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+/// 开机以来的时间 (单调时钟)
+pub fn monotonic_time() -> TimeSpec {
+    let ticks = get_time();
+    TimeSpec {
+        tv_sec: ticks / CLOCK_FREQ,
+        tv_nsec: (ticks % CLOCK_FREQ) * NANO_PER_SEC / CLOCK_FREQ,
+    }
+}
+
+/// 墙上时间 (自 Unix 纪元以来)，来自 RTC
+pub fn realtime() -> TimeSpec {
+    let ns = crate::drivers::rtc_time_ns() as usize;
+    TimeSpec { tv_sec: ns / NANO_PER_SEC, tv_nsec: ns % NANO_PER_SEC }
+}
+
 
 pub fn set_timer(time: usize) {
     unsafe {
@@ -10,6 +10,8 @@ pub struct TrapContext {
     pub kernel_satp: usize,  // kernel satp token (include PA of kernel's page table)
     pub kernel_sp: usize,    // (VA) kernel stack pointer
     pub trap_handler: usize, // (VA) kernel's trap handler pointer, we only jump to it in S mode
+    pub f: [usize; 32],      // float regs[0..31]
+    pub fcsr: usize,         // CSR fcsr
 }
 
 impl TrapContext {
@@ -28,6 +30,8 @@ impl TrapContext {
             kernel_satp,  // addr of page table
             kernel_sp,    // kernel stack
             trap_handler, // addr of trap_handler function
+            f: [0; 32],
+            fcsr: 0,
         };
         ctx.set_sp(sp);
         ctx
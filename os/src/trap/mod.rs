@@ -68,14 +68,19 @@ pub fn trap_handler() -> ! {
                 asm! {"csrw sip, {sip}", sip = in(reg) sip ^ 2};
             }
             // next time interrupt already set in "m_trap_entry"
+            // 中断只来自用户态，内核没有持有任何锁，可以在这里做定时的后台回写
+            crate::fs::writeback::tick();
             suspend_current_and_run_next();
         }
 
         Trap::Exception(Exception::UserEnvCall) => {
             // println!("[kernel] UserEnvCall");
             ctx.sepc += 4; // skip ecall instruction
-            // a7 | a0, a1, a2
-            let a0 = syscall(ctx.x[17], [ctx.x[10], ctx.x[11], ctx.x[12]]) as usize;
+            // a7 | a0, a1, a2, a3, a4, a5
+            let a0 = syscall(
+                ctx.x[17],
+                [ctx.x[10], ctx.x[11], ctx.x[12], ctx.x[13], ctx.x[14], ctx.x[15]],
+            ) as usize;
             // syscall might be 'sys_exec', we need to update the trap context
             ctx = current_trap_ctx();
             ctx.x[10] = a0 as usize;
@@ -84,7 +89,10 @@ pub fn trap_handler() -> ! {
         Trap::Exception(Exception::LoadFault) |
         Trap::Exception(Exception::LoadPageFault) |
         Trap::Exception(Exception::StoreFault) |
-        Trap::Exception(Exception::StorePageFault) => {
+        Trap::Exception(Exception::StorePageFault) |
+        Trap::Exception(Exception::InstructionFault) |
+        Trap::Exception(Exception::InstructionPageFault) |
+        Trap::Exception(Exception::InstructionMisaligned) => {
             println_red!(
                 "[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.",
                 stval, ctx.sepc
@@ -96,7 +104,12 @@ pub fn trap_handler() -> ! {
             exit_current_and_run_next(-3); // illegal instruction exit code: -3
         }
         _ => {
-            panic!("Unsupported trap {:?}, stval = {:#x}!", scause.cause(), stval);
+            // 用户程序触发的其它异常 (如 breakpoint) 只杀死该进程，不影响内核
+            println_red!(
+                "[kernel] Unsupported trap {:?}, stval = {:#x}, sepc = {:#x}, kernel killed it.",
+                scause.cause(), stval, ctx.sepc
+            );
+            exit_current_and_run_next(-4); // unsupported trap exit code: -4
         }
     }
     // println!("[kernel] return from trap_handler");
@@ -1,10 +1,19 @@
 # this file is from rCore-ch4
 .altmacro
+# global_asm 不继承 target feature，需要显式启用 D 扩展才能使用 fsd/fld
+.option arch, +d
 .macro SAVE_GP n
     sd x\n, \n*8(sp)
 .endm
 .macro LOAD_GP n
     ld x\n, \n*8(sp)
+.endm
+# f0~f31 are stored after trap_handler, at (37+n)*8
+.macro SAVE_FP n
+    fsd f\n, (\n+37)*8(sp)
+.endm
+.macro LOAD_FP n
+    fld f\n, (\n+37)*8(sp)
 .endm
     .section .text.trampoline
     .globl __alltraps
@@ -20,13 +29,22 @@ __alltraps:
     sd x1, 1*8(sp)
     # skip sp(x2), we will save it later
     sd x3, 3*8(sp)
-    # skip tp(x4), application does not use it
+    # save tp(x4), libc keeps its thread pointer in it
+    sd x4, 4*8(sp)
     # save x5~x31
     .set n, 5
     .rept 27
         SAVE_GP %n
         .set n, n+1
     .endr
+    # save f0~f31 and fcsr, sstatus.FS is enabled in rust_main
+    .set n, 0
+    .rept 32
+        SAVE_FP %n
+        .set n, n+1
+    .endr
+    frcsr t0
+    sd t0, 69*8(sp)
     # we can use t0/t1/t2 freely, because they were saved on kernel stack
     csrr t0, sstatus
     csrr t1, sepc
@@ -64,9 +82,18 @@ __restore:
     ld t1, 33*8(sp)
     csrw sstatus, t0
     csrw sepc, t1
-    # restore general purpose registers except x0/sp/tp
+    # restore f0~f31 and fcsr
+    ld t0, 69*8(sp)
+    fscsr t0
+    .set n, 0
+    .rept 32
+        LOAD_FP %n
+        .set n, n+1
+    .endr
+    # restore general purpose registers except x0/sp
     ld x1, 1*8(sp)
     ld x3, 3*8(sp)
+    ld x4, 4*8(sp)
     .set n, 5
     .rept 27
         LOAD_GP %n
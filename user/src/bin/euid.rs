@@ -0,0 +1,12 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::geteuid;
+
+// 以 effective uid 作为退出码，perm_test 用它检查 setuid 程序
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    geteuid() as i32
+}
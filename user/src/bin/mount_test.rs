@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+// 挂载点: 目录项 ".." 的 inode 编号，挂载的文件系统的根目录的 ".." 是被覆盖的挂载点的父目录
+// 其中还有打开的文件或进程的工作目录时不能卸载
+// 需要以 root 运行
+
+use user_lib::errno::EBUSY;
+use user_lib::{chdir, close, getdents, mkdir, mount, open, read, rmdir, stat, umount, write, Stat, O_CREAT, O_DIRECTORY,
+    O_RDONLY, O_RDWR};
+
+const DIR: &str = "/mount_test\0";
+const MNT: &str = "/mount_test/mnt\0";
+
+fn ino_of(path: &str) -> u64 {
+    let mut st = Stat::default();
+    assert_eq!(stat(path, &mut st), 0, "cannot stat {}", path);
+    st.ino
+}
+
+// 读出目录 path 中 "." 与 ".." 的 inode 编号
+fn dot_inos(path: &str) -> (u64, u64) {
+    let fd = open(path, O_RDONLY | O_DIRECTORY);
+    assert!(fd >= 0, "cannot open {}", path);
+    let mut buf = [0u8; 512];
+    let len = getdents(fd as usize, &mut buf);
+    assert!(len > 0);
+    close(fd as usize);
+    let (mut dot, mut dotdot) = (None, None);
+    let mut pos = 0;
+    // struct linux_dirent64: d_ino(8) d_off(8) d_reclen(2) d_type(1) d_name(NUL 结尾)
+    while pos < len as usize {
+        let record = &buf[pos..];
+        let ino = u64::from_le_bytes(record[0..8].try_into().unwrap());
+        let reclen = u16::from_le_bytes(record[16..18].try_into().unwrap()) as usize;
+        let name = &record[19..reclen];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap()];
+        match name {
+            b"." => dot = Some(ino),
+            b".." => dotdot = Some(ino),
+            _ => {}
+        }
+        pos += reclen;
+    }
+    (dot.unwrap(), dotdot.unwrap())
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(mkdir(DIR), 0);
+    assert_eq!(mkdir(MNT), 0);
+    assert_eq!(dot_inos("/\0"), (ino_of("/\0"), ino_of("/\0")));
+    assert_eq!(dot_inos(DIR), (ino_of(DIR), ino_of("/\0")));
+
+    assert_eq!(mount("tmpfs\0", MNT, "tmpfs\0"), 0);
+    assert_eq!(mkdir("/mount_test/mnt/sub\0"), 0);
+    assert_eq!(dot_inos(MNT), (ino_of(MNT), ino_of(DIR)));
+    assert_eq!(dot_inos("/mount_test/mnt/sub\0"), (ino_of("/mount_test/mnt/sub\0"), ino_of(MNT)));
+
+
+    let fd = open("/mount_test/mnt/file\0", O_RDWR | O_CREAT);
+    assert!(fd >= 0);
+    assert_eq!(umount(MNT), -EBUSY);
+    assert_eq!(write(fd as usize, b"busy"), 4);
+    close(fd as usize);
+    assert_eq!(chdir("/mount_test/mnt/sub\0"), 0);
+    assert_eq!(umount(MNT), -EBUSY);
+    assert_eq!(chdir("/\0"), 0);
+    let fd = open("/mount_test/mnt/file\0", O_RDONLY);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fd as usize, &mut buf), 4);
+    close(fd as usize);
+    assert_eq!(umount(MNT), 0);
+    assert!(open("/mount_test/mnt/file\0", O_RDONLY) < 0);
+    assert_eq!(rmdir(MNT), 0);
+    assert_eq!(rmdir(DIR), 0);
+    println!("mount_test passed!");
+    0
+}
@@ -0,0 +1,127 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+// 切换为普通用户后检查权限: 允许与拒绝的 setuid/setgid、umask、chmod/chown、open 与 exec，以及 setuid 程序
+// 需要以 root 运行 (init 启动的 shell 即为 root)，并且镜像中有 /euid
+
+use user_lib::errno::{EACCES, EPERM};
+use user_lib::{chmod, chown, close, exec, fork, getegid, geteuid, getgid, getuid, mkdir, open, open_mode, read, rmdir,
+    setgid, setuid, stat, umask, unlink, waitpid, write, Stat, O_CREAT, O_RDONLY, O_WRONLY};
+
+const DIR: &str = "/perm_test\0";
+const SECRET: &str = "/perm_test/secret\0";
+const MINE: &str = "/perm_test/mine\0";
+const SETUID_EUID: &str = "/perm_test/setuid_euid\0";
+const NOEXEC: &str = "/perm_test/noexec\0";
+const OWNER: u32 = 1000; // setuid_euid 的属主
+const USER: u32 = 2000;  // 子进程切换到的用户
+
+// 把 from 复制为新文件 to，权限为 mode
+fn copy(from: &str, to: &str, mode: u32) {
+    let src = open(from, O_RDONLY);
+    assert!(src >= 0, "cannot open {}", from);
+    let dst = open_mode(to, O_WRONLY | O_CREAT, mode);
+    assert!(dst >= 0);
+    let mut buf = [0u8; 512];
+    loop {
+        let len = read(src as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        assert_eq!(write(dst as usize, &buf[..len as usize]), len);
+    }
+    close(src as usize);
+    close(dst as usize);
+}
+
+fn mode_of(path: &str) -> (u32, u32, u32) {
+    let mut st = Stat::default();
+    assert_eq!(stat(path, &mut st), 0);
+    (st.mode & 0o7777, st.uid, st.gid)
+}
+
+// fork 后 exec path，返回退出码
+fn run(path: &str) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exec(path);
+        panic!("exec {} failed", path);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+// 以 USER 运行的部分
+fn unprivileged() -> i32 {
+    // root 调用 setgid/setuid 永久切换，之后不能再切换回 root
+    assert_eq!(setgid(USER), 0);
+    assert_eq!(setuid(USER), 0);
+    assert_eq!((getuid(), geteuid(), getgid(), getegid()), (USER, USER, USER, USER));
+    assert_eq!(setuid(0), -EPERM);
+    assert_eq!(setgid(0), -EPERM);
+    assert_eq!(setuid(USER), 0);
+
+    // 其他用户的文件: 没有读权限，不能修改权限与属主
+    assert_eq!(open(SECRET, O_RDONLY), -EACCES);
+    assert_eq!(open(SECRET, O_WRONLY), -EACCES);
+    assert_eq!(chmod(SECRET, 0o666), -EPERM);
+    assert_eq!(chown(SECRET, USER, USER), -EPERM);
+
+    // 新建的文件属于 USER，权限去掉 umask；属主可以 chmod，但不能把文件交给别人
+    assert_eq!(umask(0o077), 0o022);
+    assert_eq!(umask(0o077), 0o077);
+    let fd = open_mode(MINE, O_WRONLY | O_CREAT, 0o666);
+    assert!(fd >= 0);
+    close(fd as usize);
+    assert_eq!(mode_of(MINE), (0o600, USER, USER));
+    assert_eq!(chmod(MINE, 0o640), 0);
+    assert_eq!(mode_of(MINE).0, 0o640);
+    assert_eq!(chown(MINE, 0, 0), -EPERM);
+    // 设置 setgid 位需要属于文件的属组，否则被清除
+    assert_eq!(chmod(MINE, 0o2755), 0);
+    assert_eq!(mode_of(MINE).0, 0o2755);
+
+    // 没有执行权限的程序不能 exec，exec 失败时返回错误码
+    assert_eq!(exec(NOEXEC), -EACCES);
+    // setuid 程序以属主运行，普通程序以当前用户运行
+    assert_eq!(run(SETUID_EUID), OWNER as i32);
+    assert_eq!(run("/euid\0"), USER as i32);
+    0
+}
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    assert_eq!(getuid(), 0, "perm_test must run as root");
+    assert_eq!(mkdir(DIR), 0);
+    assert_eq!(chmod(DIR, 0o777), 0);
+    let fd = open_mode(SECRET, O_WRONLY | O_CREAT, 0o600);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"root only\n"), 10);
+    close(fd as usize);
+    copy("/euid\0", NOEXEC, 0o644);
+    // chown 会清除 setuid 位，先 chown 再 chmod
+    copy("/euid\0", SETUID_EUID, 0o755);
+    assert_eq!(chown(SETUID_EUID, OWNER, OWNER), 0);
+    assert_eq!(chmod(SETUID_EUID, 0o4755), 0);
+    assert_eq!(mode_of(SETUID_EUID), (0o4755, OWNER, OWNER));
+
+    let pid = fork();
+    if pid == 0 {
+        return unprivileged();
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+
+    for path in [SECRET, MINE, SETUID_EUID, NOEXEC] {
+        assert_eq!(unlink(path), 0);
+    }
+    assert_eq!(rmdir(DIR), 0);
+    assert_eq!(exit_code, 0);
+    println!("perm_test passed!");
+    0
+}
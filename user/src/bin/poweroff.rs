@@ -0,0 +1,15 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::poweroff;
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // 成功时不返回
+    let ret = poweroff();
+    println!("poweroff failed: {}", ret);
+    1
+}
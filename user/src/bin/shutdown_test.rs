@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+// 写入一个文件后不调用 sync 直接关机，关机时内核写回文件系统
+// 由 os/Makefile 的 test_shutdown 在 QEMU 退出后用 fs-fuse 检查 fs.img 中的文件
+
+use user_lib::errno::EPERM;
+use user_lib::{close, open, poweroff, write, O_CREAT, O_TRUNC, O_WRONLY};
+
+const CONTENT: &[u8] = b"written before poweroff\n";
+
+#[unsafe(no_mangle)]
+pub fn main() -> i32 {
+    // 延迟提交: 修改留在块缓存中，只有关机时的写回才能把它们写入磁盘
+    let fd = open("/proc/sys/vm/delayed_commit\0", O_WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"1\n"), 2);
+    close(fd as usize);
+
+    let fd = open("/shutdown_test.txt\0", O_WRONLY | O_CREAT | O_TRUNC);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, CONTENT), CONTENT.len() as isize);
+    close(fd as usize);
+
+    // 只有 root 可以关机
+    if user_lib::fork() == 0 {
+        assert_eq!(user_lib::setuid(1000), 0);
+        assert_eq!(poweroff(), -EPERM);
+        return 0;
+    }
+    let mut exit_code = 0;
+    user_lib::wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+
+    println!("shutdown_test: powering off");
+    poweroff();
+    unreachable!("poweroff returned");
+}
@@ -17,6 +17,8 @@ static SUCC_TESTS: &[(&str, &str, &str, &str, i32)] = &[
     ("forktree\0", "\0", "\0", "\0", 0),
     ("hello_world\0", "\0", "\0", "\0", 0),
     ("matrix\0", "\0", "\0", "\0", 0),
+    ("mount_test\0", "\0", "\0", "\0", 0),
+    ("perm_test\0", "\0", "\0", "\0", 0),
     ("sleep_simple\0", "\0", "\0", "\0", 0),
     ("sleep\0", "\0", "\0", "\0", 0),
     ("yield\0", "\0", "\0", "\0", 0),
@@ -4,6 +4,7 @@
 
 #[macro_use]
 pub mod console;
+pub mod errno;
 mod lang_items;
 mod syscall;
 
@@ -47,6 +48,7 @@ fn main() -> i32 {
     panic!("Cannot find main!");
 }
 
+use errno::EAGAIN;
 use syscall::*;
 
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
@@ -64,7 +66,35 @@ pub fn yield_() -> isize { sys_yield() }
 
 pub fn get_time() -> usize { sys_get_time() }
 
-pub fn sbrk(size: i32) -> isize { sys_sbrk(size) }
+// ----- time -----
+pub const CLOCK_REALTIME: usize = 0;  // 墙上时间
+pub const CLOCK_MONOTONIC: usize = 1; // 开机以来的时间
+pub const UTIME_NOW: usize = (1 << 30) - 1;  // utimens: 设置为当前时间
+pub const UTIME_OMIT: usize = (1 << 30) - 2; // utimens: 保持不变
+
+// struct timespec
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+pub fn clock_gettime(clock_id: usize) -> TimeSpec {
+    let mut tp = TimeSpec::default();
+    sys_clock_gettime(clock_id, &mut tp);
+    tp
+}
+
+// 在 brk 之上实现 sbrk: 成功返回原来的 break，失败返回 -1
+pub fn sbrk(size: i32) -> isize {
+    let old_brk = sys_brk(0);
+    let new_brk = (old_brk as usize).wrapping_add(size as isize as usize);
+    if sys_brk(new_brk) != new_brk as isize {
+        return -1;
+    }
+    old_brk
+}
 
 pub fn getpid() -> isize { sys_getpid() }
 
@@ -72,24 +102,187 @@ pub fn fork() -> isize { sys_fork() }
 
 pub fn exec(path: &str) -> isize { sys_exec(path) }
 
+// 等待任一子进程退出并返回它的 pid；没有子进程时返回 -ECHILD，exit_code 不可写时返回 -EFAULT
+// 内核在子进程仍在运行时返回 -EAGAIN，这里 yield_ 后重试，不会返回给调用者
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => { yield_(); } // Exist child processes, but no child processes have exited yet
-            exit_pid => return exit_pid, // -1 (no child processes) or a real pid
+            r if r == -EAGAIN => { yield_(); } // exist child processes, but no child processes have exited yet
+            exit_pid => return exit_pid, // -ECHILD (no child processes) or a real pid
         }
     }
 }
 
+// 等待子进程 pid 退出，返回值同 wait
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => { yield_(); }  // Exist child processes, but no child processes have exited yet
-            exit_pid => return exit_pid, // -1 (no child processes) or a real pid
+            r if r == -EAGAIN => { yield_(); } // exist child processes, but no child processes have exited yet
+            exit_pid => return exit_pid, // -ECHILD (no child processes) or a real pid
         }
     }
 }
 
+// ----- users & groups -----
+pub fn getuid() -> u32 { sys_getuid() as u32 }
+
+pub fn geteuid() -> u32 { sys_geteuid() as u32 }
+
+pub fn getgid() -> u32 { sys_getgid() as u32 }
+
+pub fn getegid() -> u32 { sys_getegid() as u32 }
+
+// root 调用时永久切换为 uid，其他用户只能在 real uid 和 saved uid 之间切换 effective uid
+pub fn setuid(uid: u32) -> isize { sys_setuid(uid) }
+
+pub fn setgid(gid: u32) -> isize { sys_setgid(gid) }
+
+// ----- file system -----
+// open flags, same as Linux
+pub const O_RDONLY: u32 = 0;
+pub const O_WRONLY: u32 = 1;
+pub const O_RDWR: u32 = 2;
+pub const O_CREAT: u32 = 0o100;
+pub const O_EXCL: u32 = 0o200;
+pub const O_TRUNC: u32 = 0o1000;
+pub const O_APPEND: u32 = 0o2000;
+pub const O_DIRECTORY: u32 = 0o200000;
+pub const O_NOFOLLOW: u32 = 0o400000;
+
+// 路径参数都需要以 '\0' 结尾，例如 open("/tmp/a\0", O_RDONLY)
+pub fn open(path: &str, flags: u32) -> isize { sys_openat(path, flags, 0o666) }
+
+// 同 open，但新建的文件权限为 mode (去掉 umask)
+pub fn open_mode(path: &str, flags: u32, mode: u32) -> isize { sys_openat(path, flags, mode) }
+
+pub fn close(fd: usize) -> isize { sys_close(fd) }
+
+pub fn mkdir(path: &str) -> isize { sys_mkdirat(path, 0o755) }
+
+pub fn unlink(path: &str) -> isize { sys_unlinkat(path, 0) }
+
+pub fn rmdir(path: &str) -> isize { sys_unlinkat(path, 0x200) }
+
+// renameat2 flags, same as Linux
+pub const RENAME_NOREPLACE: u32 = 1;
+pub const RENAME_EXCHANGE: u32 = 2;
+
+// 目标已存在时原子地替换它
+pub fn rename(old_path: &str, new_path: &str) -> isize { sys_renameat2(old_path, new_path, 0) }
+
+pub fn renameat2(old_path: &str, new_path: &str, flags: u32) -> isize { sys_renameat2(old_path, new_path, flags) }
+
+// 创建指向 target 的符号链接 link_path，两者都以 '\0' 结尾
+pub fn symlink(target: &str, link_path: &str) -> isize { sys_symlinkat(target, link_path) }
+
+// 返回链接目标的长度，写入 buf 的目标不以 '\0' 结尾
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize { sys_readlinkat(path, buf) }
+
+pub fn ftruncate(fd: usize, len: usize) -> isize { sys_ftruncate(fd, len) }
+
+// 读取目录项 (struct linux_dirent64)，返回写入 buf 的字节数，0 表示已读完
+pub fn getdents(fd: usize, buf: &mut [u8]) -> isize { sys_getdents64(fd, buf) }
+
+pub fn chdir(path: &str) -> isize { sys_chdir(path) }
+
+pub fn getcwd(buf: &mut [u8]) -> isize { sys_getcwd(buf) }
+
+// 只有文件的属主和 root 可以修改权限
+pub fn chmod(path: &str, mode: u32) -> isize { sys_fchmodat(path, mode) }
+
+// 只有 root 可以修改属主，uid 或 gid 为 u32::MAX 时保持不变
+pub fn chown(path: &str, uid: u32, gid: u32) -> isize { sys_fchownat(path, uid, gid) }
+
+// 设置新建文件时要去掉的权限位，返回原来的值
+pub fn umask(mask: u32) -> u32 { sys_umask(mask) as u32 }
+
+// struct stat (riscv64)
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Stat {
+    pub dev: u64,
+    pub ino: u64,
+    pub mode: u32,
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub rdev: u64,
+    __pad1: u64,
+    pub size: i64,
+    pub blksize: i32,
+    __pad2: i32,
+    pub blocks: i64,
+    pub atime: TimeSpec,
+    pub mtime: TimeSpec,
+    pub ctime: TimeSpec,
+    __unused: [u32; 2],
+}
+
+pub fn stat(path: &str, stat: &mut Stat) -> isize { sys_newfstatat(path, stat, 0) }
+
+// 不跟随最后一个分量的符号链接
+pub fn lstat(path: &str, stat: &mut Stat) -> isize { sys_newfstatat(path, stat, 0x100) }
+
+pub fn fstat(fd: usize, stat: &mut Stat) -> isize { sys_fstat(fd, stat) }
+
+// struct statfs (riscv64)，块数以 bsize 为单位
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StatFs {
+    pub fs_type: i64,
+    pub bsize: i64,
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub fsid: [i32; 2],
+    pub namelen: i64,
+    pub frsize: i64,
+    pub flags: i64,
+    __spare: [i64; 4],
+}
+
+// path 所在文件系统的容量与空闲空间
+pub fn statfs(path: &str, buf: &mut StatFs) -> isize { sys_statfs(path, buf) }
+
+pub fn fstatfs(fd: usize, buf: &mut StatFs) -> isize { sys_fstatfs(fd, buf) }
+
+// 把所有文件系统中尚未写入磁盘的修改写入磁盘
+pub fn sync() { sys_sync(); }
+
+// 把 fd 对应文件的修改写入磁盘，fdatasync 不等待只修改了时间戳等属性的部分
+pub fn fsync(fd: usize) -> isize { sys_fsync(fd) }
+
+pub fn fdatasync(fd: usize) -> isize { sys_fdatasync(fd) }
+
+// 把 fd 所在的文件系统写入磁盘
+pub fn syncfs(fd: usize) -> isize { sys_syncfs(fd) }
+
+// 把所有文件系统写入磁盘后关机，只有 root 可以调用；成功时不返回
+pub fn poweroff() -> isize { sys_reboot(0x4321_fedc) }
+
+// 修改 [atime, mtime]，None 表示都设置为当前时间
+pub fn utimens(path: &str, times: Option<&[TimeSpec; 2]>) -> isize { sys_utimensat(path, times) }
+
+// setxattr 的 flags
+pub const XATTR_CREATE: usize = 1;  // 属性已存在时失败
+pub const XATTR_REPLACE: usize = 2; // 属性不存在时失败
+
+pub fn setxattr(path: &str, name: &str, value: &[u8], flags: usize) -> isize { sys_setxattr(path, name, value, flags) }
+
+// value 为空时只返回属性值的长度
+pub fn getxattr(path: &str, name: &str, value: &mut [u8]) -> isize { sys_getxattr(path, name, value) }
+
+// list 中依次是以 '\0' 结尾的属性名
+pub fn listxattr(path: &str, list: &mut [u8]) -> isize { sys_listxattr(path, list) }
+
+pub fn removexattr(path: &str, name: &str) -> isize { sys_removexattr(path, name) }
+
+pub fn mount(source: &str, target: &str, fs_type: &str) -> isize { sys_mount(source, target, fs_type) }
+
+pub fn umount(target: &str) -> isize { sys_umount2(target) }
+
 pub fn sleep(period_ms: usize) {
     let start = sys_get_time();
     while sys_get_time() < start + period_ms {
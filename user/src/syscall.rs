@@ -1,5 +1,6 @@
 // user/src/syscall.rs
 use core::arch::asm;
+use crate::{Stat, StatFs, TimeSpec};
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
     unsafe {
@@ -14,15 +15,70 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+// 参数多于 3 个的系统调用 (如 mount)
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+        "ecall",
+        inlateout("x10") args[0] => ret,
+        in("x11") args[1],
+        in("x12") args[2],
+        in("x13") args[3],
+        in("x14") args[4],
+        in("x15") args[5],
+        in("x17") id
+        );
+    }
+    ret
+}
+
+const SYSCALL_SETXATTR: usize = 5;
+const SYSCALL_GETXATTR: usize = 8;
+const SYSCALL_LISTXATTR: usize = 11;
+const SYSCALL_REMOVEXATTR: usize = 14;
+const SYSCALL_GETCWD: usize = 17;
+const SYSCALL_MKDIRAT: usize = 34;
+const SYSCALL_UNLINKAT: usize = 35;
+const SYSCALL_SYMLINKAT: usize = 36;
+const SYSCALL_UMOUNT2: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_STATFS: usize = 43;
+const SYSCALL_FSTATFS: usize = 44;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_CHDIR: usize = 49;
+const SYSCALL_FCHMODAT: usize = 53;
+const SYSCALL_FCHOWNAT: usize = 54;
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_GETDENTS64: usize = 61;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_READLINKAT: usize = 78;
+const SYSCALL_NEWFSTATAT: usize = 79;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_SYNC: usize = 81;
+const SYSCALL_FSYNC: usize = 82;
+const SYSCALL_FDATASYNC: usize = 83;
+const SYSCALL_UTIMENSAT: usize = 88;
 
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_REBOOT: usize = 142;
+const SYSCALL_SETGID: usize = 144;
+const SYSCALL_SETUID: usize = 146;
+const SYSCALL_UMASK: usize = 166;
 const SYSCALL_GET_TIME: usize = 169;
-const SYSCALL_SBRK: usize = 214;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_SYNCFS: usize = 267;
+const SYSCALL_RENAMEAT2: usize = 276;
 
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
+const SYSCALL_GETEUID: usize = 175;
+const SYSCALL_GETGID: usize = 176;
+const SYSCALL_GETEGID: usize = 177;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
@@ -43,8 +99,13 @@ pub fn sys_get_time() -> usize {
     syscall(SYSCALL_GET_TIME, [0, 0, 0]) as usize
 }
 
-pub fn sys_sbrk(size: i32) -> isize {
-    syscall(SYSCALL_SBRK, [size as usize, 0, 0])
+pub fn sys_clock_gettime(clock_id: usize, tp: &mut TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock_id, tp as *mut TimeSpec as usize, 0])
+}
+
+// Linux brk: 返回新的 program break，失败时返回原来的 break
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0])
 }
 
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize { syscall(SYSCALL_READ, [fd, buffer.as_mut_ptr() as usize, buffer.len()]) }
@@ -56,3 +117,115 @@ pub fn sys_fork() -> isize { syscall(SYSCALL_FORK, [0, 0, 0]) }
 pub fn sys_exec(path: &str) -> isize { syscall(SYSCALL_EXEC, [path.as_ptr() as usize, 0, 0]) }
 
 pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize { syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0]) }
+
+pub fn sys_getuid() -> isize { syscall(SYSCALL_GETUID, [0, 0, 0]) }
+
+pub fn sys_geteuid() -> isize { syscall(SYSCALL_GETEUID, [0, 0, 0]) }
+
+pub fn sys_getgid() -> isize { syscall(SYSCALL_GETGID, [0, 0, 0]) }
+
+pub fn sys_getegid() -> isize { syscall(SYSCALL_GETEGID, [0, 0, 0]) }
+
+// 内核只支持 LINUX_REBOOT_CMD_POWER_OFF
+pub fn sys_reboot(cmd: u32) -> isize { syscall(SYSCALL_REBOOT, [0xfee1_dead, 0x2812_1969, cmd as usize]) }
+
+pub fn sys_setuid(uid: u32) -> isize { syscall(SYSCALL_SETUID, [uid as usize, 0, 0]) }
+
+pub fn sys_setgid(gid: u32) -> isize { syscall(SYSCALL_SETGID, [gid as usize, 0, 0]) }
+
+// 路径参数都需要以 '\0' 结尾
+const AT_FDCWD: isize = -100;
+
+// mode 为新建文件的权限，实际权限还要去掉 umask
+pub fn sys_openat(path: &str, flags: u32, mode: u32) -> isize {
+    syscall6(SYSCALL_OPENAT, [AT_FDCWD as usize, path.as_ptr() as usize, flags as usize, mode as usize, 0, 0])
+}
+
+pub fn sys_close(fd: usize) -> isize { syscall(SYSCALL_CLOSE, [fd, 0, 0]) }
+
+pub fn sys_mkdirat(path: &str, mode: u32) -> isize { syscall(SYSCALL_MKDIRAT, [AT_FDCWD as usize, path.as_ptr() as usize, mode as usize]) }
+
+// flags: 0 删除文件，AT_REMOVEDIR (0x200) 删除空目录
+pub fn sys_unlinkat(path: &str, flags: usize) -> isize { syscall(SYSCALL_UNLINKAT, [AT_FDCWD as usize, path.as_ptr() as usize, flags]) }
+
+// flags: RENAME_NOREPLACE (1) 目标已存在时失败，RENAME_EXCHANGE (2) 交换两者
+pub fn sys_renameat2(old_path: &str, new_path: &str, flags: u32) -> isize {
+    syscall6(SYSCALL_RENAMEAT2, [AT_FDCWD as usize, old_path.as_ptr() as usize, AT_FDCWD as usize, new_path.as_ptr() as usize, flags as usize, 0])
+}
+
+// target 以 '\0' 结尾，原样保存在符号链接中
+pub fn sys_symlinkat(target: &str, link_path: &str) -> isize {
+    syscall(SYSCALL_SYMLINKAT, [target.as_ptr() as usize, AT_FDCWD as usize, link_path.as_ptr() as usize])
+}
+
+pub fn sys_readlinkat(path: &str, buf: &mut [u8]) -> isize {
+    syscall6(SYSCALL_READLINKAT, [AT_FDCWD as usize, path.as_ptr() as usize, buf.as_mut_ptr() as usize, buf.len(), 0, 0])
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize { syscall(SYSCALL_FTRUNCATE, [fd, len, 0]) }
+
+pub fn sys_getdents64(fd: usize, buf: &mut [u8]) -> isize { syscall(SYSCALL_GETDENTS64, [fd, buf.as_mut_ptr() as usize, buf.len()]) }
+
+pub fn sys_chdir(path: &str) -> isize { syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0]) }
+
+pub fn sys_getcwd(buf: &mut [u8]) -> isize { syscall(SYSCALL_GETCWD, [buf.as_mut_ptr() as usize, buf.len(), 0]) }
+
+pub fn sys_mount(source: &str, target: &str, fs_type: &str) -> isize {
+    syscall6(SYSCALL_MOUNT, [source.as_ptr() as usize, target.as_ptr() as usize, fs_type.as_ptr() as usize, 0, 0, 0])
+}
+
+pub fn sys_fchmodat(path: &str, mode: u32) -> isize {
+    syscall6(SYSCALL_FCHMODAT, [AT_FDCWD as usize, path.as_ptr() as usize, mode as usize, 0, 0, 0])
+}
+
+// uid 或 gid 为 u32::MAX (-1) 时保持不变
+pub fn sys_fchownat(path: &str, uid: u32, gid: u32) -> isize {
+    syscall6(SYSCALL_FCHOWNAT, [AT_FDCWD as usize, path.as_ptr() as usize, uid as usize, gid as usize, 0, 0])
+}
+
+pub fn sys_umask(mask: u32) -> isize { syscall(SYSCALL_UMASK, [mask as usize, 0, 0]) }
+
+// flags: AT_SYMLINK_NOFOLLOW (0x100) 不跟随最后一个分量的符号链接
+pub fn sys_newfstatat(path: &str, stat: &mut Stat, flags: usize) -> isize {
+    syscall6(SYSCALL_NEWFSTATAT, [AT_FDCWD as usize, path.as_ptr() as usize, stat as *mut Stat as usize, flags, 0, 0])
+}
+
+pub fn sys_fstat(fd: usize, stat: &mut Stat) -> isize { syscall(SYSCALL_FSTAT, [fd, stat as *mut Stat as usize, 0]) }
+
+pub fn sys_statfs(path: &str, buf: &mut StatFs) -> isize {
+    syscall(SYSCALL_STATFS, [path.as_ptr() as usize, buf as *mut StatFs as usize, 0])
+}
+
+pub fn sys_fstatfs(fd: usize, buf: &mut StatFs) -> isize { syscall(SYSCALL_FSTATFS, [fd, buf as *mut StatFs as usize, 0]) }
+
+pub fn sys_sync() -> isize { syscall(SYSCALL_SYNC, [0, 0, 0]) }
+
+pub fn sys_fsync(fd: usize) -> isize { syscall(SYSCALL_FSYNC, [fd, 0, 0]) }
+
+pub fn sys_fdatasync(fd: usize) -> isize { syscall(SYSCALL_FDATASYNC, [fd, 0, 0]) }
+
+pub fn sys_syncfs(fd: usize) -> isize { syscall(SYSCALL_SYNCFS, [fd, 0, 0]) }
+
+// times 为 None 时 atime 和 mtime 都设置为当前时间
+pub fn sys_utimensat(path: &str, times: Option<&[TimeSpec; 2]>) -> isize {
+    let times = times.map_or(0, |times| times.as_ptr() as usize);
+    syscall6(SYSCALL_UTIMENSAT, [AT_FDCWD as usize, path.as_ptr() as usize, times, 0, 0, 0])
+}
+
+pub fn sys_setxattr(path: &str, name: &str, value: &[u8], flags: usize) -> isize {
+    syscall6(SYSCALL_SETXATTR, [path.as_ptr() as usize, name.as_ptr() as usize, value.as_ptr() as usize, value.len(), flags, 0])
+}
+
+pub fn sys_getxattr(path: &str, name: &str, value: &mut [u8]) -> isize {
+    syscall6(SYSCALL_GETXATTR, [path.as_ptr() as usize, name.as_ptr() as usize, value.as_mut_ptr() as usize, value.len(), 0, 0])
+}
+
+pub fn sys_listxattr(path: &str, list: &mut [u8]) -> isize {
+    syscall(SYSCALL_LISTXATTR, [path.as_ptr() as usize, list.as_mut_ptr() as usize, list.len()])
+}
+
+pub fn sys_removexattr(path: &str, name: &str) -> isize {
+    syscall(SYSCALL_REMOVEXATTR, [path.as_ptr() as usize, name.as_ptr() as usize, 0])
+}
+
+pub fn sys_umount2(target: &str) -> isize { syscall(SYSCALL_UMOUNT2, [target.as_ptr() as usize, 0, 0]) }